@@ -0,0 +1,81 @@
+//! Building & serializing `ApiResponse` bodies, which happens on every sync request.
+//!
+//! `formatted` replays the previous approach (message `format!`ed upfront into an owned `String`)
+//! as a baseline. Run with `cargo bench --bench response`
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use serde::Serialize;
+use std::time::Duration;
+use sync_point::api::response::ApiResponse;
+
+const UNIQUE_ID: &str = "0f8fad5b-d9cb-469f-a165-70867728950e";
+
+/// The response shape before messages became `Cow`s rendered while serializing
+#[derive(Serialize)]
+struct FormattedResponse {
+    status: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeout_duration_sec: Option<u64>,
+}
+
+fn bench_responses(c: &mut Criterion) {
+    let mut group = c.benchmark_group("success");
+    group.bench_function("api_response", |b| {
+        b.iter(|| {
+            let response = ApiResponse::success("Welcome! (first party)", black_box(UNIQUE_ID));
+            serde_json::to_vec(&response).unwrap()
+        })
+    });
+    group.bench_function("formatted", |b| {
+        b.iter(|| {
+            let response = FormattedResponse {
+                status: "success",
+                message: format!("[{}] {}", black_box(UNIQUE_ID), "Welcome! (first party)"),
+                timeout_duration_sec: None,
+            };
+            serde_json::to_vec(&response).unwrap()
+        })
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("timeout");
+    group.bench_function("api_response", |b| {
+        b.iter(|| {
+            let response = ApiResponse::timeout(Duration::from_secs(10), black_box(UNIQUE_ID));
+            serde_json::to_vec(&response).unwrap()
+        })
+    });
+    group.bench_function("formatted", |b| {
+        b.iter(|| {
+            let response = FormattedResponse {
+                status: "timeout",
+                message: format!("[{}] Request timed out", black_box(UNIQUE_ID)),
+                timeout_duration_sec: Some(10),
+            };
+            serde_json::to_vec(&response).unwrap()
+        })
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("error");
+    group.bench_function("api_response", |b| {
+        b.iter(|| {
+            let response = ApiResponse::error(black_box("Service temporarily unavailable"));
+            serde_json::to_vec(&response).unwrap()
+        })
+    });
+    group.bench_function("formatted", |b| {
+        b.iter(|| {
+            let response = FormattedResponse {
+                status: "error",
+                message: black_box("Service temporarily unavailable").to_string(),
+                timeout_duration_sec: None,
+            };
+            serde_json::to_vec(&response).unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_responses);
+criterion_main!(benches);
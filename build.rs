@@ -0,0 +1,26 @@
+fn main() {
+    #[cfg(feature = "ffi")]
+    generate_header();
+}
+
+/// Generates the C header for the `ffi` module into `OUT_DIR`, never the source tree, which may be
+/// read-only. `test_header_is_up_to_date` checks `include/sync_point.h` is a copy of it, copy it
+/// over along with changes to the C API
+#[cfg(feature = "ffi")]
+fn generate_header() {
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR is set");
+    let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_dir))
+        .expect("Valid cbindgen.toml");
+    cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .expect("Unable to generate C header")
+        .write_to_file(format!(
+            "{}/sync_point.h",
+            std::env::var("OUT_DIR").expect("OUT_DIR is set")
+        ));
+}
@@ -0,0 +1,41 @@
+//! Runs thousands of virtual clients against the sync logic in virtual time & prints a report.
+//!
+//! `cargo run --example simulate --features simulation -- --clients 10000 --ids 5000 --window-sec 60 --seed 42`
+//!
+//! Settings (e.g. timeout) are read the same way as by the server (`config.toml`, `APP_` env vars).
+use std::time::Duration;
+use sync_point::app::App;
+use sync_point::simulation::{run, SimulationConfig};
+
+fn main() {
+    let mut config = SimulationConfig::default();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    for pair in args.chunks(2) {
+        let value = pair
+            .get(1)
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or_else(|| usage(&format!("Missing or invalid value for {}", pair[0])));
+
+        match pair[0].as_str() {
+            "--clients" => config.clients = value as usize,
+            "--ids" => config.ids = value as usize,
+            "--window-sec" => config.arrival_window = Duration::from_secs(value),
+            "--seed" => config.seed = value,
+            other => usage(&format!("Unknown argument {}", other)),
+        }
+    }
+
+    let app = App::new(None).expect("Failed to initialize App");
+    let report = run(app, &config);
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&report).expect("Report is serializable")
+    );
+}
+
+fn usage(error: &str) -> ! {
+    eprintln!("{}", error);
+    eprintln!("Usage: simulate [--clients N] [--ids N] [--window-sec N] [--seed N]");
+    std::process::exit(2);
+}
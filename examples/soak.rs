@@ -0,0 +1,279 @@
+//! Runs the sync logic for hours with churny ids (every rendezvous on a fresh one), sampling the
+//! wait points & the memory of the process, & fails if they grow without bound. Guards the cleanup
+//! of wait points, notifications & match records against regressions.
+//!
+//! `cargo run --release --example soak -- --duration-sec 14400 --workers 32 --sample-sec 60`
+//!
+//! Each worker loops over the scenarios: a matched pair, a lonely party timing out, a party leaving
+//! while waiting, a dry run & a persistent notification nobody picks up. Once the run ended & all
+//! parties were answered, no wait point or party may be left. Resident memory (`/proc` on Linux,
+//! the allocator's stats otherwise), the memory of the wait points & the stored notifications may
+//! grow by at most `--max-growth-pct` from the first to the last third of the samples. Samples
+//! within `--warmup-sec` are left out, while records & caches fill up: keep it longer than
+//! `notification_ttl_sec`, before which no stored notification expires.
+//!
+//! Settings (e.g. timeout) are read the same way as by the server (`config.toml`, `APP_` env vars).
+use rocket::State;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use sync_point::allocator;
+use sync_point::api::acl::Access;
+use sync_point::api::routes::notify;
+use sync_point::api::sync_service::SyncRequest;
+use sync_point::app::App;
+use uuid::Uuid;
+
+/// Scenarios each worker loops over, see module docs
+const SCENARIOS: usize = 5;
+/// Time the first party of a pair or the leaving one waits before the next step
+const STEP_DELAY: Duration = Duration::from_millis(10);
+
+struct SoakConfig {
+    duration: Duration,
+    workers: usize,
+    sample_interval: Duration,
+    warmup: Duration,
+    max_growth_pct: u64,
+}
+
+impl Default for SoakConfig {
+    fn default() -> Self {
+        Self {
+            duration: Duration::from_secs(4 * 3600),
+            workers: 32,
+            sample_interval: Duration::from_secs(60),
+            warmup: Duration::from_secs(600),
+            max_growth_pct: 10,
+        }
+    }
+}
+
+/// State of the service & the process at some point of the run, printed as a JSON line
+#[derive(Debug, Clone, Serialize)]
+struct Sample {
+    elapsed_sec: u64,
+    /// Scenarios run so far
+    rendezvous: u64,
+    open_wait_points: usize,
+    waiting_parties: usize,
+    pending_notifications: usize,
+    memory_bytes: usize,
+    /// Missing if neither `/proc` nor the allocator report it
+    resident_bytes: Option<usize>,
+}
+
+#[rocket::main]
+async fn main() {
+    let config = parse_args();
+    let app = Arc::new(App::new(None).expect("Failed to initialize App"));
+    let started = Instant::now();
+    let until = started + config.duration;
+    let rendezvous = Arc::new(AtomicU64::new(0));
+
+    let workers: Vec<_> = (0..config.workers)
+        .map(|worker| tokio::spawn(run_worker(app.clone(), worker, until, rendezvous.clone())))
+        .collect();
+
+    let mut samples = Vec::new();
+    while Instant::now() < until {
+        tokio::time::sleep(config.sample_interval.min(until - Instant::now())).await;
+        let current = sample(&app, started, &rendezvous);
+        println!(
+            "{}",
+            serde_json::to_string(&current).expect("Sample is serializable")
+        );
+        if started.elapsed() >= config.warmup {
+            samples.push(current);
+        }
+    }
+    for worker in workers {
+        worker.await.expect("Worker panicked");
+    }
+
+    let mut failures = Vec::new();
+    let drained = sample(&app, started, &rendezvous);
+    if drained.open_wait_points > 0 || drained.waiting_parties > 0 {
+        failures.push(format!(
+            "{} wait points & {} parties left after all parties were answered",
+            drained.open_wait_points, drained.waiting_parties
+        ));
+    }
+    if samples.len() < 3 {
+        eprintln!(
+            "Only {} samples after the warmup, too few to judge the growth",
+            samples.len()
+        );
+    } else {
+        // Notifications are stored at a steady rate, so their count varies by about a worker's
+        let slack = config.workers;
+        failures.extend(growth(
+            &samples,
+            "resident_bytes",
+            config.max_growth_pct,
+            0,
+            |s| s.resident_bytes,
+        ));
+        failures.extend(growth(
+            &samples,
+            "memory_bytes",
+            config.max_growth_pct,
+            0,
+            |s| Some(s.memory_bytes),
+        ));
+        failures.extend(growth(
+            &samples,
+            "pending_notifications",
+            config.max_growth_pct,
+            slack,
+            |s| Some(s.pending_notifications),
+        ));
+    }
+
+    if failures.is_empty() {
+        eprintln!(
+            "Soak passed: {} rendezvous in {} sec",
+            drained.rendezvous, drained.elapsed_sec
+        );
+    } else {
+        for failure in &failures {
+            eprintln!("Soak failed: {}", failure);
+        }
+        std::process::exit(1);
+    }
+}
+
+/// Runs the scenarios in turn until `until`, each on a fresh id
+async fn run_worker(app: Arc<App>, worker: usize, until: Instant, rendezvous: Arc<AtomicU64>) {
+    let mut step = worker;
+    while Instant::now() < until {
+        let unique_id = format!("soak-{}", Uuid::new_v4());
+        match step % SCENARIOS {
+            0 => {
+                let first = tokio::spawn(join(app.clone(), unique_id.clone(), None, None));
+                tokio::time::sleep(STEP_DELAY).await;
+                join(app.clone(), unique_id, None, None).await;
+                first.await.expect("First party panicked");
+            }
+            1 => {
+                join(app.clone(), unique_id, Some(1), None).await;
+            }
+            2 => {
+                let leaving = tokio::spawn(join(app.clone(), unique_id, None, None));
+                tokio::time::sleep(STEP_DELAY).await;
+                leaving.abort();
+                let _ = leaving.await;
+            }
+            3 => {
+                join(app.clone(), unique_id, None, Some(true)).await;
+            }
+            _ => {
+                let state = <&State<App>>::from(app.as_ref());
+                let _ = notify(
+                    unique_id.as_str().into(),
+                    Some(true),
+                    None,
+                    Access::default(),
+                    state,
+                )
+                .await;
+            }
+        }
+        rendezvous.fetch_add(1, Ordering::Relaxed);
+        step += 1;
+    }
+}
+
+/// A party calling `POST /wait-for-second-party/<unique_id>`
+async fn join(app: Arc<App>, unique_id: String, ttl: Option<u64>, dry_run: Option<bool>) {
+    let request = SyncRequest {
+        dry_run: dry_run == Some(true),
+        ttl,
+        ..SyncRequest::default()
+    };
+    let _ = app
+        .sync_service
+        .join(&unique_id, request, Access::default(), &app)
+        .await;
+}
+
+fn sample(app: &App, started: Instant, rendezvous: &AtomicU64) -> Sample {
+    let stats = app.sync_service.current_stats();
+    Sample {
+        elapsed_sec: started.elapsed().as_secs(),
+        rendezvous: rendezvous.load(Ordering::Relaxed),
+        open_wait_points: stats.open_wait_points,
+        waiting_parties: stats.waiting_parties,
+        pending_notifications: stats.pending_notifications,
+        memory_bytes: stats.memory_bytes,
+        resident_bytes: resident_bytes().or(allocator::stats().resident_bytes),
+    }
+}
+
+/// `VmRSS` of `/proc/self/status`, Linux only
+fn resident_bytes() -> Option<usize> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kb: usize = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// Compares the average of `value` over the first & last third of `samples`
+///
+/// # Returns
+/// * `None` - If it grew by at most `max_growth_pct` (plus `slack`), or isn't reported
+/// * `Some(String)` - Describing the growth otherwise
+fn growth(
+    samples: &[Sample],
+    name: &str,
+    max_growth_pct: u64,
+    slack: usize,
+    value: impl Fn(&Sample) -> Option<usize>,
+) -> Option<String> {
+    let third = samples.len() / 3;
+    let average = |samples: &[Sample]| -> Option<f64> {
+        let values = samples.iter().map(&value).collect::<Option<Vec<_>>>()?;
+        Some(values.iter().sum::<usize>() as f64 / values.len() as f64)
+    };
+    let first = average(&samples[..third])?;
+    let last = average(&samples[samples.len() - third..])?;
+    let limit = first * (1.0 + max_growth_pct as f64 / 100.0) + slack as f64;
+    (last > limit).then(|| {
+        format!(
+            "{} grew from {:.0} to {:.0} on average, more than {}%",
+            name, first, last, max_growth_pct
+        )
+    })
+}
+
+fn parse_args() -> SoakConfig {
+    let mut config = SoakConfig::default();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    for pair in args.chunks(2) {
+        let value = pair
+            .get(1)
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or_else(|| usage(&format!("Missing or invalid value for {}", pair[0])));
+
+        match pair[0].as_str() {
+            "--duration-sec" => config.duration = Duration::from_secs(value),
+            "--workers" => config.workers = value.max(1) as usize,
+            "--sample-sec" => config.sample_interval = Duration::from_secs(value.max(1)),
+            "--warmup-sec" => config.warmup = Duration::from_secs(value),
+            "--max-growth-pct" => config.max_growth_pct = value,
+            other => usage(&format!("Unknown argument {}", other)),
+        }
+    }
+    config
+}
+
+fn usage(error: &str) -> ! {
+    eprintln!("{}", error);
+    eprintln!(
+        "Usage: soak [--duration-sec N] [--workers N] [--sample-sec N] [--warmup-sec N] \
+         [--max-growth-pct N]"
+    );
+    std::process::exit(2);
+}
@@ -0,0 +1,63 @@
+#ifndef SYNC_POINT_H
+#define SYNC_POINT_H
+
+/* Generated by cbindgen from src/ffi.rs, don't edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Outcome of `sp_wait`
+ */
+typedef enum SpStatus {
+  /**
+   * Both parties met
+   */
+  SP_STATUS_MATCHED = 0,
+  /**
+   * The peer didn't arrive within the server's timeout or `timeout_ms`
+   */
+  SP_STATUS_TIMEOUT = 1,
+  /**
+   * The wait point already has 2 parties
+   */
+  SP_STATUS_CONFLICT = 2,
+  /**
+   * The server can't take the request now (capacity, maintenance), retry later
+   */
+  SP_STATUS_UNAVAILABLE = 3,
+  /**
+   * A null or non UTF-8 argument
+   */
+  SP_STATUS_INVALID_ARGUMENT = 4,
+  /**
+   * Connection failure or unexpected response
+   */
+  SP_STATUS_ERROR = 5,
+} SpStatus;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Waits at the rendezvous `id` until the second party arrives, blocking the calling thread.
+ *
+ * # Arguments
+ * * `url` - Base URL of the server, e.g. `http://10.0.0.2:8000`
+ * * `id` - Rendezvous id, shared with the other party
+ * * `timeout_ms` - Gives up (`SP_STATUS_TIMEOUT`) after this long even if the server waits longer,
+ *   0 leaves it to the server
+ *
+ * # Safety
+ * `url` & `id` must be null or valid NUL-terminated strings, which are only read during the call.
+ */
+enum SpStatus sp_wait(const char *url, const char *id, uint64_t timeout_ms);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SYNC_POINT_H */
@@ -0,0 +1,77 @@
+//! Global allocator selection & its stats.
+//!
+//! Long-running instances churn through `HashMap` entries, which fragments the system allocator's heap.
+//! The `jemalloc` or `mimalloc` feature replaces it (jemalloc wins if both are enabled).
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+#[cfg(all(feature = "mimalloc", not(feature = "jemalloc")))]
+#[global_allocator]
+static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
+
+/// Allocator stats, returned by `GET /stats/allocator`. Fields the allocator can't report are left out
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AllocatorStats {
+    /// `system`, `jemalloc` or `mimalloc`
+    pub allocator: String,
+    /// Bytes allocated by the application
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allocated_bytes: Option<usize>,
+    /// Bytes of physical memory held, including fragmentation & allocator metadata
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resident_bytes: Option<usize>,
+}
+
+/// Current stats of the global allocator
+#[cfg(feature = "jemalloc")]
+pub fn stats() -> AllocatorStats {
+    use tikv_jemalloc_ctl::{epoch, stats};
+
+    // jemalloc caches its stats, they're only refreshed when the epoch advances
+    let refreshed = epoch::advance().is_ok();
+    let read = |value: tikv_jemalloc_ctl::Result<usize>| value.ok().filter(|_| refreshed);
+    AllocatorStats {
+        allocator: "jemalloc".to_owned(),
+        allocated_bytes: read(stats::allocated::read()),
+        resident_bytes: read(stats::resident::read()),
+    }
+}
+
+/// Current stats of the global allocator
+#[cfg(all(feature = "mimalloc", not(feature = "jemalloc")))]
+pub fn stats() -> AllocatorStats {
+    let mut current_rss = 0;
+    let mut unused = [0usize; 7];
+    let [elapsed, user, system, peak_rss, commit, peak_commit, faults] = &mut unused;
+    // SAFETY: all pointers are valid for writes for the duration of the call
+    unsafe {
+        libmimalloc_sys::mi_process_info(
+            elapsed,
+            user,
+            system,
+            &mut current_rss,
+            peak_rss,
+            commit,
+            peak_commit,
+            faults,
+        );
+    }
+    AllocatorStats {
+        allocator: "mimalloc".to_owned(),
+        allocated_bytes: None,
+        resident_bytes: Some(current_rss),
+    }
+}
+
+/// Current stats of the global allocator. The system allocator doesn't report any
+#[cfg(not(any(feature = "jemalloc", feature = "mimalloc")))]
+pub fn stats() -> AllocatorStats {
+    AllocatorStats {
+        allocator: "system".to_owned(),
+        allocated_bytes: None,
+        resident_bytes: None,
+    }
+}
@@ -0,0 +1,257 @@
+//! Wait point ACLs, for servers shared by teams which don't trust each other. The party creating a
+//! wait point may restrict who else can join it, with request headers
+//! - `X-Sync-Allow-Cidrs: 10.1.0.0/16, 192.168.1.7` - client addresses
+//! - `X-Sync-Allow-Keys: key-a, key-b` - `X-Api-Key` header values
+//!
+//! Parties not admitted get 403. Given both, a party must match both. The creating party itself is
+//! never checked, & later parties' ACL headers are ignored.
+use crate::api::connections::Protocol;
+use crate::api::priorities::Priority;
+use crate::protocol::headers;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+use rocket::Request;
+use serde_json::{Map, Value};
+use std::net::{IpAddr, SocketAddr};
+use uuid::Uuid;
+
+/// Address range, e.g. `10.1.0.0/16`. A bare address is a range of one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    /// # Returns
+    /// * `Ok(Cidr)` - The range
+    /// * `Err(String)` - Describing why `value` isn't one
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (address, prefix_len) = match value.split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len)),
+            None => (value, None),
+        };
+        let network: IpAddr = address
+            .parse()
+            .map_err(|_| format!("Invalid address in '{}'", value))?;
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len
+                .parse()
+                .ok()
+                .filter(|prefix_len| *prefix_len <= max_len)
+                .ok_or_else(|| format!("Invalid prefix length in '{}'", value))?,
+            None => max_len,
+        };
+        Ok(Self {
+            network,
+            prefix_len,
+        })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // IPv4 clients of a dual-stack listener show up as IPv4-mapped IPv6 addresses
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.prefix_len));
+                let mask = mask.unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.prefix_len));
+                let mask = mask.unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Who may join a wait point besides its creator. Empty lists don't restrict
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Acl {
+    pub cidrs: Vec<Cidr>,
+    pub api_keys: Vec<String>,
+}
+
+impl Acl {
+    pub fn permits(&self, access: &Access) -> bool {
+        let address_allowed = self.cidrs.is_empty()
+            || access
+                .ip
+                .is_some_and(|ip| self.cidrs.iter().any(|cidr| cidr.contains(ip)));
+        let key_allowed = self.api_keys.is_empty()
+            || access
+                .api_key
+                .as_ref()
+                .is_some_and(|api_key| self.api_keys.contains(api_key));
+        address_allowed && key_allowed
+    }
+}
+
+/// Identity of a sync request's party, & the ACL it asks for should it create the wait point.
+/// The default is an anonymous in-process party (e.g. the dev mock peer) asking for no ACL
+#[derive(Debug, Clone, Default)]
+pub struct Access {
+    /// Client address
+    pub ip: Option<IpAddr>,
+    /// Address & port of the connection, see `connections` module
+    pub connection: Option<SocketAddr>,
+    /// HTTP version of the request, see `connections` module
+    pub protocol: Protocol,
+    /// `X-Api-Key` header
+    pub api_key: Option<String>,
+    /// `User-Agent` header, see `origins` module
+    pub user_agent: Option<String>,
+    /// From the `X-Sync-Allow-*` headers, `None` when neither is sent
+    pub acl: Option<Acl>,
+    /// `X-Sync-Reservation` header, see `reservations` module
+    pub reservation: Option<Uuid>,
+    /// `priority` query parameter of the waiting routes, set by them. See `priorities` module
+    pub priority: Priority,
+    /// `note` query parameter of the joining routes, set by them. See `WaitPoint::add_note`
+    pub note: Option<String>,
+    /// `X-Sync-Metadata` header, see `templates` module
+    pub metadata: Option<Map<String, Value>>,
+}
+
+/// Comma separated header values, empty ones skipped
+fn list<'a>(request: &'a Request<'_>, name: &str) -> Option<Vec<&'a str>> {
+    let values = request.headers().get_one(name)?;
+    Some(
+        values
+            .split(',')
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .collect(),
+    )
+}
+
+/// Fails with 400 on an invalid ACL, reservation or metadata header
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Access {
+    type Error = String;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let cidrs = list(request, headers::ALLOW_CIDRS);
+        let api_keys = list(request, headers::ALLOW_KEYS);
+        let acl = if cidrs.is_none() && api_keys.is_none() {
+            None
+        } else {
+            let cidrs: Result<Vec<Cidr>, String> = cidrs
+                .unwrap_or_default()
+                .into_iter()
+                .map(Cidr::parse)
+                .collect();
+            match cidrs {
+                Ok(cidrs) => Some(Acl {
+                    cidrs,
+                    api_keys: api_keys
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(str::to_owned)
+                        .collect(),
+                }),
+                Err(e) => return Outcome::Error((Status::BadRequest, e)),
+            }
+        };
+
+        let reservation = match request.headers().get_one(headers::RESERVATION) {
+            Some(token) => match Uuid::parse_str(token.trim()) {
+                Ok(token) => Some(token),
+                Err(e) => {
+                    return Outcome::Error((
+                        Status::BadRequest,
+                        format!("Invalid {}: {}", headers::RESERVATION, e),
+                    ))
+                }
+            },
+            None => None,
+        };
+
+        let metadata = match request.headers().get_one(headers::METADATA) {
+            Some(metadata) => match serde_json::from_str(metadata) {
+                Ok(metadata) => Some(metadata),
+                Err(e) => {
+                    return Outcome::Error((
+                        Status::BadRequest,
+                        format!("Invalid {}, not a JSON object: {}", headers::METADATA, e),
+                    ))
+                }
+            },
+            None => None,
+        };
+
+        Outcome::Success(Access {
+            ip: request.client_ip(),
+            connection: request.remote(),
+            protocol: Protocol::of(request),
+            api_key: request
+                .headers()
+                .get_one(headers::API_KEY)
+                .map(str::to_owned),
+            user_agent: request
+                .headers()
+                .get_one(headers::USER_AGENT)
+                .map(str::to_owned),
+            acl,
+            reservation,
+            priority: Priority::default(),
+            note: None,
+            metadata,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::acl::{Access, Acl, Cidr};
+    use crate::api::connections::Protocol;
+    use crate::api::priorities::Priority;
+    use std::net::IpAddr;
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn test_cidr() {
+        let cidr = Cidr::parse("10.1.0.0/16").unwrap();
+        assert!(cidr.contains(ip("10.1.200.3")));
+        assert!(cidr.contains(ip("::ffff:10.1.0.1")));
+        assert!(!cidr.contains(ip("10.2.0.1")));
+        assert!(Cidr::parse("0.0.0.0/0").unwrap().contains(ip("8.8.8.8")));
+        assert!(Cidr::parse("fd00::/8").unwrap().contains(ip("fd12::1")));
+        assert!(Cidr::parse("192.168.1.7").unwrap().contains(ip("192.168.1.7")));
+
+        assert!(Cidr::parse("10.1.0.0/33").is_err());
+        assert!(Cidr::parse("10.1.0/16").is_err());
+    }
+
+    #[test]
+    fn test_acl_needs_every_restriction() {
+        let acl = Acl {
+            cidrs: vec![Cidr::parse("10.0.0.0/8").unwrap()],
+            api_keys: vec!["team-a".to_owned()],
+        };
+        let access = |address: &str, api_key: Option<&str>| Access {
+            ip: Some(ip(address)),
+            connection: None,
+            protocol: Protocol::Http1,
+            api_key: api_key.map(str::to_owned),
+            user_agent: None,
+            acl: None,
+            reservation: None,
+            priority: Priority::Normal,
+            note: None,
+            metadata: None,
+        };
+
+        assert!(acl.permits(&access("10.0.0.1", Some("team-a"))));
+        assert!(!acl.permits(&access("10.0.0.1", Some("team-b"))));
+        assert!(!acl.permits(&access("10.0.0.1", None)));
+        assert!(!acl.permits(&access("11.0.0.1", Some("team-a"))));
+        assert!(!acl.permits(&Access::default()));
+        assert!(Acl::default().permits(&Access::default()));
+    }
+}
@@ -0,0 +1,362 @@
+use crate::api::ndjson::{AcceptNdjson, Ndjson};
+use crate::api::observability;
+use crate::api::pagination::{Cursor, Page, MAX_LIMIT};
+use crate::api::readiness;
+use crate::api::response::ApiResponse;
+use crate::api::self_test::{self, SelfTestReport};
+use crate::api::sync_service::WaitPointStatus;
+use crate::app::{App, Maintenance};
+use crate::logging;
+use crate::protocol::{headers, ReadinessResponse};
+use crate::settings::{RuntimeConfig, RuntimeConfigPatch};
+use json_patch::Patch;
+use log::{info, warn};
+use rocket::http::{ContentType, Status};
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::status::Custom;
+use rocket::serde::json::{self, Json};
+use rocket::futures::stream::Stream;
+use rocket::response::stream::stream;
+use rocket::{get, patch, post, put, Either, Request, State};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use subtle::ConstantTimeEq;
+
+/// Request guard protecting the admin API.
+///
+/// Admin API is enabled only when `admin_token` is configured, otherwise it responds as if it
+/// doesn't exist (404). Requests must send `Authorization: Bearer <admin_token>`, or they get 401.
+pub struct Admin;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(app) = request.rocket().state::<App>() else {
+            return Outcome::Error((Status::InternalServerError, ()));
+        };
+        let Some(token) = app.settings().admin_token else {
+            return Outcome::Error((Status::NotFound, ()));
+        };
+
+        let expected = format!("Bearer {}", token);
+        // Compared in constant time, so response times don't tell how much of a guess was right
+        match request.headers().get_one(headers::AUTHORIZATION) {
+            Some(authorization)
+                if bool::from(authorization.as_bytes().ct_eq(expected.as_bytes())) =>
+            {
+                Outcome::Success(Admin)
+            }
+            _ => {
+                warn!(target: "audit", "Unauthorized admin request to {}", request.uri());
+                Outcome::Error((Status::Unauthorized, ()))
+            }
+        }
+    }
+}
+
+/// Returns the current values of runtime-adjustable settings
+#[get("/config")]
+pub fn get_config(_admin: Admin, state: &State<App>) -> Json<RuntimeConfig> {
+    Json(state.runtime_config())
+}
+
+/// Changes runtime-adjustable settings (timeout, max_wait_points, log_level) without a restart,
+/// so that current waiters aren't dropped.
+///
+/// # Arguments
+/// * `persist` - When `true`, changes are also written to `runtime_config_path` to survive restarts.
+///   Not for `log_level`, whose startup value comes from `RUST_LOG`
+/// * `preview` - When `true`, changes are only validated, the settings they'd result in returned
+/// * `content_type` - `application/json-patch+json` for an RFC 6902 JSON Patch of the settings,
+///   a JSON object with the settings to change otherwise
+/// * `patch` - JSON body with the changes
+///
+/// # Returns
+/// * `Ok(Json<RuntimeConfig>)` - Settings in effect after the change (or that would be)
+/// * `Err(Custom<Json<ApiResponse>>)` - 400 if the body or any value is invalid, or a `log_level`
+///   is to be persisted, nothing is changed then
+#[patch("/config?<persist>&<preview>", data = "<patch>")]
+pub fn patch_config(
+    _admin: Admin,
+    persist: Option<bool>,
+    preview: Option<bool>,
+    content_type: Option<&ContentType>,
+    patch: Result<Json<Value>, json::Error<'_>>,
+    state: &State<App>,
+) -> Result<Json<RuntimeConfig>, Custom<Json<ApiResponse>>> {
+    let bad_request =
+        |message: String| Custom(Status::BadRequest, Json(ApiResponse::error(message)));
+
+    let Json(body) = patch.map_err(|e| bad_request(format!("Invalid config patch: {}", e)))?;
+    let patch = if content_type.is_some_and(is_json_patch) {
+        let operations = serde_json::from_value(body)
+            .map_err(|e| bad_request(format!("Invalid JSON Patch: {}", e)))?;
+        runtime_config_patch(&state.runtime_config(), &operations).map_err(bad_request)?
+    } else {
+        serde_json::from_value(body)
+            .map_err(|e| bad_request(format!("Invalid config patch: {}", e)))?
+    };
+
+    let persist = persist.unwrap_or(false);
+    let result = if preview.unwrap_or(false) {
+        if persist {
+            return Err(bad_request(
+                "A preview can't be persisted, drop either query param".to_owned(),
+            ));
+        }
+        state.preview_runtime_config(&patch)
+    } else {
+        state.update_runtime_config(&patch, persist)
+    };
+    result.map(Json).map_err(|e| bad_request(e.to_string()))
+}
+
+/// Whether a body of `content_type` is an RFC 6902 JSON Patch
+fn is_json_patch(content_type: &ContentType) -> bool {
+    content_type.top() == "application" && content_type.sub() == "json-patch+json"
+}
+
+/// Applies the JSON Patch `operations` to `current`, e.g.
+/// `[{"op": "test", "path": "/timeout", "value": 10}, {"op": "replace", "path": "/timeout", "value": 30}]`
+///
+/// # Returns
+/// * `Ok(RuntimeConfigPatch)` - The settings the operations changed. `log_level` only if changed,
+///   then a single level as in merge patches
+/// * `Err(String)` - If an operation failed (e.g. a `test`), or the patched document isn't a
+///   `RuntimeConfig` anymore
+fn runtime_config_patch(
+    current: &RuntimeConfig,
+    operations: &Patch,
+) -> Result<RuntimeConfigPatch, String> {
+    let mut document = serde_json::to_value(current).map_err(|e| e.to_string())?;
+    json_patch::patch(&mut document, operations)
+        .map_err(|e| format!("JSON Patch failed: {}", e))?;
+    let patched: RuntimeConfigPatch =
+        serde_json::from_value(document).map_err(|e| format!("Invalid patched config: {}", e))?;
+
+    let (Some(timeout), Some(max_wait_points), Some(log_level)) =
+        (patched.timeout, patched.max_wait_points, patched.log_level)
+    else {
+        return Err("Invalid patched config: settings can't be removed".to_owned());
+    };
+    Ok(RuntimeConfigPatch {
+        timeout: Some(timeout),
+        max_wait_points: Some(max_wait_points),
+        log_level: (log_level != current.log_level).then_some(log_level),
+    })
+}
+
+/// Body of `PUT /admin/log-level`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogLevelRequest {
+    /// Default level for all modules, e.g. `info`
+    pub level: Option<String>,
+    /// Levels for specific module paths, e.g. `{"sync_point::api": "debug", "rocket": "warn"}`
+    #[serde(default)]
+    pub modules: BTreeMap<String, String>,
+}
+
+/// Log filter in effect
+#[derive(Debug, Serialize)]
+pub struct LogLevelResponse {
+    /// Filter spec in `RUST_LOG` syntax, e.g. `info,sync_point::api=debug`
+    pub filter: String,
+}
+
+/// Replaces the log filter at runtime, e.g. to flip to debug during an incident without
+/// restarting & losing wait state. Modules not listed fall back to `level`.
+///
+/// # Returns
+/// * `Ok(Json<LogLevelResponse>)` - Filter in effect after the change
+/// * `Err(Custom<Json<ApiResponse>>)` - 400 if the body, a level or module path is invalid
+#[put("/log-level", data = "<request>")]
+pub fn put_log_level(
+    _admin: Admin,
+    request: Result<Json<LogLevelRequest>, json::Error<'_>>,
+) -> Result<Json<LogLevelResponse>, Custom<Json<ApiResponse>>> {
+    let bad_request =
+        |message: String| Custom(Status::BadRequest, Json(ApiResponse::error(message)));
+
+    let request = request.map_err(|e| bad_request(format!("Invalid log level request: {}", e)))?;
+    let before = logging::current_filter();
+    let spec =
+        logging::filter_spec(request.level.as_deref(), &request.modules).map_err(bad_request)?;
+    logging::set_filter(&spec).map_err(bad_request)?;
+
+    info!(target: "audit", "Log filter changed from '{}' to '{}'", before, spec);
+    Ok(Json(LogLevelResponse { filter: spec }))
+}
+
+/// Body of `PUT /admin/maintenance`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaintenanceRequest {
+    pub enabled: bool,
+    /// Message for rejected clients, a generic one when missing
+    pub message: Option<String>,
+    /// `Retry-After` value for rejected clients, 60 when missing
+    pub retry_after_sec: Option<u64>,
+}
+
+/// Maintenance mode state
+#[derive(Debug, Serialize)]
+pub struct MaintenanceResponse {
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub maintenance: Option<Maintenance>,
+}
+
+impl MaintenanceResponse {
+    fn new(maintenance: Option<Maintenance>) -> Self {
+        Self {
+            enabled: maintenance.is_some(),
+            maintenance,
+        }
+    }
+}
+
+/// Returns the maintenance mode state
+#[get("/maintenance")]
+pub fn get_maintenance(_admin: Admin, state: &State<App>) -> Json<MaintenanceResponse> {
+    Json(MaintenanceResponse::new(state.maintenance()))
+}
+
+/// Enters or leaves maintenance mode (e.g. during backend migrations). While enabled, new sync
+/// requests get 503 with the message & `Retry-After` header, parties already waiting are not affected.
+///
+/// # Returns
+/// * `Ok(Json<MaintenanceResponse>)` - State after the change
+/// * `Err(Custom<Json<ApiResponse>>)` - 400 if the body is invalid
+#[put("/maintenance", data = "<request>")]
+pub fn put_maintenance(
+    _admin: Admin,
+    request: Result<Json<MaintenanceRequest>, json::Error<'_>>,
+    state: &State<App>,
+) -> Result<Json<MaintenanceResponse>, Custom<Json<ApiResponse>>> {
+    let request = request
+        .map_err(|e| {
+            Custom(
+                Status::BadRequest,
+                Json(ApiResponse::error(format!(
+                    "Invalid maintenance request: {}",
+                    e
+                ))),
+            )
+        })?
+        .into_inner();
+
+    let maintenance = request.enabled.then(|| Maintenance {
+        message: request
+            .message
+            .unwrap_or_else(|| Maintenance::DEFAULT_MESSAGE.to_owned()),
+        retry_after_sec: request
+            .retry_after_sec
+            .unwrap_or(Maintenance::DEFAULT_RETRY_AFTER_SEC),
+    });
+    state.set_maintenance(maintenance);
+
+    Ok(Json(MaintenanceResponse::new(state.maintenance())))
+}
+
+/// Body of `PUT /admin/lame-duck`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LameDuckRequest {
+    pub enabled: bool,
+}
+
+/// Enters or leaves lame-duck mode, e.g. before a deploy: `GET /ready` reports `lame-duck` (503)
+/// so load balancers stop routing here, while requests are still served. See `readiness` module
+///
+/// # Returns
+/// * `Ok(Json<ReadinessResponse>)` - Readiness after the change
+/// * `Err(Custom<Json<ApiResponse>>)` - 400 if the body is invalid
+#[put("/lame-duck", data = "<request>")]
+pub fn put_lame_duck(
+    _admin: Admin,
+    request: Result<Json<LameDuckRequest>, json::Error<'_>>,
+    state: &State<App>,
+) -> Result<Json<ReadinessResponse>, Custom<Json<ApiResponse>>> {
+    let request = request.map_err(|e| {
+        Custom(
+            Status::BadRequest,
+            Json(ApiResponse::error(format!(
+                "Invalid lame-duck request: {}",
+                e
+            ))),
+        )
+    })?;
+    state.readiness.set_lame_duck(request.enabled);
+    Ok(Json(readiness::check(state)))
+}
+
+/// Lists open wait points in creation order. Follow `next_cursor` for further pages, points
+/// created after the first page was taken show up in the next listing only.
+///
+/// With `Accept: application/x-ndjson` all (remaining) wait points are exported instead, one per line.
+/// They're read page by page, so the lock isn't held for the whole export.
+///
+/// # Arguments
+/// * `limit` - Page size, 100 by default, at most 1000. Ignored by the NDJSON export
+/// * `cursor` - `next_cursor` of the previous page
+///
+/// # Returns
+/// * `Ok(Either<Json<Page<WaitPointStatus>>, Ndjson>)` - A page of wait points, or the export
+/// * `Err(Custom<Json<ApiResponse>>)` - 400 if the cursor is invalid
+#[get("/wait-points?<limit>&<cursor>")]
+pub async fn list_wait_points(
+    _admin: Admin,
+    limit: Option<usize>,
+    cursor: Option<&str>,
+    accept_ndjson: AcceptNdjson,
+    state: &State<App>,
+) -> Result<
+    Either<Json<Page<WaitPointStatus>>, Ndjson<impl Stream<Item = WaitPointStatus>>>,
+    Custom<Json<ApiResponse>>,
+> {
+    let mut cursor = cursor
+        .map(Cursor::decode)
+        .transpose()
+        .map_err(|e| Custom(Status::BadRequest, Json(ApiResponse::error(e))))?;
+    if !accept_ndjson.0 {
+        return Ok(Either::Left(Json(state.sync_service.list(cursor, limit))));
+    }
+
+    let sync_service = state.sync_service.clone();
+    Ok(Either::Right(Ndjson(stream! {
+        loop {
+            let page = sync_service.list(cursor, Some(MAX_LIMIT));
+            for item in page.items {
+                yield item;
+            }
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+    })))
+}
+
+/// Returns a Grafana dashboard & Prometheus alert rules for `GET /metrics`, generated from the
+/// metric names of this build & the current settings, see `observability` module
+#[get("/observability/templates")]
+pub fn get_observability_templates(_admin: Admin, state: &State<App>) -> Json<Value> {
+    Json(observability::templates(&state.settings()))
+}
+
+/// Smoke test for after deploys: runs a full rendezvous of two synthetic parties on a fresh
+/// `_internal/selftest-` id, see `self_test` module. Checks of subsystems this build lacks are
+/// `skipped`.
+///
+/// # Returns
+/// The report of every check, with 200 when none failed, 503 otherwise
+#[post("/self-test")]
+pub async fn post_self_test(_admin: Admin, state: &State<App>) -> Custom<Json<SelfTestReport>> {
+    let report = self_test::run(state.inner()).await;
+    info!(target: "audit", "Self-test run, passed: {}", report.1.passed);
+    report
+}
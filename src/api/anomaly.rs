@@ -0,0 +1,218 @@
+//! Warn-level events under the `anomaly` log target for patterns hinting at systemic issues, e.g.
+//! peers consistently arriving late or clients reusing ids, before clients start complaining.
+//!
+//! Events are single lines of `key=value` pairs after the event name, easy to grep & parse:
+//! `slow_rendezvous unique_id=123 elapsed_ms=8500 timeout_ms=10000`
+use log::warn;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+/// `[anomalies]` config section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnomalyConfig {
+    /// A rendezvous taking longer than this fraction of the timeout is reported, 0 disables it
+    pub slow_fraction: f64,
+    /// Conflicts (409) on the same id within `window_sec` before it's reported, 0 disables it
+    pub conflict_threshold: u32,
+    /// Consecutive wait point cleanup failures before they're reported, 0 disables it
+    pub cleanup_failure_threshold: u32,
+    /// Window for counting conflicts per id
+    pub window_sec: u64,
+    /// A client clock (`X-Client-Time`) off by more than this is reported, 0 disables it
+    pub max_clock_skew_ms: u64,
+}
+
+impl Default for AnomalyConfig {
+    fn default() -> Self {
+        Self {
+            slow_fraction: 0.8,
+            conflict_threshold: 3,
+            cleanup_failure_threshold: 3,
+            window_sec: 60,
+            max_clock_skew_ms: 2000,
+        }
+    }
+}
+
+/// Counts the events needed to detect anomalies & reports them
+#[derive(Debug, Default)]
+pub struct AnomalyDetector {
+    config: AnomalyConfig,
+    /// Per id: start of the counting window & conflicts within it
+    conflicts: Mutex<HashMap<String, (Instant, u32)>>,
+    cleanup_failures: AtomicU32,
+}
+
+impl AnomalyDetector {
+    pub fn new(config: &AnomalyConfig) -> Self {
+        Self {
+            config: config.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Reports a rendezvous which took more than `slow_fraction` of `timeout`
+    ///
+    /// # Returns
+    /// Whether it was reported
+    pub fn matched(&self, unique_id: &str, elapsed: Duration, timeout: Duration) -> bool {
+        let slow = self.config.slow_fraction > 0.0
+            && elapsed.as_secs_f64() > timeout.as_secs_f64() * self.config.slow_fraction;
+        if slow {
+            warn!(
+                target: "anomaly",
+                "slow_rendezvous unique_id={} elapsed_ms={} timeout_ms={}",
+                unique_id,
+                elapsed.as_millis(),
+                timeout.as_millis()
+            );
+        }
+        slow
+    }
+
+    /// Counts a conflict on `unique_id`, reporting every `conflict_threshold`-th one within the window
+    ///
+    /// # Returns
+    /// Whether it was reported
+    pub fn conflict(&self, unique_id: &str) -> bool {
+        if self.config.conflict_threshold == 0 {
+            return false;
+        }
+        let window = Duration::from_secs(self.config.window_sec);
+        let now = Instant::now();
+        let mut conflicts = self.conflicts.lock();
+        // Conflicts are rare, so dropping expired windows on every one is cheap enough
+        conflicts.retain(|_, (started, _)| now.duration_since(*started) < window);
+
+        let (_, count) = conflicts
+            .entry(unique_id.to_owned())
+            .or_insert((now, 0));
+        *count += 1;
+        let repeated = count.is_multiple_of(self.config.conflict_threshold);
+        if repeated {
+            warn!(
+                target: "anomaly",
+                "repeated_conflicts unique_id={} count={} window_sec={}",
+                unique_id,
+                count,
+                self.config.window_sec
+            );
+        }
+        repeated
+    }
+
+    /// Counts consecutive cleanup failures, reporting every `cleanup_failure_threshold`-th one.
+    /// A success resets the count
+    ///
+    /// # Returns
+    /// Whether it was reported
+    pub fn cleanup(&self, unique_id: &str, succeeded: bool) -> bool {
+        if succeeded {
+            self.cleanup_failures.store(0, Ordering::Relaxed);
+            return false;
+        }
+        let failures = self.cleanup_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        let threshold = self.config.cleanup_failure_threshold;
+        let repeated = threshold > 0 && failures.is_multiple_of(threshold);
+        if repeated {
+            warn!(
+                target: "anomaly",
+                "repeated_cleanup_failures unique_id={} consecutive={}",
+                unique_id,
+                failures
+            );
+        }
+        repeated
+    }
+
+    /// Reports a client whose clock is off by more than `max_clock_skew_ms`
+    ///
+    /// # Arguments
+    /// * `client` - Address of the client
+    /// * `skew_ms` - Client time minus server time, negative when the client is behind
+    ///
+    /// # Returns
+    /// Whether it was reported
+    pub fn clock_skew(&self, client: &str, skew_ms: i64) -> bool {
+        let max = self.config.max_clock_skew_ms;
+        let skewed = max > 0 && skew_ms.unsigned_abs() > max;
+        if skewed {
+            warn!(
+                target: "anomaly",
+                "clock_skew client={} skew_ms={} max_ms={}",
+                client,
+                skew_ms,
+                max
+            );
+        }
+        skewed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::anomaly::{AnomalyConfig, AnomalyDetector};
+    use std::time::Duration;
+
+    #[test]
+    fn test_slow_rendezvous() {
+        let detector = AnomalyDetector::new(&AnomalyConfig::default());
+        let timeout = Duration::from_secs(10);
+
+        assert!(!detector.matched("123", Duration::from_secs(7), timeout));
+        assert!(detector.matched("123", Duration::from_millis(8500), timeout));
+    }
+
+    #[test]
+    fn test_repeated_conflicts_are_counted_per_id() {
+        let detector = AnomalyDetector::new(&AnomalyConfig::default());
+
+        assert!(!detector.conflict("a"));
+        assert!(!detector.conflict("a"));
+        assert!(!detector.conflict("b"));
+        assert!(detector.conflict("a"));
+        assert!(!detector.conflict("a"));
+    }
+
+    #[test]
+    fn test_cleanup_failures_must_be_consecutive() {
+        let detector = AnomalyDetector::new(&AnomalyConfig {
+            cleanup_failure_threshold: 2,
+            ..Default::default()
+        });
+
+        assert!(!detector.cleanup("a", false));
+        assert!(!detector.cleanup("b", true));
+        assert!(!detector.cleanup("c", false));
+        assert!(detector.cleanup("d", false));
+    }
+
+    #[test]
+    fn test_clock_skew_either_way() {
+        let detector = AnomalyDetector::new(&AnomalyConfig::default());
+
+        assert!(!detector.clock_skew("10.0.0.1", 1500));
+        assert!(detector.clock_skew("10.0.0.1", 2500));
+        assert!(detector.clock_skew("10.0.0.1", -2500));
+    }
+
+    #[test]
+    fn test_zero_disables_detection() {
+        let detector = AnomalyDetector::new(&AnomalyConfig {
+            slow_fraction: 0.0,
+            conflict_threshold: 0,
+            cleanup_failure_threshold: 0,
+            window_sec: 60,
+            max_clock_skew_ms: 0,
+        });
+
+        assert!(!detector.matched("a", Duration::from_secs(10), Duration::from_secs(10)));
+        assert!(!detector.conflict("a"));
+        assert!(!detector.cleanup("a", false));
+        assert!(!detector.clock_skew("a", 60_000));
+    }
+}
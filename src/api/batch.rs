@@ -0,0 +1,150 @@
+use crate::api::acl::Access;
+use crate::api::connections::too_many_requests;
+use crate::api::ids::IdPath;
+use crate::api::ndjson::Ndjson;
+use crate::api::priorities::Priority;
+use crate::api::response::ApiResponse;
+use crate::api::routes::party_note;
+use crate::api::sync_service::SyncRequest;
+use crate::app::App;
+use log::debug;
+use rocket::futures::stream::{FuturesUnordered, Stream};
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::json::{self, Json};
+use rocket::{post, State};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Upper bound of ids in one batch
+pub const MAX_BATCH_IDS: usize = 64;
+
+/// Body of `POST /batch/wait`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchWaitRequest {
+    pub ids: Vec<String>,
+    /// Class of the waits, like `?priority` of the sync endpoint. See `priorities` module
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    /// Note left on every wait point, like `?note` of the sync endpoint
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+/// One NDJSON line of the batch response, the same body a single sync request would get
+/// plus the id & its HTTP status
+#[derive(Debug, Serialize)]
+pub struct BatchWaitResult {
+    pub unique_id: String,
+    pub http_status: u16,
+    #[serde(flatten)]
+    pub response: ApiResponse,
+}
+
+/// Waits on several ids in one request, e.g. for test orchestrators coordinating many shards,
+/// which would otherwise need a connection per id.
+///
+/// Each id goes through the regular sync logic, concurrently. The response is NDJSON
+/// (`application/x-ndjson`), a `BatchWaitResult` line is written as soon as its id resolves,
+/// so the order of lines is the order of resolution. Waits are cancelled if the client disconnects.
+/// The request's `access` applies to every id, see `acl` module, so its metadata is checked against
+/// the template of each one (see `templates` module). The batch holds a single slot of its
+/// connection until its last id resolves, see `connections` module.
+///
+/// # Returns
+/// * `Ok(Ndjson)` - 200 with the stream of results, a 400 line for each invalid id: empty or
+///   reserved (see `ids` module). Ids are normalized like those of paths, `a//b` is `a/b`
+/// * `Err(Custom<Json<ApiResponse>>)` - 400 if the body is invalid, empty, has more than
+///   `MAX_BATCH_IDS` or duplicate ids (a batch can't be its own second party), 429 if the
+///   connection has `max_requests_per_connection` requests in progress
+#[post("/batch/wait", data = "<request>")]
+pub fn batch_wait(
+    request: Result<Json<BatchWaitRequest>, json::Error<'_>>,
+    mut access: Access,
+    state: &State<App>,
+) -> Result<Ndjson<impl Stream<Item = BatchWaitResult>>, Custom<Json<ApiResponse>>> {
+    let bad_request =
+        |message: String| Custom(Status::BadRequest, Json(ApiResponse::error(message)));
+
+    let BatchWaitRequest {
+        ids,
+        priority,
+        note,
+    } = request
+        .map_err(|e| bad_request(format!("Invalid batch request: {}", e)))?
+        .into_inner();
+    let note = party_note(note)?;
+    if ids.is_empty() || ids.len() > MAX_BATCH_IDS {
+        return Err(bad_request(format!(
+            "A batch must have between 1 and {} ids",
+            MAX_BATCH_IDS
+        )));
+    }
+    let ids: Vec<_> = ids
+        .into_iter()
+        .map(|id| IdPath::parse(&id).map_err(|e| (id, e)))
+        .collect();
+    let mut seen = HashSet::new();
+    if let Some(duplicate) = ids.iter().flatten().find(|id| !seen.insert(id.as_str())) {
+        return Err(bad_request(format!(
+            "Duplicate id in batch: {}",
+            duplicate.as_str()
+        )));
+    }
+    debug!("Batch wait request received for {} ids", ids.len());
+    let connection = state
+        .acquire_connection_slot(&access)
+        .map_err(too_many_requests)?
+        .map(Arc::new);
+    // Held by the batch as a whole, not per id
+    access.connection = None;
+
+    let app = state.inner().clone();
+    let waits: FuturesUnordered<_> = ids
+        .into_iter()
+        .map(|id| {
+            let app = app.clone();
+            let access = access.clone();
+            let note = note.clone();
+            let connection = connection.clone();
+            async move {
+                let _connection = connection;
+                let unique_id = match id {
+                    Ok(unique_id) => unique_id,
+                    Err((unique_id, e)) => {
+                        return BatchWaitResult {
+                            response: ApiResponse::error(format!(
+                                "Invalid id '{}': {}",
+                                unique_id, e
+                            )),
+                            unique_id,
+                            http_status: Status::BadRequest.code,
+                        }
+                    }
+                };
+                let request = SyncRequest {
+                    priority: priority.unwrap_or_default(),
+                    note,
+                    ..SyncRequest::default()
+                };
+                let response = app
+                    .sync_service
+                    .join(&unique_id, request, access, &app)
+                    .await;
+                let Custom(status, Json(response)) = match response {
+                    Ok(response) => response,
+                    Err(retry_after) => retry_after.0,
+                };
+                BatchWaitResult {
+                    unique_id: unique_id.as_str().to_owned(),
+                    http_status: status.code,
+                    response,
+                }
+            }
+        })
+        .collect();
+    // Yields the results in order of completion
+    Ok(Ndjson(waits))
+}
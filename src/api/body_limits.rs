@@ -0,0 +1,285 @@
+//! Checks request bodies before they reach the routes, so client bugs surface as errors instead of
+//! bodies being silently ignored:
+//! - the rendezvous routes (`/wait-for-second-party`, `/wait`, `/notify`, ...) take no body, any
+//!   body is rejected with 415 if it has a `Content-Type`, 413 otherwise
+//! - routes taking JSON reject other content types with 415 & bodies larger than
+//!   `body_limits.max_json_bytes` with 413 (a missing `Content-Type` is read as JSON, `+json` ones
+//!   like `application/json-patch+json` are JSON)
+//!
+//! Only the `Content-Length` is checked against the cap. Bodies without one are truncated at
+//! Rocket's `limits.json` (set to the same value by `build_rocket_with`), failing as invalid JSON.
+//!
+//! Bodies read must start arriving within `read_timeout_sec` (the first 512 bytes, or all of a
+//! smaller one), so a client trickling one in can't hold its request open. Others get 408.
+use crate::api::connections::CloseReason;
+use crate::api::response::ApiResponse;
+use crate::app::App;
+use log::debug;
+use rocket::data::Data;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::uri::Origin;
+use rocket::http::{ContentType, Method, Status};
+use rocket::{Request, Response};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::time::Duration;
+
+/// Bytes of a body awaited within the read timeout, what Rocket buffers at most for a peek
+const PEEK_BYTES: usize = 512;
+
+/// `[body_limits]` config section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BodyLimitsConfig {
+    /// Largest accepted JSON body (batch & admin requests) in bytes
+    pub max_json_bytes: u64,
+    /// Seconds within which the start of a body must arrive, 0 disables it
+    pub read_timeout_sec: u64,
+}
+
+impl Default for BodyLimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_json_bytes: 64 * 1024,
+            read_timeout_sec: 10,
+        }
+    }
+}
+
+/// Body a route takes
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Expected {
+    Nothing,
+    Json,
+}
+
+/// Every route taking a body must be listed, which `test_every_route_taking_a_body_is_checked`
+/// (`tests/api.rs`) checks
+///
+/// # Arguments
+/// * `path` - Request path relative to the base, e.g. `wait/123`
+///
+/// # Returns
+/// The body taken by the route serving the request, `None` if it isn't checked
+fn expected_body(method: Method, path: &str) -> Option<Expected> {
+    const NO_BODY: [&str; 5] = [
+        "wait-for-second-party/",
+        "wait/",
+        "notify/",
+        "reserve/",
+        "dev/auto-match/",
+    ];
+    match (method, path) {
+        (Method::Post, "batch/wait")
+        | (Method::Patch, "admin/config")
+        | (Method::Put, "admin/log-level" | "admin/maintenance" | "admin/lame-duck") => {
+            Some(Expected::Json)
+        }
+        (Method::Post, "admin/self-test") => Some(Expected::Nothing),
+        (Method::Post, _) if NO_BODY.iter().any(|prefix| path.starts_with(prefix)) => {
+            Some(Expected::Nothing)
+        }
+        _ => None,
+    }
+}
+
+/// Whether a body of `content_type` is JSON, `+json` structured syntax suffix included
+fn is_json(content_type: &ContentType) -> bool {
+    content_type.is_json()
+        || (content_type.top() == "application" && content_type.sub().as_str().ends_with("+json"))
+}
+
+/// Why a request was rejected, passed from `on_request` to `on_response` via the local cache
+struct Rejection(Option<(Status, String)>);
+
+/// Fairing enforcing the rules of the module docs for the routes under `base`.
+/// Attached by `SyncPointFairing`
+pub struct BodyLimits {
+    /// Base with a trailing slash, e.g. `/sync/`
+    prefix: String,
+    max_json_bytes: u64,
+    read_timeout: Duration,
+}
+
+impl BodyLimits {
+    pub fn new(base: &str, config: &BodyLimitsConfig) -> Self {
+        Self {
+            prefix: format!("{}/", base.trim_end_matches('/')),
+            max_json_bytes: config.max_json_bytes,
+            read_timeout: Duration::from_secs(config.read_timeout_sec),
+        }
+    }
+
+    /// Waits for the start of the body a request has (or may have, without `Content-Length`)
+    /// within `read_timeout`
+    async fn await_body(
+        &self,
+        expected: Expected,
+        request: &Request<'_>,
+        data: &mut Data<'_>,
+    ) -> Result<(), (Status, String)> {
+        let bytes = match expected {
+            Expected::Json => PEEK_BYTES,
+            // The body isn't read when its length is known
+            Expected::Nothing if request.headers().contains("Content-Length") => return Ok(()),
+            Expected::Nothing => 1,
+        };
+        if self.read_timeout.is_zero()
+            || tokio::time::timeout(self.read_timeout, data.peek(bytes))
+                .await
+                .is_ok()
+        {
+            return Ok(());
+        }
+        if let Some(app) = request.rocket().state::<App>() {
+            app.closed_early.record(CloseReason::BodyTimeout);
+        }
+        Err((
+            Status::RequestTimeout,
+            format!(
+                "Body not received within {} sec",
+                self.read_timeout.as_secs()
+            ),
+        ))
+    }
+
+    async fn check(
+        &self,
+        expected: Expected,
+        request: &Request<'_>,
+        data: &mut Data<'_>,
+    ) -> Result<(), (Status, String)> {
+        let content_type = request.content_type();
+        let length = request
+            .headers()
+            .get_one("Content-Length")
+            .and_then(|length| length.parse::<u64>().ok());
+        match expected {
+            Expected::Nothing => {
+                let has_body = match length {
+                    Some(length) => length > 0,
+                    None => !data.peek(1).await.is_empty(),
+                };
+                match (has_body, content_type) {
+                    (false, _) => Ok(()),
+                    (true, Some(content_type)) => Err((
+                        Status::UnsupportedMediaType,
+                        format!(
+                            "Unexpected {} body, {} takes none",
+                            content_type,
+                            request.uri().path()
+                        ),
+                    )),
+                    (true, None) => Err((
+                        Status::PayloadTooLarge,
+                        format!("Unexpected body, {} takes none", request.uri().path()),
+                    )),
+                }
+            }
+            Expected::Json => {
+                if let Some(content_type) =
+                    content_type.filter(|content_type| !is_json(content_type))
+                {
+                    return Err((
+                        Status::UnsupportedMediaType,
+                        format!(
+                            "Unsupported {} body, expected {}",
+                            content_type,
+                            ContentType::JSON
+                        ),
+                    ));
+                }
+                match length {
+                    Some(length) if length > self.max_json_bytes => Err((
+                        Status::PayloadTooLarge,
+                        format!(
+                            "Body of {} bytes exceeds the limit of {} bytes",
+                            length, self.max_json_bytes
+                        ),
+                    )),
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for BodyLimits {
+    fn info(&self) -> Info {
+        Info {
+            name: "Body limits",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, data: &mut Data<'_>) {
+        let expected = request
+            .uri()
+            .path()
+            .as_str()
+            .strip_prefix(self.prefix.as_str())
+            .and_then(|path| expected_body(request.method(), path));
+        let Some(expected) = expected else {
+            return;
+        };
+        let checked = match self.await_body(expected, request, data).await {
+            Ok(()) => self.check(expected, request, data).await,
+            Err(rejection) => Err(rejection),
+        };
+        if let Err((status, message)) = checked {
+            debug!("Rejected request to {}: {}", request.uri(), message);
+            request.local_cache(|| Rejection(Some((status, message))));
+            // Fairings can't respond, route the request to nowhere instead so no handler runs.
+            // `on_response` then replaces the 404
+            request.set_uri(Origin::parse("/.rejected-body").expect("valid origin"));
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if let Rejection(Some((status, message))) = request.local_cache(|| Rejection(None)) {
+            let body = serde_json::to_string(&ApiResponse::error(message.clone()))
+                .expect("serializable response");
+            response.set_status(*status);
+            response.set_header(ContentType::JSON);
+            response.set_sized_body(body.len(), Cursor::new(body));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::body_limits::{expected_body, is_json, Expected};
+    use rocket::http::{ContentType, Method};
+
+    #[test]
+    fn test_expected_body() {
+        assert_eq!(
+            expected_body(Method::Post, "wait-for-second-party/123"),
+            Some(Expected::Nothing)
+        );
+        assert_eq!(
+            expected_body(Method::Post, "notify/123"),
+            Some(Expected::Nothing)
+        );
+        assert_eq!(
+            expected_body(Method::Post, "batch/wait"),
+            Some(Expected::Json)
+        );
+        assert_eq!(
+            expected_body(Method::Put, "admin/maintenance"),
+            Some(Expected::Json)
+        );
+        assert_eq!(expected_body(Method::Get, "status/123"), None);
+        // Not a sync point route, e.g. of the host app
+        assert_eq!(expected_body(Method::Post, "waiting/123"), None);
+    }
+
+    #[test]
+    fn test_json_content_types() {
+        assert!(is_json(&ContentType::JSON));
+        assert!(is_json(&ContentType::new("application", "json-patch+json")));
+        assert!(!is_json(&ContentType::Plain));
+        assert!(!is_json(&ContentType::new("text", "x+json")));
+    }
+}
@@ -0,0 +1,13 @@
+use crate::api::response::ApiResponse;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::json::Json;
+use rocket::{catch, Request};
+
+/// Turns any error status without a handler-provided body (unknown routes, failed guards, ...)
+/// into the same JSON shape as the rest of the API
+#[catch(default)]
+pub fn default_catcher(status: Status, _request: &Request) -> Custom<Json<ApiResponse>> {
+    let message = status.reason().unwrap_or("Unknown error");
+    Custom(status, Json(ApiResponse::error(message)))
+}
@@ -0,0 +1,101 @@
+//! Server time reporting, since coordinated clients frequently disagree about when a window opens:
+//! - every response under the base carries `X-Server-Time` (Unix time in milliseconds)
+//! - `GET /time` reports it in the body too
+//! - clients may send their own clock as `X-Client-Time` (same format), the skew is logged &
+//!   reported as `clock_skew` anomaly beyond `anomalies.max_clock_skew_ms`
+use crate::app::App;
+use crate::log_file::rfc3339;
+use crate::protocol::headers;
+use log::debug;
+use rocket::data::Data;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::serde::json::Json;
+use rocket::{get, Request, Response};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Response of `GET /time`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ServerTime {
+    /// RFC 3339 UTC
+    pub server_time: String,
+    /// Same instant as Unix time in milliseconds, the format of `X-Server-Time`
+    pub unix_ms: u64,
+}
+
+/// Current time of the server, for clients to compare their clock with
+#[get("/time")]
+pub fn time() -> Json<ServerTime> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Json(ServerTime {
+        server_time: rfc3339(now.as_secs(), now.subsec_millis()),
+        unix_ms: now.as_millis() as u64,
+    })
+}
+
+fn unix_ms_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Fairing adding `X-Server-Time` to the responses & checking `X-Client-Time` of the requests under
+/// `base`. Attached by `SyncPointFairing`
+pub struct Clock {
+    /// Base with a trailing slash, e.g. `/sync/`
+    prefix: String,
+}
+
+impl Clock {
+    pub fn new(base: &str) -> Self {
+        Self {
+            prefix: format!("{}/", base.trim_end_matches('/')),
+        }
+    }
+
+    fn applies(&self, request: &Request<'_>) -> bool {
+        let path = request.uri().path();
+        path.as_str().starts_with(self.prefix.as_str()) || self.prefix == format!("{}/", path)
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Clock {
+    fn info(&self) -> Info {
+        Info {
+            name: "Server time",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        let Some(client_time) = request.headers().get_one(headers::CLIENT_TIME) else {
+            return;
+        };
+        if !self.applies(request) {
+            return;
+        }
+        let Ok(client_ms) = client_time.trim().parse::<u64>() else {
+            debug!("Ignoring invalid {}: {}", headers::CLIENT_TIME, client_time);
+            return;
+        };
+        let skew_ms = client_ms as i64 - unix_ms_now() as i64;
+        let client = request
+            .client_ip()
+            .map_or_else(|| "unknown".to_owned(), |ip| ip.to_string());
+        debug!("Clock of {} is off by {} ms", client, skew_ms);
+        if let Some(app) = request.rocket().state::<App>() {
+            app.sync_service.anomalies.clock_skew(&client, skew_ms);
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if self.applies(request) {
+            response.set_header(Header::new(headers::SERVER_TIME, unix_ms_now().to_string()));
+        }
+    }
+}
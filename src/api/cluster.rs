@@ -0,0 +1,53 @@
+//! Ownership API of cluster mode, see `cluster` module. Only compiled with the `cluster` feature
+use crate::api::ids::IdPath;
+use crate::api::response::ApiResponse;
+use crate::app::App;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::json::Json;
+use rocket::{get, State};
+use serde::{Deserialize, Serialize};
+
+/// Response of `GET /cluster/owner/<unique_id>`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClusterOwner {
+    pub unique_id: String,
+    /// Name of the owning node
+    pub node: String,
+    pub url: String,
+    /// Whether this node is the owner
+    pub local: bool,
+}
+
+/// Node owning `unique_id`, where both of its parties should be sent
+///
+/// # Returns
+/// * `Ok(Json<ClusterOwner>)` - The owner
+/// * `Err(Custom<Json<ApiResponse>>)` - 404 when cluster mode isn't enabled, 503 when no members
+///   are known (e.g. the SRV lookup failed so far)
+#[get("/cluster/owner/<unique_id..>")]
+pub async fn cluster_owner(
+    unique_id: IdPath,
+    state: &State<App>,
+) -> Result<Json<ClusterOwner>, Custom<Json<ApiResponse>>> {
+    let cluster = state.cluster().ok_or_else(|| {
+        Custom(
+            Status::NotFound,
+            Json(ApiResponse::error("Cluster mode is not enabled")),
+        )
+    })?;
+    cluster.refresh().await;
+
+    match cluster.owner(&unique_id) {
+        Some(owner) => Ok(Json(ClusterOwner {
+            unique_id: unique_id.as_str().to_owned(),
+            local: owner.name == cluster.node(),
+            node: owner.name,
+            url: owner.url,
+        })),
+        None => Err(Custom(
+            Status::ServiceUnavailable,
+            Json(ApiResponse::error("No cluster members known")),
+        )),
+    }
+}
@@ -0,0 +1,267 @@
+//! Records of matches kept for `completions.retention_sec` & served by `GET /completions/<pair_id>`,
+//! so systems which were offline at match time can verify results later: the id, both parties'
+//! (redacted) origins & notes, when the point was created & matched, & the receipt if issued.
+//!
+//! Records are held in memory, at most `max_records` (the oldest go first). With `path`, they are
+//! also appended to that JSON lines file & loaded from it on startup, which rewrites it without
+//! the expired ones. Knowing a `pair_id` is what grants access, like for the parties themselves.
+use crate::api::ids;
+use crate::api::response::ApiResponse;
+use crate::app::App;
+use crate::protocol::Completion;
+use log::{debug, error, warn};
+use parking_lot::Mutex;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::json::Json;
+use rocket::{get, State};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// `[completions]` config section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompletionsConfig {
+    /// How long a match stays retrievable, in seconds. 0 disables the records
+    pub retention_sec: u64,
+    /// Records kept at most, the oldest are dropped first
+    pub max_records: usize,
+    /// JSON lines file the records are appended to & loaded from, memory only when missing
+    pub path: Option<String>,
+}
+
+impl Default for CompletionsConfig {
+    fn default() -> Self {
+        Self {
+            retention_sec: 3600,
+            max_records: 10_000,
+            path: None,
+        }
+    }
+}
+
+impl CompletionsConfig {
+    /// # Returns
+    /// * `Ok(())` - If the section is valid
+    /// * `Err(String)` - Describing the first invalid value
+    pub fn validate(&self) -> Result<(), String> {
+        if self.retention_sec > 0 && self.max_records == 0 {
+            return Err("completions.max_records must be at least 1".to_owned());
+        }
+        Ok(())
+    }
+}
+
+/// A line of the file, `C` being `Completion` when read & `&Completion` when written
+#[derive(Debug, Serialize, Deserialize)]
+struct Stored<C> {
+    /// When the parties matched, seconds since the epoch
+    matched_at_sec: u64,
+    #[serde(flatten)]
+    completion: C,
+}
+
+#[derive(Debug, Default)]
+struct Records {
+    by_pair_id: HashMap<String, Arc<Completion>>,
+    /// Pair ids with their match time, oldest first
+    order: VecDeque<(u64, String)>,
+}
+
+impl Records {
+    fn insert(&mut self, matched_at_sec: u64, completion: Completion) {
+        self.order
+            .push_back((matched_at_sec, completion.pair_id.clone()));
+        self.by_pair_id
+            .insert(completion.pair_id.clone(), Arc::new(completion));
+    }
+
+    /// Drops the records matched before `oldest_sec` & the oldest ones beyond `max_records`
+    fn prune(&mut self, oldest_sec: u64, max_records: usize) {
+        while let Some((matched_at_sec, pair_id)) = self.order.front() {
+            if *matched_at_sec >= oldest_sec && self.order.len() <= max_records {
+                break;
+            }
+            self.by_pair_id.remove(pair_id);
+            self.order.pop_front();
+        }
+    }
+}
+
+/// The records, see module docs
+#[derive(Debug, Default)]
+pub struct Completions {
+    config: CompletionsConfig,
+    records: Mutex<Records>,
+    file: Option<Mutex<File>>,
+}
+
+impl Completions {
+    /// Loads the records of `config.path` still within the retention, if set
+    ///
+    /// # Returns
+    /// * `Ok(Completions)` - Ready to record
+    /// * `Err(io::Error)` - If the file can't be read or rewritten
+    pub fn open(config: &CompletionsConfig) -> io::Result<Self> {
+        let mut completions = Self {
+            config: config.clone(),
+            ..Default::default()
+        };
+        let Some(path) = config.path.as_deref().filter(|_| config.retention_sec > 0) else {
+            return Ok(completions);
+        };
+        let path = Path::new(path);
+        let mut records = Records::default();
+        if path.exists() {
+            for line in BufReader::new(File::open(path)?).lines() {
+                match serde_json::from_str::<Stored<Completion>>(&line?) {
+                    Ok(stored) => records.insert(stored.matched_at_sec, stored.completion),
+                    Err(e) => warn!("Skipping invalid completion in {}: {}", path.display(), e),
+                }
+            }
+            records.prune(completions.oldest_sec(), config.max_records);
+        }
+        debug!(
+            "Loaded {} completions from {}",
+            records.order.len(),
+            path.display()
+        );
+
+        // Rewritten with the retained records only, so the file doesn't grow across restarts
+        let retained = path.with_extension("tmp");
+        let mut file = File::create(&retained)?;
+        for (matched_at_sec, pair_id) in &records.order {
+            let stored = Stored {
+                matched_at_sec: *matched_at_sec,
+                completion: &*records.by_pair_id[pair_id],
+            };
+            writeln!(file, "{}", serde_json::to_string(&stored)?)?;
+        }
+        file.sync_all()?;
+        fs::rename(&retained, path)?;
+
+        completions.records = Mutex::new(records);
+        completions.file = Some(Mutex::new(OpenOptions::new().append(true).open(path)?));
+        Ok(completions)
+    }
+
+    /// Keeps the record of a match, when enabled & not on an internal id
+    pub fn record(&self, completion: Completion) {
+        if self.config.retention_sec == 0 || ids::is_internal(&completion.unique_id) {
+            return;
+        }
+        let matched_at_sec = now_sec();
+        if let Some(file) = &self.file {
+            let stored = Stored {
+                matched_at_sec,
+                completion: &completion,
+            };
+            let line = serde_json::to_string(&stored).expect("serializable completion");
+            if let Err(e) = writeln!(file.lock(), "{}", line) {
+                error!("Failed to persist completion {}: {}", completion.pair_id, e);
+            }
+        }
+        let mut records = self.records.lock();
+        records.insert(matched_at_sec, completion);
+        records.prune(self.oldest_sec(), self.config.max_records);
+    }
+
+    /// The record of the match with `pair_id`, `None` if unknown or expired
+    pub fn get(&self, pair_id: &Uuid) -> Option<Arc<Completion>> {
+        let mut records = self.records.lock();
+        records.prune(self.oldest_sec(), self.config.max_records);
+        records.by_pair_id.get(&pair_id.to_string()).cloned()
+    }
+
+    /// Match time of the oldest records still retained, seconds since the epoch
+    fn oldest_sec(&self) -> u64 {
+        now_sec().saturating_sub(self.config.retention_sec)
+    }
+}
+
+fn now_sec() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Returns the record of the match with `pair_id`, see module docs
+///
+/// # Returns
+/// * `Ok(Json<Completion>)` - The record
+/// * `Err(Custom<Json<ApiResponse>>)` - 400 if `pair_id` isn't a UUID, 404 if it's unknown or
+///   its record expired
+#[get("/completions/<pair_id>")]
+pub fn completion(
+    pair_id: &str,
+    state: &State<App>,
+) -> Result<Json<Completion>, Custom<Json<ApiResponse>>> {
+    let Ok(parsed) = Uuid::parse_str(pair_id) else {
+        return Err(Custom(
+            Status::BadRequest,
+            Json(ApiResponse::error(format!("Invalid pair_id: {}", pair_id))),
+        ));
+    };
+    match state.sync_service.completions.get(&parsed) {
+        Some(completion) => Ok(Json((*completion).clone())),
+        None => Err(Custom(
+            Status::NotFound,
+            Json(ApiResponse::error(format!(
+                "No completion for pair_id {}, unknown or expired",
+                pair_id
+            ))),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::completions::{Completions, CompletionsConfig};
+    use crate::protocol::Completion;
+    use uuid::Uuid;
+
+    fn completion(pair_id: Uuid) -> Completion {
+        Completion {
+            pair_id: pair_id.to_string(),
+            unique_id: "123".to_owned(),
+            parties: 2,
+            created_at: "2024-12-28T06:41:51.123Z".to_owned(),
+            matched_at: "2024-12-28T06:41:52.123Z".to_owned(),
+            wait_ms: 1000,
+            origins: Vec::new(),
+            notes: vec!["deploy v42".to_owned()],
+            receipt: None,
+        }
+    }
+
+    #[test]
+    fn test_completions_survive_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = CompletionsConfig {
+            path: Some(dir.path().join("completions.jsonl").display().to_string()),
+            max_records: 2,
+            ..Default::default()
+        };
+        let pair_ids = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+        let completions = Completions::open(&config).unwrap();
+        for pair_id in pair_ids {
+            completions.record(completion(pair_id));
+        }
+        // Beyond `max_records`
+        assert_eq!(completions.get(&pair_ids[0]), None);
+        drop(completions);
+
+        let completions = Completions::open(&config).unwrap();
+        assert_eq!(completions.get(&pair_ids[0]), None);
+        let restored = completions.get(&pair_ids[2]).expect("retained");
+        assert_eq!(*restored, completion(pair_ids[2]));
+        let lines = std::fs::read_to_string(config.path.as_ref().unwrap()).unwrap();
+        assert_eq!(lines.lines().count(), 2);
+    }
+}
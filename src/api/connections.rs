@@ -0,0 +1,280 @@
+//! Protections against connection exhaustion, which long waits make this server attractive for.
+//!
+//! Limit of simultaneous rendezvous requests per connection (`max_requests_per_connection`), so a
+//! single HTTP/2 client can't park hundreds of streams on one connection & starve the others.
+//! `/wait-for-second-party`, `/wait`, `/notify` & `/batch/wait` (as a whole) hold a slot of their
+//! connection until answered, further ones get 429. Connections are told apart by the client's
+//! address & port. Behind a reverse proxy, that's the proxy's connection, which may carry several
+//! clients.
+//!
+//! Idle keep-alive connections are closed after `keep_alive_sec` & bodies must arrive within
+//! `body_limits.read_timeout_sec` (see `body_limits` module). Requests ended before their response
+//! completed are counted per `CloseReason`, exposed by `/metrics`.
+//!
+//! Rendezvous requests are also counted per `Protocol`, in progress & since the start: an HTTP/1.1
+//! client needs a connection per wait, an HTTP/2 one multiplexes them as streams of one
+//! connection, which `keep_alive_sec` then keeps alive with PINGs.
+use crate::api::response::ApiResponse;
+use parking_lot::Mutex;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::json::Json;
+use rocket::Request;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Requests in progress per connection, connections without any are dropped
+#[derive(Debug, Default)]
+pub struct ConnectionLimits {
+    in_flight: Mutex<HashMap<SocketAddr, usize>>,
+}
+
+impl ConnectionLimits {
+    /// Takes a slot of `connection`, released when the returned `ConnectionSlot` is dropped
+    ///
+    /// # Returns
+    /// * `Ok(ConnectionSlot)` - If `connection` had less than `limit` requests in progress
+    /// * `Err(usize)` - The requests in progress otherwise
+    pub fn acquire(
+        self: &Arc<Self>,
+        connection: SocketAddr,
+        limit: usize,
+    ) -> Result<ConnectionSlot, usize> {
+        let mut in_flight = self.in_flight.lock();
+        let count = in_flight.entry(connection).or_insert(0);
+        if *count >= limit {
+            return Err(*count);
+        }
+        *count += 1;
+        Ok(ConnectionSlot {
+            limits: Some((self.clone(), connection)),
+            protocol: None,
+        })
+    }
+
+    /// Requests of `connection` in progress
+    pub fn in_flight(&self, connection: SocketAddr) -> usize {
+        self.in_flight.lock().get(&connection).copied().unwrap_or(0)
+    }
+}
+
+/// A request of a connection in progress. Owned, so streamed responses can hold it
+#[must_use = "the slot is released as soon as it's dropped"]
+#[derive(Default)]
+pub struct ConnectionSlot {
+    /// Taken from the limits, with a `max_requests_per_connection`
+    limits: Option<(Arc<ConnectionLimits>, SocketAddr)>,
+    /// Counted as in progress by these stats
+    protocol: Option<(Arc<ProtocolStats>, Protocol)>,
+}
+
+impl ConnectionSlot {
+    /// Counts the request as one of `protocol` in progress, until the slot is dropped
+    pub fn counted(mut self, stats: &Arc<ProtocolStats>, protocol: Protocol) -> Self {
+        stats.requests[protocol as usize].fetch_add(1, Ordering::Relaxed);
+        stats.in_progress[protocol as usize].fetch_add(1, Ordering::Relaxed);
+        self.protocol = Some((stats.clone(), protocol));
+        self
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        if let Some((stats, protocol)) = &self.protocol {
+            stats.in_progress[*protocol as usize].fetch_sub(1, Ordering::Relaxed);
+        }
+        let Some((limits, connection)) = &self.limits else {
+            return;
+        };
+        let mut in_flight = limits.in_flight.lock();
+        if let Some(count) = in_flight.get_mut(connection) {
+            *count -= 1;
+            if *count == 0 {
+                in_flight.remove(connection);
+            }
+        }
+    }
+}
+
+/// HTTP version of a request
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Protocol {
+    /// HTTP/1.x
+    #[default]
+    Http1,
+    /// HTTP/2, over TLS (ALPN `h2`) or plaintext with prior knowledge
+    Http2,
+}
+
+impl Protocol {
+    pub const ALL: [Protocol; 2] = [Protocol::Http1, Protocol::Http2];
+
+    /// Rocket doesn't expose the version of a request: HTTP/1.1 clients send a `Host` header, HTTP/2
+    /// ones the `:authority` pseudo-header Rocket takes the host from instead. HTTP/2 requests also
+    /// carrying a `Host` (allowed, if rarely sent) are taken for HTTP/1.1
+    pub fn of(request: &Request<'_>) -> Self {
+        if request.host().is_some() && !request.headers().contains("Host") {
+            Protocol::Http2
+        } else {
+            Protocol::Http1
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Protocol::Http1 => "http1",
+            Protocol::Http2 => "http2",
+        }
+    }
+}
+
+/// Rendezvous requests per `Protocol`, since the start & in progress
+#[derive(Debug, Default)]
+pub struct ProtocolStats {
+    requests: [AtomicU64; 2],
+    in_progress: [AtomicUsize; 2],
+}
+
+impl ProtocolStats {
+    pub fn requests(&self, protocol: Protocol) -> u64 {
+        self.requests[protocol as usize].load(Ordering::Relaxed)
+    }
+
+    pub fn in_progress(&self, protocol: Protocol) -> usize {
+        self.in_progress[protocol as usize].load(Ordering::Relaxed)
+    }
+}
+
+/// Why a request ended before its response completed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CloseReason {
+    /// Cut by the request deadline (504), see `deadline` module
+    Deadline,
+    /// Its body didn't arrive within `body_limits.read_timeout_sec` (408)
+    BodyTimeout,
+    /// The client went away while its response was streamed
+    Disconnected,
+}
+
+impl CloseReason {
+    pub const ALL: [CloseReason; 3] = [
+        CloseReason::Deadline,
+        CloseReason::BodyTimeout,
+        CloseReason::Disconnected,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CloseReason::Deadline => "deadline",
+            CloseReason::BodyTimeout => "body_timeout",
+            CloseReason::Disconnected => "disconnected",
+        }
+    }
+}
+
+/// Requests closed early since the start, per `CloseReason`
+#[derive(Debug, Default)]
+pub struct ClosedEarly {
+    counts: [AtomicU64; 3],
+}
+
+impl ClosedEarly {
+    pub fn record(&self, reason: CloseReason) {
+        self.counts[reason as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn count(&self, reason: CloseReason) -> u64 {
+        self.counts[reason as usize].load(Ordering::Relaxed)
+    }
+}
+
+/// 429 for a request whose connection has `limit` requests in progress already
+pub fn too_many_requests(limit: usize) -> Custom<Json<ApiResponse>> {
+    Custom(
+        Status::TooManyRequests,
+        Json(ApiResponse::error(format!(
+            "The connection already has {} requests in progress, the limit",
+            limit
+        ))),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::connections::{ConnectionLimits, Protocol};
+    use crate::app::App;
+    use crate::build_rocket_with;
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    /// Connects to `port` once the server listens there
+    async fn connect(port: u16) -> TcpStream {
+        for _ in 0..100 {
+            if let Ok(stream) = TcpStream::connect(("127.0.0.1", port)).await {
+                return stream;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("Nothing listens on {}", port);
+    }
+
+    #[test]
+    fn test_slots_are_released_on_drop() {
+        let limits = Arc::new(ConnectionLimits::default());
+        let a = "10.0.0.1:50000".parse().unwrap();
+        let first = limits.acquire(a, 2).unwrap();
+        let second = limits.acquire(a, 2).unwrap();
+        assert_eq!(limits.acquire(a, 2).err(), Some(2));
+        // Another connection of the same client
+        assert!(limits.acquire("10.0.0.1:50001".parse().unwrap(), 2).is_ok());
+
+        drop(first);
+        assert_eq!(limits.in_flight(a), 1);
+        drop(second);
+        assert!(limits.in_flight.lock().is_empty());
+    }
+
+    #[rocket::async_test]
+    async fn test_requests_are_counted_per_protocol() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, format!("port = {}", port)).unwrap();
+        let app = App::new(path.to_str()).unwrap();
+        let rocket = build_rocket_with(app.clone()).ignite().await.unwrap();
+        let shutdown = rocket.shutdown();
+        let server = tokio::spawn(rocket.launch());
+
+        // Nobody waits for these ids, so 404, counted all the same
+        let mut stream = connect(port).await;
+        let request = "POST /notify/h1 HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 404"), "{}", response);
+
+        // Plaintext HTTP/2 with prior knowledge
+        let (mut client, connection) = h2::client::handshake(connect(port).await).await.unwrap();
+        tokio::spawn(connection);
+        let request = http::Request::post(format!("http://127.0.0.1:{}/notify/h2", port))
+            .body(())
+            .unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        assert_eq!(response.await.unwrap().status(), 404);
+
+        assert_eq!(app.protocols.requests(Protocol::Http1), 1);
+        assert_eq!(app.protocols.requests(Protocol::Http2), 1);
+        assert_eq!(app.protocols.in_progress(Protocol::Http2), 0);
+        shutdown.notify();
+        server.await.unwrap().unwrap();
+    }
+}
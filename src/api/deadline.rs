@@ -0,0 +1,103 @@
+//! Overall deadline of requests, so no handler (e.g. one stalled by a backend) can hang
+//! indefinitely. Distinct from the rendezvous timeout: a request may take `timeout` plus
+//! `request_deadline_sec`, after which its handler is dropped & 504 is returned.
+//!
+//! Rocket fairings can't wrap handlers, so `SyncPointFairing` mounts its routes with `with_deadline`.
+//! Streamed responses (`/batch/wait`) are only covered until the handler returns the stream.
+use crate::api::connections::CloseReason;
+use crate::api::response::ApiResponse;
+use crate::app::App;
+use log::warn;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::route::{Handler, Outcome};
+use rocket::serde::json::Json;
+use rocket::{Data, Request, Route};
+
+/// Handler running `inner` within the deadline of `App::request_deadline`
+#[derive(Clone)]
+struct Deadline {
+    inner: Box<dyn Handler>,
+}
+
+#[rocket::async_trait]
+impl Handler for Deadline {
+    async fn handle<'r>(&self, request: &'r Request<'_>, data: Data<'r>) -> Outcome<'r> {
+        let Some(app) = request.rocket().state::<App>() else {
+            return self.inner.handle(request, data).await;
+        };
+        let deadline = app.request_deadline();
+        match tokio::time::timeout(deadline, self.inner.handle(request, data)).await {
+            Ok(outcome) => outcome,
+            Err(_) => {
+                warn!(
+                    "{} {} exceeded the deadline of {:?}",
+                    request.method(),
+                    request.uri(),
+                    deadline
+                );
+                app.closed_early.record(CloseReason::Deadline);
+                let message = format!(
+                    "Request exceeded the deadline of {} sec",
+                    deadline.as_secs()
+                );
+                Outcome::from(
+                    request,
+                    Custom(Status::GatewayTimeout, Json(ApiResponse::error(message))),
+                )
+            }
+        }
+    }
+}
+
+/// Wraps the handlers of `routes` into the request deadline
+pub fn with_deadline(routes: Vec<Route>) -> Vec<Route> {
+    routes
+        .into_iter()
+        .map(|mut route| {
+            route.handler = Box::new(Deadline {
+                inner: route.handler,
+            });
+            route
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::connections::CloseReason;
+    use crate::api::deadline::with_deadline;
+    use crate::app::App;
+    use rocket::http::Status;
+    use rocket::local::asynchronous::Client;
+    use rocket::{get, routes};
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[get("/stalled")]
+    async fn stalled() -> &'static str {
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        "done"
+    }
+
+    #[rocket::async_test]
+    async fn test_stalled_handler_times_out() {
+        let dir = TempDir::new().unwrap();
+        let config_path = dir.path().join("config.toml");
+        std::fs::write(&config_path, "timeout = 5\nrequest_deadline_sec = 1").unwrap();
+        let app = App::new(config_path.to_str()).unwrap();
+
+        let rocket = rocket::build()
+            .manage(app)
+            .mount("/", with_deadline(routes![stalled]));
+        let client = Client::tracked(rocket).await.unwrap();
+        let response = client.get("/stalled").dispatch().await;
+        assert_eq!(response.status(), Status::GatewayTimeout);
+        assert_eq!(
+            response.into_string().await.unwrap(),
+            r#"{"status":"error","message":"Request exceeded the deadline of 6 sec"}"#
+        );
+        let app = client.rocket().state::<App>().unwrap();
+        assert_eq!(app.closed_early.count(CloseReason::Deadline), 1);
+    }
+}
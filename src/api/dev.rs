@@ -0,0 +1,52 @@
+use crate::api::acl::Access;
+use crate::api::ids::IdPath;
+use crate::api::response::ApiResponse;
+use crate::api::sync_service::SyncRequest;
+use crate::app::App;
+use log::debug;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::json::Json;
+use rocket::{post, State};
+use std::time::Duration;
+
+/// Plays the second party for `unique_id` after a delay, so that client developers can test
+/// their integration without coordinating two processes. Mounted only when `dev.auto_match` is enabled.
+///
+/// Responds immediately, the mock peer then joins via the regular sync logic: it either notifies
+/// a client already waiting, or waits (up to the timeout) for the client to arrive.
+///
+/// # Arguments
+/// * `unique_id` - Id the mock peer joins
+/// * `delay_ms` - Overrides `dev.auto_match_delay_ms`, e.g. to make the peer arrive after the timeout
+///
+/// # Returns
+/// 202 (Accepted) with a message saying when the peer joins
+#[post("/auto-match/<unique_id..>?<delay_ms>")]
+pub fn auto_match(
+    unique_id: IdPath,
+    delay_ms: Option<u64>,
+    state: &State<App>,
+) -> Custom<Json<ApiResponse>> {
+    let delay = Duration::from_millis(delay_ms.unwrap_or(state.settings().dev.auto_match_delay_ms));
+    debug!("Mock peer joins unique_id: {} in {:?}", &*unique_id, delay);
+
+    let app = state.inner().clone();
+    let id = unique_id.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        let _ = app
+            .sync_service
+            .join(&id, SyncRequest::default(), Access::default(), &app)
+            .await;
+        debug!("Mock peer done for unique_id: {}", &*id);
+    });
+
+    Custom(
+        Status::Accepted,
+        Json(ApiResponse::success(
+            format!("Mock peer joins in {} ms", delay.as_millis()),
+            &unique_id,
+        )),
+    )
+}
@@ -0,0 +1,277 @@
+//! Digest mode of webhooks, for deployments with too many events for a call each: each digest of
+//! the `[[on_event.digests]]` config section batches the match & timeout events into an
+//! `EventDigest` POSTed as JSON to its `url`, `interval_ms` after the first event of the batch or as
+//! soon as `max_events` are pending, whichever comes first.
+//!
+//! Digests of a webhook are numbered from 1 on, so receivers notice lost ones. With a `secret`, the
+//! body is signed: `X-Sync-Signature` is `sha256=<hex>` of its HMAC-SHA256, which receivers
+//! compute & compare in constant time, like `hmac::verify_hmac_sha256`.
+//!
+//! Like hooks, digests never delay the parties: a webhook gets one digest at a time, from the
+//! background runtime, & events arriving while `max_events` more are pending are dropped & counted
+//! in the next digest. Failed posts are logged at warn level & not retried, pending events are lost
+//! on shutdown.
+use crate::api::hooks::{Event, EventKind, HookConfig};
+use crate::background::Background;
+use crate::hmac::{hex, hmac_sha256};
+use crate::log_file::rfc3339;
+use crate::protocol::{headers, EventDigest, Observation, ObservedEvent};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+/// A digest webhook of the `[on_event]` config section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DigestConfig {
+    /// Events batched, all by default
+    #[serde(default = "HookConfig::all_events")]
+    pub events: Vec<EventKind>,
+    /// `http(s)://` URL the digests are POSTed to
+    pub url: String,
+    /// Longest time an event waits for its digest
+    #[serde(default = "DigestConfig::default_interval_ms")]
+    pub interval_ms: u64,
+    /// Events per digest at most
+    #[serde(default = "DigestConfig::default_max_events")]
+    pub max_events: usize,
+    /// Key the bodies are signed with, unsigned when missing
+    #[serde(default)]
+    pub secret: Option<String>,
+}
+
+impl DigestConfig {
+    fn default_interval_ms() -> u64 {
+        10_000
+    }
+
+    fn default_max_events() -> usize {
+        500
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if !self.url.starts_with("http://") && !self.url.starts_with("https://") {
+            return Err(format!("on_event.digests url {} must be http(s)", self.url));
+        }
+        if self.interval_ms == 0 {
+            return Err("on_event.digests interval_ms must be at least 1".to_owned());
+        }
+        if self.max_events == 0 {
+            return Err("on_event.digests max_events must be at least 1".to_owned());
+        }
+        if self.secret.as_ref().is_some_and(String::is_empty) {
+            return Err("on_event.digests secret can't be empty".to_owned());
+        }
+        Ok(())
+    }
+}
+
+/// Batches the events of a digest webhook, see module docs
+pub struct Digest {
+    config: DigestConfig,
+    agent: ureq::Agent,
+    /// Events pending for the task posting the digests, started by the first event
+    queue: OnceLock<mpsc::Sender<Observation>>,
+    /// Events dropped since the last digest
+    dropped: Arc<AtomicU64>,
+}
+
+impl Digest {
+    /// The `agent` carries the timeout of the posts
+    pub fn new(config: &DigestConfig, agent: ureq::Agent) -> Self {
+        Self {
+            config: config.clone(),
+            agent,
+            queue: OnceLock::new(),
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Adds `event` to the next digest, if the webhook batches its kind
+    pub fn push(&self, event: &Event, background: &Background) {
+        if !self.config.events.contains(&event.kind) {
+            return;
+        }
+        let queue = self.queue.get_or_init(|| {
+            let (sender, receiver) = mpsc::channel(self.config.max_events);
+            let (config, agent) = (self.config.clone(), self.agent.clone());
+            background.spawn(run(config, agent, receiver, self.dropped.clone()));
+            sender
+        });
+        if queue.try_send(observation(event)).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// `event` as digests have it
+fn observation(event: &Event) -> Observation {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Observation {
+        event: match event.kind {
+            EventKind::Matched => ObservedEvent::Matched,
+            EventKind::Timeout => ObservedEvent::Timeout,
+        },
+        unique_id: event.unique_id.to_owned(),
+        pair_id: event.pair_id.map(|id| id.to_string()),
+        parties: event.parties,
+        time: rfc3339(now.as_secs(), now.subsec_millis()),
+    }
+}
+
+/// Batches the events of `queue` into digests & posts them, until the app is dropped
+async fn run(
+    config: DigestConfig,
+    agent: ureq::Agent,
+    mut queue: mpsc::Receiver<Observation>,
+    dropped: Arc<AtomicU64>,
+) {
+    let interval = Duration::from_millis(config.interval_ms);
+    let mut sequence = 0;
+    while let Some(first) = queue.recv().await {
+        let mut events = vec![first];
+        let deadline = tokio::time::Instant::now() + interval;
+        while events.len() < config.max_events {
+            match tokio::time::timeout_at(deadline, queue.recv()).await {
+                Ok(Some(event)) => events.push(event),
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        sequence += 1;
+        let count = events.len();
+        let digest = EventDigest {
+            sequence,
+            events,
+            dropped: dropped.swap(0, Ordering::Relaxed),
+        };
+        let body = serde_json::to_string(&digest).expect("serializable digest");
+        match post(agent.clone(), &config, body).await {
+            Ok(()) => debug!("Posted digest {} of {} events", sequence, count),
+            Err(e) => warn!("Digest {} of {} events failed: {}", sequence, count, e),
+        }
+    }
+}
+
+/// POSTs `body` to the webhook, signed if it has a `secret`
+async fn post(agent: ureq::Agent, config: &DigestConfig, body: String) -> Result<(), String> {
+    let url = config.url.clone();
+    let signature = config.secret.as_ref().map(|secret| {
+        let mac = hmac_sha256(secret.as_bytes(), body.as_bytes());
+        format!("sha256={}", hex(&mac))
+    });
+    tokio::task::spawn_blocking(move || {
+        let mut request = agent.post(&url).set("Content-Type", "application/json");
+        if let Some(signature) = &signature {
+            request = request.set(headers::SIGNATURE, signature);
+        }
+        request
+            .send_string(&body)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::digests::{Digest, DigestConfig};
+    use crate::api::hooks::{Event, HookConfig};
+    use crate::background::Background;
+    use crate::hmac::verify_hmac_sha256;
+    use crate::protocol::{EventDigest, ObservedEvent};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use uuid::Uuid;
+
+    /// Answers a POST with 200
+    ///
+    /// # Returns
+    /// Its `X-Sync-Signature` & body
+    fn receive(listener: &TcpListener) -> (Option<String>, String) {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let (mut signature, mut length) = (None, 0);
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line.split_once(": ").unwrap_or((line, ""));
+            match name.to_ascii_lowercase().as_str() {
+                "x-sync-signature" => signature = Some(value.to_owned()),
+                "content-length" => length = value.parse().unwrap(),
+                _ => {}
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        let response = "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+        reader.get_mut().write_all(response.as_bytes()).unwrap();
+        (signature, String::from_utf8(body).unwrap())
+    }
+
+    #[test]
+    fn test_events_are_posted_in_signed_digests() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = DigestConfig {
+            events: HookConfig::all_events(),
+            url: format!("http://{}/digests", listener.local_addr().unwrap()),
+            interval_ms: 200,
+            max_events: 2,
+            secret: Some("s3cret".to_owned()),
+        };
+        assert!(config.validate().is_ok());
+        let digest = Digest::new(&config, ureq::agent());
+        let background = Background::default();
+
+        // Posted once max_events are pending
+        let pair_id = Uuid::new_v4();
+        digest.push(&Event::matched("deploy", pair_id, 2), &background);
+        digest.push(&Event::timeout("lonely"), &background);
+        let (signature, body) = receive(&listener);
+        let signature = signature.unwrap();
+        let signature = signature.strip_prefix("sha256=").unwrap();
+        assert!(verify_hmac_sha256(b"s3cret", body.as_bytes(), signature));
+        let first: EventDigest = serde_json::from_str(&body).unwrap();
+        assert_eq!(first.sequence, 1);
+        assert_eq!(first.dropped, 0);
+        assert_eq!(first.events.len(), 2);
+        assert_eq!(first.events[0].event, ObservedEvent::Matched);
+        assert_eq!(first.events[0].pair_id, Some(pair_id.to_string()));
+        assert_eq!(first.events[1].unique_id, "lonely");
+
+        // Or once the first pending one waited interval_ms
+        digest.push(&Event::timeout("late"), &background);
+        let (_, body) = receive(&listener);
+        let second: EventDigest = serde_json::from_str(&body).unwrap();
+        assert_eq!(second.sequence, 2);
+        assert_eq!(second.events[0].unique_id, "late");
+    }
+
+    #[test]
+    fn test_digests_need_an_http_url() {
+        let config = |url: &str| DigestConfig {
+            events: HookConfig::all_events(),
+            url: url.to_owned(),
+            interval_ms: 1000,
+            max_events: 10,
+            secret: None,
+        };
+        assert!(config("https://audit.internal/events").validate().is_ok());
+        assert!(config("audit.internal").validate().is_err());
+        assert!(DigestConfig {
+            max_events: 0,
+            ..config("https://audit.internal/events")
+        }
+        .validate()
+        .is_err());
+    }
+}
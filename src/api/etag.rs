@@ -0,0 +1,87 @@
+//! Conditional GET support for polled endpoints: responses carry an `ETag` & `Cache-Control: no-cache`,
+//! so clients revalidate every time but get a body-less 304 while nothing changed.
+use crate::protocol::headers;
+use rocket::http::{Header, Status};
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::{self, Responder};
+use rocket::{Request, Response};
+
+/// `If-None-Match` request header, if sent
+pub struct IfNoneMatch(Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfNoneMatch {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(IfNoneMatch(
+            request
+                .headers()
+                .get_one(headers::IF_NONE_MATCH)
+                .map(str::to_owned),
+        ))
+    }
+}
+
+impl IfNoneMatch {
+    /// Whether the client's cached representation has the given (unquoted) ETag.
+    /// Weak comparison, as recommended for `If-None-Match`
+    pub fn matches(&self, etag: &str) -> bool {
+        let Some(header) = &self.0 else {
+            return false;
+        };
+        header.split(',').map(str::trim).any(|candidate| {
+            candidate == "*" || candidate.trim_start_matches("W/").trim_matches('"') == etag
+        })
+    }
+}
+
+/// A response tagged with an ETag, or 304 Not Modified when the client already has it
+pub enum Tagged<R> {
+    Fresh(R, String),
+    NotModified(String),
+}
+
+impl<R> Tagged<R> {
+    /// # Arguments
+    /// * `if_none_match` - Request's `If-None-Match` header
+    /// * `etag` - Current ETag of the resource (unquoted)
+    /// * `response` - Builds the body, only called when it's actually sent
+    pub fn new(if_none_match: &IfNoneMatch, etag: String, response: impl FnOnce() -> R) -> Self {
+        if if_none_match.matches(&etag) {
+            Tagged::NotModified(etag)
+        } else {
+            Tagged::Fresh(response(), etag)
+        }
+    }
+}
+
+impl<'r, R: Responder<'r, 'static>> Responder<'r, 'static> for Tagged<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let (mut response, etag) = match self {
+            Tagged::Fresh(inner, etag) => (inner.respond_to(request)?, etag),
+            Tagged::NotModified(etag) => (
+                Response::build().status(Status::NotModified).finalize(),
+                etag,
+            ),
+        };
+        response.set_header(Header::new(headers::ETAG, format!("\"{}\"", etag)));
+        response.set_header(Header::new("Cache-Control", "no-cache"));
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::etag::IfNoneMatch;
+
+    #[test]
+    fn test_if_none_match() {
+        assert!(!IfNoneMatch(None).matches("1.1"));
+        assert!(IfNoneMatch(Some("\"1.1\"".to_owned())).matches("1.1"));
+        assert!(IfNoneMatch(Some("W/\"1.1\"".to_owned())).matches("1.1"));
+        assert!(IfNoneMatch(Some("\"0.1\", \"1.1\"".to_owned())).matches("1.1"));
+        assert!(IfNoneMatch(Some("*".to_owned())).matches("1.1"));
+        assert!(!IfNoneMatch(Some("\"1.2\"".to_owned())).matches("1.1"));
+    }
+}
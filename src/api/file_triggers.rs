@@ -0,0 +1,246 @@
+//! Filesystem-based pipelines bridged into rendezvous: each trigger of the `[file_triggers]`
+//! section watches a path & joins its `unique_id` as a party, through the regular sync logic, when
+//! a file appears there (e.g. a build artifact landing). A file present at startup counts as
+//! appearing.
+//!
+//! Paths are polled every `poll_interval_ms`, which works the same on every platform & on network
+//! filesystems, unlike inotify. A trigger whose party timed out or was rejected joins again on the
+//! next poll while the file is still there. Once matched, it waits for the file to disappear
+//! before joining again, or removes it itself with `remove = true`.
+//!
+//! Triggers run on the background runtime from liftoff until shutdown, their parties are counted
+//! per `TriggerOutcome` & exposed by `/metrics`.
+use crate::api::acl::Access;
+use crate::api::ids;
+use crate::api::response::Failure;
+use crate::api::sync_service::SyncRequest;
+use crate::app::App;
+use log::{debug, info, warn};
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::json::Json;
+use rocket::tokio::select;
+use rocket::Shutdown;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Note of the trigger parties, shown in admin listings & the audit log
+const NOTE: &str = "file trigger";
+
+/// A trigger of the `[file_triggers]` config section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileTriggerConfig {
+    /// File whose appearance makes the party join
+    pub path: String,
+    /// Id the party joins
+    pub unique_id: String,
+    /// Whether the file is removed once matched, so the next one landing triggers again
+    #[serde(default)]
+    pub remove: bool,
+}
+
+/// `[file_triggers]` config section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileTriggersConfig {
+    /// Time between checks of the paths
+    pub poll_interval_ms: u64,
+    pub triggers: Vec<FileTriggerConfig>,
+}
+
+impl Default for FileTriggersConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval_ms: 1000,
+            triggers: Vec::new(),
+        }
+    }
+}
+
+impl FileTriggersConfig {
+    /// Checks the interval & that every trigger has a path & an id clients may use
+    ///
+    /// # Returns
+    /// * `Ok(())` - All of them are valid
+    /// * `Err(String)` - Describing the first invalid value
+    pub fn validate(&self) -> Result<(), String> {
+        if self.poll_interval_ms == 0 {
+            return Err("file_triggers.poll_interval_ms must be at least 1".to_owned());
+        }
+        for trigger in &self.triggers {
+            if trigger.path.is_empty() {
+                return Err("file_triggers.triggers need a path".to_owned());
+            }
+            if trigger.unique_id.split('/').any(str::is_empty) {
+                return Err(format!(
+                    "file_triggers.triggers unique_id {:?} has empty segments",
+                    trigger.unique_id
+                ));
+            }
+            if ids::is_internal(&trigger.unique_id) {
+                return Err(format!(
+                    "file_triggers.triggers unique_id {} is reserved",
+                    trigger.unique_id
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How the party of a trigger ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerOutcome {
+    Matched,
+    /// No peer arrived within the timeout
+    TimedOut,
+    /// Rejected, e.g. in maintenance mode or on a conflict
+    Failed,
+}
+
+impl TriggerOutcome {
+    pub const ALL: [TriggerOutcome; 3] = [
+        TriggerOutcome::Matched,
+        TriggerOutcome::TimedOut,
+        TriggerOutcome::Failed,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TriggerOutcome::Matched => "matched",
+            TriggerOutcome::TimedOut => "timed_out",
+            TriggerOutcome::Failed => "failed",
+        }
+    }
+}
+
+/// Parties of the triggers since the start, per `TriggerOutcome`, & those waiting right now
+#[derive(Debug, Default)]
+pub struct FileTriggerStats {
+    counts: [AtomicU64; 3],
+    waiting: AtomicUsize,
+}
+
+impl FileTriggerStats {
+    pub fn count(&self, outcome: TriggerOutcome) -> u64 {
+        self.counts[outcome as usize].load(Ordering::Relaxed)
+    }
+
+    pub fn waiting(&self) -> usize {
+        self.waiting.load(Ordering::Relaxed)
+    }
+
+    fn record(&self, outcome: TriggerOutcome) {
+        self.counts[outcome as usize].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Starts watching the configured paths on the background runtime of `app`, until `shutdown`
+pub fn spawn(app: &App, config: &FileTriggersConfig, shutdown: Shutdown) {
+    let interval = Duration::from_millis(config.poll_interval_ms);
+    for trigger in config.triggers.iter().cloned() {
+        info!(
+            "Joining {} whenever {} appears",
+            trigger.unique_id, trigger.path
+        );
+        let watcher = watch(app.clone(), trigger, interval, shutdown.clone());
+        app.sync_service.background.spawn(watcher);
+    }
+}
+
+/// Polls the path of `trigger` & joins its id when due, see module docs
+async fn watch(app: App, trigger: FileTriggerConfig, interval: Duration, mut shutdown: Shutdown) {
+    let path = Path::new(&trigger.path);
+    // Cleared by a match, until the file is gone
+    let mut armed = true;
+    loop {
+        select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = &mut shutdown => return,
+        }
+        if !path.exists() {
+            armed = true;
+            continue;
+        }
+        if !armed {
+            continue;
+        }
+        debug!("{} appeared, joining {}", trigger.path, trigger.unique_id);
+        app.file_triggers.waiting.fetch_add(1, Ordering::Relaxed);
+        let outcome = select! {
+            outcome = join(&app, &trigger.unique_id) => outcome,
+            _ = &mut shutdown => {
+                app.file_triggers.waiting.fetch_sub(1, Ordering::Relaxed);
+                return;
+            }
+        };
+        app.file_triggers.waiting.fetch_sub(1, Ordering::Relaxed);
+        app.file_triggers.record(outcome);
+
+        if outcome == TriggerOutcome::Matched {
+            armed = false;
+            if trigger.remove {
+                if let Err(e) = std::fs::remove_file(path) {
+                    warn!("Failed to remove {}: {}", trigger.path, e);
+                }
+            }
+        }
+    }
+}
+
+/// The party of a trigger calling `POST /wait-for-second-party/<unique_id>`
+async fn join(app: &App, unique_id: &str) -> TriggerOutcome {
+    let request = SyncRequest {
+        note: Some(NOTE.to_owned()),
+        ..SyncRequest::default()
+    };
+    let response = app
+        .sync_service
+        .join(unique_id, request, Access::default(), app)
+        .await;
+    let Custom(status, Json(response)) = match response {
+        Ok(response) => response,
+        Err(retry_after) => retry_after.0,
+    };
+    if status == Status::Ok {
+        TriggerOutcome::Matched
+    } else if status == app.sync_service.status_codes.status(Failure::Timeout) {
+        TriggerOutcome::TimedOut
+    } else {
+        warn!(
+            "File trigger on unique_id: {} rejected: {} {}",
+            unique_id,
+            status,
+            response.message()
+        );
+        TriggerOutcome::Failed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::file_triggers::{FileTriggerConfig, FileTriggersConfig};
+
+    #[test]
+    fn test_triggers_need_a_path_and_a_client_id() {
+        let trigger = |path: &str, unique_id: &str| FileTriggersConfig {
+            triggers: vec![FileTriggerConfig {
+                path: path.to_owned(),
+                unique_id: unique_id.to_owned(),
+                remove: false,
+            }],
+            ..FileTriggersConfig::default()
+        };
+        assert!(trigger("/builds/app.tar", "deploy/app").validate().is_ok());
+        assert!(trigger("", "deploy/app").validate().is_err());
+        assert!(trigger("/builds/app.tar", "deploy//app")
+            .validate()
+            .is_err());
+        assert!(trigger("/builds/app.tar", "").validate().is_err());
+        assert!(trigger("/builds/app.tar", "_internal/x")
+            .validate()
+            .is_err());
+    }
+}
@@ -0,0 +1,441 @@
+//! Notifications of match & timeout events without bespoke integrations: each hook of the
+//! `[on_event]` config section either runs a local command or sends a webhook, whose arguments,
+//! URL & body are templates with event placeholders
+//! - `{event}` - `matched` or `timeout`
+//! - `{unique_id}` - Id of the wait point
+//! - `{pair_id}` - Id of the match, empty for `timeout`
+//! - `{parties}` - Parties matched, 1 (the one giving up) for `timeout`
+//! - `{time}` - RFC 3339 UTC
+//!
+//! Hooks are fire & forget on the background runtime, they never delay the parties. Commands run without a shell, with an
+//! empty environment (`PATH` aside), no stdin & discarded output, & are killed after `timeout_ms`
+//! like webhooks time out. At most `max_concurrent` hooks run at a time, events finding no free
+//! slot are dropped & logged rather than queued. Webhooks batching events are `digests`, see
+//! `digests` module.
+use crate::api::digests::{Digest, DigestConfig};
+use crate::api::ids;
+use crate::background::Background;
+use crate::log_file::rfc3339;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Semaphore;
+use uuid::Uuid;
+
+/// Placeholders of the templates, see module docs
+const PLACEHOLDERS: [&str; 5] = ["event", "unique_id", "pair_id", "parties", "time"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    /// Parties matched on an id
+    Matched,
+    /// A waiting party gave up after its timeout
+    Timeout,
+}
+
+impl EventKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EventKind::Matched => "matched",
+            EventKind::Timeout => "timeout",
+        }
+    }
+}
+
+/// An event hooks are run for
+#[derive(Debug, Clone)]
+pub struct Event<'a> {
+    pub kind: EventKind,
+    pub unique_id: &'a str,
+    pub pair_id: Option<Uuid>,
+    pub parties: usize,
+}
+
+impl<'a> Event<'a> {
+    pub fn matched(unique_id: &'a str, pair_id: Uuid, parties: usize) -> Self {
+        Self {
+            kind: EventKind::Matched,
+            unique_id,
+            pair_id: Some(pair_id),
+            parties,
+        }
+    }
+
+    pub fn timeout(unique_id: &'a str) -> Self {
+        Self {
+            kind: EventKind::Timeout,
+            unique_id,
+            pair_id: None,
+            parties: 1,
+        }
+    }
+
+    /// Values of `PLACEHOLDERS`, in the same order
+    fn values(&self) -> [String; 5] {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        [
+            self.kind.as_str().to_owned(),
+            self.unique_id.to_owned(),
+            self.pair_id.map(|id| id.to_string()).unwrap_or_default(),
+            self.parties.to_string(),
+            rfc3339(now.as_secs(), now.subsec_millis()),
+        ]
+    }
+}
+
+/// A hook of the `[on_event]` config section, either `command` or `url`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HookConfig {
+    /// Events the hook runs for, all by default
+    #[serde(default = "HookConfig::all_events")]
+    pub events: Vec<EventKind>,
+    /// Program & arguments, e.g. `["/usr/local/bin/page", "{event}", "{unique_id}"]`
+    #[serde(default)]
+    pub command: Vec<String>,
+    /// `http(s)://` URL the webhook is POSTed to, placeholders are percent-encoded
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Webhook body, placeholders are JSON-escaped for a JSON `content_type`
+    #[serde(default)]
+    pub body: String,
+    #[serde(default = "HookConfig::default_content_type")]
+    pub content_type: String,
+}
+
+impl HookConfig {
+    pub(crate) fn all_events() -> Vec<EventKind> {
+        vec![EventKind::Matched, EventKind::Timeout]
+    }
+
+    fn default_content_type() -> String {
+        "application/json".to_owned()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match (&self.url, self.command.is_empty()) {
+            (Some(_), false) | (None, true) => {
+                Err("on_event.hooks need either a command or a url".to_owned())
+            }
+            (Some(url), true) if !url.starts_with("http://") && !url.starts_with("https://") => {
+                Err(format!("on_event.hooks url {} must be http(s)", url))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// `[on_event]` config section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    /// Time a command may run or a webhook may take
+    pub timeout_ms: u64,
+    /// Hooks running at a time
+    pub max_concurrent: usize,
+    pub hooks: Vec<HookConfig>,
+    /// Webhooks getting the events in batches, see `digests` module
+    pub digests: Vec<DigestConfig>,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 5000,
+            max_concurrent: 4,
+            hooks: Vec::new(),
+            digests: Vec::new(),
+        }
+    }
+}
+
+impl HooksConfig {
+    /// Checks the limits & that every hook has something to run
+    ///
+    /// # Returns
+    /// * `Ok(())` - All of them are valid
+    /// * `Err(String)` - Describing the first invalid value
+    pub fn validate(&self) -> Result<(), String> {
+        if self.timeout_ms == 0 {
+            return Err("on_event.timeout_ms must be at least 1".to_owned());
+        }
+        if self.max_concurrent == 0 {
+            return Err("on_event.max_concurrent must be at least 1".to_owned());
+        }
+        self.hooks.iter().try_for_each(HookConfig::validate)?;
+        self.digests.iter().try_for_each(DigestConfig::validate)
+    }
+}
+
+/// What a hook does for an event, its templates filled in
+#[derive(Debug, PartialEq)]
+enum Action {
+    Command(Vec<String>),
+    Webhook {
+        url: String,
+        body: String,
+        content_type: String,
+    },
+}
+
+/// Runs the configured hooks, see module docs
+pub struct Hooks {
+    config: HooksConfig,
+    /// A permit per running hook
+    slots: Arc<Semaphore>,
+    agent: ureq::Agent,
+    digests: Vec<Digest>,
+}
+
+impl Default for Hooks {
+    fn default() -> Self {
+        Self::new(&HooksConfig::default())
+    }
+}
+
+impl Hooks {
+    pub fn new(config: &HooksConfig) -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .build();
+        Self {
+            config: config.clone(),
+            slots: Arc::new(Semaphore::new(config.max_concurrent)),
+            digests: config
+                .digests
+                .iter()
+                .map(|digest| Digest::new(digest, agent.clone()))
+                .collect(),
+            agent,
+        }
+    }
+
+    /// Starts the hooks of `event` on the `background` runtime & adds it to the digests, none for
+    /// internal ids
+    pub fn fire(&self, event: &Event, background: &Background) {
+        if ids::is_internal(event.unique_id) {
+            return;
+        }
+        for digest in &self.digests {
+            digest.push(event, background);
+        }
+        let timeout = Duration::from_millis(self.config.timeout_ms);
+        for hook in self.config.hooks.iter() {
+            if !hook.events.contains(&event.kind) {
+                continue;
+            }
+            let Ok(slot) = self.slots.clone().try_acquire_owned() else {
+                warn!(
+                    "Hook for {} on unique_id: {} dropped, {} already running",
+                    event.kind.as_str(),
+                    event.unique_id,
+                    self.config.max_concurrent
+                );
+                continue;
+            };
+            let action = render(hook, event);
+            let agent = self.agent.clone();
+            let (kind, unique_id) = (event.kind, event.unique_id.to_owned());
+            background.spawn(async move {
+                let result = match action {
+                    Action::Command(argv) => run_command(argv, timeout).await,
+                    Action::Webhook {
+                        url,
+                        body,
+                        content_type,
+                    } => send_webhook(agent, url, body, content_type).await,
+                };
+                match result {
+                    Ok(()) => debug!("Hook for {} on {} ran", kind.as_str(), unique_id),
+                    Err(e) => warn!(
+                        "Hook for {} on unique_id: {} failed: {}",
+                        kind.as_str(),
+                        unique_id,
+                        e
+                    ),
+                }
+                drop(slot);
+            });
+        }
+    }
+}
+
+/// Fills in the templates of `hook` for `event`
+fn render(hook: &HookConfig, event: &Event) -> Action {
+    let values = event.values();
+    let fill = |template: &str, escape: fn(&str) -> String| {
+        PLACEHOLDERS
+            .iter()
+            .zip(values.iter())
+            .fold(template.to_owned(), |filled, (name, value)| {
+                filled.replace(&format!("{{{}}}", name), &escape(value))
+            })
+    };
+    match &hook.url {
+        Some(url) => {
+            let escape_body: fn(&str) -> String = if hook.content_type.contains("json") {
+                json_escape
+            } else {
+                str::to_owned
+            };
+            Action::Webhook {
+                url: fill(url, percent_encode),
+                body: fill(&hook.body, escape_body),
+                content_type: hook.content_type.clone(),
+            }
+        }
+        None => Action::Command(
+            hook.command
+                .iter()
+                .map(|arg| fill(arg, str::to_owned))
+                .collect(),
+        ),
+    }
+}
+
+/// `value` as the inside of a JSON string
+fn json_escape(value: &str) -> String {
+    let quoted = serde_json::Value::from(value).to_string();
+    quoted[1..quoted.len() - 1].to_owned()
+}
+
+/// `value` with everything but unreserved characters (RFC 3986) percent-encoded
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Runs `argv` in the sandbox described in the module docs, killing it after `timeout`
+async fn run_command(argv: Vec<String>, timeout: Duration) -> Result<(), String> {
+    let mut command = tokio::process::Command::new(&argv[0]);
+    command
+        .args(&argv[1..])
+        .env_clear()
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+    if let Some(path) = std::env::var_os("PATH") {
+        command.env("PATH", path);
+    }
+    let mut child = command.spawn().map_err(|e| e.to_string())?;
+    match tokio::time::timeout(timeout, child.wait()).await {
+        Ok(Ok(status)) if status.success() => Ok(()),
+        Ok(Ok(status)) => Err(status.to_string()),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => {
+            let _ = child.kill().await;
+            Err(format!("killed after {} ms", timeout.as_millis()))
+        }
+    }
+}
+
+/// POSTs `body` to `url`, the agent carries the timeout
+async fn send_webhook(
+    agent: ureq::Agent,
+    url: String,
+    body: String,
+    content_type: String,
+) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || {
+        agent
+            .post(&url)
+            .set("Content-Type", &content_type)
+            .send_string(&body)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::hooks::{render, Action, Event, HookConfig, HooksConfig};
+    use uuid::Uuid;
+
+    fn hook(toml: &str) -> HookConfig {
+        let config: HooksConfig = config::Config::builder()
+            .add_source(config::File::from_str(toml, config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+        assert!(config.validate().is_ok());
+        config.hooks[0].clone()
+    }
+
+    #[test]
+    fn test_placeholders_are_escaped_per_target() {
+        let pair_id = Uuid::new_v4();
+        let event = Event::matched("a\"b c", pair_id, 2);
+
+        let webhook = hook(
+            r#"[[hooks]]
+            url = "http://hooks.internal/{event}?id={unique_id}"
+            body = '{"text": "{parties} matched on {unique_id} ({pair_id})"}'"#,
+        );
+        assert_eq!(
+            render(&webhook, &event),
+            Action::Webhook {
+                url: "http://hooks.internal/matched?id=a%22b%20c".to_owned(),
+                body: format!(r#"{{"text": "2 matched on a\"b c ({})"}}"#, pair_id),
+                content_type: "application/json".to_owned(),
+            }
+        );
+
+        // Arguments are passed as is, there's no shell to interpret them
+        let command = hook(
+            r#"[[hooks]]
+            command = ["notify", "{event}:{unique_id}", "{pair_id}"]"#,
+        );
+        assert_eq!(
+            render(&command, &Event::timeout("a\"b c")),
+            Action::Command(vec![
+                "notify".to_owned(),
+                "timeout:a\"b c".to_owned(),
+                String::new()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_hooks_need_a_single_target() {
+        let invalid = |hook: HookConfig| {
+            HooksConfig {
+                hooks: vec![hook],
+                ..Default::default()
+            }
+            .validate()
+            .is_err()
+        };
+        let base = hook(
+            r#"[[hooks]]
+            command = ["true"]"#,
+        );
+        assert!(invalid(HookConfig {
+            command: Vec::new(),
+            ..base.clone()
+        }));
+        assert!(invalid(HookConfig {
+            url: Some("http://hooks.internal".to_owned()),
+            ..base.clone()
+        }));
+        assert!(invalid(HookConfig {
+            command: Vec::new(),
+            url: Some("ftp://hooks.internal".to_owned()),
+            ..base
+        }));
+    }
+}
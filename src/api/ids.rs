@@ -0,0 +1,137 @@
+//! Path-like ids, e.g. `deploy/eu/payments`: the sync endpoints take the rest of their path as id,
+//! so hierarchies of ids need no encoding (`deploy%2Feu%2Fpayments` is the same id). Empty
+//! segments are dropped like Rocket does, `deploy//eu` is `deploy/eu`.
+//!
+//! Observers follow a whole subtree with an `IdPattern`, see `observers` module.
+//!
+//! Ids under `_internal/` are reserved for the server's own synthetic traffic (self-test,
+//! simulation), clients get 422 for them. Such points are left out of `/stats`, the outcomes &
+//! completions, observers & hooks, so they don't pollute dashboards. In-process parties carry no
+//! API key or connection, so quotas never count them either.
+use rocket::http::uri::fmt::Path;
+use rocket::http::uri::Segments;
+use rocket::request::FromSegments;
+use std::ops::Deref;
+
+/// First segment of the reserved ids, see module docs
+pub const INTERNAL_NAMESPACE: &str = "_internal";
+
+/// Whether `unique_id` is in the reserved namespace, e.g. `_internal/selftest-<uuid>`
+pub fn is_internal(unique_id: &str) -> bool {
+    unique_id.split('/').next() == Some(INTERNAL_NAMESPACE)
+}
+
+/// Id taken from the rest of a route's path, its segments joined by `/`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdPath(String);
+
+impl IdPath {
+    /// Normalizes & validates an id given as a string, e.g. in a request body, the way the path
+    /// routes do: empty segments are dropped, `deploy//eu` is `deploy/eu`
+    ///
+    /// # Returns
+    /// * `Ok(IdPath)` - The normalized id
+    /// * `Err(&str)` - If it has no segment, or is reserved (see module docs)
+    pub fn parse(unique_id: &str) -> Result<Self, &'static str> {
+        let unique_id = unique_id
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>()
+            .join("/");
+        if unique_id.is_empty() {
+            return Err("missing id");
+        }
+        if is_internal(&unique_id) {
+            return Err("reserved id");
+        }
+        Ok(Self(unique_id))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for IdPath {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for IdPath {
+    fn from(unique_id: &str) -> Self {
+        Self(unique_id.to_owned())
+    }
+}
+
+impl<'r> FromSegments<'r> for IdPath {
+    type Error = &'static str;
+
+    fn from_segments(segments: Segments<'r, Path>) -> Result<Self, Self::Error> {
+        // Segments may hold encoded `/`, whose empty segments are dropped too (`deploy%2F%2Feu`)
+        Self::parse(&segments.collect::<Vec<_>>().join("/"))
+    }
+}
+
+/// Ids an observer follows: one id, or every id starting with a prefix when ending with `*`,
+/// e.g. `deploy/eu/*` (`*` alone matches every id)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdPattern {
+    Exact(String),
+    Prefix(String),
+}
+
+impl IdPattern {
+    pub fn parse(pattern: &str) -> Self {
+        match pattern.strip_suffix('*') {
+            Some(prefix) => IdPattern::Prefix(prefix.to_owned()),
+            None => IdPattern::Exact(pattern.to_owned()),
+        }
+    }
+
+    pub fn matches(&self, unique_id: &str) -> bool {
+        match self {
+            IdPattern::Exact(id) => id == unique_id,
+            IdPattern::Prefix(prefix) => unique_id.starts_with(prefix.as_str()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::ids::{is_internal, IdPath, IdPattern};
+
+    #[test]
+    fn test_id_patterns() {
+        let subtree = IdPattern::parse("deploy/eu/*");
+        assert!(subtree.matches("deploy/eu/payments"));
+        assert!(subtree.matches("deploy/eu/payments/canary"));
+        assert!(!subtree.matches("deploy/eu"));
+        assert!(!subtree.matches("deploy/us/payments"));
+
+        let exact = IdPattern::parse("deploy/eu");
+        assert!(exact.matches("deploy/eu"));
+        assert!(!exact.matches("deploy/eu/payments"));
+
+        assert!(IdPattern::parse("*").matches("anything"));
+    }
+
+    #[test]
+    fn test_id_parsing() {
+        assert_eq!(IdPath::parse("deploy//eu/").unwrap().as_str(), "deploy/eu");
+        assert_eq!(IdPath::parse("/shard-1").unwrap().as_str(), "shard-1");
+        assert_eq!(IdPath::parse(""), Err("missing id"));
+        assert_eq!(IdPath::parse("//"), Err("missing id"));
+        assert_eq!(IdPath::parse("_internal/probe"), Err("reserved id"));
+    }
+
+    #[test]
+    fn test_internal_namespace() {
+        assert!(is_internal("_internal"));
+        assert!(is_internal("_internal/selftest-1"));
+        assert!(!is_internal("_internals/1"));
+        assert!(!is_internal("deploy/_internal"));
+    }
+}
@@ -0,0 +1,69 @@
+//! Recent timeouts of waiting parties, so a party arriving just too late learns its peer gave up
+//! (410 with `peer_recently_timed_out`) instead of waiting in a fresh point for nobody.
+//! Disabled unless `late_arrival_grace_sec` is set.
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+use tokio::time::Instant;
+
+/// When the waiting party of an id timed out, with the end of its grace window. Only the first
+/// late party is told, a retry after it waits as usual
+#[derive(Default)]
+pub struct LateArrivals {
+    /// `late_arrival_grace_sec` setting, zero disables the window
+    grace: Duration,
+    /// Expired entries are purged whenever one is recorded, so they can't pile up
+    entries: Mutex<HashMap<String, (Instant, SystemTime)>>,
+}
+
+impl LateArrivals {
+    pub fn new(grace: Duration) -> Self {
+        Self {
+            grace,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records that the party waiting on `unique_id` timed out just now
+    pub fn timed_out(&self, unique_id: &str) {
+        if self.grace.is_zero() {
+            return;
+        }
+        let now = Instant::now();
+        let mut entries = self.entries.lock();
+        entries.retain(|_, (expires_at, _)| *expires_at > now);
+        entries.insert(unique_id.to_owned(), (now + self.grace, SystemTime::now()));
+    }
+
+    /// Consumes the timeout recorded for `unique_id`
+    ///
+    /// # Returns
+    /// When the waiting party timed out, `None` if not within the grace window
+    pub fn take(&self, unique_id: &str) -> Option<SystemTime> {
+        let (expires_at, timed_out_at) = self.entries.lock().remove(unique_id)?;
+        (expires_at > Instant::now()).then_some(timed_out_at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::late_arrivals::LateArrivals;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_timeouts_are_reported_once_within_grace() {
+        let late_arrivals = LateArrivals::new(Duration::from_millis(50));
+        late_arrivals.timed_out("a");
+        late_arrivals.timed_out("b");
+
+        assert!(late_arrivals.take("a").is_some());
+        assert_eq!(late_arrivals.take("a"), None);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(late_arrivals.take("b"), None);
+
+        let disabled = LateArrivals::default();
+        disabled.timed_out("a");
+        assert_eq!(disabled.take("a"), None);
+    }
+}
@@ -0,0 +1,199 @@
+//! Completions of configured ids signalled to co-located processes, so legacy ones can react
+//! without polling the HTTP API. When parties match on an id of the `[local_notify]` section:
+//! - with `dir`, the file `<dir>/<unique_id>` is (re)written with the `pair_id`, watchers see its
+//!   modification time change. `/` & `%` in ids are percent-encoded, as is a leading `.`
+//! - with `dbus`, `dbus-send` emits the `io.sitetester.SyncPoint.Completed` signal on object path
+//!   `/io/sitetester/SyncPoint` of the session or system bus, with the `unique_id` & `pair_id`
+//!   string arguments
+//!
+//! Both are fire & forget on the background runtime like event hooks, failures are logged at warn
+//! level. Internal ids are never signalled.
+use crate::api::ids::{self, IdPattern};
+use crate::background::Background;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use uuid::Uuid;
+
+/// Object path of the signal
+const DBUS_PATH: &str = "/io/sitetester/SyncPoint";
+/// Interface & member of the signal
+const DBUS_SIGNAL: &str = "io.sitetester.SyncPoint.Completed";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DbusBus {
+    Session,
+    System,
+}
+
+impl DbusBus {
+    fn flag(self) -> &'static str {
+        match self {
+            DbusBus::Session => "--session",
+            DbusBus::System => "--system",
+        }
+    }
+}
+
+/// `[local_notify]` config section
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LocalNotifyConfig {
+    /// Ids signalled on completion, `*` suffixed ones match every id with that prefix
+    pub ids: Vec<String>,
+    /// Directory of the signal files, created if missing
+    pub dir: Option<String>,
+    /// Bus the dbus signal is sent on, none when missing
+    pub dbus: Option<DbusBus>,
+}
+
+impl LocalNotifyConfig {
+    /// Checks that ids are signalled one way or another
+    ///
+    /// # Returns
+    /// * `Ok(())` - No ids, or a `dir` or `dbus` to signal them with
+    /// * `Err(String)` - Describing the missing target otherwise
+    pub fn validate(&self) -> Result<(), String> {
+        if self.ids.iter().any(String::is_empty) {
+            return Err("local_notify.ids can't be empty".to_owned());
+        }
+        if !self.ids.is_empty() && self.dir.is_none() && self.dbus.is_none() {
+            return Err("local_notify.ids need a dir or dbus to be signalled with".to_owned());
+        }
+        Ok(())
+    }
+}
+
+/// Signals completions of the configured ids, see module docs
+#[derive(Debug, Default)]
+pub struct LocalNotify {
+    patterns: Vec<IdPattern>,
+    dir: Option<PathBuf>,
+    dbus: Option<DbusBus>,
+}
+
+impl LocalNotify {
+    pub fn new(config: &LocalNotifyConfig) -> Self {
+        Self {
+            patterns: config.ids.iter().map(|id| IdPattern::parse(id)).collect(),
+            dir: config.dir.as_ref().map(PathBuf::from),
+            dbus: config.dbus,
+        }
+    }
+
+    /// Whether completions of `unique_id` are signalled
+    pub fn wants(&self, unique_id: &str) -> bool {
+        !ids::is_internal(unique_id) && self.patterns.iter().any(|p| p.matches(unique_id))
+    }
+
+    /// Signals the match of `unique_id` on the `background` runtime, if configured for it
+    pub fn fire(&self, unique_id: &str, pair_id: Uuid, background: &Background) {
+        if !self.wants(unique_id) {
+            return;
+        }
+        let (dir, dbus, unique_id) = (self.dir.clone(), self.dbus, unique_id.to_owned());
+        background.spawn(async move {
+            let signalled = tokio::task::spawn_blocking(move || {
+                if let Some(dir) = dir {
+                    if let Err(e) = touch(&dir, &unique_id, pair_id) {
+                        warn!(
+                            "Failed to touch the signal file of unique_id: {} in {}: {}",
+                            unique_id,
+                            dir.display(),
+                            e
+                        );
+                    }
+                }
+                if let Some(bus) = dbus {
+                    if let Err(e) = send_signal(bus, &unique_id, pair_id) {
+                        warn!(
+                            "Failed to send the dbus signal of unique_id: {}: {}",
+                            unique_id, e
+                        );
+                    }
+                }
+                debug!("Signalled the completion of {} locally", unique_id);
+            })
+            .await;
+            if let Err(e) = signalled {
+                warn!("Local notification failed: {}", e);
+            }
+        });
+    }
+}
+
+/// (Re)writes the signal file of `unique_id` in `dir` with `pair_id`
+fn touch(dir: &Path, unique_id: &str, pair_id: Uuid) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join(file_name(unique_id)), format!("{}\n", pair_id))
+}
+
+/// `unique_id` as a single file name, see module docs
+fn file_name(unique_id: &str) -> String {
+    let encoded = unique_id.replace('%', "%25").replace('/', "%2F");
+    match encoded.strip_prefix('.') {
+        Some(rest) => format!("%2E{}", rest),
+        None => encoded,
+    }
+}
+
+/// Emits the completion signal with `dbus-send`
+fn send_signal(bus: DbusBus, unique_id: &str, pair_id: Uuid) -> Result<(), String> {
+    let status = Command::new("dbus-send")
+        .args([
+            bus.flag(),
+            "--type=signal",
+            DBUS_PATH,
+            DBUS_SIGNAL,
+            &format!("string:{}", unique_id),
+            &format!("string:{}", pair_id),
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_err(|e| e.to_string())?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(status.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::local_notify::{file_name, touch, LocalNotify, LocalNotifyConfig};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_signal_files() {
+        assert_eq!(file_name("deploy-gate"), "deploy-gate");
+        assert_eq!(file_name("deploy/eu/100%"), "deploy%2Feu%2F100%25");
+        assert_eq!(file_name(".."), "%2E.");
+
+        let dir = tempfile::tempdir().expect("temp dir");
+        let signals = dir.path().join("signals");
+        let pair_id = Uuid::new_v4();
+        touch(&signals, "deploy/eu", pair_id).expect("touched");
+        let content = std::fs::read_to_string(signals.join("deploy%2Feu")).expect("signal file");
+        assert_eq!(content, format!("{}\n", pair_id));
+    }
+
+    #[test]
+    fn test_only_configured_ids_are_signalled() {
+        let config = LocalNotifyConfig {
+            ids: vec!["deploy-gate".to_owned(), "batch/*".to_owned()],
+            ..LocalNotifyConfig::default()
+        };
+        assert!(config.validate().is_err());
+
+        let local_notify = LocalNotify::new(&config);
+        assert!(local_notify.wants("deploy-gate"));
+        assert!(local_notify.wants("batch/nightly"));
+        assert!(!local_notify.wants("deploy-gate-2"));
+        assert!(!local_notify.wants("_internal/self-test"));
+    }
+}
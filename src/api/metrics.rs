@@ -0,0 +1,402 @@
+//! OpenMetrics exposition (`GET /metrics`) of the service counters. `METRICS` is the single
+//! list of metric names, also used to generate the dashboard & alert templates (see
+//! `observability` module), so they can't drift apart.
+//!
+//! Names get the `metric_prefix` setting (`sync_point_` by default) & every sample the
+//! `metric_labels`, e.g. `{region = "eu", instance = "eu-1"}`, so several environments can be
+//! scraped into one Prometheus without their series colliding.
+use crate::allocator;
+use crate::api::connections::{CloseReason, Protocol};
+use crate::api::file_triggers::TriggerOutcome;
+use crate::api::priorities::Priority;
+use crate::api::readiness;
+use crate::api::shadow::ShadowOutcome;
+use crate::app::App;
+use crate::protocol::ReadinessState;
+use rocket::http::ContentType;
+use rocket::{get, State};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MetricKind {
+    Gauge,
+    Counter,
+}
+
+impl MetricKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            MetricKind::Gauge => "gauge",
+            MetricKind::Counter => "counter",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Metric {
+    /// Name of the metric family, without prefix nor the `_total` suffix of counter samples
+    pub name: &'static str,
+    pub help: &'static str,
+    pub kind: MetricKind,
+    /// Label distinguishing the series, if any
+    pub label: Option<&'static str>,
+}
+
+pub const OPEN_WAIT_POINTS: Metric = Metric {
+    name: "open_wait_points",
+    help: "Open wait points",
+    kind: MetricKind::Gauge,
+    label: None,
+};
+pub const WAITING_PARTIES: Metric = Metric {
+    name: "waiting_parties",
+    help: "First parties waiting for their peer",
+    kind: MetricKind::Gauge,
+    label: None,
+};
+pub const MEMORY_BYTES: Metric = Metric {
+    name: "wait_points_memory_bytes",
+    help: "Approximate memory held by the open wait points",
+    kind: MetricKind::Gauge,
+    label: None,
+};
+pub const ALLOCATED_BYTES: Metric = Metric {
+    name: "allocator_allocated_bytes",
+    help: "Bytes allocated by the application, as reported by the allocator",
+    kind: MetricKind::Gauge,
+    label: Some("allocator"),
+};
+pub const RESIDENT_BYTES: Metric = Metric {
+    name: "allocator_resident_bytes",
+    help: "Bytes of physical memory mapped by the allocator",
+    kind: MetricKind::Gauge,
+    label: Some("allocator"),
+};
+pub const OPEN_BY_PRIORITY: Metric = Metric {
+    name: "open_wait_points_by_priority",
+    help: "Open wait points by the priority of their creator",
+    kind: MetricKind::Gauge,
+    label: Some("priority"),
+};
+pub const WAITS_SHED: Metric = Metric {
+    name: "waits_shed",
+    help: "Parties rejected for lack of wait point capacity",
+    kind: MetricKind::Counter,
+    label: Some("priority"),
+};
+pub const MAINTENANCE: Metric = Metric {
+    name: "maintenance",
+    help: "1 while in maintenance mode",
+    kind: MetricKind::Gauge,
+    label: None,
+};
+pub const READINESS: Metric = Metric {
+    name: "readiness_state",
+    help: "1 for the current readiness state (starting, ready, lame-duck or unhealthy)",
+    kind: MetricKind::Gauge,
+    label: Some("state"),
+};
+pub const WAITS_TIMED_OUT: Metric = Metric {
+    name: "waits_timed_out",
+    help: "First parties whose peer didn't arrive within the timeout",
+    kind: MetricKind::Counter,
+    label: Some("prefix"),
+};
+pub const WAITS_CANCELLED: Metric = Metric {
+    name: "waits_cancelled",
+    help: "First parties of batch waits whose request was cancelled while waiting",
+    kind: MetricKind::Counter,
+    label: Some("prefix"),
+};
+pub const REQUESTS_CLOSED_EARLY: Metric = Metric {
+    name: "requests_closed_early",
+    help: "Requests ended before their response completed (deadline, body_timeout or disconnected)",
+    kind: MetricKind::Counter,
+    label: Some("reason"),
+};
+pub const RENDEZVOUS_REQUESTS: Metric = Metric {
+    name: "rendezvous_requests",
+    help: "Rendezvous requests by HTTP version (http1 or http2)",
+    kind: MetricKind::Counter,
+    label: Some("protocol"),
+};
+pub const RENDEZVOUS_IN_PROGRESS: Metric = Metric {
+    name: "rendezvous_requests_in_progress",
+    help: "Rendezvous requests waiting for their answer, by HTTP version",
+    kind: MetricKind::Gauge,
+    label: Some("protocol"),
+};
+pub const FILE_TRIGGERS_WAITING: Metric = Metric {
+    name: "file_triggers_waiting",
+    help: "Parties of file triggers waiting for their peer",
+    kind: MetricKind::Gauge,
+    label: None,
+};
+pub const FILE_TRIGGER_PARTIES: Metric = Metric {
+    name: "file_trigger_parties",
+    help: "Parties joined by file triggers, by how they ended",
+    kind: MetricKind::Counter,
+    label: Some("outcome"),
+};
+pub const SHADOW_MIRRORS: Metric = Metric {
+    name: "shadow_mirrors",
+    help: "Requests mirrored to the shadow instance, by how its answer compared",
+    kind: MetricKind::Counter,
+    label: Some("outcome"),
+};
+
+impl Metric {
+    /// Name of the metric family with `prefix`, e.g. `sync_point_waits_shed`
+    pub fn family(&self, prefix: &str) -> String {
+        format!("{}{}", prefix, self.name)
+    }
+
+    /// Name of its samples with `prefix`, ending with `_total` for counters
+    pub fn sample(&self, prefix: &str) -> String {
+        match self.kind {
+            MetricKind::Gauge => self.family(prefix),
+            MetricKind::Counter => format!("{}{}_total", prefix, self.name),
+        }
+    }
+}
+
+/// All exposed metrics, in exposition order
+pub const METRICS: [&Metric; 17] = [
+    &OPEN_WAIT_POINTS,
+    &WAITING_PARTIES,
+    &MEMORY_BYTES,
+    &ALLOCATED_BYTES,
+    &RESIDENT_BYTES,
+    &OPEN_BY_PRIORITY,
+    &WAITS_SHED,
+    &MAINTENANCE,
+    &READINESS,
+    &WAITS_TIMED_OUT,
+    &WAITS_CANCELLED,
+    &REQUESTS_CLOSED_EARLY,
+    &RENDEZVOUS_REQUESTS,
+    &RENDEZVOUS_IN_PROGRESS,
+    &FILE_TRIGGERS_WAITING,
+    &FILE_TRIGGER_PARTIES,
+    &SHADOW_MIRRORS,
+];
+
+/// Checks the `metric_prefix` & `metric_labels` settings against the OpenMetrics naming rules
+///
+/// # Returns
+/// * `Ok(())` - If both are valid
+/// * `Err(String)` - Describing the first invalid value
+pub fn validate(prefix: &str, labels: &BTreeMap<String, String>) -> Result<(), String> {
+    let valid_prefix = prefix
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':');
+    if !valid_prefix || prefix.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(format!(
+            "metric_prefix must only contain letters, digits, '_' & ':', not start with a digit: {}",
+            prefix
+        ));
+    }
+    for name in labels.keys() {
+        let valid_name = !name.is_empty()
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && !name.starts_with("__");
+        if !valid_name {
+            return Err(format!("Invalid label name in metric_labels: {}", name));
+        }
+        if METRICS
+            .iter()
+            .any(|metric| metric.label == Some(name.as_str()))
+        {
+            return Err(format!("metric_labels can't redefine label {}", name));
+        }
+    }
+    Ok(())
+}
+
+/// Exposes the counters in the OpenMetrics text format
+#[get("/metrics")]
+pub fn metrics(state: &State<App>) -> (ContentType, String) {
+    let stats = state.sync_service.stats();
+    let outcomes = state.sync_service.outcome_stats();
+    let prefix = state.metric_prefix();
+
+    let mut out = Exposition::new(&prefix, &state.metric_labels());
+    out.header(&OPEN_WAIT_POINTS);
+    out.sample(&OPEN_WAIT_POINTS, None, stats.open_wait_points);
+    out.header(&WAITING_PARTIES);
+    out.sample(&WAITING_PARTIES, None, stats.waiting_parties);
+    out.header(&MEMORY_BYTES);
+    out.sample(&MEMORY_BYTES, None, stats.memory_bytes);
+    // No samples for what the allocator doesn't report, e.g. anything with the system one
+    let allocator = allocator::stats();
+    out.header(&ALLOCATED_BYTES);
+    if let Some(bytes) = allocator.allocated_bytes {
+        out.sample(&ALLOCATED_BYTES, Some(&allocator.allocator), bytes);
+    }
+    out.header(&RESIDENT_BYTES);
+    if let Some(bytes) = allocator.resident_bytes {
+        out.sample(&RESIDENT_BYTES, Some(&allocator.allocator), bytes);
+    }
+    let shed = state.sync_service.priorities.shed_counts();
+    out.header(&OPEN_BY_PRIORITY);
+    for priority in Priority::ALL {
+        let open = stats.open_by_priority.get(priority);
+        out.sample(&OPEN_BY_PRIORITY, Some(priority.as_str()), open);
+    }
+    out.header(&WAITS_SHED);
+    for priority in Priority::ALL {
+        let count = shed.get(priority);
+        out.sample(&WAITS_SHED, Some(priority.as_str()), count);
+    }
+    out.header(&MAINTENANCE);
+    out.sample(&MAINTENANCE, None, state.maintenance().is_some() as u8);
+    let current = readiness::check(state).state;
+    out.header(&READINESS);
+    for readiness in ReadinessState::ALL {
+        let value = (readiness == current) as u8;
+        out.sample(&READINESS, Some(readiness.as_str()), value);
+    }
+
+    out.header(&WAITS_TIMED_OUT);
+    for (prefix, counts) in &outcomes.by_prefix {
+        out.sample(&WAITS_TIMED_OUT, Some(prefix), counts.timed_out);
+    }
+    out.header(&WAITS_CANCELLED);
+    for (prefix, counts) in &outcomes.by_prefix {
+        out.sample(&WAITS_CANCELLED, Some(prefix), counts.cancelled);
+    }
+    out.header(&REQUESTS_CLOSED_EARLY);
+    for reason in CloseReason::ALL {
+        let count = state.closed_early.count(reason);
+        out.sample(&REQUESTS_CLOSED_EARLY, Some(reason.as_str()), count);
+    }
+    out.header(&RENDEZVOUS_REQUESTS);
+    for protocol in Protocol::ALL {
+        let count = state.protocols.requests(protocol);
+        out.sample(&RENDEZVOUS_REQUESTS, Some(protocol.as_str()), count);
+    }
+    out.header(&RENDEZVOUS_IN_PROGRESS);
+    for protocol in Protocol::ALL {
+        let count = state.protocols.in_progress(protocol);
+        out.sample(&RENDEZVOUS_IN_PROGRESS, Some(protocol.as_str()), count);
+    }
+    out.header(&FILE_TRIGGERS_WAITING);
+    out.sample(&FILE_TRIGGERS_WAITING, None, state.file_triggers.waiting());
+    out.header(&FILE_TRIGGER_PARTIES);
+    for outcome in TriggerOutcome::ALL {
+        let count = state.file_triggers.count(outcome);
+        out.sample(&FILE_TRIGGER_PARTIES, Some(outcome.as_str()), count);
+    }
+    out.header(&SHADOW_MIRRORS);
+    for outcome in ShadowOutcome::ALL {
+        let count = state.shadow.count(outcome);
+        out.sample(&SHADOW_MIRRORS, Some(outcome.as_str()), count);
+    }
+
+    (
+        ContentType::new("application", "openmetrics-text")
+            .with_params([("version", "1.0.0"), ("charset", "utf-8")]),
+        out.finish(),
+    )
+}
+
+/// Exposition being written, with the prefix & constant labels applied to every metric
+struct Exposition<'a> {
+    body: String,
+    prefix: &'a str,
+    /// Constant labels, already formatted, e.g. `region="eu",instance="eu-1"`
+    labels: String,
+}
+
+impl<'a> Exposition<'a> {
+    fn new(prefix: &'a str, labels: &BTreeMap<String, String>) -> Self {
+        let labels = labels
+            .iter()
+            .map(|(name, value)| format!("{}=\"{}\"", name, escape_label(value)))
+            .collect::<Vec<_>>()
+            .join(",");
+        Self {
+            body: String::new(),
+            prefix,
+            labels,
+        }
+    }
+
+    fn header(&mut self, metric: &Metric) {
+        let family = metric.family(self.prefix);
+        let _ = writeln!(self.body, "# TYPE {} {}", family, metric.kind.as_str());
+        let _ = writeln!(self.body, "# HELP {} {}", family, escape_label(metric.help));
+    }
+
+    /// Writes a sample, `label_value` is the value of `metric.label`
+    fn sample(
+        &mut self,
+        metric: &Metric,
+        label_value: Option<&str>,
+        value: impl std::fmt::Display,
+    ) {
+        let mut labels = self.labels.clone();
+        if let (Some(label), Some(label_value)) = (metric.label, label_value) {
+            if !labels.is_empty() {
+                labels.push(',');
+            }
+            let _ = write!(labels, "{}=\"{}\"", label, escape_label(label_value));
+        }
+        let name = metric.sample(self.prefix);
+        let _ = if labels.is_empty() {
+            writeln!(self.body, "{} {}", name, value)
+        } else {
+            writeln!(self.body, "{}{{{}}} {}", name, labels, value)
+        };
+    }
+
+    /// The exposition, terminated as OpenMetrics requires
+    fn finish(mut self) -> String {
+        self.body.push_str("# EOF\n");
+        self.body
+    }
+}
+
+/// Escapes a label value (or help text) as required by the text format
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::metrics::{escape_label, validate, Exposition, WAITS_SHED};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+
+    #[test]
+    fn test_prefix_and_constant_labels() {
+        let labels = BTreeMap::from([
+            ("region".to_owned(), "eu".to_owned()),
+            ("instance".to_owned(), "eu-1".to_owned()),
+        ]);
+        let mut out = Exposition::new("staging_", &labels);
+        out.header(&WAITS_SHED);
+        out.sample(&WAITS_SHED, Some("low"), 3);
+        assert_eq!(
+            out.finish(),
+            "# TYPE staging_waits_shed counter\n\
+             # HELP staging_waits_shed Parties rejected for lack of wait point capacity\n\
+             staging_waits_shed_total{instance=\"eu-1\",region=\"eu\",priority=\"low\"} 3\n\
+             # EOF\n"
+        );
+
+        assert!(validate("", &labels).is_ok());
+        assert!(validate("1st_", &BTreeMap::new()).is_err());
+        let priority = BTreeMap::from([("priority".to_owned(), "high".to_owned())]);
+        assert!(validate("sync_point_", &priority).is_err());
+    }
+}
@@ -1,4 +1,51 @@
 // Exposes the relevant modules
+pub mod acl;
+#[cfg(feature = "admin")]
+pub mod admin;
+pub mod anomaly;
+pub mod batch;
+pub mod body_limits;
+pub mod catchers;
+pub mod clock;
+pub mod completions;
+pub mod connections;
+#[cfg(feature = "cluster")]
+pub mod cluster;
+pub mod deadline;
+pub mod dev;
+#[cfg(feature = "hooks")]
+pub mod digests;
+pub mod etag;
+pub mod file_triggers;
+#[cfg(feature = "hooks")]
+pub mod hooks;
+pub mod ids;
+pub mod late_arrivals;
+pub mod local_notify;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod modes;
+pub mod ndjson;
+#[cfg(feature = "admin")]
+pub mod observability;
+pub mod observers;
+pub mod origins;
+pub mod outcomes;
+pub mod pagination;
+pub mod pending;
+pub mod priorities;
+pub mod quotas;
+pub mod readiness;
+#[cfg(feature = "receipts")]
+pub mod receipts;
+pub mod registry;
+pub mod reservations;
 pub mod response;
 pub mod routes;
+#[cfg(feature = "admin")]
+pub mod self_test;
+pub mod shadow;
+pub mod shutdown;
+pub mod stats_cache;
 pub mod sync_service;
+pub mod templates;
@@ -0,0 +1,114 @@
+//! Matching strategies, picked per id by the `[modes]` config section, e.g.
+//! ```toml
+//! [modes]
+//! "barrier-*" = { mode = "n-party", count = 3 }
+//! "gate-*" = { mode = "broadcast" }
+//! ```
+//! Ids matching no pattern pair up 2 parties as always.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// How parties meeting on an id are matched
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "kebab-case")]
+pub enum Mode {
+    /// The first party waits for a second one, a third is rejected (409)
+    #[default]
+    Pair,
+    /// Barrier: parties wait until `count` of them arrived, then all are released together
+    NParty { count: usize },
+    /// Gate: any number of parties wait until one joins with `?release=true`
+    Broadcast,
+}
+
+/// `[modes]` config section, id patterns to modes. `"barrier-*"` matches ids starting with
+/// `barrier-`, a pattern without `*` only the id itself. The most specific pattern wins: the exact
+/// id, then the longest prefix.
+///
+/// Config keys are lowercased, so patterns only match lowercase ids.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ModeRoutes(BTreeMap<String, Mode>);
+
+impl ModeRoutes {
+    /// Checks the patterns & modes
+    ///
+    /// # Returns
+    /// * `Ok(())` - All of them are valid
+    /// * `Err(String)` - Describing the first invalid one
+    pub fn validate(&self) -> Result<(), String> {
+        for (pattern, mode) in &self.0 {
+            let prefix = pattern.strip_suffix('*').unwrap_or(pattern);
+            if prefix.contains('*') {
+                return Err(format!(
+                    "modes: '{}' may only have a `*` at the end",
+                    pattern
+                ));
+            }
+            if let Mode::NParty { count } = mode {
+                if *count < 2 {
+                    return Err(format!("modes: '{}' needs a count of at least 2", pattern));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Mode of `unique_id`, `Mode::Pair` unless a pattern matches
+    pub fn resolve(&self, unique_id: &str) -> Mode {
+        if let Some(mode) = self.0.get(unique_id) {
+            return *mode;
+        }
+        self.0
+            .iter()
+            .filter_map(|(pattern, mode)| {
+                let prefix = pattern.strip_suffix('*')?;
+                unique_id
+                    .starts_with(prefix)
+                    .then_some((prefix.len(), *mode))
+            })
+            .max_by_key(|(len, _)| *len)
+            .map_or(Mode::Pair, |(_, mode)| mode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::modes::{Mode, ModeRoutes};
+
+    fn routes(entries: &[(&str, Mode)]) -> ModeRoutes {
+        ModeRoutes(
+            entries
+                .iter()
+                .map(|(pattern, mode)| (pattern.to_string(), *mode))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_resolve_most_specific() {
+        let routes = routes(&[
+            ("barrier-*", Mode::NParty { count: 3 }),
+            ("barrier-big-*", Mode::NParty { count: 10 }),
+            ("barrier-big-1", Mode::Pair),
+            ("gate-*", Mode::Broadcast),
+        ]);
+
+        assert_eq!(routes.resolve("barrier-1"), Mode::NParty { count: 3 });
+        assert_eq!(routes.resolve("barrier-big-2"), Mode::NParty { count: 10 });
+        assert_eq!(routes.resolve("barrier-big-1"), Mode::Pair);
+        assert_eq!(routes.resolve("gate-deploy"), Mode::Broadcast);
+        assert_eq!(routes.resolve("123"), Mode::Pair);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(routes(&[("a-*", Mode::NParty { count: 2 })])
+            .validate()
+            .is_ok());
+        assert!(routes(&[("a-*-b", Mode::Broadcast)]).validate().is_err());
+        assert!(routes(&[("a-*", Mode::NParty { count: 1 })])
+            .validate()
+            .is_err());
+    }
+}
@@ -0,0 +1,124 @@
+//! NDJSON (`application/x-ndjson`) responses for endpoints returning several results over time,
+//! one JSON document per line, so clients can process each result as soon as it's written.
+//!
+//! Streams dropped before their end, the client having gone away, count as requests closed early.
+use crate::api::connections::{CloseReason, ClosedEarly};
+use crate::app::App;
+use rocket::futures::stream::{Stream, StreamExt};
+use rocket::http::ContentType;
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::stream::TextStream;
+use rocket::response::{self, Responder};
+use rocket::Request;
+use serde::Serialize;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// `application/x-ndjson`
+pub fn content_type() -> ContentType {
+    ContentType::new("application", "x-ndjson")
+}
+
+/// Streams each item of `S` as a line of JSON
+pub struct Ndjson<S>(pub S);
+
+impl<'r, S> Responder<'r, 'r> for Ndjson<S>
+where
+    S: Stream + Send + 'r,
+    S::Item: Serialize,
+{
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'r> {
+        let closed_early = request
+            .rocket()
+            .state::<App>()
+            .map(|app| app.closed_early.clone());
+        let tracked = Tracked {
+            inner: Box::pin(self.0),
+            ended: false,
+            closed_early,
+        };
+        let lines = tracked.map(|item| {
+            let mut line = serde_json::to_string(&item).unwrap_or_default();
+            line.push('\n');
+            line
+        });
+        let mut response = TextStream(lines).respond_to(request)?;
+        response.set_header(content_type());
+        Ok(response)
+    }
+}
+
+/// Records `CloseReason::Disconnected` when dropped before the end of `inner`
+struct Tracked<S> {
+    inner: Pin<Box<S>>,
+    ended: bool,
+    closed_early: Option<Arc<ClosedEarly>>,
+}
+
+impl<S: Stream> Stream for Tracked<S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        let polled = self.inner.as_mut().poll_next(cx);
+        if let Poll::Ready(None) = polled {
+            self.ended = true;
+        }
+        polled
+    }
+}
+
+impl<S> Drop for Tracked<S> {
+    fn drop(&mut self) {
+        if let Some(closed_early) = self.closed_early.as_ref().filter(|_| !self.ended) {
+            closed_early.record(CloseReason::Disconnected);
+        }
+    }
+}
+
+/// Whether the client asked for NDJSON via `Accept`, for endpoints which otherwise respond with a
+/// single JSON document
+pub struct AcceptNdjson(pub bool);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AcceptNdjson {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let ndjson = content_type();
+        let accepted = request.accept().is_some_and(|accept| {
+            accept
+                .iter()
+                .any(|accepted| accepted.media_type() == ndjson.media_type())
+        });
+        Outcome::Success(AcceptNdjson(accepted))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::connections::{CloseReason, ClosedEarly};
+    use crate::api::ndjson::Tracked;
+    use rocket::futures::stream::{self, StreamExt};
+    use std::sync::Arc;
+
+    #[rocket::async_test]
+    async fn test_streams_dropped_early_are_counted() {
+        let closed_early = Arc::new(ClosedEarly::default());
+        let tracked = |items: Vec<u32>| Tracked {
+            inner: Box::pin(stream::iter(items)),
+            ended: false,
+            closed_early: Some(closed_early.clone()),
+        };
+
+        let mut complete = tracked(vec![1]);
+        while complete.next().await.is_some() {}
+        drop(complete);
+        assert_eq!(closed_early.count(CloseReason::Disconnected), 0);
+
+        let mut cut = tracked(vec![1, 2]);
+        assert_eq!(cut.next().await, Some(1));
+        drop(cut);
+        assert_eq!(closed_early.count(CloseReason::Disconnected), 1);
+    }
+}
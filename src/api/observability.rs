@@ -0,0 +1,183 @@
+//! Grafana dashboard & Prometheus alert rules for the metrics of `GET /metrics`, served by
+//! `GET /admin/observability/templates`. Generated from `metrics::METRICS` and the settings in
+//! effect (e.g. alert thresholds follow `max_wait_points`, names `metric_prefix`), so they match
+//! this build & deployment.
+use crate::api::metrics::{
+    Metric, MetricKind, MAINTENANCE, MEMORY_BYTES, METRICS, OPEN_WAIT_POINTS, WAITS_CANCELLED,
+    WAITS_TIMED_OUT,
+};
+use crate::settings::Settings;
+use serde_json::{json, Value};
+
+/// Share of a limit at which the capacity alerts fire
+const CAPACITY_ALERT_RATIO: f64 = 0.9;
+
+/// Both templates, `{"grafana_dashboard": {...}, "prometheus_alert_rules": {...}}`
+pub fn templates(settings: &Settings) -> Value {
+    json!({
+        "grafana_dashboard": dashboard(&settings.metric_prefix),
+        "prometheus_alert_rules": alert_rules(settings),
+    })
+}
+
+/// PromQL plotting a metric: gauges as is, counters as per-second rate by label
+fn query(metric: &Metric, prefix: &str) -> String {
+    let name = metric.sample(prefix);
+    match (metric.kind, metric.label) {
+        (MetricKind::Gauge, _) => name,
+        (MetricKind::Counter, Some(label)) => format!("sum by ({}) (rate({}[5m]))", label, name),
+        (MetricKind::Counter, None) => format!("rate({}[5m])", name),
+    }
+}
+
+/// Dashboard JSON to import into Grafana, one time series panel per metric.
+/// Queries go to a `${datasource}` Prometheus variable, picked on import
+fn dashboard(prefix: &str) -> Value {
+    let panels: Vec<Value> = METRICS
+        .iter()
+        .enumerate()
+        .map(|(index, metric)| {
+            let legend = metric.label.map_or(String::new(), |label| format!("{{{{{}}}}}", label));
+            json!({
+                "id": index + 1,
+                "type": "timeseries",
+                "title": metric.help,
+                "description": metric.sample(prefix),
+                "datasource": {"type": "prometheus", "uid": "${datasource}"},
+                // 2 panels per row, 8 high
+                "gridPos": {"h": 8, "w": 12, "x": (index % 2) * 12, "y": (index / 2) * 8},
+                "targets": [{"refId": "A", "expr": query(metric, prefix), "legendFormat": legend}]
+            })
+        })
+        .collect();
+
+    json!({
+        "title": "Sync Point",
+        "uid": "sync-point",
+        "tags": ["sync-point"],
+        "timezone": "utc",
+        "schemaVersion": 39,
+        "time": {"from": "now-6h", "to": "now"},
+        "templating": {
+            "list": [{"name": "datasource", "type": "datasource", "query": "prometheus"}]
+        },
+        "panels": panels
+    })
+}
+
+/// Prometheus rule file content. Rule files are YAML, which JSON is a subset of, so this can be
+/// saved as is (e.g. `sync-point.rules.json`) & listed in `rule_files`
+fn alert_rules(settings: &Settings) -> Value {
+    let prefix = settings.metric_prefix.as_str();
+    let mut rules = vec![
+        json!({
+            "alert": "SyncPointTimeoutsHigh",
+            "expr": format!("sum(rate({}[10m])) > 1", WAITS_TIMED_OUT.sample(prefix)),
+            "for": "10m",
+            "labels": {"severity": "warning"},
+            "annotations": {
+                "summary": "More than 1 wait/s times out without its peer, peers may be missing"
+            }
+        }),
+        json!({
+            "alert": "SyncPointBatchWaitsCancelled",
+            "expr": format!(
+                "sum(rate({cancelled}[10m])) > sum(rate({timed_out}[10m]))",
+                cancelled = WAITS_CANCELLED.sample(prefix),
+                timed_out = WAITS_TIMED_OUT.sample(prefix)
+            ),
+            "for": "30m",
+            "labels": {"severity": "info"},
+            "annotations": {
+                "summary": "More batch waits are cancelled than time out, client timeouts may be shorter than the server's"
+            }
+        }),
+        json!({
+            "alert": "SyncPointMaintenanceTooLong",
+            "expr": format!("{} == 1", MAINTENANCE.sample(prefix)),
+            "for": "1h",
+            "labels": {"severity": "warning"},
+            "annotations": {"summary": "Maintenance mode has been on for over an hour"}
+        }),
+    ];
+
+    if settings.max_wait_points > 0 {
+        rules.push(capacity_alert(
+            "SyncPointWaitPointsNearLimit",
+            OPEN_WAIT_POINTS.sample(prefix),
+            settings.max_wait_points,
+            "max_wait_points",
+        ));
+    }
+    if settings.max_memory_bytes > 0 {
+        rules.push(capacity_alert(
+            "SyncPointMemoryNearBudget",
+            MEMORY_BYTES.sample(prefix),
+            settings.max_memory_bytes,
+            "max_memory_bytes",
+        ));
+    }
+
+    json!({"groups": [{"name": "sync-point", "rules": rules}]})
+}
+
+/// Fires when `metric` (a sample name) reaches `CAPACITY_ALERT_RATIO` of `limit`, after which requests get 503
+fn capacity_alert(name: &str, metric: String, limit: usize, setting: &str) -> Value {
+    json!({
+        "alert": name,
+        "expr": format!("{} >= {}", metric, (limit as f64 * CAPACITY_ALERT_RATIO).floor()),
+        "for": "5m",
+        "labels": {"severity": "critical"},
+        "annotations": {
+            "summary": format!(
+                "Over {}% of {} ({}) is used, new wait points get 503 at the limit",
+                CAPACITY_ALERT_RATIO * 100.0,
+                setting,
+                limit
+            )
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::metrics::METRICS;
+    use crate::api::observability::templates;
+    use crate::settings::Settings;
+
+    #[test]
+    fn test_dashboard_covers_every_metric() {
+        let templates = templates(&Settings::load(None).unwrap());
+        let panels = templates["grafana_dashboard"]["panels"].as_array().unwrap();
+
+        for metric in METRICS {
+            let name = metric.sample(Settings::DEFAULT_METRIC_PREFIX);
+            assert!(
+                panels.iter().any(|panel| panel["description"] == name),
+                "{} has no panel",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_capacity_alerts_follow_settings() {
+        let mut settings = Settings::load(None).unwrap();
+        let alerts = |settings: &Settings| -> Vec<String> {
+            templates(settings)["prometheus_alert_rules"]["groups"][0]["rules"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|rule| rule["expr"].as_str().unwrap().to_owned())
+                .collect()
+        };
+        assert!(!alerts(&settings)
+            .iter()
+            .any(|expr| expr.starts_with("sync_point_open_wait_points")));
+
+        settings.max_wait_points = 100;
+        assert!(alerts(&settings).contains(&"sync_point_open_wait_points >= 90".to_owned()));
+        settings.metric_prefix = "staging_".to_owned();
+        assert!(alerts(&settings).contains(&"staging_open_wait_points >= 90".to_owned()));
+    }
+}
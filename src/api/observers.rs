@@ -0,0 +1,97 @@
+//! Observation of wait points without taking part in their rendezvous, e.g. for release
+//! dashboards: `GET /observe/<pattern>` streams Server-Sent Events of what happens at the ids
+//! matching the pattern (see `ids::IdPattern`), `deploy/eu/*` for every id under `deploy/eu/`.
+//! Each event is named after its kind (`waiting`, `matched` or `timeout`), its data is a JSON
+//! `Observation`.
+//!
+//! Events are broadcast & never wait for observers: one falling more than `CAPACITY` events
+//! behind gets a `lagged` event with the number it missed instead. Nothing is kept for observers
+//! subscribing later, `GET /status/<unique_id>` tells the current state.
+use crate::api::ids::{self, IdPath, IdPattern};
+use crate::app::App;
+use crate::log_file::rfc3339;
+use crate::protocol::{Observation, ObservedEvent};
+use log::debug;
+use rocket::response::stream::{Event, EventStream};
+use rocket::tokio::select;
+use rocket::{get, Shutdown, State};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
+
+/// Events buffered per observer
+const CAPACITY: usize = 1024;
+
+/// Fans events out to the observers
+#[derive(Debug)]
+pub struct Observers {
+    sender: broadcast::Sender<Arc<Observation>>,
+}
+
+impl Default for Observers {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CAPACITY).0,
+        }
+    }
+}
+
+impl Observers {
+    /// Sends the event to the current observers, unless on an internal id
+    pub fn publish(
+        &self,
+        event: ObservedEvent,
+        unique_id: &str,
+        pair_id: Option<Uuid>,
+        parties: usize,
+    ) {
+        // Nobody observing, the usual case, costs no allocation
+        if self.sender.receiver_count() == 0 || ids::is_internal(unique_id) {
+            return;
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let observation = Observation {
+            event,
+            unique_id: unique_id.to_owned(),
+            pair_id: pair_id.map(|id| id.to_string()),
+            parties,
+            time: rfc3339(now.as_secs(), now.subsec_millis()),
+        };
+        // Fails only when the last observer left meanwhile
+        let _ = self.sender.send(Arc::new(observation));
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<Arc<Observation>> {
+        self.sender.subscribe()
+    }
+}
+
+/// Streams the events of the ids matching `pattern` until the client disconnects or the server
+/// shuts down, see module docs
+#[get("/observe/<pattern..>")]
+pub fn observe(pattern: IdPath, state: &State<App>, mut shutdown: Shutdown) -> EventStream![] {
+    debug!("Observer subscribed to: {}", pattern.as_str());
+    let pattern = IdPattern::parse(&pattern);
+    let mut receiver = state.sync_service.observers.subscribe();
+    EventStream! {
+        loop {
+            let received = select! {
+                received = receiver.recv() => received,
+                _ = &mut shutdown => break,
+            };
+            match received {
+                Ok(observation) if pattern.matches(&observation.unique_id) => {
+                    yield Event::json(&*observation).event(observation.event.as_str());
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(missed)) => {
+                    yield Event::data(missed.to_string()).event("lagged");
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Where the parties of a wait point come from: client address, `User-Agent` & API key, recorded
+//! as they join. Operators see them in admin listings & the audit log of the match.
+//!
+//! With `share_peer_origin`, the parties of a pair also get a redacted form of their peer's as
+//! `peer` field of their success response, answering "who exactly did I just pair with?":
+//! - the /24 (IPv4) or /48 (IPv6) network of its address
+//! - the first product of its `User-Agent`, e.g. `curl/8.4.0`
+//! - the last 4 characters of its API key
+//!
+//! API keys are never kept whole, operators only get their last 4 characters too.
+use crate::api::acl::Access;
+use crate::protocol::PartyOrigin;
+use std::net::IpAddr;
+
+/// Longest `User-Agent` kept, in characters
+const MAX_USER_AGENT_CHARS: usize = 256;
+
+/// Origin of a party, as recorded on its wait point
+#[derive(Debug, Clone)]
+pub struct Origin {
+    ip: Option<IpAddr>,
+    user_agent: Option<Box<str>>,
+    /// Last 4 characters of the API key, see `key_hint`
+    api_key: Option<Box<str>>,
+}
+
+impl Origin {
+    pub fn of(access: &Access) -> Self {
+        Self {
+            ip: access.ip,
+            user_agent: access
+                .user_agent
+                .as_deref()
+                .map(|user_agent| user_agent.chars().take(MAX_USER_AGENT_CHARS).collect()),
+            api_key: access.api_key.as_deref().map(|key| key_hint(key).into()),
+        }
+    }
+
+    /// Everything recorded, for operators
+    pub fn full(&self) -> PartyOrigin {
+        PartyOrigin {
+            ip: self.ip.map(|ip| ip.to_string()),
+            user_agent: self.user_agent.as_deref().map(str::to_owned),
+            api_key: self.api_key.as_deref().map(str::to_owned),
+        }
+    }
+
+    /// Redacted as described in the module docs, for the peer
+    pub fn redacted(&self) -> PartyOrigin {
+        PartyOrigin {
+            ip: self.ip.map(network),
+            user_agent: self.user_agent.as_deref().and_then(product),
+            api_key: self.api_key.as_deref().map(str::to_owned),
+        }
+    }
+}
+
+/// `…` followed by the last 4 characters of `key`, only `…` for keys shorter than 8 characters
+fn key_hint(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() < 8 {
+        return "…".to_owned();
+    }
+    let last: String = chars[chars.len() - 4..].iter().collect();
+    format!("…{}", last)
+}
+
+/// The /24 or /48 network of `ip`, e.g. `10.1.2.0/24`
+fn network(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            format!("{}.{}.{}.0/24", a, b, c)
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            format!("{:x}:{:x}:{:x}::/48", segments[0], segments[1], segments[2])
+        }
+    }
+}
+
+/// First product token of a `User-Agent`, e.g. `curl/8.4.0` of `curl/8.4.0 (x86_64-pc-linux-gnu)`
+fn product(user_agent: &str) -> Option<String> {
+    user_agent.split_whitespace().next().map(str::to_owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::acl::Access;
+    use crate::api::origins::Origin;
+
+    #[test]
+    fn test_redacted_origin() {
+        let access = Access {
+            ip: Some("10.1.2.3".parse().unwrap()),
+            api_key: Some("payments-team-a".to_owned()),
+            user_agent: Some("curl/8.4.0 (x86_64-pc-linux-gnu)".to_owned()),
+            ..Default::default()
+        };
+        let origin = Origin::of(&access);
+
+        let full = origin.full();
+        assert_eq!(full.ip.as_deref(), Some("10.1.2.3"));
+        assert_eq!(
+            full.user_agent.as_deref(),
+            Some("curl/8.4.0 (x86_64-pc-linux-gnu)")
+        );
+        assert_eq!(full.api_key.as_deref(), Some("…am-a"));
+
+        let redacted = origin.redacted();
+        assert_eq!(redacted.ip.as_deref(), Some("10.1.2.0/24"));
+        assert_eq!(redacted.user_agent.as_deref(), Some("curl/8.4.0"));
+        assert_eq!(redacted.api_key.as_deref(), Some("…am-a"));
+
+        let access = Access {
+            ip: Some("2001:db8:1:2::7".parse().unwrap()),
+            api_key: Some("short".to_owned()),
+            ..Default::default()
+        };
+        let redacted = Origin::of(&access).redacted();
+        assert_eq!(redacted.ip.as_deref(), Some("2001:db8:1::/48"));
+        assert_eq!(redacted.api_key.as_deref(), Some("…"));
+        assert_eq!(redacted.user_agent, None);
+    }
+}
@@ -0,0 +1,131 @@
+//! Counts how unmatched first parties ended: timed out (the peer never came) or cancelled (the
+//! request was dropped while waiting), per id prefix. Many cancelled batch waits point at impatient
+//! client timeouts, many timeouts at peers which are genuinely missing.
+//!
+//! Only `/batch/wait` waits get cancelled, when their client disconnects (or on shutdown). Rocket
+//! 0.5 runs the handler of a single sync request to completion even after its client went away &
+//! doesn't expose the connection to probe it, so single waits always count as timeouts.
+//! Internal ids (see `ids` module) aren't counted.
+use crate::api::ids;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Distinct prefixes tracked, further ones are counted under `OTHER_PREFIX` to bound memory
+pub const MAX_PREFIXES: usize = 1000;
+pub const OTHER_PREFIX: &str = "(other)";
+
+/// Ids are grouped by the part before the first of these, e.g. `shard-17` -> `shard`
+const PREFIX_DELIMITERS: [char; 5] = ['-', '_', ':', '.', '/'];
+
+#[derive(Debug, Default, Clone, Copy, Serialize, PartialEq)]
+pub struct OutcomeCounts {
+    /// The timeout elapsed without a second party
+    pub timed_out: u64,
+    /// The first party's request was cancelled before the timeout, only batch waits are
+    pub cancelled: u64,
+}
+
+/// Response of `GET /stats/outcomes`
+#[derive(Debug, Clone, Serialize)]
+pub struct OutcomeStats {
+    pub total: OutcomeCounts,
+    pub by_prefix: BTreeMap<String, OutcomeCounts>,
+}
+
+#[derive(Debug, Default)]
+pub struct WaitOutcomes {
+    by_prefix: Mutex<HashMap<String, OutcomeCounts>>,
+}
+
+impl WaitOutcomes {
+    pub fn timed_out(&self, unique_id: &str) {
+        self.record(unique_id, |counts| counts.timed_out += 1);
+    }
+
+    pub fn cancelled(&self, unique_id: &str) {
+        self.record(unique_id, |counts| counts.cancelled += 1);
+    }
+
+    pub fn stats(&self) -> OutcomeStats {
+        let by_prefix: BTreeMap<_, _> = self
+            .by_prefix
+            .lock()
+            .iter()
+            .map(|(prefix, counts)| (prefix.clone(), *counts))
+            .collect();
+        let total = by_prefix
+            .values()
+            .fold(OutcomeCounts::default(), |total, counts| OutcomeCounts {
+                timed_out: total.timed_out + counts.timed_out,
+                cancelled: total.cancelled + counts.cancelled,
+            });
+        OutcomeStats { total, by_prefix }
+    }
+
+    fn record(&self, unique_id: &str, update: impl FnOnce(&mut OutcomeCounts)) {
+        if ids::is_internal(unique_id) {
+            return;
+        }
+        let prefix = prefix(unique_id);
+        let mut by_prefix = self.by_prefix.lock();
+        let key = if by_prefix.contains_key(prefix) || by_prefix.len() < MAX_PREFIXES {
+            prefix
+        } else {
+            OTHER_PREFIX
+        };
+        update(by_prefix.entry(key.to_owned()).or_default());
+    }
+}
+
+/// Part of `unique_id` before the first delimiter, the whole id if there is none
+pub fn prefix(unique_id: &str) -> &str {
+    unique_id
+        .split(PREFIX_DELIMITERS)
+        .next()
+        .unwrap_or(unique_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::outcomes::{prefix, OutcomeCounts, WaitOutcomes, MAX_PREFIXES, OTHER_PREFIX};
+
+    #[test]
+    fn test_prefix() {
+        assert_eq!(prefix("shard-17"), "shard");
+        assert_eq!(prefix("ci:build.3"), "ci");
+        assert_eq!(prefix("deploy/eu/payments"), "deploy");
+        assert_eq!(prefix("123"), "123");
+    }
+
+    #[test]
+    fn test_counts_per_prefix() {
+        let outcomes = WaitOutcomes::default();
+        outcomes.timed_out("shard-1");
+        outcomes.cancelled("shard-2");
+        outcomes.cancelled("ci-1");
+
+        let stats = outcomes.stats();
+        assert_eq!(
+            stats.by_prefix["shard"],
+            OutcomeCounts {
+                timed_out: 1,
+                cancelled: 1
+            }
+        );
+        assert_eq!(stats.by_prefix["ci"].cancelled, 1);
+        assert_eq!(stats.total.cancelled, 2);
+    }
+
+    #[test]
+    fn test_prefixes_are_bounded() {
+        let outcomes = WaitOutcomes::default();
+        for i in 0..=MAX_PREFIXES {
+            outcomes.timed_out(&i.to_string());
+        }
+
+        let stats = outcomes.stats();
+        assert_eq!(stats.by_prefix.len(), MAX_PREFIXES + 1);
+        assert_eq!(stats.by_prefix[OTHER_PREFIX].timed_out, 1);
+    }
+}
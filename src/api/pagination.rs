@@ -0,0 +1,160 @@
+//! Cursor-based pagination shared by listing endpoints.
+//!
+//! Items are listed in order of a monotonic position (e.g. the generation a wait point was created at).
+//! A cursor remembers the last position returned & the generation the first page was taken at, so later
+//! pages neither repeat nor skip items while the underlying map keeps changing. Items created after the
+//! first page are left out until the listing is restarted.
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// Page size when the client doesn't ask for one
+pub const DEFAULT_LIMIT: usize = 100;
+/// Upper bound of the page size
+pub const MAX_LIMIT: usize = 1000;
+
+/// Opaque position within a listing, sent to clients as `next_cursor`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cursor {
+    /// Position of the last item already returned
+    pub offset: u64,
+    /// Generation the listing snapshot was taken at
+    pub generation: u64,
+}
+
+impl Cursor {
+    /// Start of a new listing, snapshotting the current generation
+    pub fn start(generation: u64) -> Self {
+        Self {
+            offset: 0,
+            generation,
+        }
+    }
+
+    /// Parses a token produced by `Display`
+    ///
+    /// # Returns
+    /// * `Ok(Cursor)` - The decoded cursor
+    /// * `Err(String)` - If the token is malformed
+    pub fn decode(token: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid cursor: {}", token);
+
+        let bytes = (0..token.len())
+            .step_by(2)
+            .map(|i| {
+                token
+                    .get(i..i + 2)
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(invalid)?;
+        let decoded = String::from_utf8(bytes).map_err(|_| invalid())?;
+        let (offset, generation) = decoded.split_once(':').ok_or_else(invalid)?;
+
+        Ok(Self {
+            offset: offset.parse().map_err(|_| invalid())?,
+            generation: generation.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Hex encoded, so clients treat it as an opaque token rather than build their own
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        format!("{}:{}", self.offset, self.generation)
+            .bytes()
+            .try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
+
+/// Sent as the opaque token
+impl Serialize for Cursor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Cursor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Self::decode(&String::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+/// A page of a listing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Pass as `cursor` to get the next page, `None` on the last page
+    pub next_cursor: Option<Cursor>,
+}
+
+/// Cuts a page out of positioned items.
+///
+/// Only consumes items up to the end of the page, so pass a lazy iterator (e.g. a range of an index)
+/// to keep the cost proportional to the page size.
+///
+/// # Arguments
+/// * `items` - Items with their unique positions, sorted by position
+/// * `cursor` - Where the previous page ended, `Cursor::start` for the first page
+/// * `limit` - Requested page size, `DEFAULT_LIMIT` when `None`, capped at `MAX_LIMIT`
+///
+/// # Returns
+/// The items after the cursor, up to the snapshot generation
+pub fn paginate<T>(
+    items: impl IntoIterator<Item = (u64, T)>,
+    cursor: Cursor,
+    limit: Option<usize>,
+) -> Page<T> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let mut items: Vec<(u64, T)> = items
+        .into_iter()
+        .skip_while(|(position, _)| *position <= cursor.offset)
+        .take_while(|(position, _)| *position <= cursor.generation)
+        .take(limit + 1)
+        .collect();
+
+    let next_cursor = (items.len() > limit).then(|| Cursor {
+        offset: items[limit - 1].0,
+        generation: cursor.generation,
+    });
+    items.truncate(limit);
+
+    Page {
+        items: items.into_iter().map(|(_, item)| item).collect(),
+        next_cursor,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::pagination::{paginate, Cursor};
+
+    #[test]
+    fn test_cursor_roundtrip() {
+        let cursor = Cursor {
+            offset: 42,
+            generation: 100,
+        };
+        assert_eq!(Cursor::decode(&cursor.to_string()), Ok(cursor));
+        assert!(Cursor::decode("not a cursor").is_err());
+        assert!(Cursor::decode("3432").is_err());
+    }
+
+    #[test]
+    fn test_pages_are_stable_while_items_change() {
+        let mut items: Vec<(u64, &str)> = vec![(1, "a"), (2, "b"), (3, "c"), (4, "d")];
+
+        let page = paginate(items.clone(), Cursor::start(4), Some(2));
+        assert_eq!(page.items, vec!["a", "b"]);
+        let cursor = page.next_cursor.unwrap();
+
+        // "a" removed & "e" created after the snapshot
+        items.remove(0);
+        items.push((5, "e"));
+
+        let page = paginate(items, cursor, Some(2));
+        assert_eq!(page.items, vec!["c", "d"]);
+        assert_eq!(page.next_cursor, None);
+    }
+}
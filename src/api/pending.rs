@@ -0,0 +1,102 @@
+//! Notifications stored by `POST /notify/<unique_id>?persistent=true` while nobody waits on the id.
+//! The next party waiting on it is matched right away, until the notification expires.
+use crate::settings::Settings;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+/// Pending notification per id, with its expiry & the `pair_id` of the match it makes. Notifying
+/// an id which already has one replaces it, notifications don't add up
+pub struct PendingNotifications {
+    /// How long a notification is kept, `notification_ttl_sec` setting
+    ttl: Duration,
+    /// Expired entries are purged whenever one is stored, so they can't pile up
+    entries: Mutex<HashMap<String, (Instant, Uuid)>>,
+}
+
+impl Default for PendingNotifications {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(Settings::DEFAULT_NOTIFICATION_TTL_SEC))
+    }
+}
+
+impl PendingNotifications {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Tries `deliver` to a waiting party, storing the notification with `pair_id` if it fails.
+    ///
+    /// Both happen under the lock `take` needs too, so a party starting to wait meanwhile either
+    /// gets delivered to or finds the stored notification
+    ///
+    /// # Returns
+    /// Whether `deliver` succeeded, otherwise the notification was stored
+    pub fn deliver_or_store(
+        &self,
+        unique_id: &str,
+        pair_id: Uuid,
+        deliver: impl FnOnce() -> bool,
+    ) -> bool {
+        let mut entries = self.entries.lock();
+        if deliver() {
+            return true;
+        }
+        let now = Instant::now();
+        entries.retain(|_, (expires_at, _)| *expires_at > now);
+        entries.insert(unique_id.to_owned(), (now + self.ttl, pair_id));
+        false
+    }
+
+    /// Consumes the notification stored for `unique_id`
+    ///
+    /// # Returns
+    /// `pair_id` of the notification, `None` if there's none which hasn't expired
+    pub fn take(&self, unique_id: &str) -> Option<Uuid> {
+        let (expires_at, pair_id) = self.entries.lock().remove(unique_id)?;
+        (expires_at > Instant::now()).then_some(pair_id)
+    }
+
+    /// Stored notifications, including expired ones not purged yet
+    pub fn len(&self) -> usize {
+        self.entries.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.lock().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::pending::PendingNotifications;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn test_notifications_expire() {
+        let pending = PendingNotifications::new(Duration::from_millis(50));
+        let pair_id = Uuid::new_v4();
+        assert!(!pending.deliver_or_store("a", pair_id, || false));
+        assert!(!pending.deliver_or_store("b", Uuid::new_v4(), || false));
+        assert!(pending.deliver_or_store("c", Uuid::new_v4(), || true));
+        assert_eq!(pending.len(), 2);
+
+        assert_eq!(pending.take("a"), Some(pair_id));
+        // Consumed once
+        assert_eq!(pending.take("a"), None);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(pending.take("b"), None);
+        assert!(pending.is_empty());
+    }
+}
@@ -0,0 +1,229 @@
+//! Priority classes of waiting parties (`?priority=high|normal|low`, `normal` by default), so
+//! production gating traffic isn't shed because bulk test traffic filled `max_wait_points`.
+//!
+//! A class may only open wait points while the open ones (of every class, plus reservations) stay
+//! below its weight, the share of `max_wait_points` in percent set in the `[priorities]` section.
+//! E.g. `low = 50` keeps half of the capacity for `normal` & `high` parties.
+//!
+//! Starvation protection: each class is guaranteed `min_share` percent of `max_wait_points` for its
+//! own points. Guaranteed seats a class doesn't use are kept free for it, whatever the weights of
+//! the others.
+//!
+//! Priorities only apply when `max_wait_points` is set, joining an open point is never affected.
+use rocket::FromFormField;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, FromFormField,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    High,
+    #[default]
+    Normal,
+    Low,
+}
+
+impl Priority {
+    pub const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Priority::High => "high",
+            Priority::Normal => "normal",
+            Priority::Low => "low",
+        }
+    }
+
+    /// Position in `ALL`, indexing the per-class arrays
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// A count per class
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct PriorityCounts {
+    pub high: u64,
+    pub normal: u64,
+    pub low: u64,
+}
+
+impl PriorityCounts {
+    pub fn get(&self, priority: Priority) -> u64 {
+        match priority {
+            Priority::High => self.high,
+            Priority::Normal => self.normal,
+            Priority::Low => self.low,
+        }
+    }
+}
+
+impl From<[usize; 3]> for PriorityCounts {
+    fn from(counts: [usize; 3]) -> Self {
+        Self {
+            high: counts[Priority::High.index()] as u64,
+            normal: counts[Priority::Normal.index()] as u64,
+            low: counts[Priority::Low.index()] as u64,
+        }
+    }
+}
+
+/// `[priorities]` config section, percentages of `max_wait_points`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PriorityConfig {
+    /// Weight of `high` parties
+    pub high: u8,
+    /// Weight of `normal` parties
+    pub normal: u8,
+    /// Weight of `low` parties
+    pub low: u8,
+    /// Share guaranteed to each class, 0 disables the starvation protection
+    pub min_share: u8,
+}
+
+impl Default for PriorityConfig {
+    fn default() -> Self {
+        Self {
+            high: 100,
+            normal: 100,
+            low: 100,
+            min_share: 0,
+        }
+    }
+}
+
+impl PriorityConfig {
+    /// Checks the percentages
+    ///
+    /// # Returns
+    /// * `Ok(())` - All of them are valid
+    /// * `Err(String)` - Describing the first invalid one
+    pub fn validate(&self) -> Result<(), String> {
+        for priority in Priority::ALL {
+            if !(1..=100).contains(&self.weight(priority)) {
+                return Err(format!(
+                    "priorities.{} must be between 1 and 100",
+                    priority.as_str()
+                ));
+            }
+        }
+        if self.min_share as usize * Priority::ALL.len() > 100 {
+            return Err("priorities.min_share can't guarantee more than 100% in total".to_owned());
+        }
+        Ok(())
+    }
+
+    pub fn weight(&self, priority: Priority) -> u8 {
+        match priority {
+            Priority::High => self.high,
+            Priority::Normal => self.normal,
+            Priority::Low => self.low,
+        }
+    }
+}
+
+/// Admission of new wait points by priority, with the count of parties shed per class
+#[derive(Debug, Default)]
+pub struct Priorities {
+    config: PriorityConfig,
+    shed: [AtomicU64; 3],
+}
+
+impl Priorities {
+    pub fn new(config: &PriorityConfig) -> Self {
+        Self {
+            config: config.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Checks whether a `priority` party may open a wait point. The caller checks the hard limit
+    ///
+    /// # Arguments
+    /// * `open` - Open wait points per class, indexed by `Priority::index`
+    /// * `total` - Seats taken, open wait points & reservations
+    /// * `max_wait_points` - Limit of simultaneously open wait points, 0 means unlimited
+    pub fn admits(
+        &self,
+        priority: Priority,
+        open: [usize; 3],
+        total: usize,
+        max_wait_points: usize,
+    ) -> bool {
+        if max_wait_points == 0 {
+            return true;
+        }
+        let guaranteed = max_wait_points * self.config.min_share as usize / 100;
+        if open[priority.index()] < guaranteed {
+            return true;
+        }
+        let kept_for_others: usize = Priority::ALL
+            .into_iter()
+            .filter(|other| *other != priority)
+            .map(|other| guaranteed.saturating_sub(open[other.index()]))
+            .sum();
+        // Rounded up, so that small limits aren't closed to a class entirely
+        let share = (max_wait_points * self.config.weight(priority) as usize).div_ceil(100);
+        total + kept_for_others < share
+    }
+
+    /// Counts a `priority` party rejected for lack of capacity
+    pub fn shed(&self, priority: Priority) {
+        self.shed[priority.index()].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Parties rejected for lack of capacity per class, since the last restart
+    pub fn shed_counts(&self) -> PriorityCounts {
+        PriorityCounts {
+            high: self.shed[Priority::High.index()].load(Ordering::Relaxed),
+            normal: self.shed[Priority::Normal.index()].load(Ordering::Relaxed),
+            low: self.shed[Priority::Low.index()].load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::priorities::{Priorities, Priority, PriorityConfig};
+
+    #[test]
+    fn test_weights_keep_capacity_for_higher_classes() {
+        let priorities = Priorities::new(&PriorityConfig {
+            low: 50,
+            ..Default::default()
+        });
+        assert!(priorities.admits(Priority::Low, [0, 0, 4], 4, 10));
+        assert!(!priorities.admits(Priority::Low, [0, 0, 5], 5, 10));
+        assert!(priorities.admits(Priority::High, [0, 0, 5], 9, 10));
+        // Not limited without `max_wait_points`
+        assert!(priorities.admits(Priority::Low, [0, 0, 5], 5, 0));
+        // Rounded up
+        assert!(priorities.admits(Priority::Low, [0, 0, 0], 0, 1));
+    }
+
+    #[test]
+    fn test_min_share_prevents_starvation() {
+        let priorities = Priorities::new(&PriorityConfig {
+            min_share: 10,
+            ..Default::default()
+        });
+        // 1 seat each guaranteed, high can't take the one of low
+        assert!(priorities.admits(Priority::High, [7, 1, 0], 8, 10));
+        assert!(!priorities.admits(Priority::High, [8, 1, 0], 9, 10));
+        assert!(priorities.admits(Priority::Low, [8, 1, 0], 9, 10));
+
+        assert!(PriorityConfig::default().validate().is_ok());
+        let invalid = |config: PriorityConfig| config.validate().is_err();
+        assert!(invalid(PriorityConfig {
+            normal: 0,
+            ..Default::default()
+        }));
+        assert!(invalid(PriorityConfig {
+            min_share: 34,
+            ..Default::default()
+        }));
+    }
+}
@@ -0,0 +1,145 @@
+//! Limit of simultaneous waits per API key (`max_waits_per_key`), so one client (e.g. a batch job)
+//! can't hold all the capacity. Each wait (`/wait-for-second-party`, `/wait`, every id of
+//! `/batch/wait`) holds a slot of its `X-Api-Key` until it's answered, further ones get 429.
+//! Requests without a key aren't limited.
+//!
+//! Responses to keyed requests under the base carry `X-Api-Key-Waits: <in use>/<limit>`.
+use crate::api::response::ApiResponse;
+use crate::app::App;
+use crate::protocol::headers;
+use parking_lot::Mutex;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Header, Status};
+use rocket::response::status::Custom;
+use rocket::serde::json::Json;
+use rocket::{Request, Response};
+use std::collections::HashMap;
+
+/// Waits in progress per API key, keys without any are dropped
+#[derive(Debug, Default)]
+pub struct WaitQuotas {
+    in_use: Mutex<HashMap<String, usize>>,
+}
+
+impl WaitQuotas {
+    /// Takes a slot of `api_key`, released when the returned `WaitSlot` is dropped
+    ///
+    /// # Returns
+    /// * `Ok(WaitSlot)` - If `api_key` had less than `limit` slots in use
+    /// * `Err(usize)` - The slots in use otherwise
+    pub fn acquire(&self, api_key: &str, limit: usize) -> Result<WaitSlot<'_>, usize> {
+        let mut in_use = self.in_use.lock();
+        let count = in_use.entry(api_key.to_owned()).or_insert(0);
+        if *count >= limit {
+            return Err(*count);
+        }
+        *count += 1;
+        Ok(WaitSlot {
+            quotas: self,
+            api_key: api_key.to_owned(),
+        })
+    }
+
+    /// Slots of `api_key` in use
+    pub fn in_use(&self, api_key: &str) -> usize {
+        self.in_use.lock().get(api_key).copied().unwrap_or(0)
+    }
+}
+
+/// A wait of an API key in progress
+#[must_use = "the slot is released as soon as it's dropped"]
+pub struct WaitSlot<'a> {
+    quotas: &'a WaitQuotas,
+    api_key: String,
+}
+
+impl Drop for WaitSlot<'_> {
+    fn drop(&mut self) {
+        let mut in_use = self.quotas.in_use.lock();
+        if let Some(count) = in_use.get_mut(&self.api_key) {
+            *count -= 1;
+            if *count == 0 {
+                in_use.remove(&self.api_key);
+            }
+        }
+    }
+}
+
+/// 429 for a party whose API key holds `limit` waits already
+pub fn too_many_waits(limit: usize) -> Custom<Json<ApiResponse>> {
+    Custom(
+        Status::TooManyRequests,
+        Json(ApiResponse::error(format!(
+            "The API key already holds {} waits, the limit",
+            limit
+        ))),
+    )
+}
+
+/// Fairing adding `X-Api-Key-Waits` to the responses under `base`. Attached by `SyncPointFairing`
+pub struct QuotaHeader {
+    /// Base with a trailing slash, e.g. `/sync/`
+    prefix: String,
+}
+
+impl QuotaHeader {
+    pub fn new(base: &str) -> Self {
+        Self {
+            prefix: format!("{}/", base.trim_end_matches('/')),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for QuotaHeader {
+    fn info(&self) -> Info {
+        Info {
+            name: "Wait quota header",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some(api_key) = request.headers().get_one(headers::API_KEY) else {
+            return;
+        };
+        let Some(app) = request.rocket().state::<App>() else {
+            return;
+        };
+        let limit = app.max_waits_per_key();
+        if limit == 0
+            || !request
+                .uri()
+                .path()
+                .as_str()
+                .starts_with(self.prefix.as_str())
+        {
+            return;
+        }
+        response.set_header(Header::new(
+            headers::WAITS_IN_USE,
+            format!("{}/{}", app.waits_in_use(api_key), limit),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::quotas::WaitQuotas;
+
+    #[test]
+    fn test_slots_are_released_on_drop() {
+        let quotas = WaitQuotas::default();
+        let first = quotas.acquire("a", 2).unwrap();
+        let second = quotas.acquire("a", 2).unwrap();
+        assert_eq!(quotas.acquire("a", 2).err(), Some(2));
+        // Counted per key
+        assert!(quotas.acquire("b", 2).is_ok());
+
+        drop(first);
+        assert_eq!(quotas.in_use("a"), 1);
+        drop(second);
+        assert_eq!(quotas.in_use("a"), 0);
+        assert!(quotas.in_use.lock().is_empty());
+    }
+}
@@ -0,0 +1,127 @@
+//! Readiness for load balancers (`GET /ready`), as opposed to the liveness of `GET /health`:
+//! - `starting` until `warmup_sec` passed since launch, e.g. for cluster membership to settle
+//! - `lame-duck` once draining, via `PUT /admin/lame-duck` ahead of a deploy or when shutdown
+//!   began. Requests are still served, so parties already waiting can be matched
+//! - `unhealthy` while at `max_wait_points` or the memory budget, new wait points get 503
+//! - `ready` otherwise, the only state answered with 200
+//!
+//! The state is also exposed as the `readiness_state` metric.
+use crate::app::App;
+use crate::protocol::{ReadinessResponse, ReadinessState};
+use log::info;
+use parking_lot::Mutex;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::json::Json;
+use rocket::{get, State};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Lifecycle of the instance, shared by the clones of `App`
+#[derive(Debug, Default)]
+pub struct Readiness {
+    /// End of the warm-up, `None` until launched
+    warm_at: Mutex<Option<Instant>>,
+    lame_duck: AtomicBool,
+}
+
+impl Readiness {
+    /// Starts the warm-up, on launch
+    pub fn start(&self, warmup: Duration) {
+        *self.warm_at.lock() = Some(Instant::now() + warmup);
+    }
+
+    /// Enters (`true`) or leaves (`false`) lame-duck mode
+    pub fn set_lame_duck(&self, lame_duck: bool) {
+        info!(target: "audit", "Lame-duck mode set to {}", lame_duck);
+        self.lame_duck.store(lame_duck, Ordering::Relaxed);
+    }
+
+    /// The lifecycle part of the state, `None` when launched, warm & not draining
+    fn phase(&self) -> Option<ReadinessResponse> {
+        if self.lame_duck.load(Ordering::Relaxed) {
+            return Some(ReadinessResponse {
+                state: ReadinessState::LameDuck,
+                message: Some("Draining, route new requests to other instances".to_owned()),
+            });
+        }
+        let remaining = match *self.warm_at.lock() {
+            Some(warm_at) => warm_at.saturating_duration_since(Instant::now()),
+            None => return Some(starting("Not launched yet".to_owned())),
+        };
+        if !remaining.is_zero() {
+            let message = format!("Warming up, ready in {} ms", remaining.as_millis());
+            return Some(starting(message));
+        }
+        None
+    }
+}
+
+fn starting(message: String) -> ReadinessResponse {
+    ReadinessResponse {
+        state: ReadinessState::Starting,
+        message: Some(message),
+    }
+}
+
+/// The current state, see module docs
+pub fn check(app: &App) -> ReadinessResponse {
+    if let Some(phase) = app.readiness.phase() {
+        return phase;
+    }
+    let stats = app.sync_service.stats();
+    let max_wait_points = app.max_wait_points();
+    let max_memory_bytes = app.sync_service.max_memory_bytes;
+    let message = if max_wait_points > 0 && stats.open_wait_points >= max_wait_points {
+        Some("Too many open wait points")
+    } else if max_memory_bytes > 0 && stats.memory_bytes >= max_memory_bytes {
+        Some("Wait points memory budget exhausted")
+    } else {
+        None
+    };
+    ReadinessResponse {
+        state: match message {
+            Some(_) => ReadinessState::Unhealthy,
+            None => ReadinessState::Ready,
+        },
+        message: message.map(str::to_owned),
+    }
+}
+
+/// Reports whether load balancers should route requests here
+///
+/// # Returns
+/// 200 when `ready`, 503 with the state & the reason otherwise
+#[get("/ready")]
+pub fn ready(state: &State<App>) -> Custom<Json<ReadinessResponse>> {
+    let readiness = check(state);
+    let status = match readiness.state {
+        ReadinessState::Ready => Status::Ok,
+        _ => Status::ServiceUnavailable,
+    };
+    Custom(status, Json(readiness))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::readiness::Readiness;
+    use crate::protocol::ReadinessState;
+    use std::time::Duration;
+
+    #[test]
+    fn test_lifecycle() {
+        let readiness = Readiness::default();
+        let state = |readiness: &Readiness| readiness.phase().map(|phase| phase.state);
+        assert_eq!(state(&readiness), Some(ReadinessState::Starting));
+
+        readiness.start(Duration::from_secs(60));
+        assert_eq!(state(&readiness), Some(ReadinessState::Starting));
+        readiness.start(Duration::ZERO);
+        assert_eq!(state(&readiness), None);
+
+        readiness.set_lame_duck(true);
+        assert_eq!(state(&readiness), Some(ReadinessState::LameDuck));
+        readiness.set_lame_duck(false);
+        assert_eq!(state(&readiness), None);
+    }
+}
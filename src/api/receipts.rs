@@ -0,0 +1,189 @@
+//! Signed match receipts, for parties to prove to a third system that their rendezvous happened.
+//! With `receipt_key` set (a hex Ed25519 secret key, e.g. from `openssl rand -hex 32` via the
+//! `APP_RECEIPT_KEY` env var), both parties of a pair match get the same `Receipt`. Its public key
+//! is served by `GET /receipts/key`.
+//!
+//! Only compiled with the `receipts` feature. Stored notifications & group releases get no receipt.
+use crate::api::acl::Access;
+use crate::api::response::ApiResponse;
+use crate::app::App;
+use crate::protocol::{Receipt, ReceiptPayload};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::json::Json;
+use rocket::{get, State};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+
+/// Signs receipts with the configured key
+pub struct ReceiptSigner {
+    key: SigningKey,
+}
+
+impl ReceiptSigner {
+    /// # Returns
+    /// * `Ok(ReceiptSigner)` - For the 32 bytes secret key `secret`, hex
+    /// * `Err(String)` - Describing why `secret` isn't one
+    pub fn from_hex(secret: &str) -> Result<Self, String> {
+        let secret: [u8; 32] = decode_hex(secret)
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or("receipt_key must be 32 bytes, hex")?;
+        Ok(Self {
+            key: SigningKey::from_bytes(&secret),
+        })
+    }
+
+    /// Public key of the signer, hex
+    pub fn public_key(&self) -> String {
+        encode_hex(self.key.verifying_key().as_bytes())
+    }
+
+    pub fn sign(&self, payload: &ReceiptPayload) -> Receipt {
+        let payload = serde_json::to_string(payload).expect("serializable payload");
+        let signature = self.key.sign(payload.as_bytes());
+        Receipt {
+            payload,
+            signature: encode_hex(&signature.to_bytes()),
+        }
+    }
+}
+
+/// Checks `receipt` against the server's `public_key` (hex)
+///
+/// # Returns
+/// * `Ok(ReceiptPayload)` - What the receipt attests
+/// * `Err(String)` - Describing why it's invalid
+pub fn verify(receipt: &Receipt, public_key: &str) -> Result<ReceiptPayload, String> {
+    let public_key: [u8; 32] = decode_hex(public_key)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or("Invalid public key")?;
+    let public_key = VerifyingKey::from_bytes(&public_key).map_err(|e| e.to_string())?;
+    let signature: [u8; 64] = decode_hex(&receipt.signature)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or("Invalid signature")?;
+    public_key
+        .verify(
+            receipt.payload.as_bytes(),
+            &Signature::from_bytes(&signature),
+        )
+        .map_err(|e| e.to_string())?;
+    serde_json::from_str(&receipt.payload).map_err(|e| e.to_string())
+}
+
+/// Identifies a party in receipts without disclosing its address or API key: the first 16 bytes
+/// of their SHA-256, hex
+pub fn fingerprint(access: &Access) -> String {
+    let mut hasher = Sha256::new();
+    if let Some(ip) = access.ip {
+        hasher.update(ip.to_string());
+    }
+    hasher.update("\n");
+    if let Some(api_key) = &access.api_key {
+        hasher.update(api_key);
+    }
+    encode_hex(&hasher.finalize()[..16])
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .fold(String::with_capacity(bytes.len() * 2), |mut hex, byte| {
+            let _ = write!(hex, "{:02x}", byte);
+            hex
+        })
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Response of `GET /receipts/key`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReceiptKey {
+    pub algorithm: String,
+    /// hex
+    pub public_key: String,
+}
+
+/// Public key to verify receipts with
+///
+/// # Returns
+/// * `Ok(Json<ReceiptKey>)` - The key
+/// * `Err(Custom<Json<ApiResponse>>)` - 404 when no `receipt_key` is configured
+#[get("/receipts/key")]
+pub fn receipt_key(state: &State<App>) -> Result<Json<ReceiptKey>, Custom<Json<ApiResponse>>> {
+    match &state.sync_service.receipts {
+        Some(signer) => Ok(Json(ReceiptKey {
+            algorithm: "ed25519".to_owned(),
+            public_key: signer.public_key(),
+        })),
+        None => Err(Custom(
+            Status::NotFound,
+            Json(ApiResponse::error("Receipts are not enabled")),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::acl::Access;
+    use crate::api::connections::Protocol;
+    use crate::api::priorities::Priority;
+    use crate::api::receipts::{fingerprint, verify, ReceiptSigner};
+    use crate::protocol::ReceiptPayload;
+
+    const SECRET: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+
+    #[test]
+    fn test_sign_and_verify() {
+        let signer = ReceiptSigner::from_hex(SECRET).unwrap();
+        // RFC 8032 test vector 1
+        assert_eq!(
+            signer.public_key(),
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a"
+        );
+        let payload = ReceiptPayload {
+            unique_id: "123".to_owned(),
+            pair_id: "a".to_owned(),
+            created_at: "2024-12-28T06:41:51.123Z".to_owned(),
+            matched_at: "2024-12-28T06:41:52.456Z".to_owned(),
+            parties: vec![fingerprint(&Access::default()); 2],
+        };
+
+        let mut receipt = signer.sign(&payload);
+        assert_eq!(verify(&receipt, &signer.public_key()), Ok(payload));
+        receipt.payload = receipt.payload.replace("123", "124");
+        assert!(verify(&receipt, &signer.public_key()).is_err());
+
+        assert!(ReceiptSigner::from_hex("abc").is_err());
+        assert!(ReceiptSigner::from_hex(&SECRET[..62]).is_err());
+    }
+
+    #[test]
+    fn test_fingerprint_hides_identity() {
+        let access = Access {
+            ip: Some("10.0.0.1".parse().unwrap()),
+            connection: None,
+            protocol: Protocol::Http1,
+            api_key: Some("team-a".to_owned()),
+            user_agent: None,
+            acl: None,
+            reservation: None,
+            priority: Priority::Normal,
+            note: None,
+            metadata: None,
+        };
+        let print = fingerprint(&access);
+        assert_eq!(print.len(), 32);
+        assert!(!print.contains("team-a"));
+        assert_ne!(print, fingerprint(&Access::default()));
+    }
+}
@@ -0,0 +1,142 @@
+//! Wait point registry: the primary map by `unique_id` plus secondary indexes kept in step with it,
+//! so queries other than a lookup by id don't scan & sort every open point while holding the lock.
+use crate::api::sync_service::WaitPoint;
+use std::collections::{BTreeMap, HashMap};
+use std::mem::size_of;
+use std::ops::Bound;
+use std::sync::Arc;
+
+/// Open wait points, indexed by `unique_id` & by creation order.
+///
+/// Only mutated through `insert`/`remove`, which keep the indexes consistent.
+#[derive(Default)]
+pub struct Registry {
+    points: HashMap<String, Arc<WaitPoint>>,
+    /// Creation generation -> `unique_id`. Generations are unique, so this is a total order
+    by_creation: BTreeMap<u64, String>,
+    /// Sum of `entry_bytes` of all points
+    memory_bytes: usize,
+    /// Points per `priority`, indexed by `Priority::index`
+    by_priority: [usize; 3],
+}
+
+impl Registry {
+    pub fn get(&self, unique_id: &str) -> Option<&Arc<WaitPoint>> {
+        self.points.get(unique_id)
+    }
+
+    /// Adds a wait point, replacing (and unindexing) any previous one for the same id
+    pub fn insert(&mut self, unique_id: String, point: Arc<WaitPoint>) -> Option<Arc<WaitPoint>> {
+        self.memory_bytes += Self::entry_bytes(&unique_id);
+        self.by_creation.insert(point.generation, unique_id.clone());
+        self.by_priority[point.priority.index()] += 1;
+        let previous = self.points.insert(unique_id.clone(), point);
+        if let Some(previous) = &previous {
+            self.by_creation.remove(&previous.generation);
+            self.by_priority[previous.priority.index()] -= 1;
+            self.memory_bytes -= Self::entry_bytes(&unique_id);
+        }
+        previous
+    }
+
+    pub fn remove(&mut self, unique_id: &str) -> Option<Arc<WaitPoint>> {
+        let point = self.points.remove(unique_id)?;
+        self.by_creation.remove(&point.generation);
+        self.by_priority[point.priority.index()] -= 1;
+        self.memory_bytes -= Self::entry_bytes(unique_id);
+        Some(point)
+    }
+
+    /// Open points per `priority` of their creator, indexed by `Priority::index`
+    pub fn open_by_priority(&self) -> [usize; 3] {
+        self.by_priority
+    }
+
+    /// Approximate memory held by the open wait points, in bytes
+    pub fn memory_bytes(&self) -> usize {
+        self.memory_bytes
+    }
+
+    /// Approximate memory a wait point for `unique_id` takes: the point with its `Arc` counters,
+    /// its map & index entries and both copies of the id. Hash table & B-tree slack and allocator
+    /// overhead are left out, so treat it as a lower bound
+    pub fn entry_bytes(unique_id: &str) -> usize {
+        size_of::<WaitPoint>()
+            + 2 * size_of::<usize>()
+            + size_of::<(String, Arc<WaitPoint>)>()
+            + size_of::<(u64, String)>()
+            + 2 * unique_id.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Arc<WaitPoint>> {
+        self.points.values()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Arc<WaitPoint>)> {
+        self.points.iter()
+    }
+
+    /// Wait points created after generation `after` up to `until` (both by creation generation),
+    /// oldest first. Costs a B-tree range walk, so taking a page is O(log n + page size)
+    pub fn created_between(
+        &self,
+        after: u64,
+        until: u64,
+    ) -> impl Iterator<Item = (&str, &Arc<WaitPoint>)> {
+        self.by_creation
+            .range((Bound::Excluded(after), Bound::Included(until.max(after))))
+            .map(|(_, unique_id)| (unique_id.as_str(), &self.points[unique_id]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::registry::Registry;
+    use crate::api::sync_service::WaitPoint;
+    use std::sync::Arc;
+
+    fn ids(registry: &Registry, after: u64, until: u64) -> Vec<&str> {
+        registry
+            .created_between(after, until)
+            .map(|(unique_id, _)| unique_id)
+            .collect()
+    }
+
+    #[test]
+    fn test_indexes_follow_the_map() {
+        let mut registry = Registry::default();
+        for (generation, unique_id) in [(3, "c"), (1, "a"), (2, "b")] {
+            registry.insert(unique_id.to_owned(), Arc::new(WaitPoint::new(generation)));
+        }
+        assert_eq!(ids(&registry, 0, u64::MAX), vec!["a", "b", "c"]);
+        assert_eq!(ids(&registry, 1, 2), vec!["b"]);
+
+        registry.remove("b");
+        // Re-created under the same id, moves to the end
+        registry.insert("a".to_owned(), Arc::new(WaitPoint::new(4)));
+        assert_eq!(ids(&registry, 0, u64::MAX), vec!["c", "a"]);
+        assert_eq!(registry.len(), 2);
+        assert_eq!(registry.get("a").unwrap().generation, 4);
+        assert_eq!(
+            registry.memory_bytes(),
+            Registry::entry_bytes("a") + Registry::entry_bytes("c")
+        );
+        assert_eq!(registry.open_by_priority(), [0, 2, 0]);
+
+        // An empty range rather than a panic
+        assert!(ids(&registry, 5, 1).is_empty());
+
+        registry.remove("a");
+        registry.remove("c");
+        assert_eq!(registry.memory_bytes(), 0);
+        assert_eq!(registry.open_by_priority(), [0, 0, 0]);
+    }
+}
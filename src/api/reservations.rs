@@ -0,0 +1,117 @@
+//! Capacity reservations made with `POST /reserve/<unique_id>`, so orchestrators can check there's
+//! room for a rendezvous before kicking off the expensive work leading to it. A reservation holds a
+//! seat of `max_wait_points` (& the memory budget) for `reservation_ttl_sec`. The party sending its
+//! token as `X-Sync-Reservation` within that time is admitted to the id even at capacity, consuming
+//! it. Joining the point of the id as another party (waiter, notifier, group member) consumes it
+//! too, the seat is released then. Other rejections (maintenance, ACL, conflicts) still apply.
+use crate::api::registry::Registry;
+use crate::settings::Settings;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+/// Response of `POST /reserve/<unique_id>`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Reservation {
+    pub unique_id: String,
+    /// Sent back as `X-Sync-Reservation` header by the party joining `unique_id`
+    pub token: String,
+    /// The seat is released unless used within this time
+    pub expires_in_sec: u64,
+}
+
+/// Unexpired reservations by token, with their expiry & id
+pub struct Reservations {
+    /// How long a reservation holds its seat, `reservation_ttl_sec` setting
+    ttl: Duration,
+    /// Expired entries are purged whenever the reservations are counted or one is added
+    entries: Mutex<HashMap<Uuid, (Instant, String)>>,
+}
+
+impl Default for Reservations {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(Settings::DEFAULT_RESERVATION_TTL_SEC))
+    }
+}
+
+impl Reservations {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Reserves a seat for `unique_id`. The caller checks the capacity beforehand
+    ///
+    /// # Returns
+    /// The token of the reservation
+    pub fn add(&self, unique_id: &str) -> Uuid {
+        let token = Uuid::new_v4();
+        let now = Instant::now();
+        let mut entries = self.entries.lock();
+        entries.retain(|_, (expires_at, _)| *expires_at > now);
+        entries.insert(token, (now + self.ttl, unique_id.to_owned()));
+        token
+    }
+
+    /// Consumes the reservation `token`, if it's for `unique_id`
+    ///
+    /// # Returns
+    /// Whether it was reserved & hasn't expired
+    pub fn take(&self, token: Uuid, unique_id: &str) -> bool {
+        let mut entries = self.entries.lock();
+        match entries.get(&token) {
+            Some((expires_at, id)) if id == unique_id => {
+                let valid = *expires_at > Instant::now();
+                entries.remove(&token);
+                valid
+            }
+            _ => false,
+        }
+    }
+
+    /// Seats held, with the approximate memory the wait points they're for would take
+    pub fn held(&self) -> (usize, usize) {
+        let now = Instant::now();
+        let mut entries = self.entries.lock();
+        entries.retain(|_, (expires_at, _)| *expires_at > now);
+        let memory_bytes = entries
+            .values()
+            .map(|(_, unique_id)| Registry::entry_bytes(unique_id))
+            .sum();
+        (entries.len(), memory_bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::reservations::Reservations;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn test_reservations_are_taken_once_before_expiry() {
+        let reservations = Reservations::new(Duration::from_millis(50));
+        let a = reservations.add("a");
+        let b = reservations.add("b");
+        assert_eq!(reservations.held().0, 2);
+
+        // Only for its own id
+        assert!(!reservations.take(a, "b"));
+        assert!(reservations.take(a, "a"));
+        assert!(!reservations.take(a, "a"));
+        assert!(!reservations.take(Uuid::new_v4(), "a"));
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(reservations.held(), (0, 0));
+        assert!(!reservations.take(b, "b"));
+    }
+}
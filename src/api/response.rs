@@ -1,70 +1,149 @@
-use rocket::http::Status;
+use crate::protocol::{headers, status};
+use rocket::http::{Header, Status};
 use rocket::response::status::Custom;
+use rocket::response::{self, Responder};
 use rocket::serde::json::Json;
+use rocket::Request;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
-#[serde(rename_all = "lowercase")]
-pub enum ResponseStatus {
-    Success,
+pub use crate::protocol::{ApiResponse, DryRunOutcome, HealthResponse, HealthStatus, ResponseStatus};
+
+/// Server-side helpers, the type itself lives in `protocol` to be shared with clients
+impl ApiResponse {
+    /// A helper method to avoid repetition
+    pub fn service_unavailable() -> Custom<Json<Self>> {
+        Custom(
+            Status::ServiceUnavailable,
+            Json(Self::error("Service temporarily unavailable")),
+        )
+    }
+}
+
+/// Rendezvous failures whose HTTP status is configurable, see `StatusCodes`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Failure {
+    /// The peer didn't arrive in time, also when observing
     Timeout,
-    Error,
+    /// The wait point is taken (also dry runs which would be)
+    Conflict,
+    /// No capacity for a new wait point or reservation (also dry runs which would have none)
+    Busy,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ApiResponse {
-    status: ResponseStatus,
-    message: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    timeout_duration_sec: Option<u64>,
+/// `[status_codes]` config section, the statuses of `Failure`s. Gateways treat them differently,
+/// e.g. some retry 408 on their own but not 409. The defaults are those of `protocol::status`.
+///
+/// Only error statuses (4xx & 5xx) are allowed, so clients still see a failure. Those of this
+/// crate tell timeouts & conflicts by their body whatever the status, busy ones only by 503.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatusCodes {
+    pub timeout: u16,
+    pub conflict: u16,
+    pub busy: u16,
 }
 
-impl ApiResponse {
-    /// Generates successful API response with a message and unique identifier
-    ///
-    /// # Arguments
-    /// * `message` - A friendly welcome message
-    /// * `unique_id` - A unique identifier to track the response. This helps to make it distinguish
-    ///                 about which route such response was generated (otherwise it will be same generic
-    ///                 welcome message for each one)
-    ///
-    /// # Returns
-    /// `ApiResponse` instance with:
-    /// * `status` set to `ResponseStatus::Success`
-    /// * `message` formatted as "[unique_id] message"
-    /// * `timeout_duration_sec` set to `None`. Not visible in JSON response.
-    pub fn success(message: &str, unique_id: &str) -> Self {
+impl Default for StatusCodes {
+    fn default() -> Self {
         Self {
-            status: ResponseStatus::Success,
-            message: format!("[{}] {}", unique_id, message),
-            timeout_duration_sec: None,
+            timeout: status::TIMED_OUT,
+            conflict: status::CONFLICT,
+            busy: status::UNAVAILABLE,
         }
     }
+}
 
-    /// Same as `success` response, but with additional `timeout_duration_sec` field`
-    pub fn timeout(duration: Duration, unique_id: &str) -> Self {
-        Self {
-            status: ResponseStatus::Timeout,
-            message: format!("[{}] Request timed out", unique_id),
-            timeout_duration_sec: Some(duration.as_secs()),
+impl StatusCodes {
+    /// # Returns
+    /// * `Ok(())` - If the section is valid
+    /// * `Err(String)` - Describing the first invalid value
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, code) in [
+            ("timeout", self.timeout),
+            ("conflict", self.conflict),
+            ("busy", self.busy),
+        ] {
+            if !(400..=599).contains(&code) {
+                return Err(format!(
+                    "status_codes.{} must be a 4xx or 5xx status, not {}",
+                    name, code
+                ));
+            }
         }
+        Ok(())
     }
 
-    /// Will return critical error messages
-    pub fn error(message: &str) -> Self {
-        Self {
-            status: ResponseStatus::Error,
-            message: message.to_string(),
-            timeout_duration_sec: None,
-        }
+    /// The status responses to `failure` are sent with
+    pub fn status(&self, failure: Failure) -> Status {
+        Status::new(match failure {
+            Failure::Timeout => self.timeout,
+            Failure::Conflict => self.conflict,
+            Failure::Busy => self.busy,
+        })
     }
 
-    /// A helper method to avoid repetition
-    pub fn service_unavailable() -> Custom<Json<Self>> {
-        Custom(
-            Status::ServiceUnavailable,
-            Json(Self::error("Service temporarily unavailable")),
-        )
+    /// `response` to `failure`, with its status
+    pub fn respond(&self, failure: Failure, response: ApiResponse) -> Custom<Json<ApiResponse>> {
+        Custom(self.status(failure), Json(response))
+    }
+}
+
+/// Wraps a response with a `Retry-After` header (in seconds), telling clients when to try again
+pub struct RetryAfter(pub Custom<Json<ApiResponse>>, pub Duration);
+
+impl<'r> Responder<'r, 'static> for RetryAfter {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = self.0.respond_to(request)?;
+        response.set_header(Header::new(
+            headers::RETRY_AFTER,
+            self.1.as_secs().to_string(),
+        ));
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::ndjson;
+    use crate::api::response::{Failure, StatusCodes};
+    use crate::protocol::{headers, status};
+    use rocket::http::Status;
+
+    /// The server uses Rocket's constants, clients the protocol ones
+    #[test]
+    fn test_rocket_constants_match_protocol() {
+        assert_eq!(Status::Ok.code, status::MATCHED);
+        assert_eq!(Status::BadRequest.code, status::BAD_REQUEST);
+        assert_eq!(Status::Forbidden.code, status::FORBIDDEN);
+        assert_eq!(Status::NotFound.code, status::NOT_FOUND);
+        assert_eq!(Status::RequestTimeout.code, status::TIMED_OUT);
+        assert_eq!(Status::Conflict.code, status::CONFLICT);
+        assert_eq!(Status::ServiceUnavailable.code, status::UNAVAILABLE);
+        assert_eq!(ndjson::content_type().to_string(), headers::NDJSON_CONTENT_TYPE);
+    }
+
+    #[test]
+    fn test_status_codes() {
+        let defaults = StatusCodes::default();
+        assert_eq!(defaults.status(Failure::Timeout), Status::RequestTimeout);
+        assert_eq!(defaults.status(Failure::Conflict), Status::Conflict);
+        assert_eq!(defaults.status(Failure::Busy), Status::ServiceUnavailable);
+
+        let remapped = StatusCodes {
+            timeout: 504,
+            ..Default::default()
+        };
+        assert!(remapped.validate().is_ok());
+        assert_eq!(remapped.status(Failure::Timeout), Status::GatewayTimeout);
+        // Clients would take it for a success
+        let success = StatusCodes {
+            conflict: 200,
+            ..Default::default()
+        };
+        assert_eq!(
+            success.validate().unwrap_err(),
+            "status_codes.conflict must be a 4xx or 5xx status, not 200"
+        );
     }
 }
@@ -1,10 +1,23 @@
-use crate::api::response::ApiResponse;
+use crate::allocator::{self, AllocatorStats};
+use crate::api::acl::Access;
+use crate::api::connections::too_many_requests;
+use crate::api::etag::{IfNoneMatch, Tagged};
+use crate::api::ids::IdPath;
+use crate::api::modes::Mode;
+use crate::api::outcomes::OutcomeStats;
+use crate::api::priorities::Priority;
+use crate::api::quotas::too_many_waits;
+use crate::api::reservations::Reservation;
+use crate::api::response::{ApiResponse, HealthResponse, HealthStatus, RetryAfter};
+use crate::api::sync_service::{Stats, SyncRequest, WaitPointStatus};
 use crate::app::App;
+use crate::protocol::MAX_NOTE_CHARS;
 use log::debug;
+use rocket::http::Status;
 use rocket::response::status::Custom;
 use rocket::serde::json::Json;
-use rocket::{get, post, State};
-use std::sync::atomic::Ordering;
+use rocket::{get, post, Either, State};
+use std::time::Duration;
 
 /// Handles GET requests to the root endpoint "/"
 #[get("/")]
@@ -12,39 +25,337 @@ pub fn index() -> &'static str {
     "Welcome to Sync Point API"
 }
 
+/// Reports whether the service is up, or in maintenance (still 200, since the process is healthy)
+#[get("/health")]
+pub fn health(state: &State<App>) -> Json<HealthResponse> {
+    Json(match state.maintenance() {
+        Some(maintenance) => HealthResponse {
+            status: HealthStatus::Maintenance,
+            message: Some(maintenance.message),
+        },
+        None => HealthResponse {
+            status: HealthStatus::Ok,
+            message: None,
+        },
+    })
+}
+
+/// Reports the state of the wait point for `unique_id`, meant for polling clients & dashboards.
+/// Supports `If-None-Match`, responding 304 while the wait point didn't change.
+///
+/// # Returns
+/// State (0 parties when there's no open wait point) or 304
+#[get("/status/<unique_id..>")]
+pub fn status(
+    unique_id: IdPath,
+    if_none_match: IfNoneMatch,
+    state: &State<App>,
+) -> Tagged<Json<WaitPointStatus>> {
+    let status = state.sync_service.status(&unique_id);
+    Tagged::new(&if_none_match, status.etag.clone(), || Json(status))
+}
+
+/// Reports service-wide counters. Supports `If-None-Match` like `status`.
+#[get("/stats")]
+pub fn stats(if_none_match: IfNoneMatch, state: &State<App>) -> Tagged<Json<Stats>> {
+    let stats = state.sync_service.stats();
+    Tagged::new(&if_none_match, stats.generation.to_string(), || {
+        Json(stats)
+    })
+}
+
+/// Reports global allocator stats. Not cached, they change with every request
+#[get("/stats/allocator")]
+pub fn allocator_stats() -> Json<AllocatorStats> {
+    Json(allocator::stats())
+}
+
+/// Reports how unmatched first parties ended (timed out vs cancelled) per id prefix, since the
+/// last restart. Not cached, cancellations don't change the generation
+#[get("/stats/outcomes")]
+pub fn outcome_stats(state: &State<App>) -> Json<OutcomeStats> {
+    Json(state.sync_service.outcome_stats())
+}
+
 /// Main endpoint handler for party synchronization
 ///
 /// When a party arrives:
 /// - If they're first, they'll wait for the second party
-/// - If they're second, they'll notify the first party
+/// - If they're second, they'll hand the match to the first party
 /// - If more parties try to join, they'll be rejected
+/// - If the waiting party timed out within `late_arrival_grace_sec`, the next one is rejected (410)
+/// - If the party's API key holds `max_waits_per_key` waits already, it's rejected (429)
+/// - If its connection has `max_requests_per_connection` requests in progress, it's rejected (429)
+/// - If it only observes, it waits for the match or timeout without being a party
+/// - If its `X-Sync-Metadata` doesn't conform to the template of the id, it's rejected (422) with
+///   the invalid fields, see `templates` module
+/// - If the wait points fill the share of `max_wait_points` of the party's priority, it's rejected
+///   (503), see `priorities` module
+/// - In maintenance mode, everyone is rejected with 503 & `Retry-After` header
+///
+/// Ids configured with another mode (see `modes` module) wait as a group instead, until the
+/// `count`-th party arrives (n-party) or a party joins with `release=true` (broadcast).
 ///
 /// # Arguments
 /// * `unique_id` - A string identifier for matching parties
+/// * `request` - The query parameters `dry_run`, `release`, `observe`, `ttl`, `priority` & `note`,
+///   see `SyncRequest`
+/// * `access` - Identity of the party & the ACL of a point it creates, see `acl` module
 /// * `state` - Rocket managed App instance containing synchronization data
 ///
 /// # Returns
 /// a `Custom<Json<ApiResponse>>` with:
 /// * HTTP status code indicating relevant success/failure reason
 /// * JSON response with success/error/timeout status and a friendly message
-#[post("/wait-for-second-party/<unique_id>")]
-pub async fn wait_for_party(unique_id: &str, state: &State<App>) -> Custom<Json<ApiResponse>> {
-    debug!("Wait request received for unique_id: {}", unique_id);
+///
+/// or `RetryAfter` during maintenance. Timeouts, conflicts & busy rejections are sent with the
+/// statuses of `response::StatusCodes`, 408, 409 & 503 by default
+#[post("/wait-for-second-party/<unique_id..>?<request..>")]
+pub async fn wait_for_party(
+    unique_id: IdPath,
+    request: SyncRequest,
+    access: Access,
+    state: &State<App>,
+) -> Result<Custom<Json<ApiResponse>>, RetryAfter> {
+    debug!("Wait request received for unique_id: {}", &*unique_id);
+    state
+        .sync_service
+        .join(&unique_id, request, access, state)
+        .await
+}
 
-    let point = match state.sync_service.get_or_create_point(unique_id) {
-        Ok(point) => point,
-        Err(response) => return response,
+/// Waits as the first party for `unique_id`, never acting as the second one. For clients whose
+/// role is known up front, so a party meant to notify can't end up waiting by arriving first.
+///
+/// Parties of n-party & broadcast ids are all waiters, they wait as a group like with
+/// `wait_for_party`.
+///
+/// `ttl` bounds the wait, `priority` & `note` apply like with `wait_for_party`.
+///
+/// # Returns
+/// Same as `wait_for_party`, 409 if a party is already waiting at a pair id
+#[post("/wait/<unique_id..>?<ttl>&<priority>&<note>")]
+pub async fn wait(
+    unique_id: IdPath,
+    ttl: Option<u64>,
+    priority: Option<Priority>,
+    note: Option<String>,
+    mut access: Access,
+    state: &State<App>,
+) -> Result<Custom<Json<ApiResponse>>, RetryAfter> {
+    let unique_id = unique_id.as_str();
+    debug!("Waiter arrived for unique_id: {}", unique_id);
+    reject_in_maintenance(unique_id, state)?;
+    let _connection = match state.acquire_connection_slot(&access) {
+        Ok(slot) => slot,
+        Err(limit) => return Ok(too_many_requests(limit)),
     };
+    access.priority = priority.unwrap_or_default();
+    access.note = match party_note(note) {
+        Ok(note) => note,
+        Err(response) => return Ok(response),
+    };
+    if let Err(response) = state.sync_service.templates.admit(unique_id, &access) {
+        return Ok(response);
+    }
 
-    let previous = point.parties_count.fetch_add(1, Ordering::SeqCst);
-    match previous {
-        0 => {
+    let mode = state.sync_service.mode(unique_id);
+    let ttl = match wait_point_ttl(unique_id, ttl, mode) {
+        Ok(ttl) => ttl,
+        Err(response) => return Ok(response),
+    };
+    let _slot = match state.acquire_wait_slot(&access) {
+        Ok(slot) => slot,
+        Err(limit) => return Ok(too_many_waits(limit)),
+    };
+    Ok(match mode {
+        Mode::Pair => {
             state
                 .sync_service
-                .handle_first_party(unique_id, point, state)
+                .handle_waiter(unique_id, ttl, &access, state)
                 .await
         }
-        1 => state.sync_service.handle_second_party(unique_id, point),
-        _ => state.sync_service.handle_extra_party(unique_id, previous),
+        mode @ (Mode::NParty { .. } | Mode::Broadcast) => {
+            state
+                .sync_service
+                .handle_group_party(unique_id, mode, &access, state)
+                .await
+        }
+    })
+}
+
+/// Matches the party waiting for `unique_id` without ever waiting itself, or releases the parties
+/// waiting at a broadcast id.
+///
+/// # Arguments
+/// * `unique_id` - A string identifier for matching parties
+/// * `persistent` - When `true` & nobody is waiting at a pair id, the notification is stored for
+///   the next party waiting within `notification_ttl_sec` (202), instead of failing
+/// * `note` - Like with `wait_for_party`, kept when the party joins a pair point
+/// * `access` - Identity of the party, checked against the ACL of the point
+/// * `state` - Rocket managed App instance containing synchronization data
+///
+/// # Returns
+/// Same as `wait_for_party`, 404 right away when nobody is waiting, 400 for n-party ids (whose
+/// parties all wait) & `persistent` at other than pair ids
+#[post("/notify/<unique_id..>?<persistent>&<note>")]
+pub async fn notify(
+    unique_id: IdPath,
+    persistent: Option<bool>,
+    note: Option<String>,
+    mut access: Access,
+    state: &State<App>,
+) -> Result<Custom<Json<ApiResponse>>, RetryAfter> {
+    let unique_id = unique_id.as_str();
+    debug!("Notifier arrived for unique_id: {}", unique_id);
+    reject_in_maintenance(unique_id, state)?;
+    let _connection = match state.acquire_connection_slot(&access) {
+        Ok(slot) => slot,
+        Err(limit) => return Ok(too_many_requests(limit)),
+    };
+    access.note = match party_note(note) {
+        Ok(note) => note,
+        Err(response) => return Ok(response),
+    };
+    if let Err(response) = state.sync_service.templates.admit(unique_id, &access) {
+        return Ok(response);
+    }
+
+    let persistent = persistent == Some(true);
+    Ok(match state.sync_service.mode(unique_id) {
+        Mode::Pair => state
+            .sync_service
+            .handle_notifier(unique_id, persistent, &access, state),
+        Mode::Broadcast if !persistent => {
+            state.sync_service.release_group(unique_id, &access).await
+        }
+        Mode::Broadcast => Custom(
+            Status::BadRequest,
+            Json(ApiResponse::error(format!(
+                "Releases of broadcast id {} can't be stored",
+                unique_id
+            ))),
+        ),
+        mode @ Mode::NParty { .. } => Custom(
+            Status::BadRequest,
+            Json(ApiResponse::error(format!(
+                "Parties of {} can only wait, it is {:?}",
+                unique_id, mode
+            ))),
+        ),
+    })
+}
+
+/// Reserves a seat for a party of `unique_id`, so orchestrators can check there's capacity for
+/// the rendezvous before kicking off the expensive work leading to it. The party sending the token
+/// as `X-Sync-Reservation` within `reservation_ttl_sec` is admitted even if the wait points
+/// filled up meanwhile. See `reservations` module
+///
+/// The seat is checked against the share of `max_wait_points` of `priority` (`normal` by default),
+/// the party should wait with the same one.
+///
+/// # Returns
+/// * `Ok(Either::Left(Json<Reservation>))` - The token
+/// * `Ok(Either::Right(Custom<Json<ApiResponse>>))` - 503 if there's no seat left
+/// * `Err(RetryAfter)` - During maintenance
+#[post("/reserve/<unique_id..>?<priority>")]
+pub async fn reserve(
+    unique_id: IdPath,
+    priority: Option<Priority>,
+    state: &State<App>,
+) -> Result<Either<Json<Reservation>, Custom<Json<ApiResponse>>>, RetryAfter> {
+    let unique_id = unique_id.as_str();
+    debug!("Reservation requested for unique_id: {}", unique_id);
+    reject_in_maintenance(unique_id, state)?;
+
+    Ok(
+        match state
+            .sync_service
+            .reserve(
+                unique_id,
+                priority.unwrap_or_default(),
+                state.max_wait_points(),
+            )
+            .await
+        {
+            Ok(token) => Either::Left(Json(Reservation {
+                unique_id: unique_id.to_owned(),
+                token: token.to_string(),
+                expires_in_sec: state.sync_service.reservations.ttl().as_secs(),
+            })),
+            Err(response) => Either::Right(response),
+        },
+    )
+}
+
+/// Validates the `ttl` query parameter of a waiting party
+///
+/// # Returns
+/// * `Ok(Option<Duration>)` - The TTL, if any
+/// * `Err(Custom<Json<ApiResponse>>)` - 400 if it's 0 or `unique_id` isn't a pair id, whose
+///   points are shared by the parties of a group
+pub fn wait_point_ttl(
+    unique_id: &str,
+    ttl: Option<u64>,
+    mode: Mode,
+) -> Result<Option<Duration>, Custom<Json<ApiResponse>>> {
+    let bad_request =
+        |message: String| Custom(Status::BadRequest, Json(ApiResponse::error(message)));
+    match (ttl, mode) {
+        (None, _) => Ok(None),
+        (Some(0), _) => Err(bad_request("ttl must be at least 1 sec".to_owned())),
+        (Some(ttl), Mode::Pair) => Ok(Some(Duration::from_secs(ttl))),
+        (Some(_), mode) => Err(bad_request(format!(
+            "Only pair ids take a ttl, {} is {:?}",
+            unique_id, mode
+        ))),
     }
 }
+
+/// Validates the `note` query parameter of a joining party. Control characters are rejected,
+/// as the note ends up in log lines
+///
+/// # Returns
+/// * `Ok(Option<String>)` - The trimmed note, `None` if missing or blank
+/// * `Err(Custom<Json<ApiResponse>>)` - 400 if it's longer than `MAX_NOTE_CHARS` or has control
+///   characters
+pub fn party_note(note: Option<String>) -> Result<Option<String>, Custom<Json<ApiResponse>>> {
+    let Some(note) = note.map(|note| note.trim().to_owned()) else {
+        return Ok(None);
+    };
+    if note.chars().count() > MAX_NOTE_CHARS {
+        return Err(Custom(
+            Status::BadRequest,
+            Json(ApiResponse::error(format!(
+                "note must be at most {} characters",
+                MAX_NOTE_CHARS
+            ))),
+        ));
+    }
+    if note.chars().any(char::is_control) {
+        return Err(Custom(
+            Status::BadRequest,
+            Json(ApiResponse::error(
+                "note must not have control characters".to_owned(),
+            )),
+        ));
+    }
+    Ok((!note.is_empty()).then_some(note))
+}
+
+/// During maintenance, rejects sync requests with 503 & `Retry-After` header
+// `RetryAfter` is as large as the response it becomes, boxing it would only move the allocation
+#[allow(clippy::result_large_err)]
+pub fn reject_in_maintenance(unique_id: &str, state: &State<App>) -> Result<(), RetryAfter> {
+    let Some(maintenance) = state.maintenance() else {
+        return Ok(());
+    };
+    debug!("Maintenance mode, rejecting unique_id: {}", unique_id);
+    Err(RetryAfter(
+        Custom(
+            Status::ServiceUnavailable,
+            Json(ApiResponse::error(maintenance.message)),
+        ),
+        Duration::from_secs(maintenance.retry_after_sec),
+    ))
+}
@@ -0,0 +1,340 @@
+//! Traffic shadowing (`[shadow]`), to validate a new version or backend under real traffic before
+//! cutover: rendezvous requests (`POST /wait-for-second-party`, `/wait` & `/notify`) are mirrored to
+//! the instance at `url`, e.g. `http://sync-point-next:8000` or `https://staging/sync` for one
+//! with a base. Mirrors have the path & query of the request & the headers parties identify with
+//! (`X-Api-Key`, the ACL & metadata ones, `User-Agent`), never a body.
+//!
+//! Mirrors are sent as requests arrive, so parties meet on the shadow as they do here, but from the
+//! blocking pool of the background runtime: a party never waits for the shadow. The status the shadow answers with is
+//! compared with ours, mirrors are counted per `ShadowOutcome` & exposed by `/metrics`, differing
+//! ones are logged. At most `max_in_flight` mirrors wait for the shadow, further requests aren't
+//! mirrored.
+//!
+//! Mirrors carry `X-Sync-Shadow: 1` & aren't mirrored again, so instances shadowing each other
+//! don't loop. ACLs by address range see this server's address. Needs the `shadow` Cargo feature.
+#[cfg(feature = "shadow")]
+use crate::app::App;
+#[cfg(feature = "shadow")]
+use crate::protocol::headers;
+#[cfg(feature = "shadow")]
+use log::{debug, info};
+#[cfg(feature = "shadow")]
+use parking_lot::Mutex;
+#[cfg(feature = "shadow")]
+use rocket::fairing::{Fairing, Info, Kind};
+#[cfg(feature = "shadow")]
+use rocket::http::Method;
+#[cfg(feature = "shadow")]
+use rocket::{Data, Request, Response};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "shadow")]
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "shadow")]
+use std::time::Duration;
+#[cfg(feature = "shadow")]
+use tokio::sync::oneshot;
+
+/// Paths of the mirrored requests, relative to the base
+#[cfg(feature = "shadow")]
+const MIRRORED_PATHS: [&str; 3] = ["/wait-for-second-party/", "/wait/", "/notify/"];
+
+/// Request headers mirrors have
+#[cfg(feature = "shadow")]
+const MIRRORED_HEADERS: [&str; 5] = [
+    headers::API_KEY,
+    headers::ALLOW_CIDRS,
+    headers::ALLOW_KEYS,
+    headers::METADATA,
+    headers::USER_AGENT,
+];
+
+/// `[shadow]` config section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShadowConfig {
+    /// Base URL of the shadow instance, shadowing is disabled when missing
+    pub url: Option<String>,
+    /// Time a mirror may wait for the shadow's answer, long enough for its waits
+    pub timeout_ms: u64,
+    /// Mirrors waiting for the shadow at most
+    pub max_in_flight: usize,
+}
+
+impl Default for ShadowConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            timeout_ms: 300_000,
+            max_in_flight: 1000,
+        }
+    }
+}
+
+impl ShadowConfig {
+    /// # Returns
+    /// * `Ok(())` - If the section is valid
+    /// * `Err(String)` - Describing the first invalid value
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(url) = &self.url {
+            if cfg!(not(feature = "shadow")) {
+                return Err("shadow.url needs the `shadow` Cargo feature".to_owned());
+            }
+            if base_url(url).is_none() {
+                return Err(format!(
+                    "shadow.url {} must be an http:// or https:// URL",
+                    url
+                ));
+            }
+        }
+        if self.timeout_ms == 0 {
+            return Err("shadow.timeout_ms must be at least 1".to_owned());
+        }
+        if self.max_in_flight == 0 {
+            return Err("shadow.max_in_flight must be at least 1".to_owned());
+        }
+        Ok(())
+    }
+}
+
+/// How the shadow's answer to a mirror compared with ours
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShadowOutcome {
+    /// Same status
+    Agreed,
+    Differed,
+    /// No answer within the timeout, or the shadow is unreachable
+    Failed,
+    /// Not mirrored, `max_in_flight` mirrors were waiting already
+    Dropped,
+}
+
+impl ShadowOutcome {
+    pub const ALL: [ShadowOutcome; 4] = [
+        ShadowOutcome::Agreed,
+        ShadowOutcome::Differed,
+        ShadowOutcome::Failed,
+        ShadowOutcome::Dropped,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ShadowOutcome::Agreed => "agreed",
+            ShadowOutcome::Differed => "differed",
+            ShadowOutcome::Failed => "failed",
+            ShadowOutcome::Dropped => "dropped",
+        }
+    }
+}
+
+/// Mirrors since the start per `ShadowOutcome`, & those waiting for the shadow right now
+#[derive(Debug, Default)]
+pub struct ShadowStats {
+    counts: [AtomicU64; 4],
+    #[cfg(feature = "shadow")]
+    in_flight: AtomicUsize,
+}
+
+impl ShadowStats {
+    pub fn count(&self, outcome: ShadowOutcome) -> u64 {
+        self.counts[outcome as usize].load(Ordering::Relaxed)
+    }
+
+    #[cfg(feature = "shadow")]
+    fn record(&self, outcome: ShadowOutcome) {
+        self.counts[outcome as usize].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// `url` without trailing slash, which mirrored paths are appended to. `None` unless it's an
+/// `http://` or `https://` URL with a host
+fn base_url(url: &str) -> Option<&str> {
+    let rest = url
+        .strip_prefix("http://")
+        .or_else(|| url.strip_prefix("https://"))?;
+    let host = rest.split('/').next().unwrap_or_default();
+    (!host.is_empty()).then(|| url.trim_end_matches('/'))
+}
+
+/// Our status for a mirrored request, sent once responded. In its local cache
+#[cfg(feature = "shadow")]
+struct PrimaryStatus(Mutex<Option<oneshot::Sender<u16>>>);
+
+/// Fairing mirroring the rendezvous requests under `base` to the shadow, see module docs. Attached
+/// by `SyncPointFairing` when `shadow.url` is set
+#[cfg(feature = "shadow")]
+pub struct Shadow {
+    /// Base with a trailing slash, e.g. `/sync/`
+    prefix: String,
+    /// Base URL of the shadow without trailing slash
+    target: String,
+    /// Carries the timeout
+    agent: ureq::Agent,
+    max_in_flight: usize,
+}
+
+/// A mirrored request, as sent to the shadow
+#[cfg(feature = "shadow")]
+struct Mirror {
+    url: String,
+    headers: Vec<(&'static str, String)>,
+}
+
+#[cfg(feature = "shadow")]
+impl Shadow {
+    /// # Returns
+    /// `None` without a valid `config.url`
+    pub fn new(base: &str, config: &ShadowConfig) -> Option<Self> {
+        Some(Self {
+            prefix: format!("{}/", base.trim_end_matches('/')),
+            target: base_url(config.url.as_deref()?)?.to_owned(),
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_millis(config.timeout_ms))
+                .build(),
+            max_in_flight: config.max_in_flight,
+        })
+    }
+
+    /// The mirror of `request`, `None` if it isn't mirrored
+    fn mirror_of(&self, request: &Request<'_>) -> Option<Mirror> {
+        if request.method() != Method::Post || request.headers().contains(headers::SHADOW) {
+            return None;
+        }
+        let relative = request
+            .uri()
+            .path()
+            .as_str()
+            .strip_prefix(self.prefix.as_str())
+            .map(|path| format!("/{}", path))?;
+        if !MIRRORED_PATHS.iter().any(|p| relative.starts_with(p)) {
+            return None;
+        }
+        let mut url = format!("{}{}", self.target, relative);
+        if let Some(query) = request.uri().query() {
+            url.push('?');
+            url.push_str(query.as_str());
+        }
+
+        let mut headers = vec![(headers::SHADOW, "1".to_owned())];
+        for name in MIRRORED_HEADERS {
+            for value in request.headers().get(name) {
+                headers.push((name, value.to_owned()));
+            }
+        }
+        Some(Mirror { url, headers })
+    }
+}
+
+#[cfg(feature = "shadow")]
+#[rocket::async_trait]
+impl Fairing for Shadow {
+    fn info(&self) -> Info {
+        Info {
+            name: "Traffic shadowing",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data<'_>) {
+        let Some(app) = request.rocket().state::<App>() else {
+            return;
+        };
+        let Some(mirror) = self.mirror_of(request) else {
+            return;
+        };
+        let stats = app.shadow.clone();
+        if stats.in_flight.fetch_add(1, Ordering::Relaxed) >= self.max_in_flight {
+            stats.in_flight.fetch_sub(1, Ordering::Relaxed);
+            stats.record(ShadowOutcome::Dropped);
+            return;
+        }
+
+        let (sender, primary) = oneshot::channel();
+        request.local_cache(|| PrimaryStatus(Mutex::new(Some(sender))));
+        let agent = self.agent.clone();
+        app.sync_service.background.spawn(async move {
+            let url = mirror.url.clone();
+            let answer = tokio::task::spawn_blocking(move || send(&agent, &mirror))
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|answer| answer);
+            stats.in_flight.fetch_sub(1, Ordering::Relaxed);
+            let status = match answer {
+                Ok(status) => status,
+                Err(e) => {
+                    debug!("Failed to mirror {} to the shadow: {}", url, e);
+                    return stats.record(ShadowOutcome::Failed);
+                }
+            };
+            // Dropped unsent when the request was cancelled, nothing to compare with then
+            let Ok(primary) = primary.await else {
+                return;
+            };
+            if status == primary {
+                stats.record(ShadowOutcome::Agreed);
+            } else {
+                info!(
+                    "Shadow answered {} with {}, this server with {}",
+                    url, status, primary
+                );
+                stats.record(ShadowOutcome::Differed);
+            }
+        });
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let primary = request.local_cache(|| PrimaryStatus(Mutex::new(None)));
+        if let Some(sender) = primary.0.lock().take() {
+            let _ = sender.send(response.status().code);
+        }
+    }
+}
+
+/// POSTs `mirror` without a body, the agent carries the timeout
+///
+/// # Returns
+/// * `Ok(u16)` - The status the shadow answered with, errors included
+/// * `Err(String)` - If it didn't answer
+#[cfg(feature = "shadow")]
+fn send(agent: &ureq::Agent, mirror: &Mirror) -> Result<u16, String> {
+    let mut request = agent.post(&mirror.url);
+    for (name, value) in &mirror.headers {
+        request = request.set(name, value);
+    }
+    match request.send_bytes(&[]) {
+        Ok(response) => Ok(response.status()),
+        Err(ureq::Error::Status(status, _)) => Ok(status),
+        Err(ureq::Error::Transport(transport)) => Err(transport.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::shadow::{base_url, ShadowConfig};
+
+    #[test]
+    fn test_base_urls() {
+        assert_eq!(
+            base_url("http://sync-point-next:8000"),
+            Some("http://sync-point-next:8000")
+        );
+        assert_eq!(
+            base_url("https://staging/sync/"),
+            Some("https://staging/sync")
+        );
+        assert_eq!(base_url("http://[::1]/"), Some("http://[::1]"));
+
+        for url in [
+            "ftp://sync-point-next",
+            "http://",
+            "https:///sync",
+            "sync-point-next:8000",
+        ] {
+            let config = ShadowConfig {
+                url: Some(url.to_owned()),
+                ..ShadowConfig::default()
+            };
+            assert!(config.validate().is_err(), "{}", url);
+        }
+    }
+}
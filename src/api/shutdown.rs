@@ -0,0 +1,127 @@
+//! Summary of a shutdown, so rolling restarts silently killing rendezvous show up: of the parties
+//! waiting when it began, how many were
+//! - `drained` - answered (matched or timed out) within Rocket's grace & mercy periods
+//! - `cancelled` - gone meanwhile, their client having disconnected
+//! - `force_dropped` - still waiting when the periods ended, their connections are closed
+//!
+//! The report, with the final `/stats` snapshot, is logged once the parties left or the periods
+//! ended. With `shutdown_report.webhook_url` (`hooks` feature), it's also POSTed there as JSON.
+use crate::api::sync_service::{Stats, SyncService};
+use log::info;
+#[cfg(feature = "hooks")]
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Interval at which the waiting parties are counted while draining
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// `[shutdown_report]` config section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShutdownReportConfig {
+    /// `http(s)://` URL the report is POSTed to, only logged when missing
+    pub webhook_url: Option<String>,
+    /// Time the webhook may take
+    pub timeout_ms: u64,
+}
+
+impl Default for ShutdownReportConfig {
+    fn default() -> Self {
+        Self {
+            webhook_url: None,
+            timeout_ms: 2000,
+        }
+    }
+}
+
+impl ShutdownReportConfig {
+    /// # Returns
+    /// * `Ok(())` - If the section is valid
+    /// * `Err(String)` - Describing the first invalid value
+    pub fn validate(&self) -> Result<(), String> {
+        let Some(url) = &self.webhook_url else {
+            return Ok(());
+        };
+        if cfg!(not(feature = "hooks")) {
+            return Err("shutdown_report.webhook_url needs the hooks feature".to_owned());
+        }
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(format!(
+                "shutdown_report.webhook_url {} must be http(s)",
+                url
+            ));
+        }
+        if self.timeout_ms == 0 {
+            return Err("shutdown_report.timeout_ms must be at least 1".to_owned());
+        }
+        Ok(())
+    }
+}
+
+/// What became of the parties waiting when the shutdown began, see module docs
+#[derive(Debug, Clone, Serialize)]
+pub struct ShutdownReport {
+    /// Parties waiting when the shutdown began
+    pub waiting_parties: usize,
+    pub drained: usize,
+    pub cancelled: usize,
+    pub force_dropped: usize,
+    /// Time from the start of the shutdown until the report
+    pub duration_ms: u64,
+    /// `/stats` at the time of the report
+    pub stats: Stats,
+}
+
+/// Waits for the waiting parties to be answered or leave, at most `grace` (Rocket's grace & mercy
+/// periods), then builds the report
+pub async fn drain(sync_service: &SyncService, grace: Duration) -> ShutdownReport {
+    let started = Instant::now();
+    let waiting_parties = sync_service.current_stats().waiting_parties;
+    let cancelled = sync_service.outcomes.stats().total.cancelled;
+
+    let mut remaining = waiting_parties;
+    while remaining > 0 && started.elapsed() < grace {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        remaining = sync_service.current_stats().waiting_parties;
+    }
+
+    let cancelled = sync_service.outcomes.stats().total.cancelled - cancelled;
+    let cancelled = (cancelled as usize).min(waiting_parties);
+    let force_dropped = remaining.min(waiting_parties - cancelled);
+    ShutdownReport {
+        waiting_parties,
+        drained: waiting_parties - cancelled - force_dropped,
+        cancelled,
+        force_dropped,
+        duration_ms: started.elapsed().as_millis() as u64,
+        stats: sync_service.current_stats(),
+    }
+}
+
+/// Logs `report` & POSTs it to the webhook, if configured
+#[cfg_attr(not(feature = "hooks"), allow(unused_variables))]
+pub async fn publish(report: &ShutdownReport, config: &ShutdownReportConfig) {
+    let json = serde_json::to_string(report).expect("serializable report");
+    info!("Shutdown report: {}", json);
+    #[cfg(feature = "hooks")]
+    if let Some(url) = config.webhook_url.clone() {
+        let agent = ureq::AgentBuilder::new()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .build();
+        let sent = tokio::task::spawn_blocking(move || {
+            agent
+                .post(&url)
+                .set("Content-Type", "application/json")
+                .send_string(&json)
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        })
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|sent| sent);
+        if let Err(e) = sent {
+            warn!("Failed to send the shutdown report: {}", e);
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//! Snapshots of the service counters taken every `stats_interval_ms` by a background task & served
+//! by `/stats`, `/stats/outcomes` & `/metrics`, so dashboards polling aggressively don't contend
+//! with rendezvous for the locks of the wait points & outcomes. Served data is up to an interval
+//! old, its `generation` (the `ETag` of `/stats`) tells which state it reflects.
+//!
+//! Disabled (0, the default), the counters are computed for every request.
+use crate::api::outcomes::OutcomeStats;
+use crate::api::sync_service::{Stats, SyncService};
+use parking_lot::RwLock;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+
+/// Counters as of the last refresh
+#[derive(Debug)]
+pub struct Snapshot {
+    pub stats: Stats,
+    pub outcomes: OutcomeStats,
+}
+
+/// The latest snapshot, empty until the refresh task took the first one
+#[derive(Debug, Default)]
+pub struct StatsCache {
+    snapshot: RwLock<Option<Arc<Snapshot>>>,
+}
+
+impl StatsCache {
+    pub fn get(&self) -> Option<Arc<Snapshot>> {
+        self.snapshot.read().clone()
+    }
+
+    fn set(&self, snapshot: Snapshot) {
+        *self.snapshot.write() = Some(Arc::new(snapshot));
+    }
+}
+
+/// Starts refreshing the snapshot of `service` every `interval` on its background runtime, for as
+/// long as the service lives
+pub fn spawn(service: &Arc<SyncService>, interval: Duration) {
+    let weak = Arc::downgrade(service);
+    service.background.spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        // A slow refresh mustn't be followed by a burst of them
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            let Some(service) = weak.upgrade() else {
+                return;
+            };
+            let snapshot = Snapshot {
+                stats: service.current_stats(),
+                outcomes: service.outcomes.stats(),
+            };
+            service.stats_cache.set(snapshot);
+        }
+    });
+}
@@ -1,39 +1,336 @@
-use crate::api::response::ApiResponse;
-use std::collections::HashMap;
+use crate::api::acl::{Access, Acl};
+use crate::api::anomaly::AnomalyDetector;
+use crate::api::completions::Completions;
+use crate::api::connections::too_many_requests;
+#[cfg(feature = "hooks")]
+use crate::api::hooks::{Event, Hooks};
+use crate::api::ids;
+use crate::api::late_arrivals::LateArrivals;
+use crate::api::local_notify::LocalNotify;
+use crate::api::modes::{Mode, ModeRoutes};
+use crate::api::observers::Observers;
+use crate::api::origins::Origin;
+use crate::api::outcomes::{OutcomeStats, WaitOutcomes};
+use crate::api::pagination::{paginate, Cursor, Page};
+use crate::api::pending::PendingNotifications;
+use crate::api::priorities::{Priorities, Priority, PriorityCounts};
+use crate::api::quotas::too_many_waits;
+#[cfg(feature = "receipts")]
+use crate::api::receipts::{self, ReceiptSigner};
+use crate::api::registry::Registry;
+use crate::api::reservations::Reservations;
+use crate::api::response::{ApiResponse, DryRunOutcome, Failure, RetryAfter, StatusCodes};
+use crate::api::routes::{party_note, reject_in_maintenance, wait_point_ttl};
+use crate::api::stats_cache::StatsCache;
+use crate::api::templates::Templates;
+use crate::log_file::rfc3339;
+#[cfg(feature = "receipts")]
+use crate::protocol::ReceiptPayload;
+use crate::protocol::{
+    Completion, ConflictDetails, ObservedEvent, PartyOrigin, PeerTimeout, Receipt,
+};
 
 use crate::app::App;
-use log::{debug, error};
-use parking_lot::RwLock;
+use crate::background::Background;
+#[cfg(feature = "chaos")]
+use crate::chaos::ChaosConfig;
+use log::{debug, error, info, warn};
+use parking_lot::{Mutex, RwLock};
 use rocket::http::Status;
 use rocket::response::status::Custom;
 use rocket::serde::json::Json;
-use rocket::State;
-use std::sync::atomic::AtomicUsize;
+use rocket::{FromForm, State};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::Notify;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{oneshot, watch};
+use tokio::time::Instant;
+use uuid::Uuid;
 
 /// Type alias for our shared state.
-/// Uses `parking_lot::RwLock` for better performance than `std::sync::RwLock`.
+/// Uses a blocking `parking_lot::RwLock`: every critical section is a few map operations, & its
+/// guards aren't `Send`, so the compiler rejects holding one across an `.await` in a handler.
+/// No request can keep the registry locked while it waits for its peer or another lock.
 /// Outer `Arc` is not needed, because Rocket's State<T> already provides the sharing mechanism we need
 /// Without inner `Arc`, we wouldn't be able to apply `.cloned()`
-/// `RwLock` itself provides thread-safe sharing
-pub type WaitPoints = RwLock<HashMap<String, Arc<WaitPoint>>>;
+/// `RwLock` itself provides thread-safe sharing, also between the registry's map & its indexes
+pub type WaitPoints = RwLock<Registry>;
+
+/// Sent by the second party to the waiting first one
+#[derive(Debug, Clone)]
+pub struct Match {
+    /// When the second party arrived
+    pub at: Instant,
+    /// Id of the match, sent to both parties
+    pub pair_id: Uuid,
+    /// Sent to both parties too, when receipts are issued
+    pub receipt: Option<Receipt>,
+}
+
+/// How a wait point ended, told to its observers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ended {
+    /// Its parties matched, with the `pair_id` of the match
+    Matched(Uuid),
+    /// Its parties gave up after their timeout
+    TimedOut,
+}
 
 /// Represents a synchronization point where two parties can meet
 pub struct WaitPoint {
-    /// Notifies the first waiting party when the second party arrives
-    pub notify: Notify,
+    /// Taken by the second party to hand the match over to the first one
+    sender: Mutex<Option<oneshot::Sender<Match>>>,
+    /// Taken by the first party to wait for the match.
+    /// Dropped when it stops waiting, so a late second party learns the first one has left
+    receiver: Mutex<Option<oneshot::Receiver<Match>>>,
     /// Atomic (thread-safe) counter to track how many parties have arrived (0, 1, or 2). Single CPU instruction, never blocks
     /// `Mutex` is overkill for simple counter, requires kernel-level locking/resources, threads block waiting for lock
     pub parties_count: AtomicUsize,
+    /// Service generation when this point was created, tells apart points re-created for the same id
+    pub generation: u64,
+    /// Reported to parties rejected with 409
+    pub created_at: SystemTime,
+    /// Matching strategy of the id, see `modes` module
+    pub mode: Mode,
+    /// Set once to the `pair_id` of the match releasing the waiting parties of a n-party or
+    /// broadcast point, `None` for pairs. A group point is never kept after its release,
+    /// `parties_count` only counts the parties still waiting
+    released: Option<watch::Sender<Option<Uuid>>>,
+    /// Who may join besides the creator, see `acl` module. Boxed, as most points have none
+    acl: Option<Box<Acl>>,
+    /// Class of the creating party, see `priorities` module
+    pub priority: Priority,
+    /// Fingerprint of the waiting party of a pair, only recorded when receipts are issued
+    waiter: Mutex<Option<Box<str>>>,
+    /// Notes the parties left for operators, see `add_note`
+    notes: Mutex<Vec<Box<str>>>,
+    /// Where the parties came from in order of arrival, see `origins` module
+    origins: Mutex<Vec<Origin>>,
+    /// Set once the point matched or timed out, its receivers are the observers of the point. A
+    /// point dropped without (e.g. its party went away) closes the channel instead
+    ended: watch::Sender<Option<Ended>>,
 }
 
 impl WaitPoint {
-    pub(crate) fn new() -> Self {
+    /// Notes & origins are only kept for this many parties, a group point may have any number
+    pub const MAX_RECORDED_PARTIES: usize = 8;
+
+    pub(crate) fn new(generation: u64) -> Self {
+        Self::with_mode(generation, Mode::Pair)
+    }
+
+    pub(crate) fn with_mode(generation: u64, mode: Mode) -> Self {
+        // A channel per point (i.e. per generation), so a match can't leak into a re-created point
+        let (sender, receiver) = oneshot::channel();
         Self {
-            notify: Notify::new(),
+            sender: Mutex::new(Some(sender)),
+            receiver: Mutex::new(Some(receiver)),
             parties_count: AtomicUsize::new(0),
+            generation,
+            created_at: SystemTime::now(),
+            mode,
+            released: (mode != Mode::Pair).then(|| watch::channel(None).0),
+            acl: None,
+            priority: Priority::Normal,
+            waiter: Mutex::new(None),
+            notes: Mutex::new(Vec::new()),
+            origins: Mutex::new(Vec::new()),
+            ended: watch::channel(None).0,
+        }
+    }
+
+    pub(crate) fn with_acl(mut self, acl: Option<Acl>) -> Self {
+        self.acl = acl.map(Box::new);
+        self
+    }
+
+    pub(crate) fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Keeps the note of a joining party (e.g. "waiting for blue/green switch of payments v42"),
+    /// shown in admin listings & the audit log of the match so stuck points explain themselves
+    pub fn add_note(&self, note: &str) {
+        let mut notes = self.notes.lock();
+        if notes.len() < Self::MAX_RECORDED_PARTIES {
+            notes.push(note.into());
+        }
+    }
+
+    /// Records where a joining party came from
+    fn add_origin(&self, access: &Access) {
+        let mut origins = self.origins.lock();
+        if origins.len() < Self::MAX_RECORDED_PARTIES {
+            origins.push(Origin::of(access));
+        }
+    }
+
+    /// Where the parties came from in order of arrival, for operators
+    pub fn origins(&self) -> Vec<PartyOrigin> {
+        self.origins.lock().iter().map(Origin::full).collect()
+    }
+
+    /// Where the parties came from in order of arrival, redacted for the record of the match
+    pub fn redacted_origins(&self) -> Vec<PartyOrigin> {
+        self.origins.lock().iter().map(Origin::redacted).collect()
+    }
+
+    /// Where the `position`-th party (from 0) came from, redacted for its peer
+    pub fn peer_origin(&self, position: usize) -> Option<PartyOrigin> {
+        self.origins.lock().get(position).map(Origin::redacted)
+    }
+
+    pub fn notes(&self) -> Vec<String> {
+        self.notes
+            .lock()
+            .iter()
+            .map(|note| note.to_string())
+            .collect()
+    }
+
+    /// Whether a party may join the point, always `true` without ACL
+    pub fn admits(&self, access: &Access) -> bool {
+        self.acl.as_ref().is_none_or(|acl| acl.permits(access))
+    }
+
+    /// Parties waiting for their peer(s)
+    pub fn waiting_parties(&self) -> usize {
+        let parties = self.parties_count.load(Ordering::SeqCst);
+        match self.mode {
+            Mode::Pair => usize::from(parties == 1),
+            Mode::NParty { .. } | Mode::Broadcast => parties,
+        }
+    }
+
+    /// Hands the match to the first party, `Err` if it has stopped waiting (or it was already sent)
+    fn send_match(&self, pair_id: Uuid, receipt: Option<Receipt>) -> Result<(), Match> {
+        let matched = Match {
+            at: Instant::now(),
+            pair_id,
+            receipt,
+        };
+        match self.sender.lock().take() {
+            Some(sender) => sender.send(matched),
+            None => Err(matched),
+        }
+    }
+
+    /// Releases the parties waiting at a group point
+    fn release(&self, pair_id: Uuid) {
+        if let Some(released) = &self.released {
+            released.send_replace(Some(pair_id));
+        }
+        self.end(Ended::Matched(pair_id));
+    }
+
+    /// Tells the observers how the point ended
+    fn end(&self, ended: Ended) {
+        self.ended.send_replace(Some(ended));
+    }
+
+    /// Subscribes an observer to the end of the point
+    ///
+    /// # Returns
+    /// The subscription, `None` if the point has `max` observers already
+    fn observe(&self, max: usize) -> Option<watch::Receiver<Option<Ended>>> {
+        let receiver = self.ended.subscribe();
+        // Counted once subscribed, so observers arriving together can't exceed `max`
+        (self.ended.receiver_count() <= max).then_some(receiver)
+    }
+
+    fn is_released(&self) -> bool {
+        self.released
+            .as_ref()
+            .is_some_and(|released| released.borrow().is_some())
+    }
+}
+
+/// Snapshot of a wait point, returned by `GET /status/<unique_id>`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WaitPointStatus {
+    pub unique_id: String,
+    /// Parties which joined so far, 0 when there's no open wait point
+    pub parties: usize,
+    /// Whether parties are waiting for their peer(s)
+    pub waiting: bool,
+    /// Wait point state generation, also the `ETag` of the response
+    #[serde(skip)]
+    pub etag: String,
+    /// Notes left by the parties, only in admin listings. See `WaitPoint::add_note`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+    /// Where the parties came from, only in admin listings. See `origins` module
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub origins: Vec<PartyOrigin>,
+}
+
+/// Service-wide counters, returned by `GET /stats`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Stats {
+    pub open_wait_points: usize,
+    pub waiting_parties: usize,
+    /// Approximate memory held by the open wait points, in bytes
+    pub memory_bytes: usize,
+    /// Open wait points by the class of their creator, see `priorities` module
+    pub open_by_priority: PriorityCounts,
+    /// Notifications stored by `persistent` notifiers for the next waiting party
+    pub pending_notifications: usize,
+    /// Incremented on every change of the wait points, also the `ETag` of the response
+    pub generation: u64,
+}
+
+/// What a party asks for when joining a wait point, the query parameters of
+/// `POST /wait-for-second-party/<unique_id>`. The default is a plain join, see `SyncService::join`
+#[derive(Debug, Clone, Default, FromForm)]
+pub struct SyncRequest {
+    /// Only reports what would happen (client preflight checks), without creating or joining a
+    /// wait point
+    pub dry_run: bool,
+    /// Releases the parties waiting at a broadcast id instead of waiting, 404 if there are none,
+    /// 400 for other modes
+    pub release: bool,
+    /// Waits for the point to match or time out without joining it, so monitoring doesn't take a
+    /// party's seat. See `SyncService::observe`
+    pub observe: bool,
+    /// Seconds the wait point may exist at most when this party creates it, bounding its wait
+    /// below `timeout`. Ignored when it joins as the second party, 400 for other than pair ids
+    pub ttl: Option<u64>,
+    /// Class of the party when creating a wait point, `normal` by default
+    #[field(default = Priority::Normal)]
+    pub priority: Priority,
+    /// Shown to operators in admin listings & the audit log of the match, e.g. what the party
+    /// waits for. At most `MAX_NOTE_CHARS` characters without control characters, 400 otherwise
+    pub note: Option<String>,
+}
+
+/// Armed while a first party waits. Dropped unfinished means the request was cancelled (a batch
+/// client disconnected, see `outcomes` module): the wait counts as cancelled & its point is cleaned
+/// up, as `handle_first_party` won't get to it. For a group point, only the party leaves it
+#[must_use = "dropping it right away counts the wait as cancelled"]
+struct PendingWait<'a> {
+    app: &'a App,
+    unique_id: &'a str,
+    group: Option<Arc<WaitPoint>>,
+    finished: bool,
+}
+
+impl Drop for PendingWait<'_> {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        debug!("Wait cancelled for unique_id: {}", self.unique_id);
+        let service = &self.app.sync_service;
+        service.outcomes.cancelled(self.unique_id);
+        match &self.group {
+            Some(point) => {
+                service.leave_group(self.unique_id, point);
+            }
+            None => {
+                let _ = service.cleanup_wait_point(self.unique_id);
+            }
         }
     }
 }
@@ -41,21 +338,178 @@ impl WaitPoint {
 /// Manages the logic when 2 or more parties attempt to connect on some unique identifier
 pub struct SyncService {
     pub wait_points: WaitPoints,
+    /// Bumped on every wait point change (creation, join, removal), so pollers can cheaply detect changes
+    generation: AtomicU64,
+    /// Approximate memory budget of the wait points in bytes, 0 means unlimited
+    pub(crate) max_memory_bytes: usize,
+    /// Reports slow rendezvous, repeated conflicts & cleanup failures
+    pub(crate) anomalies: AnomalyDetector,
+    /// How unmatched first parties ended, see `GET /stats/outcomes`
+    pub outcomes: WaitOutcomes,
+    /// Matching strategy per id, see `modes` module
+    pub(crate) modes: ModeRoutes,
+    /// Metadata schemas parties must conform to, see `templates` module
+    pub(crate) templates: Templates,
+    /// Statuses of timeouts, conflicts & busy rejections, see `response::StatusCodes`
+    pub(crate) status_codes: StatusCodes,
+    /// Notifications waiting for a party, see `pending` module
+    pub(crate) pending: PendingNotifications,
+    /// Recent timeouts of waiting parties, see `late_arrivals` module
+    pub(crate) late_arrivals: LateArrivals,
+    /// Admission of new wait points by class, see `priorities` module
+    pub(crate) priorities: Priorities,
+    /// Seats held for parties by `POST /reserve/<unique_id>`, see `reservations` module
+    pub(crate) reservations: Reservations,
+    /// Latest counters when `stats_interval_ms` is set, see `stats_cache` module
+    pub(crate) stats_cache: StatsCache,
+    /// Runs hooks & the stats refresh, see `background` module
+    pub(crate) background: Background,
+    /// Whether the parties of a pair get the redacted origin of their peer, see `origins` module
+    pub(crate) share_peer_origin: bool,
+    /// Signs match receipts, none are issued when missing. See `receipts` module
+    #[cfg(feature = "receipts")]
+    pub(crate) receipts: Option<ReceiptSigner>,
+    /// Event streams of `GET /observe/<pattern>`, see `observers` module
+    pub(crate) observers: Observers,
+    /// Records of recent matches, see `completions` module
+    pub(crate) completions: Completions,
+    /// Commands & webhooks run on events, see `hooks` module
+    #[cfg(feature = "hooks")]
+    pub(crate) hooks: Hooks,
+    /// Signal files & dbus signals on completions, see `local_notify` module
+    pub(crate) local_notify: LocalNotify,
+    /// Fault injection settings, see `chaos` module
+    #[cfg(feature = "chaos")]
+    pub(crate) chaos: ChaosConfig,
 }
 
 impl SyncService {
     pub(crate) fn new() -> Self {
         Self {
-            wait_points: RwLock::new(HashMap::new()),
+            wait_points: RwLock::new(Registry::default()),
+            generation: AtomicU64::new(0),
+            max_memory_bytes: 0,
+            anomalies: AnomalyDetector::default(),
+            outcomes: WaitOutcomes::default(),
+            modes: ModeRoutes::default(),
+            templates: Templates::default(),
+            status_codes: StatusCodes::default(),
+            pending: PendingNotifications::default(),
+            late_arrivals: LateArrivals::default(),
+            priorities: Priorities::default(),
+            reservations: Reservations::default(),
+            stats_cache: StatsCache::default(),
+            background: Background::default(),
+            share_peer_origin: false,
+            #[cfg(feature = "receipts")]
+            receipts: None,
+            observers: Observers::default(),
+            completions: Completions::default(),
+            #[cfg(feature = "hooks")]
+            hooks: Hooks::default(),
+            local_notify: LocalNotify::default(),
+            #[cfg(feature = "chaos")]
+            chaos: ChaosConfig::default(),
+        }
+    }
+
+    /// Joins `unique_id` like `POST /wait-for-second-party/<unique_id>` does, the entry point for
+    /// the route & for the server's own parties (simulations, self tests, bridges...)
+    ///
+    /// # Arguments
+    /// * `unique_id` - A string identifier for matching parties
+    /// * `request` - What the party asks for, see `SyncRequest`
+    /// * `access` - Identity of the party & the ACL of a point it creates, see `acl` module
+    /// * `app` - Application state containing synchronization data
+    ///
+    /// # Returns
+    /// the response of the route, or `RetryAfter` during maintenance
+    pub async fn join(
+        &self,
+        unique_id: &str,
+        request: SyncRequest,
+        mut access: Access,
+        app: &App,
+    ) -> Result<Custom<Json<ApiResponse>>, RetryAfter> {
+        let state = <&State<App>>::from(app);
+        reject_in_maintenance(unique_id, state)?;
+        let _connection = match app.acquire_connection_slot(&access) {
+            Ok(slot) => slot,
+            Err(limit) => return Ok(too_many_requests(limit)),
+        };
+        if request.observe {
+            return Ok(self.observe(unique_id, &access, state).await);
+        }
+        access.priority = request.priority;
+        access.note = match party_note(request.note) {
+            Ok(note) => note,
+            Err(response) => return Ok(response),
+        };
+        if let Err(response) = self.templates.admit(unique_id, &access) {
+            return Ok(response);
         }
+
+        if request.dry_run {
+            return Ok(self.dry_run(unique_id, access.priority, app.max_wait_points()));
+        }
+
+        let mode = self.mode(unique_id);
+        let ttl = match wait_point_ttl(unique_id, request.ttl, mode) {
+            Ok(ttl) => ttl,
+            Err(response) => return Ok(response),
+        };
+        let _slot = match app.acquire_wait_slot(&access) {
+            Ok(slot) => slot,
+            Err(limit) => return Ok(too_many_waits(limit)),
+        };
+        match (mode, request.release) {
+            (Mode::Pair, false) => {}
+            (Mode::Broadcast, true) => return Ok(self.release_group(unique_id, &access).await),
+            (_, true) => {
+                return Ok(Custom(
+                    Status::BadRequest,
+                    Json(ApiResponse::error(format!(
+                        "Only broadcast ids can be released, {} is {:?}",
+                        unique_id, mode
+                    ))),
+                ))
+            }
+            (Mode::NParty { .. } | Mode::Broadcast, false) => {
+                return Ok(self
+                    .handle_group_party(unique_id, mode, &access, state)
+                    .await)
+            }
+        }
+
+        if let Some(response) = self.late_arrival(unique_id) {
+            return Ok(response);
+        }
+        let point = match self
+            .open_point(unique_id, &access, app.max_wait_points())
+            .await
+        {
+            Ok(point) => point,
+            Err(response) => return Ok(response),
+        };
+
+        let previous = self.arrive(&point, &access);
+        if previous < 2 {
+            self.redeem_reservation(unique_id, &access);
+        }
+        Ok(match previous {
+            0 => self.handle_first_party(unique_id, point, ttl, state).await,
+            1 => self.handle_second_party(unique_id, point, &access, state),
+            _ => self.handle_extra_party(unique_id, &point, previous, app.timeout()),
+        })
     }
 
-    /// Handles logic when first party arrives. It will wait for a notification within timeout
+    /// Handles logic when first party arrives. It will wait for the match within timeout
     /// & return either timeout or welcome message
     ///
     /// # Arguments
     /// * `unique_id` - A string identifier for matching parties
     /// * `point: Arc<WaitPoint>` - The newly created wait point
+    /// * `ttl` - How long the point may exist at most, bounding the wait below the timeout
     /// * `state` - Application state containing the timeout config
     ///
     /// # Returns
@@ -66,58 +520,183 @@ impl SyncService {
         &self,
         unique_id: &str,
         point: Arc<WaitPoint>,
+        ttl: Option<Duration>,
         state: &State<App>,
     ) -> Custom<Json<ApiResponse>> {
-        // Wait for a notification with a timeout
-        // A future which completes when `notify_one()` is called
-        let result = tokio::time::timeout(
-            Duration::from_secs(state.timeout.as_secs()),
-            point.notify.notified(),
-        )
-        .await; // Execution suspends here
-        
+        // Read once, so that a runtime change can't make the response disagree with the actual wait
+        let timeout = ttl.map_or(state.timeout(), |ttl| ttl.min(state.timeout()));
+
+        let Some(receiver) = point.receiver.lock().take() else {
+            // Only the party which joined an empty point gets here, so someone else took it already
+            return self.handle_extra_party(unique_id, &point, 0, timeout);
+        };
+        // Dropping the receiver tells a second party arriving meanwhile that it's too late
+        if let Some(pair_id) = self.pending.take(unique_id) {
+            drop(receiver);
+            debug!("Stored notification taken for unique_id: {}", unique_id);
+            self.record_match(unique_id, pair_id, 2, &point, None);
+            point.end(Ended::Matched(pair_id));
+            self.bump_generation();
+            if let Err(e) = self.cleanup_wait_point(unique_id) {
+                return e;
+            }
+            return Custom(
+                Status::Ok,
+                Json(
+                    ApiResponse::success("Welcome! (first party)", unique_id)
+                        .with_pair_id(&pair_id.to_string()),
+                ),
+            );
+        }
+        let started = Instant::now();
+        let mut pending = PendingWait {
+            app: state.inner(),
+            unique_id,
+            group: None,
+            finished: false,
+        };
+        self.observers
+            .publish(ObservedEvent::Waiting, unique_id, None, 1);
+
+        // Wait for the match with a timeout
+        // The receiver is dropped as soon as the timeout elapses, a second party arriving after that
+        // can tell it was too late
+        let result = tokio::time::timeout(timeout, receiver).await; // Execution suspends here
+        pending.finished = true;
+
         if let Err(e) = self.cleanup_wait_point(unique_id) {
             return e;
         }
 
         match result {
-            Ok(_) => {
-                debug!("Notification received for unique_id: {}", unique_id);
-                Custom(
-                    Status::Ok,
-                    Json(ApiResponse::success("Welcome! (first party)", unique_id)),
-                )
+            Ok(Ok(matched)) => {
+                let elapsed = matched.at - started;
+                debug!(
+                    "Match received for unique_id: {} after {:?}",
+                    unique_id, elapsed
+                );
+                self.anomalies.matched(unique_id, elapsed, timeout);
+                point.end(Ended::Matched(matched.pair_id));
+                let mut response = ApiResponse::success("Welcome! (first party)", unique_id)
+                    .with_pair_id(&matched.pair_id.to_string());
+                if let Some(receipt) = matched.receipt {
+                    response = response.with_receipt(receipt);
+                }
+                if let Some(peer) = self.peer_origin(&point, 1) {
+                    response = response.with_peer(peer);
+                }
+                Custom(Status::Ok, Json(response))
+            }
+            // The sender lives in the point we hold, so it's only dropped unsent after a timeout
+            Ok(Err(_)) | Err(_) => {
+                self.outcomes.timed_out(unique_id);
+                self.late_arrivals.timed_out(unique_id);
+                point.end(Ended::TimedOut);
+                self.observers
+                    .publish(ObservedEvent::Timeout, unique_id, None, 1);
+                #[cfg(feature = "hooks")]
+                self.hooks
+                    .fire(&Event::timeout(unique_id), &self.background);
+                self.status_codes
+                    .respond(Failure::Timeout, ApiResponse::timeout(timeout, unique_id))
             }
-            Err(_) => Custom(
-                Status::RequestTimeout,
-                Json(ApiResponse::timeout(state.timeout, unique_id))
-            )
         }
     }
 
+    /// Rejects a party finding nobody waiting at `unique_id` with 410, when the waiting party
+    /// timed out within `late_arrival_grace_sec`, see `late_arrivals` module
+    ///
+    /// # Returns
+    /// The rejection, `None` if the party goes on as usual (e.g. another party waits meanwhile)
+    pub fn late_arrival(&self, unique_id: &str) -> Option<Custom<Json<ApiResponse>>> {
+        let timed_out_at = self.late_arrivals.take(unique_id)?;
+        if self.wait_points.read().get(unique_id).is_some() {
+            return None;
+        }
+        debug!(
+            "Waiting party recently timed out for unique_id: {}",
+            unique_id
+        );
+        let timed_out_at = timed_out_at.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let details = PeerTimeout {
+            timed_out_at: rfc3339(timed_out_at.as_secs(), timed_out_at.subsec_millis()),
+        };
+        Some(Custom(
+            Status::Gone,
+            Json(ApiResponse::peer_recently_timed_out(details, unique_id)),
+        ))
+    }
+
     /// Handles logic when second party arrives for the same unique endpoint.
-    /// It will then notify the first party and return a welcome message
+    /// It will then hand the match to the first party and return a welcome message
     ///
     /// # Arguments
     /// * `unique_id` - A string identifier for matching parties
     /// * `point: Arc<WaitPoint>` - The existing wait point created for first party
+    /// * `access` - Identity of the party, for the match receipt
+    /// * `state` - Application state containing the timeout config
     ///
     /// # Returns
     /// a `Custom<Json<ApiResponse>>` with:
     /// * HTTP Status code indicating relevant success/failure reason
-    /// * JSON response with success status and a friendly message
+    /// * JSON response with success status and a friendly message, or timeout if the first
+    ///   party timed out just before. `delivered` tells which, unless chaos delays the match
     pub fn handle_second_party(
         &self,
         unique_id: &str,
         point: Arc<WaitPoint>,
+        access: &Access,
+        state: &State<App>,
     ) -> Custom<Json<ApiResponse>> {
         debug!("Second party arrived for unique_id: {}", unique_id);
-        point.notify.notify_one();
+        // Generated here, where the match happens, so both parties get the same one
+        let pair_id = Uuid::new_v4();
+        let receipt = self.receipt(unique_id, pair_id, &point, access);
+        let peer = self.peer_origin(&point, 0);
+        let welcome = || {
+            let response = ApiResponse::success("Welcome! (second party)", unique_id)
+                .with_pair_id(&pair_id.to_string());
+            match &peer {
+                Some(peer) => response.with_peer(peer.clone()),
+                None => response,
+            }
+        };
+        match self.chaos_notification_delay() {
+            Some(delay) => {
+                let unique_id = unique_id.to_owned();
+                let app = state.inner().clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    if point.send_match(pair_id, receipt.clone()).is_ok() {
+                        app.sync_service.record_match(
+                            &unique_id,
+                            pair_id,
+                            2,
+                            &point,
+                            receipt.as_ref(),
+                        );
+                    }
+                });
+            }
+            None => {
+                if point.send_match(pair_id, receipt.clone()).is_err() {
+                    debug!("First party already left unique_id: {}", unique_id);
+                    return self.status_codes.respond(
+                        Failure::Timeout,
+                        ApiResponse::timeout(state.timeout(), unique_id).with_delivered(false),
+                    );
+                }
+                self.record_match(unique_id, pair_id, 2, &point, receipt.as_ref());
+                let mut response = welcome().with_delivered(true);
+                if let Some(receipt) = receipt {
+                    response = response.with_receipt(receipt);
+                }
+                return Custom(Status::Ok, Json(response));
+            }
+        }
 
-        Custom(
-            Status::Ok,
-            Json(ApiResponse::success("Welcome! (second party)", unique_id)),
-        )
+        // The delayed match may still find the first party gone, so no receipt either
+        Custom(Status::Ok, Json(welcome()))
     }
 
     /// Handles logic when more than 2 parties try to join the same unique endpoint.
@@ -128,27 +707,507 @@ impl SyncService {
     ///
     /// # Arguments
     /// * `unique_id` - A string identifier for matching parties
+    /// * `point` - The wait point which is taken
     /// * `previous` - Party count indicator
+    /// * `timeout` - How long the first party waits at most
     ///
     /// # Returns
     /// a `Custom<Json<ApiResponse>>` with:
     /// * 409 (Conflict) HTTP Status code
-    /// * JSON response with error message indicating party limit exceeded
+    /// * JSON response with error message indicating party limit exceeded, plus the state of the
+    ///   point & when to retry
     pub fn handle_extra_party(
         &self,
         unique_id: &str,
+        point: &WaitPoint,
         previous: usize,
+        timeout: Duration,
     ) -> Custom<Json<ApiResponse>> {
         debug!(
             "Unexpected party count {} for unique_id: {}",
             previous, unique_id
         );
+        self.anomalies.conflict(unique_id);
+
+        // The point is removed once its first party stops waiting: right after a match, at the
+        // latest when its timeout elapses
+        let retry_after = if previous >= 2 {
+            Duration::from_secs(1)
+        } else {
+            let elapsed = point.created_at.elapsed().unwrap_or_default();
+            timeout.saturating_sub(elapsed)
+        };
+        let created_at = point
+            .created_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let details = ConflictDetails {
+            parties: previous,
+            created_at: rfc3339(created_at.as_secs(), created_at.subsec_millis()),
+            generation: point.generation,
+            retry_after_sec: retry_after.as_secs_f64().ceil().max(1.0) as u64,
+        };
+        self.status_codes
+            .respond(Failure::Conflict, ApiResponse::conflict(details))
+    }
+
+    /// Matching strategy of `unique_id`, see `modes` module
+    pub fn mode(&self, unique_id: &str) -> Mode {
+        self.modes.resolve(unique_id)
+    }
+
+    /// Handles a party arriving at a n-party or broadcast id. It waits within the timeout until
+    /// the point is released: by the `count`-th party (n-party) or a releasing party (broadcast),
+    /// see `release_group`. A party timing out leaves the point, the others keep waiting
+    ///
+    /// # Arguments
+    /// * `unique_id` - A string identifier for matching parties
+    /// * `mode` - Group mode of `unique_id`
+    /// * `access` - Identity of the party, checked against the ACL of the point
+    /// * `state` - Application state containing the timeout config
+    ///
+    /// # Returns
+    /// a `Custom<Json<ApiResponse>>` with:
+    /// * HTTP Status code indicating relevant success/failure reason
+    /// * JSON response with success/error/timeout status and a friendly message
+    pub async fn handle_group_party(
+        &self,
+        unique_id: &str,
+        mode: Mode,
+        access: &Access,
+        state: &State<App>,
+    ) -> Custom<Json<ApiResponse>> {
+        let timeout = state.timeout();
+        let (point, position) =
+            match self.join_group(unique_id, mode, access, state.max_wait_points()) {
+                Ok(joined) => joined,
+                Err(response) => return response,
+            };
+        let welcome = match mode {
+            Mode::NParty { count } => format!("Welcome! (party {} of {})", position, count),
+            Mode::Pair | Mode::Broadcast => "Welcome! (released)".to_owned(),
+        };
+
+        // `wait_for` sees a release which happened before subscribing too
+        let mut receiver = point.released.as_ref().expect("group point").subscribe();
+        let mut pending = PendingWait {
+            app: state.inner(),
+            unique_id,
+            group: Some(point.clone()),
+            finished: false,
+        };
+        self.observers
+            .publish(ObservedEvent::Waiting, unique_id, None, position);
+        // The sender lives in the point we hold, so waiting only fails on timeout
+        let pair_id = match tokio::time::timeout(timeout, receiver.wait_for(Option::is_some)).await
+        {
+            Ok(Ok(released)) => *released,
+            Ok(Err(_)) | Err(_) => None,
+        };
+        pending.finished = true;
+
+        let pair_id = match pair_id {
+            Some(pair_id) => pair_id,
+            None if self.leave_group(unique_id, &point) => {
+                self.outcomes.timed_out(unique_id);
+                // The last party leaving removed the point
+                if point.parties_count.load(Ordering::SeqCst) == 0 {
+                    point.end(Ended::TimedOut);
+                }
+                self.observers
+                    .publish(ObservedEvent::Timeout, unique_id, None, 1);
+                #[cfg(feature = "hooks")]
+                self.hooks
+                    .fire(&Event::timeout(unique_id), &self.background);
+                return self
+                    .status_codes
+                    .respond(Failure::Timeout, ApiResponse::timeout(timeout, unique_id));
+            }
+            // Released right as the timeout elapsed counts as released
+            None => receiver.borrow().expect("released"),
+        };
+        Custom(
+            Status::Ok,
+            Json(ApiResponse::success(welcome, unique_id).with_pair_id(&pair_id.to_string())),
+        )
+    }
+
+    /// Releases the parties waiting at a broadcast id & removes its point
+    ///
+    /// # Returns
+    /// a `Custom<Json<ApiResponse>>` with:
+    /// * 200 & the number of released parties
+    /// * 403 when the ACL of the point doesn't admit `access`
+    /// * 404 when nobody is waiting
+    pub async fn release_group(
+        &self,
+        unique_id: &str,
+        access: &Access,
+    ) -> Custom<Json<ApiResponse>> {
+        if self.chaos_lock_failure() {
+            return ApiResponse::service_unavailable();
+        }
+        // Joining & leaving take the write lock too, so the count can't change meanwhile
+        let mut points = self.wait_points.write();
+        let Some(point) = points.get(unique_id) else {
+            return Self::nobody_waiting(unique_id);
+        };
+        if !point.admits(access) {
+            return Self::forbidden(unique_id);
+        }
+        let point = points.remove(unique_id).expect("point found above");
+        self.redeem_reservation(unique_id, access);
+        let pair_id = Uuid::new_v4();
+        point.release(pair_id);
+        self.bump_generation();
+
+        let parties = point.parties_count.load(Ordering::SeqCst);
+        debug!("Released {} parties of unique_id: {}", parties, unique_id);
+        // The releasing party is part of the match
+        self.record_match(unique_id, pair_id, parties + 1, &point, None);
+        Custom(
+            Status::Ok,
+            Json(
+                ApiResponse::success(format!("Released {} waiting parties", parties), unique_id)
+                    .with_delivered(true)
+                    .with_pair_id(&pair_id.to_string()),
+            ),
+        )
+    }
+
+    /// Handles a party of `POST /wait/<unique_id>`, which only ever waits: it joins the point as
+    /// its first party (for `ttl` at most, see `handle_first_party`), or is rejected (409) if the
+    /// point already has one
+    ///
+    /// # Returns
+    /// Same as `handle_first_party`, or 409 with the state of the point, or 403 when the point
+    /// was created by another party & its ACL doesn't admit `access`
+    pub async fn handle_waiter(
+        &self,
+        unique_id: &str,
+        ttl: Option<Duration>,
+        access: &Access,
+        state: &State<App>,
+    ) -> Custom<Json<ApiResponse>> {
+        let point = match self
+            .open_point(unique_id, access, state.max_wait_points())
+            .await
+        {
+            Ok(point) => point,
+            Err(response) => return response,
+        };
+        match self.join_as(&point, 0, access) {
+            Ok(()) => {
+                self.redeem_reservation(unique_id, access);
+                self.handle_first_party(unique_id, point, ttl, state).await
+            }
+            Err(parties) => self.handle_extra_party(unique_id, &point, parties, state.timeout()),
+        }
+    }
+
+    /// Handles a party of `POST /notify/<unique_id>`, which never waits: it matches the first party
+    /// waiting at the point, without creating one
+    ///
+    /// # Arguments
+    /// * `unique_id` - A string identifier for matching parties
+    /// * `persistent` - When nobody is waiting, stores the notification for the next party
+    ///   waiting within `notification_ttl_sec`, see `pending` module
+    /// * `access` - Identity of the party, checked against the ACL of the point
+    /// * `state` - Application state containing the timeout config
+    ///
+    /// # Returns
+    /// Same as `handle_second_party`, or when nobody is waiting 404 (202 if stored, 410 if the
+    /// waiting party recently timed out), or 403 when the ACL of the point doesn't admit `access`
+    pub fn handle_notifier(
+        &self,
+        unique_id: &str,
+        persistent: bool,
+        access: &Access,
+        state: &State<App>,
+    ) -> Custom<Json<ApiResponse>> {
+        let point = self.wait_points.read().get(unique_id).cloned();
+        if point.as_ref().is_some_and(|point| !point.admits(access)) {
+            return Self::forbidden(unique_id);
+        }
+        // A point with no party yet is about to get its first one, which isn't waiting yet either
+        let deliver = || {
+            point
+                .as_ref()
+                .is_some_and(|point| self.join_as(point, 1, access).is_ok())
+        };
+        // Only used when stored, a delivered match gets its own
+        let pair_id = Uuid::new_v4();
+        let delivered = if persistent {
+            self.pending.deliver_or_store(unique_id, pair_id, deliver)
+        } else {
+            deliver()
+        };
+
+        match point {
+            Some(point) if delivered => {
+                self.redeem_reservation(unique_id, access);
+                self.handle_second_party(unique_id, point, access, state)
+            }
+            _ if persistent => {
+                debug!("Notification stored for unique_id: {}", unique_id);
+                self.bump_generation();
+                let message = format!(
+                    "Notification stored for the next waiting party, expires in {} sec",
+                    self.pending.ttl().as_secs()
+                );
+                Custom(
+                    Status::Accepted,
+                    Json(
+                        ApiResponse::success(message, unique_id)
+                            .with_delivered(false)
+                            .with_pair_id(&pair_id.to_string()),
+                    ),
+                )
+            }
+            _ => match self.late_arrival(unique_id) {
+                Some(response) => response,
+                None => Self::nobody_waiting(unique_id),
+            },
+        }
+    }
+
+    /// Handles an observer of `POST /wait-for-second-party/<unique_id>?observe=true`, which waits
+    /// for the point to match or time out without counting as one of its parties
+    ///
+    /// # Arguments
+    /// * `unique_id` - A string identifier for matching parties
+    /// * `access` - Identity of the observer, checked against the ACL of the point
+    /// * `state` - Application state containing the timeout & `max_observers_per_point` config
+    ///
+    /// # Returns
+    /// a `Custom<Json<ApiResponse>>` with:
+    /// * 200 & the `pair_id` once matched
+    /// * 408 once its parties timed out, or after `timeout` with the point still open
+    /// * 404 when nobody is waiting, or its parties went away without a match (e.g. cancelled)
+    /// * 403 when the ACL of the point doesn't admit `access`
+    /// * 429 when the point has `max_observers_per_point` observers already
+    pub async fn observe(
+        &self,
+        unique_id: &str,
+        access: &Access,
+        state: &State<App>,
+    ) -> Custom<Json<ApiResponse>> {
+        let timeout = state.timeout();
+        let Some(point) = self.wait_points.read().get(unique_id).cloned() else {
+            return Self::nobody_waiting(unique_id);
+        };
+        if !point.admits(access) {
+            return Self::forbidden(unique_id);
+        }
+        let max = state.max_observers_per_point();
+        let Some(mut ended) = point.observe(max) else {
+            return Custom(
+                Status::TooManyRequests,
+                Json(ApiResponse::error(format!(
+                    "At most {} observers per wait point",
+                    max
+                ))),
+            );
+        };
+        // Not kept alive by its observers, a point dropped without ending closes the channel
+        drop(point);
+        debug!("Observer attached to unique_id: {}", unique_id);
+
+        let ended = match tokio::time::timeout(timeout, ended.wait_for(Option::is_some)).await {
+            Ok(Ok(ended)) => *ended,
+            Ok(Err(_)) => None,
+            Err(_) => Some(Ended::TimedOut),
+        };
+        match ended {
+            Some(Ended::Matched(pair_id)) => Custom(
+                Status::Ok,
+                Json(
+                    ApiResponse::success("Observed the match", unique_id)
+                        .with_pair_id(&pair_id.to_string()),
+                ),
+            ),
+            Some(Ended::TimedOut) => self
+                .status_codes
+                .respond(Failure::Timeout, ApiResponse::timeout(timeout, unique_id)),
+            None => Custom(
+                Status::NotFound,
+                Json(ApiResponse::error(format!(
+                    "The parties of {} left without a match",
+                    unique_id
+                ))),
+            ),
+        }
+    }
+
+    /// 404 for a notifying or releasing party finding nobody to wake up
+    fn nobody_waiting(unique_id: &str) -> Custom<Json<ApiResponse>> {
+        Custom(
+            Status::NotFound,
+            Json(
+                ApiResponse::error(format!("Nobody is waiting on {}", unique_id))
+                    .with_delivered(false),
+            ),
+        )
+    }
+
+    /// 403 for a party the ACL of the point doesn't admit
+    pub(crate) fn forbidden(unique_id: &str) -> Custom<Json<ApiResponse>> {
+        warn!("Party not allowed to join unique_id: {}", unique_id);
         Custom(
-            Status::Conflict,
-            Json(ApiResponse::error("Only 2 parties allowed at a time")),
+            Status::Forbidden,
+            Json(ApiResponse::error(format!(
+                "Not allowed to join {}",
+                unique_id
+            ))),
         )
     }
 
+    /// Adds a party to the group point of `unique_id`, creating the point if needed. The `count`-th
+    /// party of a n-party point releases it right away.
+    ///
+    /// # Returns
+    /// * `Ok((Arc<WaitPoint>, usize))` - The point & the party's position in it, from 1
+    /// * `Err(Custom<Json<ApiResponse>>>)` - Relevant error info
+    fn join_group(
+        &self,
+        unique_id: &str,
+        mode: Mode,
+        access: &Access,
+        max_wait_points: usize,
+    ) -> Result<(Arc<WaitPoint>, usize), Custom<Json<ApiResponse>>> {
+        if self.chaos_lock_failure() {
+            return Err(ApiResponse::service_unavailable());
+        }
+
+        // Under the write lock, so that the point can't be released or emptied while joining
+        let mut points = self.wait_points.write();
+        let point = match points.get(unique_id) {
+            Some(point) if !point.admits(access) => return Err(Self::forbidden(unique_id)),
+            Some(point) => point.clone(),
+            None => {
+                self.admit_new_point(&points, unique_id, access, max_wait_points)?;
+                let point = WaitPoint::with_mode(self.bump_generation(), mode)
+                    .with_acl(access.acl.clone())
+                    .with_priority(access.priority);
+                let point = Arc::new(point);
+                points.insert(unique_id.to_owned(), point.clone());
+                debug!(
+                    "Created new {:?} wait point for unique_id: {}",
+                    mode, unique_id
+                );
+                point
+            }
+        };
+
+        let position = self.arrive(&point, access) + 1;
+        self.redeem_reservation(unique_id, access);
+        if let Mode::NParty { count } = point.mode {
+            if position >= count {
+                debug!(
+                    "Party {} of {} arrived for unique_id: {}",
+                    position, count, unique_id
+                );
+                points.remove(unique_id);
+                let pair_id = Uuid::new_v4();
+                point.release(pair_id);
+                self.record_match(unique_id, pair_id, count, &point, None);
+            }
+        }
+        Ok((point, position))
+    }
+
+    /// Removes a party which stopped waiting from its group point, & the point once empty
+    ///
+    /// # Returns
+    /// Whether the party left, `false` if the point was released in the meantime
+    fn leave_group(&self, unique_id: &str, point: &Arc<WaitPoint>) -> bool {
+        let mut points = self.wait_points.write();
+        if point.is_released() {
+            return false;
+        }
+
+        let remaining = point.parties_count.fetch_sub(1, Ordering::SeqCst) - 1;
+        self.bump_generation();
+        // Not released, so still the registered point
+        if remaining == 0 {
+            points.remove(unique_id);
+            debug!("Cleaned up wait point for unique_id: {}", unique_id);
+        }
+        true
+    }
+
+    /// Records a match in the audit log with the origins & notes of its parties, so its
+    /// `pair_id` can be traced back, tells observers & hooks & keeps its completion record with
+    /// `receipt`, see `completions` module
+    fn record_match(
+        &self,
+        unique_id: &str,
+        pair_id: Uuid,
+        parties: usize,
+        point: &WaitPoint,
+        receipt: Option<&Receipt>,
+    ) {
+        let origins: Vec<String> = point
+            .origins()
+            .into_iter()
+            .map(|origin| {
+                [origin.ip, origin.user_agent, origin.api_key]
+                    .map(|part| part.unwrap_or_else(|| "-".to_owned()))
+                    .join(" ")
+            })
+            .collect();
+        let notes = point.notes();
+        if notes.is_empty() {
+            info!(
+                target: "audit",
+                "Matched {} parties on unique_id: {} pair_id: {} origins: {:?}",
+                parties, unique_id, pair_id, origins
+            );
+        } else {
+            info!(
+                target: "audit",
+                "Matched {} parties on unique_id: {} pair_id: {} origins: {:?} notes: {:?}",
+                parties, unique_id, pair_id, origins, notes
+            );
+        }
+        self.observers
+            .publish(ObservedEvent::Matched, unique_id, Some(pair_id), parties);
+        #[cfg(feature = "hooks")]
+        self.hooks.fire(
+            &Event::matched(unique_id, pair_id, parties),
+            &self.background,
+        );
+        self.local_notify.fire(unique_id, pair_id, &self.background);
+
+        let created_at = point
+            .created_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let matched_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        self.completions.record(Completion {
+            pair_id: pair_id.to_string(),
+            unique_id: unique_id.to_owned(),
+            parties,
+            created_at: rfc3339(created_at.as_secs(), created_at.subsec_millis()),
+            matched_at: rfc3339(matched_at.as_secs(), matched_at.subsec_millis()),
+            wait_ms: matched_at.saturating_sub(created_at).as_millis() as u64,
+            origins: point.redacted_origins(),
+            notes,
+            receipt: receipt.cloned(),
+        });
+    }
+
+    /// Where the `position`-th party of `point` came from, for its peer. `None` unless the
+    /// server has `share_peer_origin`
+    fn peer_origin(&self, point: &WaitPoint, position: usize) -> Option<PartyOrigin> {
+        if !self.share_peer_origin {
+            return None;
+        }
+        point.peer_origin(position)
+    }
+
     /// Removes a wait point from the service state.
     ///
     /// # Arguments
@@ -156,69 +1215,507 @@ impl SyncService {
     ///
     /// # Returns
     /// * `Ok(())` - If the wait point was successfully removed
-    /// * `Err(Custom<Json<ApiResponse>>>)` - Relevant error info
+    /// * `Err(Custom<Json<ApiResponse>>>)` - Relevant error info (only injected by `chaos`)
     fn cleanup_wait_point(&self, unique_id: &str) -> Result<(), Custom<Json<ApiResponse>>> {
-        match self.wait_points.try_write() {
-            Some(mut points) => {
-                if points.remove(unique_id).is_some() {
-                    debug!("Cleaned up wait point for unique_id: {}", unique_id);
-                }
-                Ok(())
+        if self.chaos_dropped_cleanup() {
+            warn!("Chaos: skipping cleanup of wait point: {}", unique_id);
+            return Ok(());
+        }
+        let failed = self.chaos_lock_failure();
+        self.anomalies.cleanup(unique_id, !failed);
+        if failed {
+            return Err(ApiResponse::service_unavailable());
+        }
+
+        if self.wait_points.write().remove(unique_id).is_some() {
+            self.bump_generation();
+            debug!("Cleaned up wait point for unique_id: {}", unique_id);
+        }
+        Ok(())
+    }
+
+    /// Registers a party arriving at the wait point. `access` identifies the waiting party of a
+    /// pair in its match receipt
+    ///
+    /// # Returns
+    /// Number of parties which arrived before this one
+    pub fn arrive(&self, point: &WaitPoint, access: &Access) -> usize {
+        // Under the lock, so that the second party finds the waiting one's fingerprint
+        let mut waiter = point.waiter.lock();
+        let previous = point.parties_count.fetch_add(1, Ordering::SeqCst);
+        if previous == 0 && point.mode == Mode::Pair {
+            *waiter = self.fingerprint(access);
+        }
+        if let Some(note) = &access.note {
+            point.add_note(note);
+        }
+        point.add_origin(access);
+        self.bump_generation();
+        previous
+    }
+
+    /// Registers a party arriving at the wait point, only if `expected` parties arrived before it.
+    /// Same as `arrive` otherwise
+    ///
+    /// # Returns
+    /// * `Ok(())` - If the party joined
+    /// * `Err(usize)` - The number of parties which arrived instead, the party didn't join
+    pub fn join_as(
+        &self,
+        point: &WaitPoint,
+        expected: usize,
+        access: &Access,
+    ) -> Result<(), usize> {
+        let mut waiter = point.waiter.lock();
+        point.parties_count.compare_exchange(
+            expected,
+            expected + 1,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        )?;
+        if expected == 0 && point.mode == Mode::Pair {
+            *waiter = self.fingerprint(access);
+        }
+        if let Some(note) = &access.note {
+            point.add_note(note);
+        }
+        point.add_origin(access);
+        self.bump_generation();
+        Ok(())
+    }
+
+    /// Fingerprint of a party for match receipts, `None` unless they're issued
+    fn fingerprint(&self, access: &Access) -> Option<Box<str>> {
+        #[cfg(feature = "receipts")]
+        if self.receipts.is_some() {
+            return Some(receipts::fingerprint(access).into());
+        }
+        #[cfg(not(feature = "receipts"))]
+        let _ = access;
+        None
+    }
+
+    /// Signed receipt of the pair match `pair_id` between the party waiting at `point` &
+    /// `notifier`, `None` unless receipts are issued
+    fn receipt(
+        &self,
+        unique_id: &str,
+        pair_id: Uuid,
+        point: &WaitPoint,
+        notifier: &Access,
+    ) -> Option<Receipt> {
+        let waiter = point.waiter.lock().clone()?;
+        #[cfg(feature = "receipts")]
+        if let Some(signer) = &self.receipts {
+            let timestamp = |at: SystemTime| {
+                let at = at.duration_since(UNIX_EPOCH).unwrap_or_default();
+                rfc3339(at.as_secs(), at.subsec_millis())
+            };
+            return Some(signer.sign(&ReceiptPayload {
+                unique_id: unique_id.to_owned(),
+                pair_id: pair_id.to_string(),
+                created_at: timestamp(point.created_at),
+                matched_at: timestamp(SystemTime::now()),
+                parties: vec![waiter.into(), receipts::fingerprint(notifier)],
+            }));
+        }
+        #[cfg(not(feature = "receipts"))]
+        let _ = (unique_id, pair_id, notifier, waiter);
+        None
+    }
+
+    /// Current state of the wait point for `unique_id`.
+    ///
+    /// The ETag combines the point's creation generation with its party count, so it changes
+    /// whenever a party joins or the point is removed & re-created.
+    pub fn status(&self, unique_id: &str) -> WaitPointStatus {
+        let points = self.wait_points.read();
+
+        let (parties, waiting, etag) = match points.get(unique_id) {
+            Some(point) => {
+                let parties = point.parties_count.load(Ordering::SeqCst);
+                let etag = format!("{}.{}", point.generation, parties);
+                (parties, point.waiting_parties() > 0, etag)
             }
-            None => {
-                error!(
-                    "Failed to acquire write lock for cleanup of wait point: {}",
-                    unique_id
-                );
-                Err(ApiResponse::service_unavailable())
+            None => (0, false, "none".to_owned()),
+        };
+        WaitPointStatus {
+            unique_id: unique_id.to_owned(),
+            parties,
+            waiting,
+            etag,
+            notes: Vec::new(),
+            origins: Vec::new(),
+        }
+    }
+
+    /// Lists open wait points in creation order, a page at a time.
+    ///
+    /// # Arguments
+    /// * `cursor` - Where the previous page ended, `None` for the first page
+    /// * `limit` - Page size, see `pagination::paginate`
+    pub fn list(&self, cursor: Option<Cursor>, limit: Option<usize>) -> Page<WaitPointStatus> {
+        let generation = self.generation.load(Ordering::SeqCst);
+        let points = self.wait_points.read();
+
+        let cursor = cursor.unwrap_or(Cursor::start(generation));
+        let items = points
+            .created_between(cursor.offset, cursor.generation)
+            .map(|(unique_id, point)| {
+                let parties = point.parties_count.load(Ordering::SeqCst);
+                let status = WaitPointStatus {
+                    unique_id: unique_id.to_owned(),
+                    parties,
+                    waiting: point.waiting_parties() > 0,
+                    etag: format!("{}.{}", point.generation, parties),
+                    notes: point.notes(),
+                    origins: point.origins(),
+                };
+                (point.generation, status)
+            });
+        paginate(items, cursor, limit)
+    }
+
+    /// Service-wide counters, the latest snapshot when they're cached (see `stats_cache` module)
+    pub fn stats(&self) -> Stats {
+        match self.stats_cache.get() {
+            Some(snapshot) => snapshot.stats.clone(),
+            None => self.current_stats(),
+        }
+    }
+
+    /// Outcomes of unmatched first parties, the latest snapshot when they're cached
+    pub fn outcome_stats(&self) -> OutcomeStats {
+        match self.stats_cache.get() {
+            Some(snapshot) => snapshot.outcomes.clone(),
+            None => self.outcomes.stats(),
+        }
+    }
+
+    /// Service-wide counters as of now
+    pub fn current_stats(&self) -> Stats {
+        // Read before the points, so the data is never older than its generation
+        let generation = self.generation.load(Ordering::SeqCst);
+        let points = self.wait_points.read();
+
+        // Internal points are left out, but not of the memory the budget applies to
+        let mut open_by_priority = points.open_by_priority();
+        let (mut internal, mut waiting_parties) = (0, 0);
+        for (unique_id, point) in points.iter() {
+            if ids::is_internal(unique_id) {
+                internal += 1;
+                open_by_priority[point.priority.index()] -= 1;
+            } else {
+                waiting_parties += point.waiting_parties();
             }
         }
+        Stats {
+            open_wait_points: points.len() - internal,
+            waiting_parties,
+            memory_bytes: points.memory_bytes(),
+            open_by_priority: open_by_priority.into(),
+            pending_notifications: self.pending.len(),
+            generation,
+        }
+    }
+
+    /// Predicts what a sync request would do, without creating or joining a wait point.
+    ///
+    /// # Arguments
+    /// * `unique_id` - The unique identifier for the wait point
+    /// * `priority` - Class of the party, see `priorities` module
+    /// * `max_wait_points` - Limit of simultaneously open wait points, 0 means unlimited
+    ///
+    /// # Returns
+    /// a `Custom<Json<ApiResponse>>` with:
+    /// * HTTP Status code the real request would get right away (200 if it would wait or match)
+    /// * JSON response with the predicted outcome
+    pub fn dry_run(
+        &self,
+        unique_id: &str,
+        priority: Priority,
+        max_wait_points: usize,
+    ) -> Custom<Json<ApiResponse>> {
+        let points = self.wait_points.read();
+
+        let (status, outcome) = match points.get(unique_id) {
+            Some(point) => {
+                let parties = point.parties_count.load(Ordering::SeqCst);
+                match point.mode {
+                    Mode::Pair if parties >= 2 => (
+                        self.status_codes.status(Failure::Conflict),
+                        DryRunOutcome::Conflict,
+                    ),
+                    Mode::Pair => (Status::Ok, DryRunOutcome::Match),
+                    Mode::NParty { count } if parties + 1 >= count => {
+                        (Status::Ok, DryRunOutcome::Match)
+                    }
+                    Mode::NParty { .. } | Mode::Broadcast => (Status::Ok, DryRunOutcome::Wait),
+                }
+            }
+            None if self
+                .at_capacity(&points, unique_id, priority, max_wait_points)
+                .is_some() =>
+            {
+                (
+                    self.status_codes.status(Failure::Busy),
+                    DryRunOutcome::Unavailable,
+                )
+            }
+            None => (Status::Ok, DryRunOutcome::Wait),
+        };
+        debug!("Dry run for unique_id: {} -> {:?}", unique_id, outcome);
+
+        Custom(status, Json(ApiResponse::dry_run(outcome, unique_id)))
     }
 
     /// Gets an existing wait point or creates a new one if it doesn't exist.
     ///
     /// # Arguments
     /// * `unique_id` - The unique identifier for the wait point
+    /// * `max_wait_points` - Limit of simultaneously open wait points, 0 means unlimited
     ///
     /// # Returns
     /// * `Ok(Arc<WaitPoint>)` - The existing or newly created wait point
     /// * `Err(Custom<Json<ApiResponse>>>)` - Relevant error info
-    pub fn get_or_create_point(
+    pub async fn get_or_create_point(
         &self,
         unique_id: &str,
+        max_wait_points: usize,
     ) -> Result<Arc<WaitPoint>, Custom<Json<ApiResponse>>> {
-        // Try to get existing point with a non-blocking read (deadlock prevention)
-        if let Some(guard) = self.wait_points.try_read() {
-            // `.cloned` will turn `&Arc<WaitPoint>` into `Arc<WaitPoint>`
-            if let Some(point) = guard.get(&unique_id.to_owned()).cloned() {
-                debug!("Wait point found for unique_id: {}", unique_id);
-                return Ok(point);
-            }
-            // The lock is automatically released when `guard` goes out of scope
-        } else {
-            error!(
-                "Failed to acquire read lock for cleanup of wait point: {}",
-                unique_id
-            );
+        self.open_point(unique_id, &Access::default(), max_wait_points)
+            .await
+    }
+
+    /// Same as `get_or_create_point`, for the party with `access`: a point it creates gets the ACL
+    /// it asks for, an existing point must admit it (403 otherwise)
+    pub async fn open_point(
+        &self,
+        unique_id: &str,
+        access: &Access,
+        max_wait_points: usize,
+    ) -> Result<Arc<WaitPoint>, Custom<Json<ApiResponse>>> {
+        if self.chaos_lock_failure() {
             return Err(ApiResponse::service_unavailable());
         }
+        let found = |point: Arc<WaitPoint>| {
+            debug!("Wait point found for unique_id: {}", unique_id);
+            if point.admits(access) {
+                Ok(point)
+            } else {
+                Err(Self::forbidden(unique_id))
+            }
+        };
+
+        // Most lookups find nothing to change, so try with a shared read lock first
+        // `.cloned` will turn `&Arc<WaitPoint>` into `Arc<WaitPoint>`
+        if let Some(point) = self.wait_points.read().get(unique_id).cloned() {
+            return found(point);
+        }
+        // The read lock is released at the end of the statement above
 
         // Create new point otherwise
-        match self.wait_points.try_write() {
-            Some(mut points) => {
-                // If write lock acquired
-                // `points  is a mutable reference to the HashMap inside the lock
-                let point = Arc::new(WaitPoint::new());
-                // `point.clone()` because we want to return this `point` (pointer) eventually
-                // Both refer to the same WaitPoint instance (actual WaitPoint data lives on the heap)
-                let point_clone = point.clone();
-                // The HashMap needs to own a reference to the WaitPoint
-                points.insert(unique_id.to_owned(), point_clone);
-                debug!("Created new wait point for unique_id: {}", unique_id);
-                Ok(point)
+        // `points` is a mutable reference to the registry inside the lock
+        let mut points = self.wait_points.write();
+        // Another party may have created it while we were waiting for the write lock
+        if let Some(point) = points.get(unique_id).cloned() {
+            return found(point);
+        }
+        self.admit_new_point(&points, unique_id, access, max_wait_points)?;
+
+        let point = WaitPoint::new(self.bump_generation())
+            .with_acl(access.acl.clone())
+            .with_priority(access.priority);
+        let point = Arc::new(point);
+        // `point.clone()` because we want to return this `point` (pointer) eventually
+        // Both refer to the same WaitPoint instance (actual WaitPoint data lives on the heap)
+        let point_clone = point.clone();
+        // The registry needs to own a reference to the WaitPoint
+        points.insert(unique_id.to_owned(), point_clone);
+        debug!("Created new wait point for unique_id: {}", unique_id);
+        Ok(point)
+    }
+
+    /// Reserves a seat for the party of `unique_id` for `reservation_ttl_sec`, so it's admitted
+    /// even if the wait points fill up meanwhile. Checked like a new wait point, also when the
+    /// point exists already (its party may be matched & gone by the time the holder arrives).
+    ///
+    /// # Returns
+    /// * `Ok(Uuid)` - The reservation token, for the `X-Sync-Reservation` header
+    /// * `Err(Custom<Json<ApiResponse>>>)` - 503 if there's no seat left
+    pub async fn reserve(
+        &self,
+        unique_id: &str,
+        priority: Priority,
+        max_wait_points: usize,
+    ) -> Result<Uuid, Custom<Json<ApiResponse>>> {
+        // Under the write lock, so that no wait point can take the seat while it's reserved
+        let points = self.wait_points.write();
+        if let Some(message) = self.at_capacity(&points, unique_id, priority, max_wait_points) {
+            self.priorities.shed(priority);
+            warn!("{}, no reservation for unique_id: {}", message, unique_id);
+            let response = ApiResponse::error(format!("{}, try again later", message));
+            return Err(self.status_codes.respond(Failure::Busy, response));
+        }
+        let token = self.reservations.add(unique_id);
+        debug!("Reserved a seat for unique_id: {}", unique_id);
+        Ok(token)
+    }
+
+    /// Consumes the reservation the party with `access` holds for `unique_id`, if any. Called when
+    /// it creates the point (taking the reserved seat) & whenever it joins one (releasing the seat)
+    ///
+    /// # Returns
+    /// Whether it held a valid one
+    fn redeem_reservation(&self, unique_id: &str, access: &Access) -> bool {
+        access
+            .reservation
+            .is_some_and(|token| self.reservations.take(token, unique_id))
+    }
+
+    /// Lets a party create the wait point for `unique_id` if it holds a reservation for it, or
+    /// there's capacity left. Called under the write lock of `points`
+    ///
+    /// # Returns
+    /// * `Ok(())` - If the point may be created
+    /// * `Err(Custom<Json<ApiResponse>>>)` - 503 otherwise
+    fn admit_new_point(
+        &self,
+        points: &Registry,
+        unique_id: &str,
+        access: &Access,
+        max_wait_points: usize,
+    ) -> Result<(), Custom<Json<ApiResponse>>> {
+        if self.redeem_reservation(unique_id, access) {
+            debug!("Reserved seat used for unique_id: {}", unique_id);
+            return Ok(());
+        }
+        match self.at_capacity(points, unique_id, access.priority, max_wait_points) {
+            Some(message) => {
+                self.priorities.shed(access.priority);
+                error!("{}, rejecting unique_id: {}", message, unique_id);
+                let response = ApiResponse::error(format!("{}, try again later", message));
+                Err(self.status_codes.respond(Failure::Busy, response))
             }
+            None => Ok(()),
+        }
+    }
 
-            None => Err(ApiResponse::service_unavailable()),
+    /// Checks whether a new wait point for `unique_id` would exceed the wait points limit or
+    /// the memory budget, counting the seats reserved for other parties, or the share of the
+    /// limit `priority` may fill.
+    ///
+    /// # Returns
+    /// The reason for shedding the new point, `None` if it fits
+    fn at_capacity(
+        &self,
+        points: &Registry,
+        unique_id: &str,
+        priority: Priority,
+        max_wait_points: usize,
+    ) -> Option<&'static str> {
+        let (reserved, reserved_bytes) = self.reservations.held();
+        let taken = points.len() + reserved;
+        if max_wait_points > 0 && taken >= max_wait_points {
+            return Some("Too many open wait points");
         }
+        if !self
+            .priorities
+            .admits(priority, points.open_by_priority(), taken, max_wait_points)
+        {
+            return Some("Wait points kept for other priorities");
+        }
+        let needed = points.memory_bytes() + reserved_bytes + Registry::entry_bytes(unique_id);
+        if self.max_memory_bytes > 0 && needed > self.max_memory_bytes {
+            return Some("Wait points memory budget exhausted");
+        }
+        None
+    }
+
+    /// Increments the generation, returning the new value
+    fn bump_generation(&self) -> u64 {
+        self.generation.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    // Fault injection hooks, constant no-ops (optimized away) unless built with `chaos` feature
+
+    #[cfg(feature = "chaos")]
+    fn chaos_lock_failure(&self) -> bool {
+        self.chaos.lock_failure()
+    }
+
+    #[cfg(not(feature = "chaos"))]
+    fn chaos_lock_failure(&self) -> bool {
+        false
+    }
+
+    #[cfg(feature = "chaos")]
+    fn chaos_notification_delay(&self) -> Option<Duration> {
+        self.chaos.notification_delay()
+    }
+
+    #[cfg(not(feature = "chaos"))]
+    fn chaos_notification_delay(&self) -> Option<Duration> {
+        None
+    }
+
+    #[cfg(feature = "chaos")]
+    fn chaos_dropped_cleanup(&self) -> bool {
+        self.chaos.dropped_cleanup()
+    }
+
+    #[cfg(not(feature = "chaos"))]
+    fn chaos_dropped_cleanup(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::sync_service::{SyncService, WaitPoint};
+    use rocket::http::Status;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn test_match_is_kept_until_first_party_waits() {
+        let point = WaitPoint::new(1);
+        let receiver = point.receiver.lock().take().expect("receiver");
+
+        // Second party is faster than the first one starts waiting
+        assert!(point.send_match(Uuid::new_v4(), None).is_ok());
+        assert!(receiver.await.is_ok());
+        // Only one match per point
+        assert!(point.send_match(Uuid::new_v4(), None).is_err());
+    }
+
+    #[test]
+    fn test_late_second_party_learns_first_party_left() {
+        let point = WaitPoint::new(1);
+        drop(point.receiver.lock().take());
+
+        assert!(point.send_match(Uuid::new_v4(), None).is_err());
+    }
+
+    #[test]
+    fn test_conflict_details() {
+        let service = SyncService::new();
+        let point = WaitPoint::new(7);
+        let timeout = Duration::from_secs(10);
+
+        let response = service.handle_extra_party("123", &point, 2, timeout);
+        assert_eq!(response.0, Status::Conflict);
+        let json = serde_json::to_value(&response.1 .0).unwrap();
+        assert_eq!(json["message"], "Only 2 parties allowed at a time");
+        assert_eq!(json["conflict"]["parties"], 2);
+        assert_eq!(json["conflict"]["generation"], 7);
+        // Matched, the point goes away right away
+        assert_eq!(json["conflict"]["retry_after_sec"], 1);
+        assert!(json["conflict"]["created_at"].as_str().unwrap().ends_with('Z'));
+
+        // Still waiting, the point lives until the first party's timeout
+        let response = service.handle_extra_party("123", &point, 0, timeout);
+        let details = response.1 .0.conflict_details().unwrap();
+        assert_eq!(details.retry_after_sec, 10);
     }
 }
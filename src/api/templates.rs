@@ -0,0 +1,510 @@
+//! Wait point templates, light governance for shared deployments: the parties joining ids of a
+//! template must send metadata conforming to its JSON schema, e.g.
+//! ```toml
+//! [[templates]]
+//! pattern = "deploy/*"
+//! schema = '''
+//! {
+//!   "type": "object",
+//!   "required": ["team", "change_id"],
+//!   "properties": {
+//!     "team": {"enum": ["payments", "search"]},
+//!     "change_id": {"type": "string", "pattern": "^CHG-[0-9]+$"}
+//!   }
+//! }'''
+//! ```
+//! Parties send their metadata as a JSON object in the `X-Sync-Metadata` header, a missing one is
+//! checked as `{}`. Those of `/wait-for-second-party` (except observers), `/wait`, `/notify` &
+//! every id of `/batch/wait` not conforming get 422 with an `errors` field, an item per invalid
+//! field: `[{"field":"/team","message":"is required"}]`.
+//!
+//! Patterns are like those of `/observe` (see `ids::IdPattern`), the most specific one applies:
+//! the exact id, then the longest prefix. Ids without a template & internal ids aren't checked.
+//!
+//! Schemas support a subset of JSON Schema: `type`, `enum`, `const`, `required`, `properties`,
+//! `additionalProperties` (a boolean), `items`, `minLength`, `maxLength`, `pattern`, `minimum` &
+//! `maximum`, besides the annotations `$schema`, `title` & `description`. Other keywords fail the
+//! config validation, so a schema never checks less than it appears to.
+use crate::api::acl::Access;
+use crate::api::ids::{self, IdPattern};
+use crate::api::response::ApiResponse;
+use crate::protocol::FieldError;
+use regex::Regex;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::json::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// A template of the `[[templates]]` config section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateConfig {
+    /// Ids the template applies to, e.g. `deploy/*`
+    pub pattern: String,
+    /// JSON schema of the parties' metadata, as JSON text (TOML would lowercase its keys)
+    pub schema: String,
+}
+
+/// Compiled templates, see module docs
+#[derive(Debug, Default)]
+pub struct Templates(Vec<(IdPattern, Schema)>);
+
+impl Templates {
+    /// Checks the patterns & compiles the schemas
+    ///
+    /// # Returns
+    /// * `Ok(Templates)` - Ready to check parties
+    /// * `Err(String)` - Describing the first invalid template
+    pub fn new(configs: &[TemplateConfig]) -> Result<Self, String> {
+        let mut templates = Vec::with_capacity(configs.len());
+        for config in configs {
+            let prefix = config.pattern.strip_suffix('*').unwrap_or(&config.pattern);
+            if prefix.contains('*') {
+                return Err(format!(
+                    "templates: '{}' may only have a `*` at the end",
+                    config.pattern
+                ));
+            }
+            let schema = serde_json::from_str(&config.schema)
+                .map_err(|e| e.to_string())
+                .and_then(|schema| Schema::compile(&schema, ""))
+                .map_err(|e| format!("templates: invalid schema of '{}': {}", config.pattern, e))?;
+            templates.push((IdPattern::parse(&config.pattern), schema));
+        }
+        Ok(Self(templates))
+    }
+
+    /// Schema of `unique_id`: of the exact pattern, else of the longest matching prefix
+    fn resolve(&self, unique_id: &str) -> Option<&Schema> {
+        self.0
+            .iter()
+            .filter(|(pattern, _)| pattern.matches(unique_id))
+            .max_by_key(|(pattern, _)| match pattern {
+                IdPattern::Exact(_) => usize::MAX,
+                IdPattern::Prefix(prefix) => prefix.len(),
+            })
+            .map(|(_, schema)| schema)
+    }
+
+    /// Checks the metadata of a party joining `unique_id` against its template
+    ///
+    /// # Returns
+    /// * `Ok(())` - If it conforms, or the id has no template
+    /// * `Err(Custom<Json<ApiResponse>>)` - 422 with the invalid fields otherwise
+    pub fn admit(&self, unique_id: &str, access: &Access) -> Result<(), Custom<Json<ApiResponse>>> {
+        if ids::is_internal(unique_id) {
+            return Ok(());
+        }
+        let Some(schema) = self.resolve(unique_id) else {
+            return Ok(());
+        };
+        let metadata = Value::Object(access.metadata.clone().unwrap_or_default());
+        let mut errors = Vec::new();
+        schema.check(&metadata, "", &mut errors);
+        if errors.is_empty() {
+            return Ok(());
+        }
+        Err(Custom(
+            Status::UnprocessableEntity,
+            Json(ApiResponse::invalid_metadata(errors, unique_id)),
+        ))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum JsonType {
+    Object,
+    Array,
+    String,
+    Number,
+    Integer,
+    Boolean,
+    Null,
+}
+
+impl JsonType {
+    fn parse(name: &str) -> Result<Self, String> {
+        Ok(match name {
+            "object" => JsonType::Object,
+            "array" => JsonType::Array,
+            "string" => JsonType::String,
+            "number" => JsonType::Number,
+            "integer" => JsonType::Integer,
+            "boolean" => JsonType::Boolean,
+            "null" => JsonType::Null,
+            _ => return Err(format!("unknown type {}", name)),
+        })
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            JsonType::Object => "object",
+            JsonType::Array => "array",
+            JsonType::String => "string",
+            JsonType::Number => "number",
+            JsonType::Integer => "integer",
+            JsonType::Boolean => "boolean",
+            JsonType::Null => "null",
+        }
+    }
+
+    fn matches(self, value: &Value) -> bool {
+        match self {
+            JsonType::Object => value.is_object(),
+            JsonType::Array => value.is_array(),
+            JsonType::String => value.is_string(),
+            JsonType::Number => value.is_number(),
+            JsonType::Integer => value.as_f64().is_some_and(|number| number.fract() == 0.0),
+            JsonType::Boolean => value.is_boolean(),
+            JsonType::Null => value.is_null(),
+        }
+    }
+}
+
+/// A compiled schema, see module docs for its keywords
+#[derive(Debug)]
+struct Schema {
+    /// Any type when empty
+    types: Vec<JsonType>,
+    /// From `enum` or `const`
+    allowed: Option<Vec<Value>>,
+    required: Vec<String>,
+    properties: BTreeMap<String, Schema>,
+    additional_properties: bool,
+    items: Option<Box<Schema>>,
+    min_length: Option<u64>,
+    max_length: Option<u64>,
+    pattern: Option<Regex>,
+    minimum: Option<f64>,
+    maximum: Option<f64>,
+}
+
+impl Default for Schema {
+    fn default() -> Self {
+        Self {
+            types: Vec::new(),
+            allowed: None,
+            required: Vec::new(),
+            properties: BTreeMap::new(),
+            additional_properties: true,
+            items: None,
+            min_length: None,
+            max_length: None,
+            pattern: None,
+            minimum: None,
+            maximum: None,
+        }
+    }
+}
+
+impl Schema {
+    /// Compiles the schema at `path` (a JSON pointer, for errors)
+    fn compile(schema: &Value, path: &str) -> Result<Self, String> {
+        let Value::Object(keywords) = schema else {
+            return Err(format!("{}: a schema must be an object", pointer(path)));
+        };
+        let invalid =
+            |keyword: &str, expected: &str| format!("{}/{}: must be {}", path, keyword, expected);
+        let mut compiled = Schema::default();
+        for (keyword, value) in keywords {
+            match keyword.as_str() {
+                "type" => {
+                    let names = match value {
+                        Value::Array(names) => names.iter().collect(),
+                        name => vec![name],
+                    };
+                    compiled.types = names
+                        .into_iter()
+                        .map(|name| {
+                            let name = name.as_str().ok_or_else(|| {
+                                invalid(keyword, "a type name or an array of them")
+                            })?;
+                            JsonType::parse(name)
+                        })
+                        .collect::<Result<_, _>>()?;
+                }
+                "enum" => {
+                    let values = value
+                        .as_array()
+                        .ok_or_else(|| invalid(keyword, "an array"))?;
+                    compiled.allowed = Some(values.clone());
+                }
+                "const" => compiled.allowed = Some(vec![value.clone()]),
+                "required" => {
+                    compiled.required = value
+                        .as_array()
+                        .and_then(|names| {
+                            names
+                                .iter()
+                                .map(|name| name.as_str().map(str::to_owned))
+                                .collect()
+                        })
+                        .ok_or_else(|| invalid(keyword, "an array of property names"))?;
+                }
+                "properties" => {
+                    let properties = value
+                        .as_object()
+                        .ok_or_else(|| invalid(keyword, "an object"))?;
+                    for (name, schema) in properties {
+                        let path = format!("{}/properties/{}", path, escape(name));
+                        compiled
+                            .properties
+                            .insert(name.clone(), Schema::compile(schema, &path)?);
+                    }
+                }
+                "additionalProperties" => {
+                    compiled.additional_properties = value
+                        .as_bool()
+                        .ok_or_else(|| invalid(keyword, "a boolean"))?;
+                }
+                "items" => {
+                    let path = format!("{}/items", path);
+                    compiled.items = Some(Box::new(Schema::compile(value, &path)?));
+                }
+                "minLength" => {
+                    compiled.min_length =
+                        Some(value.as_u64().ok_or_else(|| invalid(keyword, "a count"))?);
+                }
+                "maxLength" => {
+                    compiled.max_length =
+                        Some(value.as_u64().ok_or_else(|| invalid(keyword, "a count"))?);
+                }
+                "pattern" => {
+                    let pattern = value.as_str().ok_or_else(|| invalid(keyword, "a string"))?;
+                    let pattern = Regex::new(pattern)
+                        .map_err(|e| invalid(keyword, &format!("a regex ({})", e)))?;
+                    compiled.pattern = Some(pattern);
+                }
+                "minimum" => {
+                    compiled.minimum =
+                        Some(value.as_f64().ok_or_else(|| invalid(keyword, "a number"))?);
+                }
+                "maximum" => {
+                    compiled.maximum =
+                        Some(value.as_f64().ok_or_else(|| invalid(keyword, "a number"))?);
+                }
+                "$schema" | "title" | "description" => {}
+                _ => return Err(format!("{}/{}: unsupported keyword", path, keyword)),
+            }
+        }
+        Ok(compiled)
+    }
+
+    /// Checks `value`, the `field` (a JSON pointer) of the metadata, adding its errors to `errors`
+    fn check(&self, value: &Value, field: &str, errors: &mut Vec<FieldError>) {
+        let mut fail = |message: String| {
+            errors.push(FieldError {
+                field: field.to_owned(),
+                message,
+            })
+        };
+        if !self.types.is_empty() && !self.types.iter().any(|kind| kind.matches(value)) {
+            let names: Vec<_> = self.types.iter().map(|kind| kind.as_str()).collect();
+            return fail(format!("must be of type {}", names.join(" or ")));
+        }
+        if let Some(allowed) = self
+            .allowed
+            .as_ref()
+            .filter(|allowed| !allowed.contains(value))
+        {
+            let values: Vec<_> = allowed.iter().map(Value::to_string).collect();
+            fail(format!("must be one of {}", values.join(", ")));
+        }
+        match value {
+            Value::String(text) => {
+                let length = text.chars().count() as u64;
+                if let Some(min) = self.min_length.filter(|min| length < *min) {
+                    fail(format!("must have at least {} characters", min));
+                }
+                if let Some(max) = self.max_length.filter(|max| length > *max) {
+                    fail(format!("must have at most {} characters", max));
+                }
+                if let Some(pattern) = self
+                    .pattern
+                    .as_ref()
+                    .filter(|pattern| !pattern.is_match(text))
+                {
+                    fail(format!("must match {}", pattern.as_str()));
+                }
+            }
+            Value::Number(number) => {
+                let number = number.as_f64().unwrap_or_default();
+                if let Some(minimum) = self.minimum.filter(|minimum| number < *minimum) {
+                    fail(format!("must be at least {}", minimum));
+                }
+                if let Some(maximum) = self.maximum.filter(|maximum| number > *maximum) {
+                    fail(format!("must be at most {}", maximum));
+                }
+            }
+            Value::Object(properties) => self.check_properties(properties, field, errors),
+            Value::Array(items) => {
+                if let Some(schema) = &self.items {
+                    for (index, item) in items.iter().enumerate() {
+                        schema.check(item, &format!("{}/{}", field, index), errors);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn check_properties(
+        &self,
+        properties: &Map<String, Value>,
+        field: &str,
+        errors: &mut Vec<FieldError>,
+    ) {
+        let child = |name: &str| format!("{}/{}", field, escape(name));
+        for name in self
+            .required
+            .iter()
+            .filter(|name| !properties.contains_key(*name))
+        {
+            errors.push(FieldError {
+                field: child(name),
+                message: "is required".to_owned(),
+            });
+        }
+        for (name, value) in properties {
+            match self.properties.get(name) {
+                Some(schema) => schema.check(value, &child(name), errors),
+                None if !self.additional_properties => errors.push(FieldError {
+                    field: child(name),
+                    message: "isn't allowed".to_owned(),
+                }),
+                None => {}
+            }
+        }
+    }
+}
+
+/// `name` as a JSON pointer segment
+fn escape(name: &str) -> String {
+    name.replace('~', "~0").replace('/', "~1")
+}
+
+/// `path` for error messages, `/` for the root
+fn pointer(path: &str) -> &str {
+    if path.is_empty() {
+        "/"
+    } else {
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::acl::Access;
+    use crate::api::templates::{TemplateConfig, Templates};
+    use crate::protocol::FieldError;
+    use serde_json::json;
+
+    fn templates(entries: &[(&str, serde_json::Value)]) -> Result<Templates, String> {
+        let configs: Vec<_> = entries
+            .iter()
+            .map(|(pattern, schema)| TemplateConfig {
+                pattern: pattern.to_string(),
+                schema: schema.to_string(),
+            })
+            .collect();
+        Templates::new(&configs)
+    }
+
+    fn errors(
+        templates: &Templates,
+        unique_id: &str,
+        metadata: serde_json::Value,
+    ) -> Vec<FieldError> {
+        let access = Access {
+            metadata: metadata.as_object().cloned(),
+            ..Default::default()
+        };
+        match templates.admit(unique_id, &access) {
+            Ok(()) => Vec::new(),
+            Err(response) => response.1 .0.errors().to_vec(),
+        }
+    }
+
+    fn error(field: &str, message: &str) -> FieldError {
+        FieldError {
+            field: field.to_owned(),
+            message: message.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_field_errors() {
+        let templates = templates(&[(
+            "deploy/*",
+            json!({
+                "type": "object",
+                "required": ["team", "change_id"],
+                "additionalProperties": false,
+                "properties": {
+                    "team": {"enum": ["payments", "search"]},
+                    "change_id": {"type": "string", "pattern": "^CHG-[0-9]+$"},
+                    "shards": {"type": "array", "items": {"type": "integer", "minimum": 0}}
+                }
+            }),
+        )])
+        .unwrap();
+
+        let valid = json!({"team": "search", "change_id": "CHG-42", "shards": [0, 3]});
+        assert_eq!(errors(&templates, "deploy/eu", valid), []);
+        assert_eq!(
+            errors(
+                &templates,
+                "deploy/eu",
+                json!({"team": "ads", "shards": [1, -1], "x": 1})
+            ),
+            [
+                error("/change_id", "is required"),
+                error("/shards/1", "must be at least 0"),
+                error("/team", r#"must be one of "payments", "search""#),
+                error("/x", "isn't allowed"),
+            ]
+        );
+        assert_eq!(
+            errors(
+                &templates,
+                "deploy/eu",
+                json!({"team": "search", "change_id": 42})
+            ),
+            [error("/change_id", "must be of type string")]
+        );
+        // Without a template or internal, anything goes
+        assert_eq!(errors(&templates, "other", json!({})), []);
+        assert_eq!(errors(&templates, "_internal/deploy", json!({})), []);
+    }
+
+    #[test]
+    fn test_most_specific_template() {
+        let templates = templates(&[
+            ("deploy/*", json!({"required": ["team"]})),
+            ("deploy/eu/*", json!({"required": ["region"]})),
+            ("deploy/eu/canary", json!({})),
+        ])
+        .unwrap();
+        assert_eq!(
+            errors(&templates, "deploy/us", json!({})),
+            [error("/team", "is required")]
+        );
+        assert_eq!(
+            errors(&templates, "deploy/eu/1", json!({})),
+            [error("/region", "is required")]
+        );
+        assert_eq!(errors(&templates, "deploy/eu/canary", json!({})), []);
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        assert!(templates(&[("a-*-b", json!({}))]).is_err());
+        assert_eq!(
+            templates(&[("a", json!({"properties": {"b": {"format": "email"}}}))]).unwrap_err(),
+            "templates: invalid schema of 'a': /properties/b/format: unsupported keyword"
+        );
+        assert!(templates(&[("a", json!({"pattern": "("}))]).is_err());
+        assert!(templates(&[("a", json!({"type": "text"}))]).is_err());
+    }
+}
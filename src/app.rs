@@ -1,90 +1,378 @@
+use crate::api::acl::Access;
+use crate::api::anomaly::AnomalyDetector;
+use crate::api::completions::Completions;
+use crate::api::connections::{ClosedEarly, ConnectionLimits, ConnectionSlot, ProtocolStats};
+use crate::api::file_triggers::FileTriggerStats;
+#[cfg(feature = "hooks")]
+use crate::api::hooks::Hooks;
+use crate::api::late_arrivals::LateArrivals;
+use crate::api::local_notify::LocalNotify;
+use crate::api::pending::PendingNotifications;
+use crate::api::priorities::Priorities;
+use crate::api::quotas::{WaitQuotas, WaitSlot};
+use crate::api::readiness::Readiness;
+#[cfg(feature = "receipts")]
+use crate::api::receipts::ReceiptSigner;
+use crate::api::reservations::Reservations;
+use crate::api::shadow::ShadowStats;
 use crate::api::sync_service::SyncService;
-use config::File;
-use config::{Config, ConfigError, Environment, FileFormat};
-use log::debug;
+use crate::api::templates::Templates;
+use crate::background::Background;
+#[cfg(feature = "cluster")]
+use crate::cluster::Cluster;
+use crate::logging;
+use crate::settings::{Overrides, RuntimeConfig, RuntimeConfigPatch, Settings};
+use config::ConfigError;
+use log::{debug, info};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "metrics")]
+use std::collections::BTreeMap;
+use std::sync::Arc;
 use std::time::Duration;
 
-/// Application state container managing timeout and sync Service
+/// Maintenance mode details, during which new sync requests are rejected
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Maintenance {
+    /// Shown to rejected clients & by the health endpoint
+    pub message: String,
+    /// Suggested delay before clients retry, sent as `Retry-After` header
+    pub retry_after_sec: u64,
+}
+
+impl Maintenance {
+    pub const DEFAULT_MESSAGE: &'static str = "Service is under maintenance, please retry later";
+    pub const DEFAULT_RETRY_AFTER_SEC: u64 = 60;
+}
+
+/// Application state container managing settings and sync Service
 /// Rocket manages the sharing between routes via State<App>
 /// Each route receives a thread-safe reference (`&State<App>`) to this instance
+///
+/// Cloning is cheap and shares the same state, which lets background tasks outlive a request
+#[derive(Clone)]
 pub struct App {
-    /// Used for a notification from 2nd party with this timeout value
-    pub timeout: Duration,
+    /// Current settings. Only the `RuntimeConfig` subset changes after startup (via admin API)
+    settings: Arc<RwLock<Settings>>,
+    /// Serializes runtime config changes, held while one is persisted instead of `settings`, which
+    /// every request reads
+    config_updates: Arc<Mutex<()>>,
+    /// Set while in maintenance mode, toggled via admin API
+    maintenance: Arc<RwLock<Option<Maintenance>>>,
+    /// Warm-up & lame-duck state, see `api::readiness` module
+    pub readiness: Arc<Readiness>,
     /// A service holding parties sync logic
-    pub sync_service: SyncService,
+    pub sync_service: Arc<SyncService>,
+    /// Waits in progress per API key, see `api::quotas` module
+    wait_quotas: Arc<WaitQuotas>,
+    /// Requests in progress per connection, see `api::connections` module
+    connection_limits: Arc<ConnectionLimits>,
+    /// Requests ended before their response completed, see `api::connections` module
+    pub closed_early: Arc<ClosedEarly>,
+    /// Rendezvous requests per HTTP version, see `api::connections` module
+    pub protocols: Arc<ProtocolStats>,
+    /// Parties of the file triggers, see `api::file_triggers` module
+    pub file_triggers: Arc<FileTriggerStats>,
+    /// Requests mirrored to the shadow instance, see `api::shadow` module
+    pub shadow: Arc<ShadowStats>,
+    /// Ownership of ids, `None` unless cluster mode is configured
+    #[cfg(feature = "cluster")]
+    cluster: Option<Arc<Cluster>>,
 }
 
 impl App {
-    // Currently hardcoded values, but could be configurable from outside.
-    const MIN_TIMEOUT: u64 = 5;
-    const MAX_TIMEOUT: u64 = 300;
-    const DEFAULT_TIMEOUT: u64 = 10;
-
     /// Creates a new instance of the application with configuration.
     ///
     /// Configuration can be provided via
-    /// - TOML config file (optional)
+    /// - TOML, YAML or JSON config file (optional), detected by extension
+    /// - profile file selected by `APP_PROFILE` (e.g. `config.prod.toml`)
     /// - `APP_` prefix environment variable
     ///
+    /// See `Settings::load` for the precedence of these sources.
+    ///
     /// # Arguments
-    /// * `config_path` - Optional path to TOML config file. See tests how we could pass a custom path.
+    /// * `config_path` - Optional path to config file (TOML by default). See tests how we could pass a custom path.
     ///
     /// # Returns
     /// * `Ok(App)` - Successfully initialized application
     /// * `Err(ConfigError)` - If configuration is invalid or file cannot be read
     pub fn new(config_path: Option<&str>) -> Result<Self, ConfigError> {
-        let config = Config::builder()
-            .set_default("timeout", Self::DEFAULT_TIMEOUT)?
-            .add_source(match config_path {
-                Some(path) => File::new(path, FileFormat::Toml).required(true),
-                None => File::new("config", FileFormat::Toml).required(false),
-            })
-            // e.g. APP_TIMEOUT=30, check relevant `test_app_env_timeout` test below
-            .add_source(Environment::with_prefix("APP"))
-            .build()?;
-
-        let timeout_secs: u64 = config.get("timeout")?;
-        Self::validate_timeout(timeout_secs)?;
-
-        let app = Self {
-            timeout: Duration::from_secs(timeout_secs),
-            sync_service: SyncService::new(),
+        Self::with_overrides(config_path, &Overrides::default())
+    }
+
+    /// Same as `new`, with `overrides` (e.g. command-line flags) taking precedence over all sources
+    pub fn with_overrides(
+        config_path: Option<&str>,
+        overrides: &Overrides,
+    ) -> Result<Self, ConfigError> {
+        let settings = Settings::load_with(config_path, overrides)?;
+        debug!("app.timeout: {} sec", settings.timeout);
+
+        let mut sync_service = SyncService::new();
+        sync_service.max_memory_bytes = settings.max_memory_bytes;
+        sync_service.share_peer_origin = settings.share_peer_origin;
+        sync_service.background = Background::new(&settings.background);
+        sync_service.completions = Completions::open(&settings.completions)
+            .map_err(|e| ConfigError::Message(format!("Failed to load completions: {}", e)))?;
+        sync_service.anomalies = AnomalyDetector::new(&settings.anomalies);
+        sync_service.modes = settings.modes.clone();
+        sync_service.templates =
+            Templates::new(&settings.templates).map_err(ConfigError::Message)?;
+        sync_service.status_codes = settings.status_codes.clone();
+        sync_service.priorities = Priorities::new(&settings.priorities);
+        sync_service.pending =
+            PendingNotifications::new(Duration::from_secs(settings.notification_ttl_sec));
+        sync_service.late_arrivals =
+            LateArrivals::new(Duration::from_secs(settings.late_arrival_grace_sec));
+        sync_service.reservations =
+            Reservations::new(Duration::from_secs(settings.reservation_ttl_sec));
+        #[cfg(feature = "receipts")]
+        {
+            sync_service.receipts = settings
+                .receipt_key
+                .as_deref()
+                .map(ReceiptSigner::from_hex)
+                .transpose()
+                .map_err(ConfigError::Message)?;
+        }
+        #[cfg(feature = "hooks")]
+        {
+            sync_service.hooks = Hooks::new(&settings.on_event);
+        }
+        sync_service.local_notify = LocalNotify::new(&settings.local_notify);
+        #[cfg(feature = "chaos")]
+        {
+            sync_service.chaos = settings.chaos.clone();
+        }
+
+        Ok(Self {
+            #[cfg(feature = "cluster")]
+            cluster: Cluster::new(&settings.cluster).map(Arc::new),
+            settings: Arc::new(RwLock::new(settings)),
+            config_updates: Arc::new(Mutex::new(())),
+            maintenance: Arc::new(RwLock::new(None)),
+            readiness: Arc::new(Readiness::default()),
+            sync_service: Arc::new(sync_service),
+            wait_quotas: Arc::new(WaitQuotas::default()),
+            connection_limits: Arc::new(ConnectionLimits::default()),
+            closed_early: Arc::new(ClosedEarly::default()),
+            protocols: Arc::new(ProtocolStats::default()),
+            file_triggers: Arc::new(FileTriggerStats::default()),
+            shadow: Arc::new(ShadowStats::default()),
+        })
+    }
+
+    /// Used for a notification from 2nd party with this timeout value
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.settings.read().timeout)
+    }
+
+    /// Overall deadline of a request, see `api::deadline` module
+    pub fn request_deadline(&self) -> Duration {
+        let settings = self.settings.read();
+        Duration::from_secs(settings.timeout + settings.request_deadline_sec)
+    }
+
+    /// Cluster mode state, `None` unless configured
+    #[cfg(feature = "cluster")]
+    pub fn cluster(&self) -> Option<&Arc<Cluster>> {
+        self.cluster.as_ref()
+    }
+
+    /// Maximum number of simultaneously open wait points, 0 means unlimited
+    pub fn max_wait_points(&self) -> usize {
+        self.settings.read().max_wait_points
+    }
+
+    /// How long the instance reports `starting` after launch, see `api::readiness` module
+    pub fn warmup(&self) -> Duration {
+        Duration::from_secs(self.settings.read().warmup_sec)
+    }
+
+    /// Maximum number of observers per wait point, 0 disables observing
+    pub fn max_observers_per_point(&self) -> usize {
+        self.settings.read().max_observers_per_point
+    }
+
+    /// Maximum number of simultaneous waits per API key, 0 means unlimited
+    pub fn max_waits_per_key(&self) -> usize {
+        self.settings.read().max_waits_per_key
+    }
+
+    /// Takes a wait slot of the party's API key for as long as the returned slot lives
+    ///
+    /// # Returns
+    /// * `Ok(Option<WaitSlot>)` - The slot, `None` for parties without a key or without a limit
+    /// * `Err(usize)` - The limit, which the key has reached
+    pub fn acquire_wait_slot(&self, access: &Access) -> Result<Option<WaitSlot<'_>>, usize> {
+        let limit = self.max_waits_per_key();
+        match &access.api_key {
+            Some(api_key) if limit > 0 => self
+                .wait_quotas
+                .acquire(api_key, limit)
+                .map(Some)
+                .map_err(|_| limit),
+            _ => Ok(None),
+        }
+    }
+
+    /// Waits of `api_key` in progress
+    pub fn waits_in_use(&self, api_key: &str) -> usize {
+        self.wait_quotas.in_use(api_key)
+    }
+
+    /// Maximum number of simultaneous rendezvous requests per connection, 0 means unlimited
+    pub fn max_requests_per_connection(&self) -> usize {
+        self.settings.read().max_requests_per_connection
+    }
+
+    /// Takes a slot of the party's connection for as long as the returned slot lives, counting
+    /// the request per protocol meanwhile
+    ///
+    /// # Returns
+    /// * `Ok(Option<ConnectionSlot>)` - The slot, `None` for in-process parties
+    /// * `Err(usize)` - The limit, which the connection has reached
+    pub fn acquire_connection_slot(
+        &self,
+        access: &Access,
+    ) -> Result<Option<ConnectionSlot>, usize> {
+        let limit = self.max_requests_per_connection();
+        let slot = match access.connection {
+            None => return Ok(None),
+            Some(connection) if limit > 0 => self
+                .connection_limits
+                .acquire(connection, limit)
+                .map_err(|_| limit)?,
+            Some(_) => ConnectionSlot::default(),
         };
+        Ok(Some(slot.counted(&self.protocols, access.protocol)))
+    }
+
+    /// Maintenance mode details, `None` when not in maintenance
+    pub fn maintenance(&self) -> Option<Maintenance> {
+        self.maintenance.read().clone()
+    }
+
+    /// Enters (`Some`) or leaves (`None`) maintenance mode. Parties already waiting are not affected.
+    pub fn set_maintenance(&self, maintenance: Option<Maintenance>) {
+        info!(target: "audit", "Maintenance mode set to {:?}", maintenance);
+        *self.maintenance.write() = maintenance;
+    }
+
+    /// Prefix of the metric names, see `api::metrics` module
+    #[cfg(feature = "metrics")]
+    pub fn metric_prefix(&self) -> String {
+        self.settings.read().metric_prefix.clone()
+    }
+
+    /// Labels added to every metric sample, see `api::metrics` module
+    #[cfg(feature = "metrics")]
+    pub fn metric_labels(&self) -> BTreeMap<String, String> {
+        self.settings.read().metric_labels.clone()
+    }
+
+    /// A snapshot of the current settings
+    pub fn settings(&self) -> Settings {
+        self.settings.read().clone()
+    }
 
-        debug!("app.timeout: {:?}", app.timeout);
-        Ok(app)
+    /// Current values of the runtime-adjustable settings
+    pub fn runtime_config(&self) -> RuntimeConfig {
+        let settings = self.settings.read();
+        RuntimeConfig {
+            timeout: settings.timeout,
+            max_wait_points: settings.max_wait_points,
+            log_level: logging::current_filter(),
+        }
     }
 
-    /// Validates that the timeout value is within acceptable bounds.
+    /// Validates & applies a runtime settings change. Requests already waiting keep the timeout they started with.
     ///
     /// # Arguments
-    /// * `timeout` - The timeout value in seconds
+    /// * `patch` - Settings to change
+    /// * `persist` - Whether to also write the result to `runtime_config_path`, to survive restarts.
+    ///   Not with a `log_level`
     ///
     /// # Returns
-    /// * `Ok(())` - If timeout is within [MIN_TIMEOUT, MAX_TIMEOUT] range
-    /// * `Err(ConfigError)` - If timeout is outside the valid range
-    fn validate_timeout(timeout: u64) -> Result<(), ConfigError> {
-        if timeout < Self::MIN_TIMEOUT {
-            return Err(ConfigError::Message(format!(
-                "Timeout cannot be less than {} seconds",
-                Self::MIN_TIMEOUT
-            )));
+    /// * `Ok(RuntimeConfig)` - The settings in effect after the change
+    /// * `Err(ConfigError)` - If the patch is invalid, persists a `log_level` or persisting failed,
+    ///   nothing is changed then
+    pub fn update_runtime_config(
+        &self,
+        patch: &RuntimeConfigPatch,
+        persist: bool,
+    ) -> Result<RuntimeConfig, ConfigError> {
+        let _update = self.config_updates.lock();
+        let before = self.runtime_config();
+        // Checked upfront, so an invalid request doesn't apply anything
+        let log_filter = log_filter(patch)?;
+        // Swapped in once everything succeeded
+        let mut patched = self.settings.read().clone();
+        if persist && patched.runtime_config_path.is_none() {
+            return Err(ConfigError::Message(
+                "Cannot persist: runtime_config_path is not configured".to_owned(),
+            ));
         }
-        if timeout > Self::MAX_TIMEOUT {
-            return Err(ConfigError::Message(format!(
-                "timeout cannot exceed {} seconds",
-                Self::MAX_TIMEOUT
-            )));
+        // The log filter comes from `RUST_LOG` on startup, so it can't survive a restart
+        if persist && log_filter.is_some() {
+            return Err(ConfigError::Message(
+                "Cannot persist log_level, set RUST_LOG instead".to_owned(),
+            ));
         }
-        Ok(())
+        patched.apply(patch)?;
+        if persist {
+            patched.persist_runtime_config()?;
+        }
+        if let Some(spec) = &log_filter {
+            logging::set_filter(spec).map_err(ConfigError::Message)?;
+        }
+        *self.settings.write() = patched;
+
+        let after = self.runtime_config();
+        info!(
+            target: "audit",
+            "Runtime config changed from {:?} to {:?} (persisted: {})",
+            before, after, persist
+        );
+        Ok(after)
+    }
+
+    /// Validates a runtime settings change like `update_runtime_config`, without applying it
+    ///
+    /// # Returns
+    /// * `Ok(RuntimeConfig)` - The settings that would be in effect after the change
+    /// * `Err(ConfigError)` - If the patch is invalid
+    pub fn preview_runtime_config(
+        &self,
+        patch: &RuntimeConfigPatch,
+    ) -> Result<RuntimeConfig, ConfigError> {
+        let log_filter = log_filter(patch)?;
+        let mut settings = self.settings();
+        settings.apply(patch)?;
+        Ok(RuntimeConfig {
+            timeout: settings.timeout,
+            max_wait_points: settings.max_wait_points,
+            log_level: log_filter.unwrap_or_else(logging::current_filter),
+        })
     }
 }
 
+/// Filter spec of the single `log_level` of `patch`, if it has one
+fn log_filter(patch: &RuntimeConfigPatch) -> Result<Option<String>, ConfigError> {
+    patch
+        .log_level
+        .as_deref()
+        .map(|level| logging::filter_spec(Some(level), &Default::default()))
+        .transpose()
+        .map_err(ConfigError::Message)
+}
+
 /// The `#[serial]` attribute is used to mark tests that should run sequentially
 /// This is to avoid timeout conflicts occurring from config file or env vars
 #[cfg(test)]
 mod tests {
     use crate::app::App;
+    use crate::settings::Settings;
     use config::ConfigError;
     use serial_test::serial;
     use std::time::Duration;
@@ -94,7 +382,7 @@ mod tests {
     async fn test_app_default_timeout() -> Result<(), ConfigError> {
         // Without config file
         let app = App::new(None)?;
-        assert_eq!(app.timeout, Duration::from_secs(App::DEFAULT_TIMEOUT));
+        assert_eq!(app.timeout(), Duration::from_secs(Settings::DEFAULT_TIMEOUT));
         Ok(())
     }
 
@@ -111,7 +399,7 @@ mod tests {
             .expect("Unable to write config file");
 
         let app = App::new(Some(config_path.to_str().unwrap()))?;
-        assert_eq!(app.timeout, Duration::from_secs(20));
+        assert_eq!(app.timeout(), Duration::from_secs(20));
 
         Ok(())
     }
@@ -122,7 +410,7 @@ mod tests {
         std::env::set_var("APP_TIMEOUT", "15");
 
         let app = App::new(None)?;
-        assert_eq!(app.timeout, Duration::from_secs(15));
+        assert_eq!(app.timeout(), Duration::from_secs(15));
 
         std::env::remove_var("APP_TIMEOUT"); // reset
         Ok(())
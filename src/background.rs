@@ -0,0 +1,110 @@
+//! Dedicated runtime for background work: event hooks (incl. their blocking webhook calls) & the
+//! stats refresh. A burst of it can't add latency to the rendezvous served by Rocket's runtime.
+//! Audit records bound for syslog have their own writer thread, see `logging::audit_to_syslog`.
+//!
+//! The runtime is built on first use, so apps without background work don't start its threads.
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::OnceLock;
+use tokio::runtime::{Builder, Runtime};
+
+/// `[background]` config section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackgroundConfig {
+    /// Threads running background tasks
+    pub worker_threads: usize,
+    /// Threads for blocking calls of background tasks, e.g. webhooks
+    pub max_blocking_threads: usize,
+}
+
+impl Default for BackgroundConfig {
+    fn default() -> Self {
+        Self {
+            worker_threads: 2,
+            max_blocking_threads: 8,
+        }
+    }
+}
+
+impl BackgroundConfig {
+    /// # Returns
+    /// * `Ok(())` - If the section is valid
+    /// * `Err(String)` - Describing the first invalid value
+    pub fn validate(&self) -> Result<(), String> {
+        if self.worker_threads == 0 {
+            return Err("background.worker_threads must be at least 1".to_owned());
+        }
+        if self.max_blocking_threads == 0 {
+            return Err("background.max_blocking_threads must be at least 1".to_owned());
+        }
+        Ok(())
+    }
+}
+
+/// The background runtime, shut down without waiting for its tasks when dropped
+#[derive(Debug, Default)]
+pub struct Background {
+    config: BackgroundConfig,
+    runtime: OnceLock<Runtime>,
+}
+
+impl Background {
+    pub fn new(config: &BackgroundConfig) -> Self {
+        Self {
+            config: config.clone(),
+            runtime: OnceLock::new(),
+        }
+    }
+
+    /// Runs `task` on the background runtime
+    pub fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.runtime().spawn(task);
+    }
+
+    fn runtime(&self) -> &Runtime {
+        self.runtime.get_or_init(|| {
+            Builder::new_multi_thread()
+                .worker_threads(self.config.worker_threads)
+                .max_blocking_threads(self.config.max_blocking_threads)
+                .thread_name("sync-point-background")
+                .enable_all()
+                .build()
+                .expect("background runtime")
+        })
+    }
+}
+
+impl Drop for Background {
+    fn drop(&mut self) {
+        // Dropping a runtime blocks, which isn't allowed within another one
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::background::{Background, BackgroundConfig};
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn test_tasks_run_on_background_threads() {
+        let background = Background::new(&BackgroundConfig::default());
+        let (sender, receiver) = oneshot::channel();
+        background.spawn(async move {
+            let name = std::thread::current().name().map(str::to_owned);
+            let _ = sender.send(name);
+        });
+        assert_eq!(
+            receiver.await.unwrap().as_deref(),
+            Some("sync-point-background")
+        );
+        // Dropped within a runtime
+        drop(background);
+    }
+}
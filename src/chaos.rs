@@ -0,0 +1,117 @@
+//! Fault injection for verifying that clients handle 503/timeout paths.
+//! Only compiled with the `chaos` feature, and inactive unless `chaos.enabled` is set in config.
+use config::ConfigError;
+use log::warn;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// `[chaos]` config section. Probabilities are in the [0.0, 1.0] range
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChaosConfig {
+    pub enabled: bool,
+    /// Chance that acquiring the wait points lock fails (resulting in 503)
+    pub lock_failure_probability: f64,
+    /// Chance that the second party's notification is delayed by `notification_delay_ms`
+    pub notification_delay_probability: f64,
+    pub notification_delay_ms: u64,
+    /// Chance that a wait point is not removed after the rendezvous (later parties get 409)
+    pub dropped_cleanup_probability: f64,
+}
+
+impl ChaosConfig {
+    /// Validates that probabilities are within [0.0, 1.0]
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let probabilities = [
+            ("lock_failure_probability", self.lock_failure_probability),
+            (
+                "notification_delay_probability",
+                self.notification_delay_probability,
+            ),
+            (
+                "dropped_cleanup_probability",
+                self.dropped_cleanup_probability,
+            ),
+        ];
+
+        for (name, probability) in probabilities {
+            if !(0.0..=1.0).contains(&probability) {
+                return Err(ConfigError::Message(format!(
+                    "chaos.{} must be between 0.0 and 1.0",
+                    name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a lock acquisition should fail
+    pub fn lock_failure(&self) -> bool {
+        self.roll(self.lock_failure_probability, "lock failure")
+    }
+
+    /// Delay to apply to a notification, if any
+    pub fn notification_delay(&self) -> Option<Duration> {
+        self.roll(self.notification_delay_probability, "notification delay")
+            .then(|| Duration::from_millis(self.notification_delay_ms))
+    }
+
+    /// Whether a wait point cleanup should be skipped
+    pub fn dropped_cleanup(&self) -> bool {
+        self.roll(self.dropped_cleanup_probability, "dropped cleanup")
+    }
+
+    fn roll(&self, probability: f64, fault: &str) -> bool {
+        let injected = self.enabled && rand::thread_rng().gen_bool(probability);
+        if injected {
+            warn!(target: "chaos", "Injecting {}", fault);
+        }
+        injected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chaos::ChaosConfig;
+    use std::time::Duration;
+
+    #[test]
+    fn test_disabled_chaos_never_injects() {
+        let chaos = ChaosConfig {
+            enabled: false,
+            lock_failure_probability: 1.0,
+            notification_delay_probability: 1.0,
+            notification_delay_ms: 100,
+            dropped_cleanup_probability: 1.0,
+        };
+
+        assert!(!chaos.lock_failure());
+        assert_eq!(chaos.notification_delay(), None);
+        assert!(!chaos.dropped_cleanup());
+    }
+
+    #[test]
+    fn test_enabled_chaos_injects_by_probability() {
+        let chaos = ChaosConfig {
+            enabled: true,
+            lock_failure_probability: 1.0,
+            notification_delay_probability: 1.0,
+            notification_delay_ms: 100,
+            dropped_cleanup_probability: 0.0,
+        };
+
+        assert!(chaos.lock_failure());
+        assert_eq!(chaos.notification_delay(), Some(Duration::from_millis(100)));
+        assert!(!chaos.dropped_cleanup());
+    }
+
+    #[test]
+    fn test_invalid_probability_is_rejected() {
+        let chaos = ChaosConfig {
+            lock_failure_probability: 1.5,
+            ..Default::default()
+        };
+        assert!(chaos.validate().is_err());
+    }
+}
@@ -0,0 +1,408 @@
+//! Ownership of ids across the nodes of a cluster, by consistent hashing: each node gets
+//! `weight * VNODES_PER_WEIGHT` points on a hash ring & owns the ids hashing up to its points.
+//! A membership change only moves the ids of the joining or leaving node, the others keep their
+//! owner.
+//!
+//! Served by `GET /cluster/owner/<unique_id>`, so that clients & proxies can send both parties of
+//! an id to the same node. Members come from the `[cluster]` config section, either listed
+//! statically, looked up from DNS SRV records (refreshed by the requests), discovered by gossip
+//! (see `gossip` module) or from Kubernetes Leases (see `k8s` module, with the `k8s` feature).
+//! Only compiled with the `cluster` feature.
+use crate::gossip::GossipConfig;
+#[cfg(feature = "k8s")]
+use crate::k8s::K8sConfig;
+use config::ConfigError;
+use hickory_resolver::TokioAsyncResolver;
+use log::{info, warn};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+/// Points on the ring per unit of weight, more points spread the ids more evenly
+const VNODES_PER_WEIGHT: u32 = 64;
+
+/// `[cluster]` config section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClusterConfig {
+    /// Name of this node among the members, cluster mode is disabled when missing (unless `k8s`
+    /// is configured, the pod name is used then)
+    pub node: Option<String>,
+    /// Static members
+    pub nodes: Vec<Node>,
+    /// SRV record listing the members instead of `nodes`, e.g. `_sync-point._tcp.example.internal`.
+    /// Members are named `<target>:<port>` then
+    pub srv: Option<String>,
+    /// How long the members of an SRV lookup are used before looking them up again, in seconds
+    pub srv_refresh_sec: u64,
+    /// Discovers the members by gossip instead of `nodes` or `srv`
+    pub gossip: Option<GossipConfig>,
+    /// Members are the holders of Kubernetes Leases instead
+    #[cfg(feature = "k8s")]
+    pub k8s: Option<K8sConfig>,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self {
+            node: None,
+            nodes: Vec::new(),
+            srv: None,
+            srv_refresh_sec: 30,
+            gossip: None,
+            #[cfg(feature = "k8s")]
+            k8s: None,
+        }
+    }
+}
+
+impl ClusterConfig {
+    /// Name of this node, `None` if cluster mode is disabled
+    pub fn node_name(&self) -> Option<String> {
+        #[cfg(feature = "k8s")]
+        if self.node.is_none() && self.k8s.is_some() {
+            return std::env::var("HOSTNAME").ok();
+        }
+        self.node.clone()
+    }
+
+    /// Validates the section, only when cluster mode is enabled
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let message = |message: &str| Err(ConfigError::Message(message.to_owned()));
+        #[cfg(feature = "k8s")]
+        if self.k8s.is_some() && self.node_name().is_none() {
+            return message("cluster.node is required unless the HOSTNAME env var is set");
+        }
+        let Some(node) = self.node_name() else {
+            return Ok(());
+        };
+        let sources = [
+            !self.nodes.is_empty(),
+            self.srv.is_some(),
+            self.gossip.is_some(),
+            #[cfg(feature = "k8s")]
+            self.k8s.is_some(),
+        ];
+        match sources.iter().filter(|source| **source).count() {
+            0 => {
+                return message(
+                    "cluster.nodes, cluster.srv, cluster.gossip or cluster.k8s is required",
+                )
+            }
+            1 => {}
+            _ => return message(
+                "Only one of cluster.nodes, cluster.srv, cluster.gossip & cluster.k8s is allowed",
+            ),
+        }
+        if let Some(gossip) = &self.gossip {
+            gossip.validate().map_err(ConfigError::Message)?;
+        }
+        #[cfg(feature = "k8s")]
+        if let Some(k8s) = &self.k8s {
+            k8s.validate().map_err(ConfigError::Message)?;
+        }
+        if self.srv_refresh_sec == 0 {
+            return message("cluster.srv_refresh_sec must be at least 1");
+        }
+        if self.nodes.iter().any(|node| node.weight == 0) {
+            return message("cluster.nodes weights must be at least 1");
+        }
+        let mut names = HashSet::new();
+        if !self
+            .nodes
+            .iter()
+            .all(|node| names.insert(node.name.as_str()))
+        {
+            return message("cluster.nodes names must be unique");
+        }
+        if !self.nodes.is_empty() && !names.contains(node.as_str()) {
+            return message("cluster.node must be one of cluster.nodes");
+        }
+        Ok(())
+    }
+}
+
+/// Member of the cluster
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Node {
+    pub name: String,
+    /// Base URL clients reach the node at, e.g. `http://10.0.0.2:8000`
+    pub url: String,
+    /// Share of the ids relative to the other nodes
+    #[serde(default = "Node::default_weight")]
+    pub weight: u32,
+}
+
+impl Node {
+    fn default_weight() -> u32 {
+        1
+    }
+}
+
+/// Consistent hash ring of the members
+#[derive(Debug, Default)]
+pub struct HashRing {
+    /// Sorted by name, so that the same members make the same ring
+    nodes: Vec<Node>,
+    /// Hash & index in `nodes` of each point, sorted by hash
+    points: Vec<(u64, usize)>,
+}
+
+impl HashRing {
+    pub fn new(mut nodes: Vec<Node>) -> Self {
+        nodes.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut points: Vec<(u64, usize)> = nodes
+            .iter()
+            .enumerate()
+            .flat_map(|(index, node)| {
+                (0..node.weight * VNODES_PER_WEIGHT).map(move |point| {
+                    let point = format!("{}#{}", node.name, point);
+                    (hash(point.as_bytes()), index)
+                })
+            })
+            .collect();
+        points.sort_unstable();
+        Self { nodes, points }
+    }
+
+    /// Node owning `unique_id`, `None` without members
+    pub fn owner(&self, unique_id: &str) -> Option<&Node> {
+        if self.points.is_empty() {
+            return None;
+        }
+        let hash = hash(unique_id.as_bytes());
+        // First point at or after the hash, wrapping around to the first one
+        let point = self.points.partition_point(|(point, _)| *point < hash) % self.points.len();
+        Some(&self.nodes[self.points[point].1])
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+}
+
+/// 64-bit FNV-1a with the MurmurHash3 finalizer. Unlike `DefaultHasher`, it's the same on every
+/// node & Rust version, which all nodes agreeing on the owners relies on. The finalizer spreads
+/// similar inputs (e.g. `node-a#1` & `node-a#2`), which FNV alone keeps close
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hash = bytes.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
+
+/// Members & ownership as seen by this node
+pub struct Cluster {
+    node: String,
+    srv: Option<String>,
+    srv_refresh: Duration,
+    ring: RwLock<HashRing>,
+    /// Time of the last SRV lookup, `None` before the first one
+    looked_up_at: Mutex<Option<Instant>>,
+}
+
+impl Cluster {
+    /// # Returns
+    /// The cluster with the static members (none until the first lookup with SRV records, only
+    /// this node until gossip finds others), `None` if cluster mode isn't enabled
+    pub fn new(config: &ClusterConfig) -> Option<Self> {
+        let node = config.node_name()?;
+        #[allow(unused_mut)]
+        let mut nodes = match &config.gossip {
+            Some(gossip) => vec![gossip.node(&node)],
+            None => config.nodes.clone(),
+        };
+        #[cfg(feature = "k8s")]
+        if let Some(k8s) = &config.k8s {
+            nodes = vec![k8s.node(&node)];
+        }
+        Some(Self {
+            node,
+            srv: config.srv.clone(),
+            srv_refresh: Duration::from_secs(config.srv_refresh_sec),
+            ring: RwLock::new(HashRing::new(nodes)),
+            looked_up_at: Mutex::new(None),
+        })
+    }
+
+    /// Name of this node
+    pub fn node(&self) -> &str {
+        &self.node
+    }
+
+    /// Number of known members
+    pub fn members(&self) -> usize {
+        self.ring.read().nodes().len()
+    }
+
+    /// Node owning `unique_id`, `None` without members
+    pub fn owner(&self, unique_id: &str) -> Option<Node> {
+        self.ring.read().owner(unique_id).cloned()
+    }
+
+    /// Rebuilds the ring if the members changed
+    pub fn set_members(&self, mut nodes: Vec<Node>) {
+        nodes.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut ring = self.ring.write();
+        if ring.nodes() == nodes.as_slice() {
+            return;
+        }
+        info!(
+            "Cluster members changed from {} to {} nodes, rebalancing",
+            ring.nodes().len(),
+            nodes.len()
+        );
+        *ring = HashRing::new(nodes);
+    }
+
+    /// Looks the members up again when they come from SRV records older than `srv_refresh_sec`.
+    /// A failed lookup keeps the previous members, until the next refresh
+    pub async fn refresh(&self) {
+        let Some(srv) = &self.srv else {
+            return;
+        };
+        {
+            let mut looked_up_at = self.looked_up_at.lock();
+            if looked_up_at.is_some_and(|at| at.elapsed() < self.srv_refresh) {
+                return;
+            }
+            // Set before the lookup, so concurrent requests don't look up too
+            *looked_up_at = Some(Instant::now());
+        }
+        match lookup_srv(srv).await {
+            Ok(nodes) => self.set_members(nodes),
+            Err(e) => warn!("SRV lookup of {} failed, keeping the members: {}", srv, e),
+        }
+    }
+}
+
+async fn lookup_srv(name: &str) -> Result<Vec<Node>, String> {
+    let resolver = TokioAsyncResolver::tokio_from_system_conf().map_err(|e| e.to_string())?;
+    let lookup = resolver.srv_lookup(name).await.map_err(|e| e.to_string())?;
+    Ok(lookup
+        .iter()
+        .map(|srv| {
+            let target = srv.target().to_utf8();
+            let address = format!("{}:{}", target.trim_end_matches('.'), srv.port());
+            Node {
+                url: format!("http://{}", address),
+                name: address,
+                weight: u32::from(srv.weight()).max(1),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cluster::{ClusterConfig, HashRing, Node};
+    use crate::gossip::GossipConfig;
+    use std::collections::HashMap;
+
+    fn node(name: &str, weight: u32) -> Node {
+        Node {
+            name: name.to_owned(),
+            url: format!("http://{}:8000", name),
+            weight,
+        }
+    }
+
+    fn owners(ring: &HashRing) -> Vec<String> {
+        (0..3000)
+            .map(|id| ring.owner(&format!("id-{}", id)).unwrap().name.clone())
+            .collect()
+    }
+
+    fn shares(owners: &[String]) -> HashMap<&str, usize> {
+        owners.iter().fold(HashMap::new(), |mut shares, owner| {
+            *shares.entry(owner.as_str()).or_default() += 1;
+            shares
+        })
+    }
+
+    #[test]
+    fn test_owners_are_spread_by_weight() {
+        assert!(HashRing::default().owner("a").is_none());
+
+        let ring = HashRing::new(vec![node("a", 1), node("b", 1), node("c", 2)]);
+        let owners = owners(&ring);
+        let shares = shares(&owners);
+        // 750, 750 & 1500 ideally
+        assert!((550..950).contains(&shares["a"]), "{:?}", shares);
+        assert!((550..950).contains(&shares["b"]), "{:?}", shares);
+        assert!((1250..1750).contains(&shares["c"]), "{:?}", shares);
+
+        // The order of the members doesn't matter
+        let reordered = HashRing::new(vec![node("c", 2), node("a", 1), node("b", 1)]);
+        assert_eq!(owners, self::owners(&reordered));
+    }
+
+    #[test]
+    fn test_membership_change_only_moves_ids_of_the_changed_node() {
+        let ring = HashRing::new(vec![node("a", 1), node("b", 1), node("c", 1)]);
+        let before = owners(&ring);
+        let ring = HashRing::new(vec![node("a", 1), node("b", 1), node("c", 1), node("d", 1)]);
+        let after = owners(&ring);
+
+        let moved: Vec<_> = before
+            .iter()
+            .zip(&after)
+            .filter(|(before, after)| before != after)
+            .collect();
+        assert!(moved.iter().all(|(_, after)| *after == "d"));
+        assert!((500..1000).contains(&moved.len()), "{}", moved.len());
+    }
+
+    #[test]
+    fn test_validate() {
+        let config = |node: Option<&str>, nodes: Vec<Node>, srv: Option<&str>| ClusterConfig {
+            node: node.map(str::to_owned),
+            nodes,
+            srv: srv.map(str::to_owned),
+            ..ClusterConfig::default()
+        };
+        assert!(ClusterConfig::default().validate().is_ok());
+        assert!(config(Some("a"), vec![node("a", 1), node("b", 3)], None)
+            .validate()
+            .is_ok());
+        assert!(config(Some("a"), vec![], Some("_sp._tcp.example"))
+            .validate()
+            .is_ok());
+
+        assert!(config(Some("a"), vec![], None).validate().is_err());
+        assert!(
+            config(Some("a"), vec![node("a", 1)], Some("_sp._tcp.example"))
+                .validate()
+                .is_err()
+        );
+        assert!(config(Some("c"), vec![node("a", 1)], None)
+            .validate()
+            .is_err());
+        assert!(config(Some("a"), vec![node("a", 0)], None)
+            .validate()
+            .is_err());
+        let gossip = ClusterConfig {
+            node: Some("a".to_owned()),
+            gossip: Some(GossipConfig {
+                url: "http://10.0.0.1:8000".to_owned(),
+                ..GossipConfig::default()
+            }),
+            ..ClusterConfig::default()
+        };
+        assert!(gossip.validate().is_ok());
+        assert!(ClusterConfig {
+            srv: Some("_sp._tcp.example".to_owned()),
+            ..gossip
+        }
+        .validate()
+        .is_err());
+        assert!(config(Some("a"), vec![node("a", 1), node("a", 1)], None)
+            .validate()
+            .is_err());
+    }
+}
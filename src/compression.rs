@@ -0,0 +1,186 @@
+//! Response compression negotiated via `Accept-Encoding`, mainly for large JSON responses.
+//! Only compiled with the `compression` feature, and attached when `compression.enabled` is set.
+use brotli::CompressorWriter;
+use flate2::write::GzEncoder;
+use log::error;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, Header};
+use rocket::{Request, Response};
+use serde::{Deserialize, Serialize};
+use std::io::{Cursor, Write};
+
+/// `[compression]` config section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompressionConfig {
+    pub enabled: bool,
+    /// Responses smaller than this (in bytes) are sent as is, compressing them isn't worth it
+    pub min_size: usize,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_size: 1024,
+        }
+    }
+}
+
+/// Supported encodings, in order of preference when the client accepts several equally
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    fn compress(self, body: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Encoding::Brotli => {
+                let mut output = Vec::new();
+                {
+                    // Quality 5 is a good speed/ratio balance for dynamic responses
+                    let mut writer = CompressorWriter::new(&mut output, 4096, 5, 22);
+                    writer.write_all(body)?;
+                }
+                Ok(output)
+            }
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// Picks the encoding to use from an `Accept-Encoding` header value.
+///
+/// # Returns
+/// The supported encoding with the highest quality value (`br` wins ties), `None` if the client
+/// accepts none of them
+pub fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    let mut best: Option<(Encoding, f32)> = None;
+
+    for entry in accept_encoding.split(',') {
+        let mut parts = entry.split(';');
+        let name = parts.next().unwrap_or_default().trim().to_lowercase();
+        let quality = parts
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        let encoding = match name.as_str() {
+            "br" => Encoding::Brotli,
+            "gzip" | "x-gzip" => Encoding::Gzip,
+            _ => continue,
+        };
+        if quality <= 0.0 {
+            continue;
+        }
+
+        let better = match best {
+            None => true,
+            Some((current, current_quality)) => {
+                quality > current_quality
+                    || (quality == current_quality
+                        && encoding == Encoding::Brotli
+                        && current != Encoding::Brotli)
+            }
+        };
+        if better {
+            best = Some((encoding, quality));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+/// Fairing compressing sized JSON & text responses. Streamed (unsized) bodies are left alone,
+/// so they are not buffered
+pub struct Compression {
+    min_size: usize,
+}
+
+impl Compression {
+    pub fn new(config: &CompressionConfig) -> Self {
+        Self {
+            min_size: config.min_size,
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Compression {
+    fn info(&self) -> Info {
+        Info {
+            name: "Response compression",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if response.headers().contains("Content-Encoding") {
+            return;
+        }
+        let compressible = response
+            .content_type()
+            .map(|content_type| content_type == ContentType::JSON || content_type.top() == "text")
+            .unwrap_or(false);
+        if !compressible {
+            return;
+        }
+        match response.body().preset_size() {
+            Some(size) if size >= self.min_size => {}
+            _ => return,
+        }
+        let Some(encoding) = request
+            .headers()
+            .get_one("Accept-Encoding")
+            .and_then(negotiate)
+        else {
+            return;
+        };
+
+        let body = match response.body_mut().to_bytes().await {
+            Ok(body) => body,
+            Err(e) => {
+                error!("Failed to read response body for compression: {}", e);
+                return;
+            }
+        };
+        match encoding.compress(&body) {
+            Ok(compressed) => {
+                response.set_header(Header::new("Content-Encoding", encoding.name()));
+                response.set_sized_body(compressed.len(), Cursor::new(compressed));
+            }
+            Err(e) => {
+                error!("Failed to compress response body: {}", e);
+                response.set_sized_body(body.len(), Cursor::new(body));
+            }
+        }
+        response.adjoin_header(Header::new("Vary", "Accept-Encoding"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compression::{negotiate, Encoding};
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate("gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("gzip, deflate, br"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("br;q=0.5, gzip;q=0.8"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("gzip;q=0, deflate"), None);
+        assert_eq!(negotiate("identity"), None);
+        assert_eq!(negotiate(""), None);
+    }
+}
@@ -0,0 +1,135 @@
+//! Classic (double-fork) daemonization & PID file for init-script deployments, see `serve --daemon`.
+//! Unix only, and it must happen before any thread (e.g. the async runtime) is started.
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+/// Detaches the process from its terminal & session, the calling process exits once the daemon is
+/// forked. The working directory is kept, so relative config paths stay valid.
+///
+/// # Arguments
+/// * `log_file` - stdout & stderr of the daemon are appended to it, discarded (`/dev/null`) when missing
+pub fn daemonize(log_file: Option<&Path>) -> io::Result<()> {
+    // Opened before forking, so errors still reach the terminal
+    let output = match log_file {
+        Some(path) => OpenOptions::new().create(true).append(true).open(path)?,
+        None => OpenOptions::new().write(true).open("/dev/null")?,
+    };
+    let input = File::open("/dev/null")?;
+
+    fork_and_exit_parent()?;
+    // New session without a controlling terminal
+    if unsafe { libc::setsid() } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // No longer a session leader, so it can't acquire a terminal again
+    fork_and_exit_parent()?;
+
+    redirect(&input, libc::STDIN_FILENO)?;
+    redirect(&output, libc::STDOUT_FILENO)?;
+    redirect(&output, libc::STDERR_FILENO)
+}
+
+fn fork_and_exit_parent() -> io::Result<()> {
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(()),
+        _ => unsafe { libc::_exit(0) },
+    }
+}
+
+fn redirect(file: &File, fd: libc::c_int) -> io::Result<()> {
+    if unsafe { libc::dup2(file.as_raw_fd(), fd) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// PID of the process recorded in `path`, if that process is still running
+pub fn running_pid(path: &Path) -> Option<i32> {
+    let pid: i32 = std::fs::read_to_string(path).ok()?.trim().parse().ok()?;
+    if pid <= 0 {
+        return None;
+    }
+    // Signal 0 only checks whether the process exists, EPERM means it's someone else's
+    let running = unsafe { libc::kill(pid, 0) } == 0
+        || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM);
+    running.then_some(pid)
+}
+
+/// File holding the PID of the running server, removed when dropped (i.e. on clean shutdown)
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Writes the current PID to `path`. A stale file (its process is gone) is replaced.
+    ///
+    /// # Returns
+    /// * `Ok(PidFile)` - The file was written
+    /// * `Err(io::Error)` - `AlreadyExists` if another running instance owns the file, or a write error
+    pub fn create(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        if let Some(pid) = running_pid(&path) {
+            if pid != std::process::id() as i32 {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} belongs to running process {}", path.display(), pid),
+                ));
+            }
+        }
+        std::fs::write(&path, format!("{}\n", std::process::id()))?;
+        Ok(Self { path })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            log::warn!("Failed to remove PID file {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::daemon::{running_pid, PidFile};
+    use std::io::ErrorKind;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_pid_file_is_removed_on_drop() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("sync-point.pid");
+
+        let pid_file = PidFile::create(&path).expect("PID file");
+        assert_eq!(running_pid(&path), Some(std::process::id() as i32));
+
+        drop(pid_file);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_pid_file_of_running_process_is_kept() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("sync-point.pid");
+        // PID 1 always runs
+        std::fs::write(&path, "1").unwrap();
+
+        let error = PidFile::create(&path).expect_err("owned by another process");
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "1");
+    }
+
+    #[test]
+    fn test_stale_pid_file_is_replaced() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("sync-point.pid");
+        std::fs::write(&path, "not a pid").unwrap();
+
+        let _pid_file = PidFile::create(&path).expect("PID file");
+        assert_eq!(running_pid(&path), Some(std::process::id() as i32));
+    }
+}
@@ -0,0 +1,294 @@
+//! The sync point subsystem packaged as a fairing, for embedding into an existing Rocket app:
+//! ```no_run
+//! use sync_point::app::App;
+//! use sync_point::fairing::SyncPointFairing;
+//!
+//! let app = App::new(Some("sync-point.toml")).expect("valid config");
+//! let rocket = rocket::build().attach(SyncPointFairing::new(app).at("/sync"));
+//! ```
+//! `build_rocket` uses it too, adding what belongs to a standalone server (port & listeners,
+//! compression, systemd notifications) on top.
+#[cfg(feature = "amqp")]
+use crate::amqp;
+#[cfg(feature = "admin")]
+use crate::api::admin::{
+    get_config, get_maintenance, get_observability_templates, list_wait_points, patch_config,
+    post_self_test, put_lame_duck, put_log_level, put_maintenance,
+};
+use crate::api::batch::batch_wait;
+use crate::api::body_limits::BodyLimits;
+use crate::api::catchers::default_catcher;
+use crate::api::clock::{time, Clock};
+#[cfg(feature = "cluster")]
+use crate::api::cluster::cluster_owner;
+use crate::api::completions::completion;
+use crate::api::deadline::with_deadline;
+use crate::api::dev::auto_match;
+use crate::api::file_triggers;
+#[cfg(feature = "metrics")]
+use crate::api::metrics::metrics;
+use crate::api::observers::observe;
+use crate::api::quotas::QuotaHeader;
+use crate::api::readiness::ready;
+#[cfg(feature = "receipts")]
+use crate::api::receipts::receipt_key;
+use crate::api::routes::{
+    allocator_stats, health, index, notify, outcome_stats, reserve, stats, status, wait,
+    wait_for_party,
+};
+#[cfg(feature = "shadow")]
+use crate::api::shadow::Shadow;
+use crate::api::shutdown;
+use crate::api::stats_cache;
+use crate::app::App;
+#[cfg(feature = "cluster")]
+use crate::gossip;
+#[cfg(feature = "k8s")]
+use crate::k8s;
+#[cfg(feature = "redis")]
+use crate::redis_stream;
+use log::{error, warn};
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::http::uri::Origin;
+use rocket::{catchers, routes, Build, Orbit, Rocket};
+use std::time::Duration;
+
+/// Manages the `App` state, mounts the routes (admin API under `<base>/admin`, the dev route under
+/// `<base>/dev` when enabled) within the request deadline, registers the JSON error catcher for
+/// `<base>` & attaches the `BodyLimits`, `Clock`, `QuotaHeader` & (with `shadow.url`) `Shadow`
+/// fairings. `/metrics`, `/receipts/key`, `/cluster/owner` & the admin API are only mounted with
+/// their Cargo features.
+///
+/// The warm-up of `GET /ready`, the file triggers, the AMQP bridge & the Redis stream sink start on
+/// liftoff, lame-duck mode on shutdown (see `api::readiness` module), which is then reported once
+/// drained (see `api::shutdown` module).
+///
+/// Attach it once per Rocket instance, Rocket can manage only one `App`. Instances sharing an `App`
+/// (see `listeners` module) attach it `listener_only` but one. The subsystem has no
+/// background tasks (except cluster gossip, Lease renewal, the stats refresh, file triggers, the
+/// AMQP bridge & the Redis stream sink when configured), wait points are cleaned up by the requests
+/// owning them.
+pub struct SyncPointFairing {
+    app: App,
+    base: String,
+    /// Serves the routes only, without the background tasks & the shutdown drain
+    listener_only: bool,
+}
+
+impl SyncPointFairing {
+    /// Mounts at `/`, like the standalone server
+    pub fn new(app: App) -> Self {
+        Self {
+            app,
+            base: "/".to_owned(),
+            listener_only: false,
+        }
+    }
+
+    /// Serves the routes only, for an instance sharing the `App` with another one, which runs the
+    /// background tasks & drains the wait points on shutdown
+    pub fn listener_only(mut self) -> Self {
+        self.listener_only = true;
+        self
+    }
+
+    /// Mounts under `base` instead, e.g. `/sync` serves `/sync/wait-for-second-party/<unique_id>`.
+    /// An invalid base fails the launch
+    pub fn at(mut self, base: &str) -> Self {
+        let base = base.trim_end_matches('/');
+        self.base = if base.is_empty() { "/" } else { base }.to_owned();
+        self
+    }
+
+    /// `path` under the base, e.g. `/sync` + `/admin` -> `/sync/admin`
+    fn under_base(&self, path: &str) -> String {
+        format!("{}{}", self.base.trim_end_matches('/'), path)
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for SyncPointFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Sync point",
+            kind: Kind::Ignite | Kind::Liftoff | Kind::Shutdown,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        // Rocket panics on an invalid base, fail the launch with a message instead
+        if let Err(e) = Origin::parse(&self.base) {
+            error!("Invalid sync point base '{}': {}", self.base, e);
+            return Err(rocket);
+        }
+
+        let rocket = rocket
+            // Attach our application state to Rocket's managed state
+            // This makes the App available to all route handlers
+            .manage(self.app.clone())
+            .mount(
+                self.base.as_str(),
+                with_deadline(routes![
+                    index,
+                    health,
+                    ready,
+                    status,
+                    stats,
+                    allocator_stats,
+                    outcome_stats,
+                    time,
+                    completion,
+                    observe,
+                    wait_for_party,
+                    wait,
+                    notify,
+                    reserve,
+                    batch_wait
+                ]),
+            )
+            .register(self.base.as_str(), catchers![default_catcher])
+            .attach(BodyLimits::new(
+                &self.base,
+                &self.app.settings().body_limits,
+            ))
+            .attach(Clock::new(&self.base))
+            .attach(QuotaHeader::new(&self.base));
+        #[cfg(feature = "shadow")]
+        let rocket = match Shadow::new(&self.base, &self.app.settings().shadow) {
+            Some(shadow) => rocket.attach(shadow),
+            None => rocket,
+        };
+
+        #[cfg(feature = "metrics")]
+        let rocket = rocket.mount(self.base.as_str(), with_deadline(routes![metrics]));
+        #[cfg(feature = "receipts")]
+        let rocket = rocket.mount(self.base.as_str(), with_deadline(routes![receipt_key]));
+        #[cfg(feature = "cluster")]
+        let rocket = rocket.mount(self.base.as_str(), with_deadline(routes![cluster_owner]));
+        if !self.listener_only {
+            #[cfg(feature = "cluster")]
+            if let (Some(cluster), Some(config)) =
+                (self.app.cluster(), &self.app.settings().cluster.gossip)
+            {
+                if let Err(e) = gossip::spawn(cluster.clone(), config).await {
+                    error!("Failed to start gossip on {}: {}", config.bind, e);
+                    return Err(rocket);
+                }
+            }
+            #[cfg(feature = "k8s")]
+            if let (Some(cluster), Some(config)) =
+                (self.app.cluster(), &self.app.settings().cluster.k8s)
+            {
+                if let Err(e) = k8s::spawn(cluster.clone(), config).await {
+                    error!("Failed to connect to the Kubernetes API: {}", e);
+                    return Err(rocket);
+                }
+            }
+
+            let stats_interval_ms = self.app.settings().stats_interval_ms;
+            if stats_interval_ms > 0 {
+                stats_cache::spawn(
+                    &self.app.sync_service,
+                    Duration::from_millis(stats_interval_ms),
+                );
+            }
+        }
+
+        // Admin API, disabled unless `admin_token` is configured
+        #[cfg(feature = "admin")]
+        let rocket = rocket.mount(
+            self.under_base("/admin"),
+            with_deadline(routes![
+                get_config,
+                patch_config,
+                put_log_level,
+                get_maintenance,
+                put_maintenance,
+                put_lame_duck,
+                list_wait_points,
+                get_observability_templates,
+                post_self_test
+            ]),
+        );
+
+        if self.app.settings().dev.auto_match {
+            warn!("Dev auto-match route is enabled, don't use in production");
+            return Ok(rocket.mount(self.under_base("/dev"), with_deadline(routes![auto_match])));
+        }
+        Ok(rocket)
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        if self.listener_only {
+            return;
+        }
+        self.app.readiness.start(self.app.warmup());
+        let file_triggers = &self.app.settings().file_triggers;
+        if !file_triggers.triggers.is_empty() {
+            file_triggers::spawn(&self.app, file_triggers, rocket.shutdown());
+        }
+        #[cfg(feature = "amqp")]
+        if let Some(config) = &self.app.settings().amqp {
+            amqp::spawn(&self.app, config, rocket.shutdown());
+        }
+        #[cfg(feature = "redis")]
+        if let Some(config) = &self.app.settings().redis_stream {
+            redis_stream::spawn(&self.app, config, rocket.shutdown());
+        }
+    }
+
+    async fn on_shutdown(&self, rocket: &Rocket<Orbit>) {
+        if self.listener_only {
+            return;
+        }
+        self.app.readiness.set_lame_duck(true);
+        let periods = &rocket.config().shutdown;
+        let grace = Duration::from_secs(u64::from(periods.grace) + u64::from(periods.mercy));
+        let report = shutdown::drain(&self.app.sync_service, grace).await;
+        shutdown::publish(&report, &self.app.settings().shutdown_report).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::App;
+    use crate::fairing::SyncPointFairing;
+    use rocket::error::ErrorKind;
+    use rocket::http::Status;
+    use rocket::local::asynchronous::Client;
+    use rocket::{get, routes};
+
+    #[get("/")]
+    fn host_index() -> &'static str {
+        "host"
+    }
+
+    #[rocket::async_test]
+    async fn test_embedded_under_base() {
+        let rocket = rocket::build()
+            .mount("/", routes![host_index])
+            .attach(SyncPointFairing::new(App::new(None).unwrap()).at("/sync/"));
+        let client = Client::tracked(rocket).await.unwrap();
+
+        let response = client.get("/").dispatch().await;
+        assert_eq!(response.into_string().await.unwrap(), "host");
+        let response = client.get("/sync/health").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        // Disabled without `admin_token` (or not compiled in), the catcher answers either way
+        let response = client.get("/sync/admin/config").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(
+            response.into_string().await.unwrap(),
+            r#"{"status":"error","message":"Not Found"}"#
+        );
+    }
+
+    #[rocket::async_test]
+    async fn test_invalid_base_fails_launch() {
+        let rocket =
+            rocket::build().attach(SyncPointFairing::new(App::new(None).unwrap()).at("sync"));
+        let error = Client::tracked(rocket).await.expect_err("launch fails");
+        // Inspecting the error marks it handled, Rocket panics on dropping unhandled ones
+        assert!(matches!(error.kind(), ErrorKind::FailedFairings(_)));
+    }
+}
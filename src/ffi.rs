@@ -0,0 +1,149 @@
+//! Minimal C client API, so firmware test rigs written in C can join a rendezvous without an HTTP
+//! library of their own. Only compiled with the `ffi` feature, which also generates the header
+//! committed as `include/sync_point.h`.
+//!
+//! Build a linkable library with e.g. `cargo rustc --release --features ffi --lib --crate-type cdylib`.
+//! Only plain `http://` URLs are supported.
+use crate::protocol::{self, status};
+use std::ffi::{c_char, CStr};
+use std::time::Duration;
+
+/// Outcome of `sp_wait`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpStatus {
+    /// Both parties met
+    Matched = 0,
+    /// The peer didn't arrive within the server's timeout or `timeout_ms`
+    Timeout = 1,
+    /// The wait point already has 2 parties
+    Conflict = 2,
+    /// The server can't take the request now (capacity, maintenance), retry later
+    Unavailable = 3,
+    /// A null or non UTF-8 argument
+    InvalidArgument = 4,
+    /// Connection failure or unexpected response
+    Error = 5,
+}
+
+impl SpStatus {
+    /// Maps the HTTP status of a sync response, assuming the server's default `status_codes`
+    fn from_http(code: u16) -> Self {
+        match code {
+            status::MATCHED => SpStatus::Matched,
+            status::TIMED_OUT => SpStatus::Timeout,
+            status::CONFLICT => SpStatus::Conflict,
+            status::UNAVAILABLE => SpStatus::Unavailable,
+            _ => SpStatus::Error,
+        }
+    }
+}
+
+/// Waits at the rendezvous `id` until the second party arrives, blocking the calling thread.
+///
+/// # Arguments
+/// * `url` - Base URL of the server, e.g. `http://10.0.0.2:8000`
+/// * `id` - Rendezvous id, shared with the other party
+/// * `timeout_ms` - Gives up (`SP_STATUS_TIMEOUT`) after this long even if the server waits longer,
+///   0 leaves it to the server
+///
+/// # Safety
+/// `url` & `id` must be null or valid NUL-terminated strings, which are only read during the call.
+#[no_mangle]
+pub unsafe extern "C" fn sp_wait(
+    url: *const c_char,
+    id: *const c_char,
+    timeout_ms: u64,
+) -> SpStatus {
+    let (Some(url), Some(id)) = (to_str(url), to_str(id)) else {
+        return SpStatus::InvalidArgument;
+    };
+    wait(url, id, timeout_ms)
+}
+
+/// Borrows a C string, `None` if it's null or not UTF-8
+///
+/// # Safety
+/// Same as `sp_wait` arguments
+unsafe fn to_str<'a>(ptr: *const c_char) -> Option<&'a str> {
+    if ptr.is_null() {
+        return None;
+    }
+    CStr::from_ptr(ptr).to_str().ok()
+}
+
+fn wait(url: &str, id: &str, timeout_ms: u64) -> SpStatus {
+    let mut agent = ureq::AgentBuilder::new();
+    if timeout_ms > 0 {
+        agent = agent.timeout(Duration::from_millis(timeout_ms));
+    }
+    let endpoint = format!("{}{}", url.trim_end_matches('/'), protocol::wait_path(id));
+
+    match agent.build().post(&endpoint).call() {
+        Ok(response) => SpStatus::from_http(response.status()),
+        Err(ureq::Error::Status(code, _)) => SpStatus::from_http(code),
+        Err(ureq::Error::Transport(transport)) => match transport.kind() {
+            ureq::ErrorKind::Io if is_timeout(&transport) => SpStatus::Timeout,
+            _ => SpStatus::Error,
+        },
+    }
+}
+
+/// Whether the request ran out of `timeout_ms`
+fn is_timeout(transport: &ureq::Transport) -> bool {
+    let source = std::error::Error::source(transport);
+    source
+        .and_then(|e| e.downcast_ref::<std::io::Error>())
+        .is_some_and(|e| {
+            matches!(
+                e.kind(),
+                std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ffi::{sp_wait, SpStatus};
+    use std::ffi::CString;
+    use std::net::TcpListener;
+    use std::ptr;
+
+    #[test]
+    fn test_invalid_arguments() {
+        let id = CString::new("123").unwrap();
+        let status = unsafe { sp_wait(ptr::null(), id.as_ptr(), 0) };
+        assert_eq!(status, SpStatus::InvalidArgument);
+    }
+
+    #[test]
+    fn test_status_mapping() {
+        assert_eq!(SpStatus::from_http(200), SpStatus::Matched);
+        assert_eq!(SpStatus::from_http(408), SpStatus::Timeout);
+        assert_eq!(SpStatus::from_http(409), SpStatus::Conflict);
+        assert_eq!(SpStatus::from_http(503), SpStatus::Unavailable);
+        assert_eq!(SpStatus::from_http(404), SpStatus::Error);
+    }
+
+    #[test]
+    fn test_gives_up_after_timeout_ms() {
+        // Accepts the connection, but never responds
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = CString::new(format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        let id = CString::new("123").unwrap();
+
+        let status = unsafe { sp_wait(url.as_ptr(), id.as_ptr(), 200) };
+        assert_eq!(status, SpStatus::Timeout);
+    }
+
+    #[test]
+    fn test_header_is_up_to_date() {
+        let generated = include_str!(concat!(env!("OUT_DIR"), "/sync_point.h"));
+        let committed = include_str!("../include/sync_point.h");
+        assert!(
+            generated == committed,
+            "include/sync_point.h is stale, copy {}/sync_point.h over it",
+            env!("OUT_DIR")
+        );
+    }
+}
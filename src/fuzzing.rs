@@ -0,0 +1,185 @@
+//! Bodies of the fuzz targets under `fuzz/` (see README), kept in the library so they're built &
+//! checked along with it. Each takes the raw input of the fuzzer & panics when an invariant breaks:
+//! - `ids` - ids taken from request paths, & the patterns matching them
+//! - `config` - config files, in any supported format
+//! - `cursor` - pagination cursors, the tokens clients send back to resume a listing
+//! - `wait_points` - sequences of parties joining, previewing (`dry_run`) & leaving wait points
+//!
+//! Only compiled with the `fuzzing` feature.
+use crate::api::acl::Access;
+use crate::api::ids::{is_internal, IdPath, IdPattern};
+use crate::api::pagination::Cursor;
+use crate::api::response::Failure;
+use crate::api::sync_service::SyncRequest;
+use crate::app::App;
+use crate::settings::Settings;
+use config::FileFormat;
+use rocket::http::uri::Origin;
+use rocket::http::Status;
+use rocket::request::FromSegments;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Distinct ids of the `wait_points` target, few so parties meet
+const IDS: u8 = 4;
+/// Steps of the `wait_points` target at most, more take long without exploring further
+const MAX_STEPS: usize = 64;
+
+/// Ids taken from a request path must be non-empty, free of empty segments & outside the reserved
+/// namespace. A pattern of the id matches it, so does one of the id followed by `*`
+pub fn ids(data: &[u8]) {
+    let Ok(path) = std::str::from_utf8(data) else {
+        return;
+    };
+    IdPattern::parse(path).matches(path);
+    let Ok(origin) = Origin::parse_owned(format!("/{}", path)) else {
+        return;
+    };
+    let Ok(unique_id) = IdPath::from_segments(origin.path().segments()) else {
+        return;
+    };
+    assert!(!unique_id.is_empty());
+    assert!(!unique_id.split('/').any(str::is_empty), "{:?}", unique_id);
+    assert!(!is_internal(&unique_id), "{:?}", unique_id);
+    assert!(IdPattern::parse(&unique_id).matches(&unique_id));
+    assert!(IdPattern::parse(&format!("{}*", unique_id.as_str())).matches(&unique_id));
+}
+
+/// Config files are parsed without panicking, in the format picked by the first byte. Settings
+/// which parse are valid & can be printed
+pub fn config(data: &[u8]) {
+    let Some((format, content)) = data.split_first() else {
+        return;
+    };
+    let Ok(content) = std::str::from_utf8(content) else {
+        return;
+    };
+    let format = match format % 3 {
+        0 => FileFormat::Toml,
+        1 => FileFormat::Json,
+        _ => FileFormat::Yaml,
+    };
+    if let Ok(settings) = Settings::parse(content, format) {
+        settings.validate().expect("parsed settings are valid");
+        assert!(settings.redacted().is_object());
+    }
+}
+
+/// Cursors are decoded without panicking, those which decode are encoded back the same way
+pub fn cursor(data: &[u8]) {
+    let Ok(token) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Ok(cursor) = Cursor::decode(token) {
+        assert_eq!(Cursor::decode(&cursor.to_string()), Ok(cursor));
+    }
+}
+
+/// Every 2 bytes are a step on one of `IDS` ids: the first picks the id & whether a party joins
+/// (with a `ttl` of 1-4 sec), previews or the last party to join leaves. The second is the time
+/// before the next step, up to ~5 sec. Time is virtual, like in the `simulation` module.
+///
+/// Parties are answered with a match, a timeout or a conflict. Once all are, no wait point is left
+pub fn wait_points(data: &[u8]) {
+    let app = Arc::new(App::new(None).expect("default settings"));
+    tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .start_paused(true)
+        .build()
+        .expect("Failed to build fuzzing runtime")
+        .block_on(async move {
+            let mut parties = Vec::new();
+            for step in data.chunks_exact(2).take(MAX_STEPS) {
+                let unique_id = format!("fuzz-{}", step[0] % IDS);
+                match step[0] / IDS % 3 {
+                    0 => {
+                        let app = app.clone();
+                        let ttl = u64::from(step[1] % 4) + 1;
+                        parties.push(tokio::spawn(async move {
+                            join(&app, &unique_id, Some(ttl), None).await
+                        }));
+                    }
+                    1 => {
+                        join(&app, &unique_id, None, Some(true)).await;
+                    }
+                    _ => {
+                        if let Some(party) = parties.pop() {
+                            party.abort();
+                        }
+                    }
+                }
+                tokio::time::sleep(Duration::from_millis(u64::from(step[1]) * 20)).await;
+            }
+
+            let status_codes = &app.sync_service.status_codes;
+            for party in parties {
+                let Ok(status) = party.await else {
+                    continue;
+                };
+                assert!(
+                    status == Status::Ok
+                        || status == status_codes.status(Failure::Timeout)
+                        || status == status_codes.status(Failure::Conflict),
+                    "unexpected {}",
+                    status
+                );
+            }
+            let stats = app.sync_service.current_stats();
+            assert_eq!(stats.waiting_parties, 0);
+            assert_eq!(stats.open_wait_points, 0);
+        });
+}
+
+/// Status of a party calling `POST /wait-for-second-party/<unique_id>`
+async fn join(app: &App, unique_id: &str, ttl: Option<u64>, dry_run: Option<bool>) -> Status {
+    let request = SyncRequest {
+        dry_run: dry_run == Some(true),
+        ttl,
+        ..SyncRequest::default()
+    };
+    match app
+        .sync_service
+        .join(unique_id, request, Access::default(), app)
+        .await
+    {
+        Ok(response) => response.0,
+        Err(retry_after) => retry_after.0 .0,
+    }
+}
+
+/// App reads env vars, hence `#[serial]` like the tests in `app.rs`
+#[cfg(test)]
+mod tests {
+    use crate::fuzzing::{config, cursor, ids, wait_points};
+    use serial_test::serial;
+
+    #[test]
+    fn test_parsers() {
+        for input in [
+            "",
+            "/",
+            "deploy//eu/*",
+            "_internal/1",
+            "%2F%2f",
+            "a/%00/..",
+            "\u{1F600}",
+        ] {
+            ids(input.as_bytes());
+            cursor(input.as_bytes());
+        }
+        cursor(b"313a32");
+        config(b"\x00timeout = 30\n[status_codes]\ntimeout = 504");
+        config(b"\x01{\"timeout\": 1}");
+        config(b"\x02templates: [{pattern: '*', schema: '{'}]");
+    }
+
+    #[test]
+    #[serial]
+    fn test_wait_points() {
+        // Matched, timed out, previewed & left
+        wait_points(&[
+            0, 0, 0, 10, 1, 0, 1, 0, 1, 0, 1, 200, 4, 0, 2, 0, 8, 250, 3, 255,
+        ]);
+        wait_points(&[0, 0, 8, 0, 8, 0]);
+    }
+}
@@ -0,0 +1,377 @@
+//! Gossip membership of cluster mode (`[cluster.gossip]`), so nodes discover each other & detect
+//! failures without a static member list. SWIM-style: every `interval_ms` a node increments its
+//! heartbeat & pings the next member (round robin, the seeds until it knows any), which answers
+//! with an ack. Both carry the sender's view of the members as JSON over UDP.
+//!
+//! A member whose heartbeat wasn't refreshed (directly or through another node) within
+//! `failure_timeout_ms` is considered failed & leaves the hash ring, see `cluster` module.
+//! There are no indirect probes & no encryption, keep gossip on a trusted network.
+use crate::cluster::{Cluster, Node};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::net::UdpSocket;
+
+/// Largest UDP payload, enough for a few hundred members
+const MAX_DATAGRAM: usize = 65_507;
+
+/// `[cluster.gossip]` config section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GossipConfig {
+    /// UDP address gossip is received on
+    pub bind: String,
+    /// Base URL clients reach this node at, e.g. `http://10.0.0.1:8000`
+    pub url: String,
+    /// Share of the ids of this node relative to the others
+    pub weight: u32,
+    /// Gossip addresses of nodes to join the cluster through, e.g. `10.0.0.2:7946`
+    pub seeds: Vec<String>,
+    pub interval_ms: u64,
+    pub failure_timeout_ms: u64,
+}
+
+impl Default for GossipConfig {
+    fn default() -> Self {
+        Self {
+            bind: "0.0.0.0:7946".to_owned(),
+            url: String::new(),
+            weight: 1,
+            seeds: Vec::new(),
+            interval_ms: 1000,
+            failure_timeout_ms: 5000,
+        }
+    }
+}
+
+impl GossipConfig {
+    /// # Returns
+    /// * `Ok(())` - If the section is valid
+    /// * `Err(String)` - Describing the first invalid value
+    pub fn validate(&self) -> Result<(), String> {
+        if self.url.is_empty() {
+            return Err("cluster.gossip.url is required".to_owned());
+        }
+        if self.weight == 0 {
+            return Err("cluster.gossip.weight must be at least 1".to_owned());
+        }
+        if self.interval_ms == 0 {
+            return Err("cluster.gossip.interval_ms must be at least 1".to_owned());
+        }
+        if self.failure_timeout_ms <= self.interval_ms {
+            return Err("cluster.gossip.failure_timeout_ms must exceed interval_ms".to_owned());
+        }
+        Ok(())
+    }
+
+    /// This node as a member of the ring
+    pub fn node(&self, name: &str) -> Node {
+        Node {
+            name: name.to_owned(),
+            url: self.url.clone(),
+            weight: self.weight,
+        }
+    }
+}
+
+/// A member as gossiped
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Member {
+    node: Node,
+    /// Gossip address, as seen by the node which last heard from it directly
+    address: String,
+    /// Start of the node in ms since the epoch, so a restarted node's heartbeats count as newer
+    incarnation: u64,
+    heartbeat: u64,
+}
+
+impl Member {
+    fn version(&self) -> (u64, u64) {
+        (self.incarnation, self.heartbeat)
+    }
+}
+
+/// Ping or ack, the sender comes first in `members`
+#[derive(Debug, Serialize, Deserialize)]
+struct Message {
+    ack: bool,
+    members: Vec<Member>,
+}
+
+struct Peer {
+    member: Member,
+    /// When a newer heartbeat was last heard of
+    updated_at: Instant,
+    failed: bool,
+}
+
+/// Membership state of a node, without IO
+struct Gossip {
+    local: Member,
+    /// By name, sorted for a stable probe order
+    peers: BTreeMap<String, Peer>,
+    seeds: Vec<String>,
+    /// Round robin position of the next probe
+    next: usize,
+    failure_timeout: Duration,
+}
+
+impl Gossip {
+    fn new(local: Member, seeds: Vec<String>, failure_timeout: Duration) -> Self {
+        Self {
+            local,
+            peers: BTreeMap::new(),
+            seeds,
+            next: 0,
+            failure_timeout,
+        }
+    }
+
+    /// Increments the heartbeat, detects failed members & picks the member to ping
+    ///
+    /// # Returns
+    /// The address to send the ping to, `None` if no members or seeds are known
+    fn tick(&mut self, now: Instant) -> Option<(String, Message)> {
+        self.local.heartbeat += 1;
+        for (name, peer) in &mut self.peers {
+            if !peer.failed && now.duration_since(peer.updated_at) > self.failure_timeout {
+                warn!(
+                    "Cluster node {} failed, no heartbeat within {:?}",
+                    name, self.failure_timeout
+                );
+                peer.failed = true;
+            }
+        }
+        // Failed members are kept a while, so that stale gossip about them isn't taken as news
+        let forget_after = self.failure_timeout * 3;
+        self.peers
+            .retain(|_, peer| !peer.failed || now.duration_since(peer.updated_at) < forget_after);
+
+        let mut targets: Vec<&str> = self.alive().map(|member| member.address.as_str()).collect();
+        if targets.is_empty() {
+            targets = self.seeds.iter().map(String::as_str).collect();
+        }
+        if targets.is_empty() {
+            return None;
+        }
+        let target = targets[self.next % targets.len()].to_owned();
+        self.next = self.next.wrapping_add(1);
+        Some((target, self.message(false)))
+    }
+
+    /// Takes the newer heartbeats of `members`, received from `source`
+    fn merge(&mut self, source: SocketAddr, mut members: Vec<Member>, now: Instant) {
+        if let Some(sender) = members.first_mut() {
+            sender.address = source.to_string();
+        }
+        for member in members {
+            if member.node.name == self.local.node.name {
+                continue;
+            }
+            match self.peers.get_mut(&member.node.name) {
+                Some(peer) if member.version() <= peer.member.version() => {}
+                Some(peer) => {
+                    if peer.failed {
+                        info!("Cluster node {} is back", member.node.name);
+                    }
+                    *peer = Peer {
+                        member,
+                        updated_at: now,
+                        failed: false,
+                    };
+                }
+                None => {
+                    info!("Cluster node {} joined", member.node.name);
+                    let peer = Peer {
+                        member,
+                        updated_at: now,
+                        failed: false,
+                    };
+                    self.peers.insert(peer.member.node.name.clone(), peer);
+                }
+            }
+        }
+    }
+
+    fn message(&self, ack: bool) -> Message {
+        Message {
+            ack,
+            members: std::iter::once(&self.local)
+                .chain(self.alive())
+                .cloned()
+                .collect(),
+        }
+    }
+
+    fn alive(&self) -> impl Iterator<Item = &Member> {
+        self.peers
+            .values()
+            .filter(|peer| !peer.failed)
+            .map(|peer| &peer.member)
+    }
+
+    /// Members of the ring, this node included
+    fn nodes(&self) -> Vec<Node> {
+        std::iter::once(&self.local)
+            .chain(self.alive())
+            .map(|member| member.node.clone())
+            .collect()
+    }
+}
+
+/// Binds the gossip socket & runs the gossip of this node in the background, feeding the members
+/// to `cluster`. Started by `SyncPointFairing` on ignite
+///
+/// # Returns
+/// * `Ok(SocketAddr)` - The address gossip is received on
+/// * `Err(io::Error)` - If `config.bind` can't be bound
+pub async fn spawn(cluster: Arc<Cluster>, config: &GossipConfig) -> io::Result<SocketAddr> {
+    let socket = UdpSocket::bind(&config.bind).await?;
+    let address = socket.local_addr()?;
+    let incarnation = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let local = Member {
+        node: config.node(cluster.node()),
+        address: address.to_string(),
+        incarnation,
+        heartbeat: 0,
+    };
+    let mut gossip = Gossip::new(
+        local,
+        config.seeds.clone(),
+        Duration::from_millis(config.failure_timeout_ms),
+    );
+    let mut ticks = tokio::time::interval(Duration::from_millis(config.interval_ms));
+    info!("Gossiping on {}", address);
+
+    tokio::spawn(async move {
+        let mut buffer = vec![0; MAX_DATAGRAM];
+        loop {
+            tokio::select! {
+                _ = ticks.tick() => {
+                    if let Some((target, ping)) = gossip.tick(Instant::now()) {
+                        send(&socket, &target, &ping).await;
+                    }
+                }
+                received = socket.recv_from(&mut buffer) => {
+                    let (len, source) = match received {
+                        Ok(received) => received,
+                        Err(e) => {
+                            debug!("Failed to receive gossip: {}", e);
+                            continue;
+                        }
+                    };
+                    match serde_json::from_slice::<Message>(&buffer[..len]) {
+                        Ok(message) => {
+                            gossip.merge(source, message.members, Instant::now());
+                            if !message.ack {
+                                send(&socket, &source.to_string(), &gossip.message(true)).await;
+                            }
+                        }
+                        Err(e) => debug!("Invalid gossip from {}: {}", source, e),
+                    }
+                }
+            }
+            cluster.set_members(gossip.nodes());
+        }
+    });
+    Ok(address)
+}
+
+async fn send(socket: &UdpSocket, target: &str, message: &Message) {
+    let payload = serde_json::to_vec(message).expect("serializable message");
+    if let Err(e) = socket.send_to(&payload, target).await {
+        debug!("Failed to send gossip to {}: {}", target, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cluster::{Cluster, ClusterConfig};
+    use crate::gossip::{spawn, Gossip, GossipConfig, Member};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    fn member(name: &str, heartbeat: u64) -> Member {
+        Member {
+            node: GossipConfig::default().node(name),
+            address: format!("{}:7946", name),
+            incarnation: 1,
+            heartbeat,
+        }
+    }
+
+    fn names(gossip: &Gossip) -> Vec<String> {
+        gossip.nodes().into_iter().map(|node| node.name).collect()
+    }
+
+    #[test]
+    fn test_failure_detection() {
+        let timeout = Duration::from_secs(5);
+        let start = Instant::now();
+        let mut gossip = Gossip::new(member("a", 0), vec!["seed:7946".to_owned()], timeout);
+        assert_eq!(gossip.tick(start).unwrap().0, "seed:7946");
+
+        let source = "10.0.0.2:7946".parse().unwrap();
+        gossip.merge(source, vec![member("b", 1), member("c", 1)], start);
+        assert_eq!(names(&gossip), ["a", "b", "c"]);
+        // Probes go to the known members now, round robin. The sender (b) at the address it was
+        // heard from, c as gossiped
+        let (target, ping) = gossip.tick(start).unwrap();
+        assert_eq!(target, "c:7946");
+        assert_eq!(ping.members.len(), 3);
+        assert_eq!(gossip.tick(start).unwrap().0, "10.0.0.2:7946");
+
+        // b keeps beating, c doesn't
+        let later = start + Duration::from_secs(4);
+        gossip.merge(source, vec![member("b", 5), member("c", 1)], later);
+        gossip.tick(start + Duration::from_secs(6));
+        assert_eq!(names(&gossip), ["a", "b"]);
+        // Stale gossip doesn't revive c, a newer heartbeat does
+        gossip.merge(source, vec![member("b", 6), member("c", 1)], later);
+        assert_eq!(names(&gossip), ["a", "b"]);
+        gossip.merge(source, vec![member("b", 6), member("c", 2)], later);
+        assert_eq!(names(&gossip), ["a", "b", "c"]);
+    }
+
+    #[rocket::async_test]
+    async fn test_nodes_discover_each_other() {
+        let gossip = |seeds: Vec<String>| GossipConfig {
+            bind: "127.0.0.1:0".to_owned(),
+            url: "http://127.0.0.1:8000".to_owned(),
+            seeds,
+            interval_ms: 20,
+            failure_timeout_ms: 1000,
+            ..GossipConfig::default()
+        };
+        let cluster = |node: &str, gossip: &GossipConfig| {
+            let config = ClusterConfig {
+                node: Some(node.to_owned()),
+                gossip: Some(gossip.clone()),
+                ..ClusterConfig::default()
+            };
+            Arc::new(Cluster::new(&config).unwrap())
+        };
+
+        let config_a = gossip(vec![]);
+        let a = cluster("a", &config_a);
+        let address = spawn(a.clone(), &config_a).await.unwrap();
+        let config_b = gossip(vec![address.to_string()]);
+        let b = cluster("b", &config_b);
+        spawn(b.clone(), &config_b).await.unwrap();
+
+        for _ in 0..50 {
+            if a.members() == 2 && b.members() == 2 {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("Nodes didn't discover each other");
+    }
+}
@@ -0,0 +1,77 @@
+//! HMAC-SHA256 (RFC 2104), signing webhook digests (see `api::digests` module), & the lowercase
+//! hex of signatures & checksums (also of log archive uploads, see `archive` module)
+use ::hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fmt::Write;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    mac(key, data).finalize().into_bytes().into()
+}
+
+/// Whether `signature` (lowercase or uppercase hex) is the HMAC-SHA256 of `data` with `key`,
+/// compared in constant time as receivers of signed digests should
+pub fn verify_hmac_sha256(key: &[u8], data: &[u8], signature: &str) -> bool {
+    let Some(signature) = unhex(signature) else {
+        return false;
+    };
+    mac(key, data).verify_slice(&signature).is_ok()
+}
+
+fn mac(key: &[u8], data: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac
+}
+
+/// Lowercase hex of `bytes`, as signatures & digests are sent
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{:02x}", byte);
+        hex
+    })
+}
+
+/// Bytes of `hex`, `None` if it isn't hex
+fn unhex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hmac::{hex, hmac_sha256, verify_hmac_sha256};
+
+    #[test]
+    fn test_hmac_sha256() {
+        // RFC 4231 test cases 2 & 6 (key longer than a block)
+        assert_eq!(
+            hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            hex(&hmac_sha256(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            )),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[test]
+    fn test_verify_hmac_sha256() {
+        let data = b"what do ya want for nothing?";
+        let signature = "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843";
+        assert!(verify_hmac_sha256(b"Jefe", data, signature));
+        assert!(verify_hmac_sha256(b"Jefe", data, &signature.to_uppercase()));
+        assert!(!verify_hmac_sha256(b"jefe", data, signature));
+        assert!(!verify_hmac_sha256(b"Jefe", data, &signature[..62]));
+        assert!(!verify_hmac_sha256(b"Jefe", data, "not hex"));
+    }
+}
@@ -0,0 +1,226 @@
+//! Cluster membership from Kubernetes Lease objects (`[cluster.k8s]`), for deployments on
+//! Kubernetes only, instead of gossip. Each node holds a Lease (`<cluster>-<node>`, labeled
+//! `sync-point/cluster=<cluster>`) & renews it every `renew_interval_sec`. The holders of the
+//! unexpired Leases are the members of the hash ring, see `cluster` module.
+//!
+//! Runs with the pod's service account, which needs `get`, `list` & `patch` on `leases` in the
+//! namespace. Only compiled with the `k8s` feature.
+use crate::cluster::{Cluster, Node};
+use k8s_openapi::api::coordination::v1::{Lease, LeaseSpec};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{MicroTime, ObjectMeta};
+use k8s_openapi::chrono::{DateTime, Duration as ChronoDuration, Utc};
+use kube::api::{Api, ListParams, Patch, PatchParams};
+use kube::Client;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+const CLUSTER_LABEL: &str = "sync-point/cluster";
+const URL_ANNOTATION: &str = "sync-point/url";
+const WEIGHT_ANNOTATION: &str = "sync-point/weight";
+/// Field manager of the server-side applied Leases
+const FIELD_MANAGER: &str = "sync-point";
+
+/// `[cluster.k8s]` config section. The node name defaults to the pod name (`HOSTNAME`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct K8sConfig {
+    /// Namespace of the Leases, the pod's own when missing
+    pub namespace: Option<String>,
+    /// Name of the cluster, several can share a namespace
+    pub cluster: String,
+    /// Base URL clients reach this node at, `http://<POD_IP env>:<port>` when missing
+    pub url: Option<String>,
+    pub port: u16,
+    /// Share of the ids of this node relative to the others
+    pub weight: u32,
+    /// A node whose Lease wasn't renewed for this long leaves the ring
+    pub lease_duration_sec: u64,
+    pub renew_interval_sec: u64,
+}
+
+impl Default for K8sConfig {
+    fn default() -> Self {
+        Self {
+            namespace: None,
+            cluster: "sync-point".to_owned(),
+            url: None,
+            port: 8000,
+            weight: 1,
+            lease_duration_sec: 15,
+            renew_interval_sec: 5,
+        }
+    }
+}
+
+impl K8sConfig {
+    /// # Returns
+    /// * `Ok(())` - If the section is valid
+    /// * `Err(String)` - Describing the first invalid value
+    pub fn validate(&self) -> Result<(), String> {
+        if self.node_url().is_none() {
+            return Err("cluster.k8s.url is required unless the POD_IP env var is set".to_owned());
+        }
+        if self.weight == 0 {
+            return Err("cluster.k8s.weight must be at least 1".to_owned());
+        }
+        if self.renew_interval_sec == 0 || self.lease_duration_sec <= self.renew_interval_sec {
+            return Err(
+                "cluster.k8s.lease_duration_sec must exceed renew_interval_sec (at least 1)"
+                    .to_owned(),
+            );
+        }
+        Ok(())
+    }
+
+    /// This node as a member of the ring
+    pub fn node(&self, name: &str) -> Node {
+        Node {
+            name: name.to_owned(),
+            url: self.node_url().unwrap_or_default(),
+            weight: self.weight,
+        }
+    }
+
+    fn node_url(&self) -> Option<String> {
+        self.url.clone().or_else(|| {
+            let ip = std::env::var("POD_IP").ok()?;
+            Some(format!("http://{}:{}", ip, self.port))
+        })
+    }
+
+    fn lease_name(&self, node: &str) -> String {
+        format!("{}-{}", self.cluster, node)
+    }
+
+    /// Lease of `node`, renewed at `now`
+    fn lease(&self, node: &Node, now: DateTime<Utc>) -> Lease {
+        Lease {
+            metadata: ObjectMeta {
+                name: Some(self.lease_name(&node.name)),
+                labels: Some(BTreeMap::from([(
+                    CLUSTER_LABEL.to_owned(),
+                    self.cluster.clone(),
+                )])),
+                annotations: Some(BTreeMap::from([
+                    (URL_ANNOTATION.to_owned(), node.url.clone()),
+                    (WEIGHT_ANNOTATION.to_owned(), node.weight.to_string()),
+                ])),
+                ..ObjectMeta::default()
+            },
+            spec: Some(LeaseSpec {
+                holder_identity: Some(node.name.clone()),
+                lease_duration_seconds: Some(self.lease_duration_sec as i32),
+                renew_time: Some(MicroTime(now)),
+                ..LeaseSpec::default()
+            }),
+        }
+    }
+}
+
+/// Holders of the Leases unexpired at `now`, sorted by name
+fn members(leases: &[Lease], now: DateTime<Utc>) -> Vec<Node> {
+    leases
+        .iter()
+        .filter_map(|lease| {
+            let spec = lease.spec.as_ref()?;
+            let renewed = spec.renew_time.as_ref()?.0;
+            let duration = ChronoDuration::seconds(spec.lease_duration_seconds?.into());
+            if renewed + duration < now {
+                return None;
+            }
+            let annotations = lease.metadata.annotations.as_ref()?;
+            Some(Node {
+                name: spec.holder_identity.clone()?,
+                url: annotations.get(URL_ANNOTATION)?.clone(),
+                weight: annotations
+                    .get(WEIGHT_ANNOTATION)
+                    .and_then(|weight| weight.parse().ok())
+                    .unwrap_or(1)
+                    .max(1),
+            })
+        })
+        .collect()
+}
+
+/// Connects to the API server with the in-cluster config (or kubeconfig) & renews this node's
+/// Lease in the background, feeding the members to `cluster`. Started by `SyncPointFairing` on
+/// ignite
+///
+/// # Returns
+/// * `Ok(())` - If the client could be configured
+/// * `Err(kube::Error)` - Otherwise, e.g. outside Kubernetes
+pub async fn spawn(cluster: Arc<Cluster>, config: &K8sConfig) -> Result<(), kube::Error> {
+    let client = Client::try_default().await?;
+    let leases: Api<Lease> = match &config.namespace {
+        Some(namespace) => Api::namespaced(client, namespace),
+        None => Api::default_namespaced(client),
+    };
+    let config = config.clone();
+    let node = config.node(cluster.node());
+    let mut ticks = tokio::time::interval(Duration::from_secs(config.renew_interval_sec));
+    info!("Holding Lease {}", config.lease_name(&node.name));
+
+    tokio::spawn(async move {
+        let params = PatchParams::apply(FIELD_MANAGER).force();
+        let selector =
+            ListParams::default().labels(&format!("{}={}", CLUSTER_LABEL, config.cluster));
+        loop {
+            ticks.tick().await;
+            let lease = config.lease(&node, Utc::now());
+            if let Err(e) = leases
+                .patch(
+                    &config.lease_name(&node.name),
+                    &params,
+                    &Patch::Apply(&lease),
+                )
+                .await
+            {
+                warn!("Failed to renew Lease of {}: {}", node.name, e);
+            }
+            match leases.list(&selector).await {
+                Ok(list) => cluster.set_members(members(&list.items, Utc::now())),
+                Err(e) => warn!("Failed to list Leases, keeping the members: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::k8s::{members, K8sConfig};
+    use k8s_openapi::chrono::{Duration, Utc};
+
+    #[test]
+    fn test_members_are_unexpired_lease_holders() {
+        let config = K8sConfig {
+            url: Some("http://10.0.0.1:8000".to_owned()),
+            weight: 2,
+            ..K8sConfig::default()
+        };
+        assert!(config.validate().is_ok());
+        let now = Utc::now();
+        let a = config.node("pod-a");
+        let b = config.node("pod-b");
+        let lease = config.lease(&a, now);
+        assert_eq!(lease.metadata.name.as_deref(), Some("sync-point-pod-a"));
+
+        let leases = [lease, config.lease(&b, now - Duration::seconds(20))];
+        assert_eq!(members(&leases, now), std::slice::from_ref(&a));
+        assert_eq!(members(&leases, now - Duration::seconds(10)), [a, b]);
+    }
+
+    #[test]
+    fn test_validate() {
+        let config = |url: Option<&str>, lease_duration_sec: u64| K8sConfig {
+            url: url.map(str::to_owned),
+            lease_duration_sec,
+            ..K8sConfig::default()
+        };
+        assert!(config(Some("http://10.0.0.1:8000"), 15).validate().is_ok());
+        assert!(config(Some("http://10.0.0.1:8000"), 5).validate().is_err());
+    }
+}
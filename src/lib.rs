@@ -2,16 +2,65 @@
 // This eliminates the need to manually declare `mod api;` in `main.rs`.
 // Instead, `lib.rs` defines all of project's modules, which can be accessed
 // from anywhere including `main.rs` or tests
-use crate::api::routes::{index, wait_for_party};
+// The OpenAPI document is a single `json!` literal, deeper than the default limit allows
+#![recursion_limit = "256"]
+extern crate alloc;
+
 use app::App;
+use fairing::SyncPointFairing;
+use listeners::Listeners;
 use log::debug;
-use rocket::{self, routes, Build, Rocket};
+use rocket::figment::Figment;
+use rocket::{self, Build, Rocket};
+use settings::Settings;
 
 // Public modules available to other crates
 // since the binary crate is technically a separate crate that 
 // depends on this library crate
+pub mod allocator;
+#[cfg(feature = "amqp")]
+pub mod amqp;
 pub mod api;
 pub mod app;
+#[cfg(feature = "archive")]
+pub mod archive;
+pub mod background;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+#[cfg(feature = "compression")]
+pub mod compression;
+#[cfg(feature = "cluster")]
+pub mod cluster;
+#[cfg(all(unix, feature = "cli"))]
+pub mod daemon;
+pub mod fairing;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+#[cfg(feature = "cluster")]
+pub mod gossip;
+#[cfg(any(feature = "archive", feature = "hooks"))]
+pub mod hmac;
+#[cfg(feature = "k8s")]
+pub mod k8s;
+pub mod listeners;
+pub mod log_file;
+pub mod logging;
+#[cfg(feature = "cli")]
+pub mod openapi;
+pub mod protocol;
+pub mod recording;
+#[cfg(feature = "redis")]
+pub mod redis_stream;
+pub mod settings;
+#[cfg(feature = "simulation")]
+pub mod simulation;
+pub mod syslog;
+#[cfg(feature = "systemd")]
+pub mod systemd;
+#[cfg(feature = "test-support")]
+pub mod test_support;
 
 /// Builds and configures a Rocket application instance.  
 /// Accessible from application as well as tests
@@ -25,10 +74,69 @@ pub fn build_rocket() -> Rocket<Build> {
         App::new(None).expect("Failed to initialize App with defaults")
     };
 
-    rocket::build()
-        // Attach our application state to Rocket's managed state
-        // This makes the App available to all route handlers
-        .manage(app)
-        // Mounts a collection of routes at the base path "/"
-        .mount("/", routes![index, wait_for_party])
+    build_rocket_with(app)
+}
+
+/// Same as `build_rocket`, but with an already configured `App` (e.g. from a custom config path).
+/// To embed the service into another Rocket app, attach `fairing::SyncPointFairing` instead.
+///
+/// With `listeners` configured, the instance listens on the first one & launches one instance per
+/// other listener with it, see `listeners` module
+pub fn build_rocket_with(app: App) -> Rocket<Build> {
+    let settings = app.settings();
+    let figment = figment(&settings);
+    let Some((first, others)) = settings.listeners.split_first() else {
+        return instance(app, &settings, figment, false);
+    };
+
+    let others = others
+        .iter()
+        .map(|listener| {
+            let figment = listeners::configure(figment.clone(), listener);
+            instance(app.clone(), &settings, figment, true)
+        })
+        .collect();
+    let figment = listeners::configure(figment, first);
+    instance(app, &settings, figment, false).attach(Listeners::new(others))
+}
+
+/// Rocket's configuration with the settings of the standalone server applied
+fn figment(settings: &Settings) -> Figment {
+    // Rocket's own sources (`Rocket.toml`, `ROCKET_` env) apply unless the port is configured here
+    let mut figment = rocket::Config::figment();
+    if let Some(port) = settings.port {
+        figment = figment.merge(("port", port));
+    }
+    if let Some(keep_alive_sec) = settings.keep_alive_sec {
+        figment = figment.merge(("keep_alive", keep_alive_sec));
+    }
+    // Caps JSON bodies without a `Content-Length` too, see `api::body_limits`
+    figment.merge(("limits.json", settings.body_limits.max_json_bytes))
+}
+
+/// A Rocket instance serving `app`, `listener_only` for all but one of those sharing it
+fn instance(app: App, settings: &Settings, figment: Figment, listener_only: bool) -> Rocket<Build> {
+    let mut rocket = rocket::custom(figment);
+    // Recorded responses are served instead of the API, see `recording` module
+    rocket = match &settings.dev.replay_dir {
+        Some(dir) => rocket.attach(recording::Replay::new(dir)),
+        None if listener_only => rocket.attach(SyncPointFairing::new(app).listener_only()),
+        None => rocket.attach(SyncPointFairing::new(app)),
+    };
+    // Before compression, so the recorded bodies are plain
+    if let Some(dir) = &settings.dev.record_dir {
+        rocket = rocket.attach(recording::Recorder::new(dir));
+    }
+
+    #[cfg(feature = "compression")]
+    if settings.compression.enabled {
+        rocket = rocket.attach(compression::Compression::new(&settings.compression));
+    }
+
+    #[cfg(feature = "systemd")]
+    if !listener_only {
+        rocket = rocket.attach(systemd::Systemd);
+    }
+
+    rocket
 }
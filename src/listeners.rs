@@ -0,0 +1,265 @@
+//! Several listeners in one process (`[[listeners]]`), each with its own TLS settings, e.g.
+//! plaintext on localhost for sidecar probes & TLS on the external interface:
+//! ```toml
+//! [[listeners]]
+//! address = "127.0.0.1"
+//! port = 8080
+//!
+//! [[listeners]]
+//! address = "0.0.0.0"
+//! port = 8443
+//! tls = { certs = "/etc/sync-point/cert.pem", key = "/etc/sync-point/key.pem" }
+//! ```
+//! A Rocket instance listens on one address, so `build_rocket_with` configures its instance for
+//! the first listener & `Listeners` launches one more per other listener once it lifted off. They
+//! share the `App`, parties meet whichever listener they came through, but only the first instance
+//! runs the background tasks & drains wait points on shutdown. A listener failing to launch (e.g.
+//! its port is taken) shuts the server down, the others shut down with the first one.
+//!
+//! TLS needs the `tls` Cargo feature, a `tls` table is rejected without it rather than served as
+//! plain HTTP. Without listeners, Rocket's own configuration (`port`, `Rocket.toml`, `ROCKET_` env)
+//! applies.
+use log::{error, info};
+use parking_lot::Mutex;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::figment::Figment;
+use rocket::{Build, Orbit, Rocket, Shutdown};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::Path;
+
+/// An entry of the `[[listeners]]` config section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListenerConfig {
+    /// Interface to listen on, localhost by default
+    #[serde(default = "ListenerConfig::default_address")]
+    pub address: IpAddr,
+    pub port: u16,
+    /// Serves HTTPS with it, plain HTTP when missing
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+}
+
+/// TLS settings of a listener
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsConfig {
+    /// PEM file with the certificate chain, leaf first
+    pub certs: String,
+    /// PEM file with the private key of the leaf certificate
+    pub key: String,
+}
+
+impl ListenerConfig {
+    fn default_address() -> IpAddr {
+        IpAddr::V4(Ipv4Addr::LOCALHOST)
+    }
+}
+
+/// # Returns
+/// * `Ok(())` - If the listeners are valid
+/// * `Err(String)` - Describing the first invalid one
+pub fn validate(listeners: &[ListenerConfig]) -> Result<(), String> {
+    let mut addresses = HashSet::new();
+    for listener in listeners {
+        if listener.port == 0 {
+            return Err(format!("listeners on {} need a port", listener.address));
+        }
+        if !addresses.insert((listener.address, listener.port)) {
+            return Err(format!(
+                "listeners {}:{} is configured twice",
+                listener.address, listener.port
+            ));
+        }
+        let Some(tls) = &listener.tls else {
+            continue;
+        };
+        if cfg!(not(feature = "tls")) {
+            return Err("listeners tls needs the `tls` Cargo feature".to_owned());
+        }
+        for path in [&tls.certs, &tls.key] {
+            if !Path::new(path).is_file() {
+                return Err(format!("listeners tls file {} doesn't exist", path));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `figment` with the address, port & TLS settings of `listener`
+pub(crate) fn configure(figment: Figment, listener: &ListenerConfig) -> Figment {
+    let figment = figment
+        .merge(("address", listener.address))
+        .merge(("port", listener.port));
+    match &listener.tls {
+        Some(tls) => figment
+            .merge(("tls.certs", &tls.certs))
+            .merge(("tls.key", &tls.key)),
+        None => figment,
+    }
+}
+
+/// Fairing launching the instances of the listeners after the first one with the instance it's
+/// attached to, see module docs. Attached by `build_rocket_with`
+pub struct Listeners {
+    /// Instances not launched yet
+    pending: Mutex<Vec<Rocket<Build>>>,
+    /// Shutdown handles of the launched instances
+    launched: Mutex<Vec<Shutdown>>,
+}
+
+impl Listeners {
+    pub(crate) fn new(instances: Vec<Rocket<Build>>) -> Self {
+        Self {
+            pending: Mutex::new(instances),
+            launched: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Listeners {
+    fn info(&self) -> Info {
+        Info {
+            name: "Listeners",
+            kind: Kind::Liftoff | Kind::Shutdown,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let instances = std::mem::take(&mut *self.pending.lock());
+        for instance in instances {
+            let instance = match instance.ignite().await {
+                Ok(instance) => instance,
+                Err(e) => {
+                    error!("Failed to start a listener: {}", e);
+                    return rocket.shutdown().notify();
+                }
+            };
+            let config = instance.config();
+            let scheme = if config.tls_enabled() {
+                "https"
+            } else {
+                "http"
+            };
+            info!(
+                "Listening on {}://{}:{} too",
+                scheme, config.address, config.port
+            );
+            self.launched.lock().push(instance.shutdown());
+            let primary = rocket.shutdown();
+            rocket::tokio::spawn(async move {
+                if let Err(e) = instance.launch().await {
+                    error!("Listener failed: {}", e);
+                    primary.notify();
+                }
+            });
+        }
+    }
+
+    async fn on_shutdown(&self, _rocket: &Rocket<Orbit>) {
+        for shutdown in self.launched.lock().drain(..) {
+            shutdown.notify();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::App;
+    use crate::build_rocket_with;
+    use crate::listeners::{configure, validate, ListenerConfig, TlsConfig};
+    use std::io::{Read, Write};
+    use std::net::{IpAddr, Ipv4Addr, TcpListener, TcpStream};
+    use std::time::Duration;
+
+    fn listener(port: u16) -> ListenerConfig {
+        ListenerConfig {
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port,
+            tls: None,
+        }
+    }
+
+    /// A port nothing listens on, most likely still free when used
+    fn free_port() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    /// Status line of `GET /health` on `port`, `None` while nothing listens there
+    fn health(port: u16) -> Option<String> {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).ok()?;
+        stream
+            .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .ok()?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).ok()?;
+        response.lines().next().map(str::to_owned)
+    }
+
+    #[test]
+    fn test_listeners_validation() {
+        assert!(validate(&[listener(8080), listener(8443)]).is_ok());
+        assert!(validate(&[listener(8080), listener(8080)]).is_err());
+        assert!(validate(&[listener(0)]).is_err());
+
+        let tls = ListenerConfig {
+            tls: Some(TlsConfig {
+                certs: "/nonexistent/cert.pem".to_owned(),
+                key: "/nonexistent/key.pem".to_owned(),
+            }),
+            ..listener(8443)
+        };
+        assert!(validate(&[tls]).is_err());
+    }
+
+    #[test]
+    fn test_figment_of_a_listener() {
+        let figment = configure(
+            rocket::Config::figment(),
+            &ListenerConfig {
+                address: "0.0.0.0".parse().unwrap(),
+                ..listener(8443)
+            },
+        );
+        let config = rocket::Config::from(figment);
+        assert_eq!(config.address, "0.0.0.0".parse::<IpAddr>().unwrap());
+        assert_eq!(config.port, 8443);
+        assert!(!config.tls_enabled());
+    }
+
+    #[rocket::async_test]
+    async fn test_every_listener_serves_the_api() {
+        let (first, second) = (free_port(), free_port());
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let config = format!(
+            "[[listeners]]\nport = {}\n\n[[listeners]]\nport = {}\n",
+            first, second
+        );
+        std::fs::write(&path, config).unwrap();
+        let app = App::new(path.to_str()).unwrap();
+
+        let rocket = build_rocket_with(app).ignite().await.unwrap();
+        let shutdown = rocket.shutdown();
+        let server = rocket::tokio::spawn(rocket.launch());
+
+        for port in [first, second] {
+            let mut status = None;
+            for _ in 0..100 {
+                status = rocket::tokio::task::spawn_blocking(move || health(port))
+                    .await
+                    .unwrap();
+                if status.is_some() {
+                    break;
+                }
+                rocket::tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            assert_eq!(status.as_deref(), Some("HTTP/1.1 200 OK"), "port {}", port);
+        }
+
+        shutdown.notify();
+        server.await.unwrap().unwrap();
+    }
+}
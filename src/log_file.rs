@@ -0,0 +1,325 @@
+//! JSON lines log file with size & time based rotation, written next to (not instead of) the
+//! stderr output, so logs survive when a container runtime truncates or drops stdout/stderr.
+//! Enabled by the `[file_log]` config section, see `logging::log_to_file`.
+use log::Record;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// `[file_log]` config section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileLogConfig {
+    /// Log file, file logging is disabled when missing
+    pub path: Option<String>,
+    /// Rotates once the file would exceed this size, 0 means no size limit
+    pub max_size_bytes: u64,
+    /// Rotates when the (UTC) hour or day changes
+    pub rotation: Rotation,
+    /// Rotated files to keep (`<path>.1` being the newest), older ones are deleted
+    pub max_files: usize,
+}
+
+impl Default for FileLogConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            max_size_bytes: 10 * 1024 * 1024,
+            rotation: Rotation::Never,
+            max_files: 5,
+        }
+    }
+}
+
+/// Time based rotation period
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Rotation {
+    Never,
+    Hourly,
+    Daily,
+}
+
+impl Rotation {
+    /// Index of the period `secs` (since the epoch) falls into, rotation happens when it changes
+    fn period(self, secs: u64) -> u64 {
+        match self {
+            Rotation::Never => 0,
+            Rotation::Hourly => secs / 3600,
+            Rotation::Daily => secs / 86400,
+        }
+    }
+}
+
+/// Appends JSON lines to the configured file, rotating it as configured
+pub struct RotatingFile {
+    config: FileLogConfig,
+    path: PathBuf,
+    file: File,
+    size: u64,
+    period: u64,
+    /// Directory rotated files are moved to instead of `<path>.N`, set by `spool_to`
+    spool: Option<PathBuf>,
+}
+
+impl RotatingFile {
+    /// Opens (or creates) the log file, appending to existing content
+    ///
+    /// # Returns
+    /// * `Ok(RotatingFile)` - Ready to write
+    /// * `Err(io::Error)` - If `config.path` is missing or the file can't be opened
+    pub fn open(config: &FileLogConfig) -> io::Result<Self> {
+        let path = config.path.as_ref().map(PathBuf::from).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "file_log.path is not set")
+        })?;
+        let file = open_append(&path)?;
+        let metadata = file.metadata()?;
+        // An existing file from an earlier period is rotated on the first write
+        let modified = metadata.modified().map_or(0, epoch_secs);
+
+        Ok(Self {
+            period: config.rotation.period(modified),
+            config: config.clone(),
+            path,
+            file,
+            size: metadata.len(),
+            spool: None,
+        })
+    }
+
+    /// Moves rotated files to `dir` (created if missing) instead of keeping `max_files` of them,
+    /// named after the file & the time of the rotation, e.g. `app.log.2024-12-28T064151.123Z`. The
+    /// archive uploader ships & deletes them, see `archive` module
+    pub fn spool_to(&mut self, dir: &Path) -> io::Result<()> {
+        std::fs::create_dir_all(dir)?;
+        self.spool = Some(dir.to_owned());
+        Ok(())
+    }
+
+    /// Rotates now unless the file is empty
+    pub fn rotate_now(&mut self) -> io::Result<()> {
+        if self.size > 0 {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    /// Writes a record as one JSON line, e.g.
+    /// `{"ts":"2024-12-28T06:41:51.123Z","level":"INFO","target":"sync_point::app","message":"..."}`
+    pub fn write_record(&mut self, record: &Record) -> io::Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut line = json!({
+            "ts": rfc3339(now.as_secs(), now.subsec_millis()),
+            "level": record.level().as_str(),
+            "target": record.target(),
+            "message": record.args().to_string(),
+        })
+        .to_string();
+        line.push('\n');
+        self.write_line(line.as_bytes(), now.as_secs())
+    }
+
+    /// Writes `line` at time `now` (seconds since the epoch), rotating first if it's due
+    fn write_line(&mut self, line: &[u8], now: u64) -> io::Result<()> {
+        let period = self.config.rotation.period(now);
+        let too_large = self.config.max_size_bytes > 0
+            && self.size > 0
+            && self.size + line.len() as u64 > self.config.max_size_bytes;
+        if too_large || period != self.period {
+            self.rotate()?;
+            self.period = period;
+        }
+
+        // Unbuffered, so nothing is lost when the process is killed
+        self.file.write_all(line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Shifts `<path>.N` to `<path>.N+1` (dropping the oldest), moves the current file to `<path>.1`
+    /// & starts an empty one
+    fn rotate(&mut self) -> io::Result<()> {
+        if let Some(spool) = &self.spool {
+            std::fs::rename(&self.path, spooled(&self.path, spool))?;
+        } else if self.config.max_files == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            let _ = std::fs::remove_file(self.rotated(self.config.max_files));
+            for index in (1..self.config.max_files).rev() {
+                let from = self.rotated(index);
+                if from.exists() {
+                    std::fs::rename(from, self.rotated(index + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, self.rotated(1))?;
+        }
+
+        self.file = open_append(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        path.into()
+    }
+}
+
+/// Free path in `spool` for `path` rotated now
+fn spooled(path: &Path, spool: &Path) -> PathBuf {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let name = format!(
+        "{}.{}",
+        path.file_name().unwrap_or_default().to_string_lossy(),
+        rfc3339(now.as_secs(), now.subsec_millis()).replace(':', "")
+    );
+    // Several rotations within a millisecond only happen with tiny size limits
+    let mut spooled = spool.join(&name);
+    let mut index = 1;
+    while spooled.exists() {
+        spooled = spool.join(format!("{}-{}", name, index));
+        index += 1;
+    }
+    spooled
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn epoch_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Formats a UTC timestamp like `2024-12-28T06:41:51.123Z`
+pub(crate) fn rfc3339(secs: u64, millis: u32) -> String {
+    // Civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let days = (secs / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    let time = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60,
+        millis
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::log_file::{rfc3339, FileLogConfig, RotatingFile, Rotation};
+    use log::Level;
+    use serde_json::Value;
+    use tempfile::TempDir;
+
+    fn config(dir: &TempDir, max_size_bytes: u64, rotation: Rotation) -> FileLogConfig {
+        FileLogConfig {
+            path: Some(dir.path().join("app.log").to_str().unwrap().to_owned()),
+            max_size_bytes,
+            rotation,
+            max_files: 2,
+        }
+    }
+
+    fn read(dir: &TempDir, name: &str) -> String {
+        std::fs::read_to_string(dir.path().join(name)).unwrap_or_default()
+    }
+
+    #[test]
+    fn test_rfc3339() {
+        assert_eq!(rfc3339(0, 0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(rfc3339(1735368111, 123), "2024-12-28T06:41:51.123Z");
+        assert_eq!(rfc3339(951782400, 5), "2000-02-29T00:00:00.005Z");
+    }
+
+    #[test]
+    fn test_writes_json_lines() {
+        let dir = TempDir::new().unwrap();
+        let mut file = RotatingFile::open(&config(&dir, 0, Rotation::Never)).unwrap();
+        file.write_record(
+            &log::Record::builder()
+                .level(Level::Warn)
+                .target("sync_point::app")
+                .args(format_args!("Timeout \"{}\"", 10))
+                .build(),
+        )
+        .unwrap();
+
+        let line: Value = serde_json::from_str(read(&dir, "app.log").trim()).unwrap();
+        assert_eq!(line["level"], "WARN");
+        assert_eq!(line["target"], "sync_point::app");
+        assert_eq!(line["message"], "Timeout \"10\"");
+        assert!(line["ts"].as_str().unwrap().ends_with('Z'));
+    }
+
+    #[test]
+    fn test_rotates_by_size_and_keeps_max_files() {
+        let dir = TempDir::new().unwrap();
+        let mut file = RotatingFile::open(&config(&dir, 10, Rotation::Never)).unwrap();
+        for line in ["one\n", "two\n", "three\n", "four\n"] {
+            file.write_line(line.as_bytes(), 0).unwrap();
+        }
+
+        assert_eq!(read(&dir, "app.log"), "four\n");
+        assert_eq!(read(&dir, "app.log.1"), "three\n");
+        assert_eq!(read(&dir, "app.log.2"), "one\ntwo\n");
+        assert!(!dir.path().join("app.log.3").exists());
+    }
+
+    #[test]
+    fn test_rotates_when_period_changes() {
+        let dir = TempDir::new().unwrap();
+        let mut file = RotatingFile::open(&config(&dir, 0, Rotation::Hourly)).unwrap();
+        file.period = 0;
+        file.write_line(b"first hour\n", 3599).unwrap();
+        file.write_line(b"second hour\n", 3600).unwrap();
+
+        assert_eq!(read(&dir, "app.log"), "second hour\n");
+        assert_eq!(read(&dir, "app.log.1"), "first hour\n");
+    }
+
+    #[test]
+    fn test_rotated_files_are_spooled() {
+        let dir = TempDir::new().unwrap();
+        let spool = dir.path().join("spool");
+        let mut file = RotatingFile::open(&config(&dir, 10, Rotation::Never)).unwrap();
+        file.spool_to(&spool).unwrap();
+        for line in ["one\n", "two\n", "three\n", "four\n"] {
+            file.write_line(line.as_bytes(), 0).unwrap();
+        }
+        file.rotate_now().unwrap();
+        file.rotate_now().unwrap();
+
+        assert_eq!(read(&dir, "app.log"), "");
+        assert!(!dir.path().join("app.log.1").exists());
+        let mut spooled: Vec<_> = std::fs::read_dir(&spool)
+            .unwrap()
+            .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+            .collect();
+        spooled.sort();
+        assert_eq!(spooled, ["four\n", "one\ntwo\n", "three\n"]);
+    }
+}
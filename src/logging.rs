@@ -0,0 +1,322 @@
+#[cfg(feature = "archive")]
+use crate::archive::{self, ArchiveConfig, Uploader};
+use crate::log_file::{FileLogConfig, RotatingFile};
+use crate::syslog::{SyslogConfig, SyslogWriter};
+use env_filter::Filter;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use parking_lot::{Mutex, RwLock};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::OnceLock;
+
+/// Installed by `init`. A static is needed since `log` requires a `&'static dyn Log`
+static LOGGER: OnceLock<ReloadableLogger> = OnceLock::new();
+
+/// Audit records waiting for the syslog writer thread, further ones are dropped
+const SYSLOG_QUEUE: usize = 1024;
+
+/// Target of the audit records, logged whatever the filter so changing the log level never turns
+/// off the audit trail
+const AUDIT_TARGET: &str = "audit";
+
+/// Wraps `env_logger` (used for formatting & output only) with a filter that can be replaced at
+/// runtime, so the log level can be changed without restarting & losing wait state
+struct ReloadableLogger {
+    inner: env_logger::Logger,
+    /// Filter together with the `RUST_LOG` like spec it was built from
+    filter: RwLock<(Filter, String)>,
+    /// JSON log file receiving the same records, set by `log_to_file`
+    file: OnceLock<Mutex<RotatingFile>>,
+    /// Queue of the thread sending the audit records to syslog, set by `audit_to_syslog`
+    syslog: OnceLock<SyncSender<(Level, String)>>,
+}
+
+impl Log for ReloadableLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.target() == AUDIT_TARGET || self.filter.read().0.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if record.target() == AUDIT_TARGET || self.filter.read().0.matches(record) {
+            self.inner.log(record);
+            if let Some(file) = self.file.get() {
+                if let Err(e) = file.lock().write_record(record) {
+                    eprintln!("Failed to write log file: {}", e);
+                }
+            }
+            if let Some(syslog) = self.syslog.get().filter(|_| record.target() == AUDIT_TARGET) {
+                if syslog
+                    .try_send((record.level(), record.args().to_string()))
+                    .is_err()
+                {
+                    eprintln!("Audit record dropped, the syslog queue is full");
+                }
+            }
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Installs the global logger.
+///
+/// # Arguments
+/// * `default_filter` - Filter spec (`RUST_LOG` syntax) used when `RUST_LOG` isn't set
+pub fn init(default_filter: &str) {
+    let spec = std::env::var("RUST_LOG").unwrap_or_else(|_| default_filter.to_owned());
+    let filter = env_filter::Builder::new().parse(&spec).build();
+
+    let inner = env_logger::Builder::new()
+        // Filtering is done by `ReloadableLogger`
+        .filter_level(LevelFilter::Trace)
+        .parse_write_style(&std::env::var("RUST_LOG_STYLE").unwrap_or_default())
+        .build();
+
+    log::set_max_level(max_level(&filter));
+    let logger = LOGGER.get_or_init(|| ReloadableLogger {
+        inner,
+        filter: RwLock::new((filter, spec)),
+        file: OnceLock::new(),
+        syslog: OnceLock::new(),
+    });
+    if log::set_logger(logger).is_err() {
+        eprintln!(
+            "Logger already initialized, runtime log level changes are limited to the max level"
+        );
+    }
+}
+
+/// Also writes logs (after the same filter) as JSON lines to the file configured in `[file_log]`,
+/// see `log_file` module. Does nothing if no path is configured.
+///
+/// # Returns
+/// * `Ok(())` - If file logging is enabled or not configured
+/// * `Err(String)` - If the file can't be opened, or `init` wasn't called (or a file is already set)
+pub fn log_to_file(config: &FileLogConfig) -> Result<(), String> {
+    if config.path.is_none() {
+        return Ok(());
+    }
+    let logger = LOGGER
+        .get()
+        .ok_or_else(|| "Logger is not initialized".to_owned())?;
+    let file = RotatingFile::open(config).map_err(|e| format!("Failed to open log file: {}", e))?;
+    logger
+        .file
+        .set(Mutex::new(file))
+        .map_err(|_| "Log file is already set".to_owned())
+}
+
+/// Also sends the audit records (target `audit`, whatever the filter) to the syslog collector
+/// configured in `[audit_syslog]`, see `syslog` module. Does nothing if no address is configured.
+/// They're sent by a dedicated thread, so a slow collector never delays the requests logging them.
+///
+/// # Returns
+/// * `Ok(())` - If syslog output is enabled or not configured
+/// * `Err(String)` - If the collector can't be connected to, or `init` wasn't called (or a
+///   collector is already set)
+pub fn audit_to_syslog(config: &SyslogConfig) -> Result<(), String> {
+    if config.address.is_none() {
+        return Ok(());
+    }
+    let logger = LOGGER
+        .get()
+        .ok_or_else(|| "Logger is not initialized".to_owned())?;
+    if logger.syslog.get().is_some() {
+        return Err("Syslog collector is already set".to_owned());
+    }
+    let mut writer = SyslogWriter::connect(config)
+        .map_err(|e| format!("Failed to connect to syslog collector: {}", e))?;
+    let (sender, receiver) = mpsc::sync_channel::<(Level, String)>(SYSLOG_QUEUE);
+    std::thread::Builder::new()
+        .name("audit-syslog".to_owned())
+        .spawn(move || {
+            for (level, message) in receiver {
+                let result = writer.write_record(
+                    &Record::builder()
+                        .level(level)
+                        .target(AUDIT_TARGET)
+                        .args(format_args!("{}", message))
+                        .build(),
+                );
+                if let Err(e) = result {
+                    eprintln!("Failed to send audit record to syslog: {}", e);
+                }
+            }
+        })
+        .map_err(|e| format!("Failed to start the syslog writer: {}", e))?;
+    logger
+        .syslog
+        .set(sender)
+        .map_err(|_| "Syslog collector is already set".to_owned())
+}
+
+/// Ships the log file, rotated every `interval_sec`, to the object store configured in `[archive]`,
+/// see `archive` module. A dedicated thread rotates it & uploads what's spooled, starting with the
+/// files a previous run left.
+///
+/// # Returns
+/// * `Ok(())` - If the uploader is started
+/// * `Err(String)` - If `log_to_file` didn't set a log file, or the spool directory can't be
+///   created
+#[cfg(feature = "archive")]
+pub fn archive_log_file(config: &ArchiveConfig, file_log: &FileLogConfig) -> Result<(), String> {
+    let file = LOGGER
+        .get()
+        .and_then(|logger| logger.file.get())
+        .ok_or_else(|| "Archiving needs a log file".to_owned())?;
+    let spool =
+        archive::spool_dir(file_log).ok_or_else(|| "file_log.path is not set".to_owned())?;
+    file.lock()
+        .spool_to(&spool)
+        .map_err(|e| format!("Failed to create {}: {}", spool.display(), e))?;
+    let uploader = Uploader::new(config, &spool);
+    let interval = std::time::Duration::from_secs(config.interval_sec);
+    std::thread::Builder::new()
+        .name("log-archive".to_owned())
+        .spawn(move || loop {
+            uploader.upload_spooled();
+            std::thread::sleep(interval);
+            if let Err(e) = file.lock().rotate_now() {
+                eprintln!("Failed to rotate log file: {}", e);
+            }
+        })
+        .map(|_| ())
+        .map_err(|e| format!("Failed to start the log archive uploader: {}", e))
+}
+
+/// Returns the filter spec in effect, e.g. `info,sync_point::api=debug`
+pub fn current_filter() -> String {
+    match LOGGER.get() {
+        Some(logger) => logger.filter.read().1.clone(),
+        None => log::max_level().to_string().to_lowercase(),
+    }
+}
+
+/// Builds a filter spec from a default level and per-module levels.
+///
+/// # Arguments
+/// * `level` - Default level for all modules, e.g. `info`
+/// * `modules` - Levels for specific module paths, e.g. `sync_point::api` => `debug`
+///
+/// # Returns
+/// * `Ok(String)` - Filter spec in `RUST_LOG` syntax
+/// * `Err(String)` - Describing the first invalid module or level
+pub fn filter_spec(
+    level: Option<&str>,
+    modules: &BTreeMap<String, String>,
+) -> Result<String, String> {
+    let mut directives = Vec::with_capacity(modules.len() + 1);
+    if let Some(level) = level {
+        directives.push(parse_level(level)?.to_string().to_lowercase());
+    }
+    for (module, level) in modules {
+        if module.is_empty() || module.contains([',', '=', '/']) {
+            return Err(format!("Invalid module path '{}'", module));
+        }
+        directives.push(format!(
+            "{}={}",
+            module,
+            parse_level(level)?.to_string().to_lowercase()
+        ));
+    }
+
+    if directives.is_empty() {
+        return Err("Either a level or module levels are required".to_owned());
+    }
+    Ok(directives.join(","))
+}
+
+/// Replaces the filter of the installed logger, audit records are still logged.
+/// Without it (e.g. in tests or when embedded), only the global max level is adjusted
+///
+/// # Arguments
+/// * `spec` - Filter spec in `RUST_LOG` syntax, see `filter_spec`
+///
+/// # Returns
+/// * `Ok(())` - If the filter was applied
+/// * `Err(String)` - If the spec is invalid, the previous filter stays in effect then
+pub fn set_filter(spec: &str) -> Result<(), String> {
+    let filter = env_filter::Builder::new()
+        .try_parse(spec)
+        .map_err(|e| format!("Invalid log filter '{}': {}", spec, e))?
+        .build();
+
+    log::set_max_level(max_level(&filter));
+    if let Some(logger) = LOGGER.get() {
+        *logger.filter.write() = (filter, spec.to_owned());
+    }
+    Ok(())
+}
+
+/// Global max level for `filter`, at least `Info` so the `log` macros never stop audit records
+/// before they reach the logger
+fn max_level(filter: &Filter) -> LevelFilter {
+    filter.filter().max(LevelFilter::Info)
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter, String> {
+    LevelFilter::from_str(level).map_err(|_| {
+        format!(
+            "Invalid log level '{}' (expected off, error, warn, info, debug or trace)",
+            level
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::logging::{filter_spec, set_filter, ReloadableLogger};
+    use log::{Level, Log, Metadata};
+    use parking_lot::RwLock;
+    use std::collections::BTreeMap;
+    use std::sync::OnceLock;
+
+    #[test]
+    fn test_filter_spec() {
+        let modules = BTreeMap::from([
+            ("rocket".to_owned(), "warn".to_owned()),
+            ("sync_point::api".to_owned(), "TRACE".to_owned()),
+        ]);
+
+        assert_eq!(
+            filter_spec(Some("info"), &modules).unwrap(),
+            "info,rocket=warn,sync_point::api=trace"
+        );
+        assert_eq!(
+            filter_spec(Some("debug"), &BTreeMap::new()).unwrap(),
+            "debug"
+        );
+    }
+
+    #[test]
+    fn test_filter_spec_rejects_invalid_input() {
+        assert!(filter_spec(Some("loud"), &BTreeMap::new()).is_err());
+        assert!(filter_spec(None, &BTreeMap::new()).is_err());
+
+        let modules = BTreeMap::from([("a=b".to_owned(), "info".to_owned())]);
+        assert!(filter_spec(None, &modules).is_err());
+    }
+
+    #[test]
+    fn test_audit_records_bypass_the_filter() {
+        let filter = env_filter::Builder::new().parse("warn").build();
+        let logger = ReloadableLogger {
+            inner: env_logger::Builder::new().build(),
+            filter: RwLock::new((filter, "warn".to_owned())),
+            file: OnceLock::new(),
+            syslog: OnceLock::new(),
+        };
+        let metadata = |target| Metadata::builder().level(Level::Info).target(target).build();
+
+        assert!(logger.enabled(&metadata("audit")));
+        assert!(!logger.enabled(&metadata("sync_point::api")));
+    }
+
+    #[test]
+    fn test_set_filter_rejects_invalid_spec() {
+        assert!(set_filter("sync_point=loud").is_err());
+    }
+}
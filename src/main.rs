@@ -1,15 +1,227 @@
-use sync_point::build_rocket;
-
-#[rocket::main]
-async fn main() -> Result<(), rocket::Error> {
-    // Use `RUST_LOG` to configure log level via environment
-    env_logger::Builder::from_env(
-        env_logger::Env::default().default_filter_or("debug"), // Set default log level to debug
-    )
-    .init();
-
-    log::info!("🚀 Starting server...");
-    let rocket = build_rocket();
-    rocket.launch().await?;
-    Ok(())
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+#[cfg(unix)]
+use std::path::Path;
+#[cfg(unix)]
+use sync_point::daemon;
+use sync_point::app::App;
+use sync_point::build_rocket_with;
+use sync_point::settings::{Overrides, Settings};
+
+/// Rendezvous service: the first party to request an id waits until the second one arrives
+#[derive(Debug, Parser)]
+#[command(version, args_conflicts_with_subcommands = true)]
+struct Cli {
+    /// Same as `serve` when no subcommand is given
+    #[command(flatten)]
+    serve: ServeArgs,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Starts the server (the default)
+    Serve(ServeArgs),
+    /// Validates the configuration (e.g. in CI before deploys) instead of starting the server
+    CheckConfig {
+        /// Config file, `config.toml` when present otherwise
+        path: Option<String>,
+    },
+    /// Prints the OpenAPI document of the HTTP API
+    ExportOpenapi {
+        /// Writes to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Prints random ids to use for wait points, one per line
+    GenerateId {
+        /// How many ids to print
+        #[arg(short = 'n', long, default_value_t = 1)]
+        count: usize,
+        /// Prepended to every id, e.g. `ci-`
+        #[arg(long, default_value = "")]
+        prefix: String,
+    },
+}
+
+/// Flags take precedence over the config files & env vars
+#[derive(Debug, Args)]
+struct ServeArgs {
+    /// Config file, `config.toml` when present otherwise
+    #[arg(long, value_name = "PATH")]
+    config: Option<String>,
+    /// Seconds the first party waits for the second one
+    #[arg(long)]
+    timeout: Option<u64>,
+    /// Port to listen on
+    #[arg(long)]
+    port: Option<u16>,
+    /// Maximum number of simultaneously open wait points, 0 means unlimited
+    #[arg(long)]
+    max_wait_points: Option<usize>,
+    /// Appends every request & its response to `<DIR>/interactions.jsonl`
+    #[arg(long, value_name = "DIR", conflicts_with = "replay")]
+    record: Option<String>,
+    /// Serves the responses recorded with `--record` to `<DIR>` instead of the API
+    #[arg(long, value_name = "DIR")]
+    replay: Option<String>,
+    /// Shows the merged configuration (after profile & env layering) instead of starting the server
+    #[arg(long)]
+    print_config: bool,
+    /// Detaches from the terminal, output goes to the configured `log_file`
+    #[cfg(unix)]
+    #[arg(long)]
+    daemon: bool,
+    /// Writes the server PID to this file, removed on clean shutdown
+    #[cfg(unix)]
+    #[arg(long, value_name = "PATH")]
+    pid_file: Option<PathBuf>,
+}
+
+impl ServeArgs {
+    fn overrides(&self) -> Overrides {
+        Overrides {
+            timeout: self.timeout,
+            port: self.port,
+            max_wait_points: self.max_wait_points,
+            record_dir: self.record.clone(),
+            replay_dir: self.replay.clone(),
+        }
+    }
+}
+
+// `rocket::Error` is large, but it's returned only once when launching fails
+#[allow(clippy::result_large_err)]
+fn main() -> Result<(), rocket::Error> {
+    let cli = Cli::parse();
+
+    // Use `RUST_LOG` to configure log level via environment, adjustable at runtime via admin API
+    sync_point::logging::init("debug"); // Set default log level to debug
+
+    match cli.command {
+        None => serve(cli.serve),
+        Some(Command::Serve(args)) => serve(args),
+        Some(Command::CheckConfig { path }) => check_config(path.as_deref()),
+        Some(Command::ExportOpenapi { output }) => {
+            export_openapi(output);
+            Ok(())
+        }
+        Some(Command::GenerateId { count, prefix }) => {
+            for _ in 0..count {
+                println!("{}{}", prefix, uuid::Uuid::new_v4());
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Runs the server until shutdown. The async runtime is started only here, as daemonizing
+/// (forking) has to happen before any of its threads exist
+#[allow(clippy::result_large_err)]
+fn serve(args: ServeArgs) -> Result<(), rocket::Error> {
+    let config_path = args.config.as_deref();
+    if args.print_config {
+        print_config(config_path, &args.overrides());
+        return Ok(());
+    }
+
+    let app = match App::with_overrides(config_path, &args.overrides()) {
+        Ok(app) => app,
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = sync_point::logging::log_to_file(&app.settings().file_log) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    #[cfg(unix)]
+    let _pid_file = detach(&args, app.settings().log_file.as_deref());
+    // The syslog writer & uploads run on threads, started once daemonized as forking drops them
+    if let Err(e) = sync_point::logging::audit_to_syslog(&app.settings().audit_syslog) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    #[cfg(feature = "archive")]
+    if let Some(archive) = &app.settings().archive {
+        if let Err(e) = sync_point::logging::archive_log_file(archive, &app.settings().file_log) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    rocket::execute(async move {
+        log::info!("🚀 Starting server...");
+        build_rocket_with(app).launch().await?;
+        Ok(())
+    })
+}
+
+/// Daemonizes & writes the PID file as requested, exits non-zero if either fails
+#[cfg(unix)]
+fn detach(args: &ServeArgs, log_file: Option<&str>) -> Option<daemon::PidFile> {
+    let fail = |message: String| -> ! {
+        eprintln!("{}", message);
+        std::process::exit(1);
+    };
+
+    // Checked before forking, so init scripts see the failure
+    if let Some(pid) = args.pid_file.as_deref().and_then(daemon::running_pid) {
+        fail(format!("Already running with PID {}", pid));
+    }
+    if args.daemon {
+        if let Err(e) = daemon::daemonize(log_file.map(Path::new)) {
+            fail(format!("Failed to daemonize: {}", e));
+        }
+    }
+    let pid_file = args.pid_file.as_ref().map(daemon::PidFile::create)?;
+    Some(pid_file.unwrap_or_else(|e| fail(format!("Failed to write PID file: {}", e))))
+}
+
+/// Prints the merged & validated settings with secrets redacted, or exits non-zero on invalid config
+fn print_config(config_path: Option<&str>, overrides: &Overrides) {
+    match Settings::load_with(config_path, overrides) {
+        Ok(settings) => println!(
+            "{}",
+            serde_json::to_string_pretty(&settings.redacted()).expect("Settings are serializable")
+        ),
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Loads and validates the configuration, then exits with 0 if it's valid or 1 with the error otherwise
+///
+/// # Arguments
+/// * `config_path` - Optional config file path, same as `App::new` when missing
+fn check_config(config_path: Option<&str>) -> ! {
+    match Settings::load(config_path) {
+        Ok(_) => {
+            println!("Configuration OK");
+            std::process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Prints the OpenAPI document as pretty JSON, or writes it to `output`
+fn export_openapi(output: Option<PathBuf>) {
+    let document = serde_json::to_string_pretty(&sync_point::openapi::document())
+        .expect("OpenAPI document is serializable");
+    match output {
+        Some(path) => {
+            if let Err(e) = std::fs::write(&path, document + "\n") {
+                eprintln!("Unable to write {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+        None => println!("{}", document),
+    }
 }
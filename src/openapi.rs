@@ -0,0 +1,782 @@
+//! OpenAPI 3 description of the HTTP API, printed by `sync-point export-openapi` for client
+//! generators & API gateways. Written by hand, a test checks that every mounted route is covered.
+use crate::protocol::{headers, MAX_NOTE_CHARS};
+use serde_json::{json, Value};
+
+/// The OpenAPI document
+pub fn document() -> Value {
+    let api_response = json!({"$ref": "#/components/schemas/ApiResponse"});
+    let error = json!({
+        "description": "Error",
+        "content": {"application/json": {"schema": api_response}}
+    });
+    // See `api::ids` module
+    let unique_id = json!({
+        "name": "unique_id",
+        "in": "path",
+        "required": true,
+        "description": "May be path-like, e.g. `deploy/eu/payments`, with or without encoding the `/`",
+        "schema": {"type": "string"}
+    });
+    let ttl = json!({
+        "name": "ttl",
+        "in": "query",
+        "description": "Creating party of a pair id only: seconds the wait point may exist at most, below `timeout`",
+        "schema": {"type": "integer", "minimum": 1}
+    });
+    // See `api::priorities` module
+    let priority = json!({
+        "name": "priority",
+        "in": "query",
+        "description": "Class of the party, limited to its share of `max_wait_points` when creating a wait point",
+        "schema": {"$ref": "#/components/schemas/Priority"}
+    });
+    // See `WaitPoint::add_note`
+    let note = json!({
+        "name": "note",
+        "in": "query",
+        "description": "Shown to operators in admin listings & the audit log, e.g. what the party waits for",
+        "schema": {"type": "string", "maxLength": MAX_NOTE_CHARS}
+    });
+    // See `acl` module
+    let header = |name: &str, description: &str| {
+        json!({"name": name, "in": "header", "description": description, "schema": {"type": "string"}})
+    };
+    let api_key = header(
+        headers::API_KEY,
+        "Checked against the ACL of the wait point",
+    );
+    let allow_cidrs = header(
+        headers::ALLOW_CIDRS,
+        "Creating party only: comma separated address ranges allowed to join",
+    );
+    let allow_keys = header(
+        headers::ALLOW_KEYS,
+        "Creating party only: comma separated API keys allowed to join",
+    );
+    // See `api::reservations` module
+    let reservation = header(
+        headers::RESERVATION,
+        "Token of POST /reserve, admits the party to its id at capacity",
+    );
+    // See `api::templates` module
+    let metadata = header(
+        headers::METADATA,
+        "JSON object, checked against the template of the id",
+    );
+    let invalid_metadata = json!({
+        "description": "The X-Sync-Metadata doesn't conform to the template of the id, see errors",
+        "content": {"application/json": {"schema": api_response}}
+    });
+    let forbidden = json!({
+        "description": "The ACL of the wait point doesn't admit the party",
+        "content": {"application/json": {"schema": api_response}}
+    });
+    // See `api::late_arrivals` module
+    let peer_timed_out = json!({
+        "description": "Nobody is waiting, the waiting party timed out within `late_arrival_grace_sec`",
+        "content": {"application/json": {"schema": api_response}}
+    });
+    // See `api::quotas` module
+    let too_many_requests = json!({
+        "description": "The connection has `max_requests_per_connection` requests in progress",
+        "content": {"application/json": {"schema": api_response}}
+    });
+    let too_many_waits = json!({
+        "description": "The API key holds `max_waits_per_key` waits already (see X-Api-Key-Waits), or the connection has `max_requests_per_connection` requests in progress",
+        "content": {"application/json": {"schema": api_response}}
+    });
+    // See `api::body_limits` module
+    let too_large = json!({
+        "description": "Unexpected body, or a JSON body over `body_limits.max_json_bytes`",
+        "content": {"application/json": {"schema": api_response}}
+    });
+    let unsupported_media_type = json!({
+        "description": "Unexpected body with a Content-Type, or a body which isn't JSON",
+        "content": {"application/json": {"schema": api_response}}
+    });
+    let json_body = |schema: &str| {
+        json!({"content": {"application/json": {"schema": {"$ref": format!("#/components/schemas/{}", schema)}}}})
+    };
+    let ok = |schema: &str| {
+        let mut response = json_body(schema);
+        response["description"] = json!("OK");
+        response
+    };
+    let admin = json!([{"bearerAuth": []}]);
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Sync Point API",
+            "description": "Rendezvous of two parties: the first one to arrive waits until the second one requests the same id. Timeouts (408), conflicts (409) & busy rejections (503) may be sent with other statuses, see `status_codes`",
+            "version": env!("CARGO_PKG_VERSION")
+        },
+        "paths": {
+            "/": {
+                "get": {
+                    "summary": "Welcome message",
+                    "responses": {"200": {"description": "OK", "content": {"text/plain": {"schema": {"type": "string"}}}}}
+                }
+            },
+            "/health": {
+                "get": {
+                    "summary": "Whether the service is up or in maintenance",
+                    "responses": {"200": ok("HealthResponse")}
+                }
+            },
+            "/ready": {
+                "get": {
+                    "summary": "Whether load balancers should route here: starting, ready, lame-duck or unhealthy",
+                    "responses": {
+                        "200": ok("ReadinessResponse"),
+                        "503": {"description": "Not ready, see state & message", "content": {"application/json": {"schema": {"$ref": "#/components/schemas/ReadinessResponse"}}}}
+                    }
+                }
+            },
+            "/status/{unique_id}": {
+                "get": {
+                    "summary": "State of a wait point, supports If-None-Match",
+                    "parameters": [unique_id],
+                    "responses": {"200": ok("WaitPointStatus"), "304": {"description": "Not modified"}}
+                }
+            },
+            "/completions/{pair_id}": {
+                "get": {
+                    "summary": "Record of a recent match, for systems verifying it after the fact",
+                    "parameters": [{"name": "pair_id", "in": "path", "required": true, "schema": {"type": "string", "format": "uuid"}}],
+                    "responses": {
+                        "200": ok("Completion"),
+                        "400": {"description": "Invalid pair_id", "content": {"application/json": {"schema": api_response}}},
+                        "404": {"description": "Unknown pair_id, or its record expired after `completions.retention_sec`", "content": {"application/json": {"schema": api_response}}}
+                    }
+                }
+            },
+            "/observe/{pattern}": {
+                "get": {
+                    "summary": "Streams (Server-Sent Events) what happens at the matching ids, without taking part",
+                    "parameters": [{
+                        "name": "pattern",
+                        "in": "path",
+                        "required": true,
+                        "description": "An id, or every id starting with a prefix when ending with `*`, e.g. `deploy/eu/*`",
+                        "schema": {"type": "string"}
+                    }],
+                    "responses": {
+                        "200": {
+                            "description": "Events named `waiting`, `matched` or `timeout` with an Observation as data, `lagged` with the number of missed events",
+                            "content": {"text/event-stream": {"schema": {"$ref": "#/components/schemas/Observation"}}}
+                        }
+                    }
+                }
+            },
+            "/stats": {
+                "get": {
+                    "summary": "Service-wide counters, supports If-None-Match",
+                    "responses": {"200": ok("Stats"), "304": {"description": "Not modified"}}
+                }
+            },
+            "/stats/allocator": {
+                "get": {
+                    "summary": "Global allocator stats",
+                    "responses": {"200": ok("AllocatorStats")}
+                }
+            },
+            "/time": {
+                "get": {
+                    "summary": "The server's clock, also sent as X-Server-Time header with every response",
+                    "responses": {"200": ok("ServerTime")}
+                }
+            },
+            "/stats/outcomes": {
+                "get": {
+                    "summary": "How unmatched first parties ended (timed out vs cancelled) per id prefix",
+                    "responses": {"200": ok("OutcomeStats")}
+                }
+            },
+            "/metrics": {
+                "get": {
+                    "summary": "Counters in the OpenMetrics text format",
+                    "responses": {"200": {"description": "OK", "content": {"application/openmetrics-text": {"schema": {"type": "string"}}}}}
+                }
+            },
+            "/receipts/key": {
+                "get": {
+                    "summary": "Ed25519 public key verifying match receipts",
+                    "responses": {"200": ok("ReceiptKey"), "404": error}
+                }
+            },
+            "/cluster/owner/{unique_id}": {
+                "get": {
+                    "summary": "Node owning the id in cluster mode, where both of its parties should be sent",
+                    "parameters": [unique_id],
+                    "responses": {
+                        "200": ok("ClusterOwner"),
+                        "404": {"description": "Cluster mode is not enabled", "content": {"application/json": {"schema": api_response}}},
+                        "503": {"description": "No cluster members known", "content": {"application/json": {"schema": api_response}}}
+                    }
+                }
+            },
+            "/wait-for-second-party/{unique_id}": {
+                "post": {
+                    "summary": "Waits for the second party, or matches the waiting first one (n-party & broadcast ids wait as a group)",
+                    "parameters": [
+                        unique_id,
+                        {"name": "dry_run", "in": "query", "schema": {"type": "boolean"}},
+                        {"name": "release", "in": "query", "description": "Releases the parties waiting at a broadcast id", "schema": {"type": "boolean"}},
+                        {"name": "observe", "in": "query", "description": "Waits for the point to match (200) or time out (408) without joining it, 404 if nobody is waiting, 429 beyond `max_observers_per_point`", "schema": {"type": "boolean"}},
+                        ttl,
+                        priority,
+                        note,
+                        api_key,
+                        allow_cidrs,
+                        allow_keys,
+                        reservation,
+                        metadata
+                    ],
+                    "responses": {
+                        "200": {"description": "Matched or released (or dry run outcome)", "content": {"application/json": {"schema": api_response}}},
+                        "400": {"description": "`release` for an id which isn't broadcast, an invalid `ttl`, `note`, ACL, reservation or metadata header", "content": {"application/json": {"schema": api_response}}},
+                        "403": forbidden,
+                        "404": {"description": "Nobody is waiting to be released", "content": {"application/json": {"schema": api_response}}},
+                        "408": {"description": "The peer didn't arrive within the timeout", "content": {"application/json": {"schema": api_response}}},
+                        "409": {"description": "The wait point already has 2 parties", "content": {"application/json": {"schema": api_response}}},
+                        "410": peer_timed_out,
+                        "413": too_large,
+                        "415": unsupported_media_type,
+                        "422": invalid_metadata,
+                        "429": too_many_waits,
+                        "503": {"description": "No capacity or maintenance, see Retry-After", "content": {"application/json": {"schema": api_response}}}
+                    }
+                }
+            },
+            "/wait/{unique_id}": {
+                "post": {
+                    "summary": "Waits as the first party, never matches a waiting one",
+                    "parameters": [unique_id, ttl, priority, note, api_key, allow_cidrs, allow_keys, reservation, metadata],
+                    "responses": {
+                        "200": {"description": "Matched or released", "content": {"application/json": {"schema": api_response}}},
+                        "400": {"description": "Invalid `ttl`, `note`, ACL, reservation or metadata header", "content": {"application/json": {"schema": api_response}}},
+                        "403": forbidden,
+                        "408": {"description": "The peer didn't arrive within the timeout", "content": {"application/json": {"schema": api_response}}},
+                        "409": {"description": "A party is already waiting", "content": {"application/json": {"schema": api_response}}},
+                        "413": too_large,
+                        "415": unsupported_media_type,
+                        "422": invalid_metadata,
+                        "429": too_many_waits,
+                        "503": {"description": "No capacity or maintenance, see Retry-After", "content": {"application/json": {"schema": api_response}}}
+                    }
+                }
+            },
+            "/notify/{unique_id}": {
+                "post": {
+                    "summary": "Matches the waiting party (or releases a broadcast id), never waits",
+                    "parameters": [
+                        unique_id,
+                        {"name": "persistent", "in": "query", "description": "Stores the notification for the next waiting party when nobody is waiting", "schema": {"type": "boolean"}},
+                        note,
+                        api_key,
+                        metadata
+                    ],
+                    "responses": {
+                        "200": {"description": "Matched or released", "content": {"application/json": {"schema": api_response}}},
+                        "202": {"description": "Nobody is waiting, the notification is stored", "content": {"application/json": {"schema": api_response}}},
+                        "400": {"description": "The id is n-party, its parties can only wait (or `persistent` at a broadcast id), or an invalid `note` or metadata header", "content": {"application/json": {"schema": api_response}}},
+                        "403": forbidden,
+                        "404": {"description": "Nobody is waiting", "content": {"application/json": {"schema": api_response}}},
+                        "408": {"description": "The waiting party left just before", "content": {"application/json": {"schema": api_response}}},
+                        "410": peer_timed_out,
+                        "413": too_large,
+                        "415": unsupported_media_type,
+                        "422": invalid_metadata,
+                        "429": too_many_requests,
+                        "503": {"description": "Maintenance, see Retry-After", "content": {"application/json": {"schema": api_response}}}
+                    }
+                }
+            },
+            "/reserve/{unique_id}": {
+                "post": {
+                    "summary": "Reserves a seat for a party of the id for `reservation_ttl_sec`, to check capacity up front",
+                    "parameters": [unique_id, priority],
+                    "responses": {
+                        "200": ok("Reservation"),
+                        "413": too_large,
+                        "415": unsupported_media_type,
+                        "503": {"description": "No capacity or maintenance, see Retry-After", "content": {"application/json": {"schema": api_response}}}
+                    }
+                }
+            },
+            "/batch/wait": {
+                "post": {
+                    "summary": "Waits on several ids, streaming a line per id as it resolves",
+                    "parameters": [api_key, allow_cidrs, allow_keys, metadata],
+                    "requestBody": json_body("BatchWaitRequest"),
+                    "responses": {
+                        "200": {"description": "One BatchWaitResult per line", "content": {"application/x-ndjson": {"schema": {"$ref": "#/components/schemas/BatchWaitResult"}}}},
+                        "400": error,
+                        "413": too_large,
+                        "415": unsupported_media_type,
+                        "429": too_many_requests
+                    }
+                }
+            },
+            "/admin/config": {
+                "get": {
+                    "summary": "Runtime-adjustable settings",
+                    "security": admin,
+                    "responses": {"200": ok("RuntimeConfig")}
+                },
+                "patch": {
+                    "summary": "Changes runtime-adjustable settings, or previews the change",
+                    "security": admin,
+                    "parameters": [
+                        {"name": "persist", "in": "query", "schema": {"type": "boolean"}},
+                        {"name": "preview", "in": "query", "schema": {"type": "boolean"}}
+                    ],
+                    "requestBody": {"content": {
+                        "application/json": {"schema": {"$ref": "#/components/schemas/RuntimeConfigPatch"}},
+                        "application/json-patch+json": {"schema": {"$ref": "#/components/schemas/JsonPatch"}}
+                    }},
+                    "responses": {"200": ok("RuntimeConfig"), "400": error}
+                }
+            },
+            "/admin/log-level": {
+                "put": {
+                    "summary": "Replaces the log filter",
+                    "security": admin,
+                    "requestBody": json_body("LogLevelRequest"),
+                    "responses": {"200": ok("LogLevelResponse"), "400": error}
+                }
+            },
+            "/admin/maintenance": {
+                "get": {
+                    "summary": "Maintenance mode state",
+                    "security": admin,
+                    "responses": {"200": ok("MaintenanceResponse")}
+                },
+                "put": {
+                    "summary": "Enters or leaves maintenance mode",
+                    "security": admin,
+                    "requestBody": json_body("MaintenanceRequest"),
+                    "responses": {"200": ok("MaintenanceResponse"), "400": error}
+                }
+            },
+            "/admin/lame-duck": {
+                "put": {
+                    "summary": "Enters or leaves lame-duck mode, /ready then reports lame-duck",
+                    "security": admin,
+                    "requestBody": json_body("LameDuckRequest"),
+                    "responses": {"200": ok("ReadinessResponse"), "400": error}
+                }
+            },
+            "/admin/wait-points": {
+                "get": {
+                    "summary": "Open wait points in creation order, a page at a time (or all as NDJSON)",
+                    "security": admin,
+                    "parameters": [
+                        {"name": "limit", "in": "query", "schema": {"type": "integer", "minimum": 1, "maximum": 1000}},
+                        {"name": "cursor", "in": "query", "schema": {"type": "string"}}
+                    ],
+                    "responses": {"200": ok("WaitPointPage"), "400": error}
+                }
+            },
+            "/admin/observability/templates": {
+                "get": {
+                    "summary": "Grafana dashboard & Prometheus alert rules for /metrics",
+                    "security": admin,
+                    "responses": {"200": {"description": "`grafana_dashboard` & `prometheus_alert_rules` documents", "content": {"application/json": {"schema": {"type": "object"}}}}}
+                }
+            },
+            "/admin/self-test": {
+                "post": {
+                    "summary": "Runs a rendezvous on a fresh _internal/selftest- id & reports each check",
+                    "security": admin,
+                    "responses": {"200": ok("SelfTestReport"), "503": ok("SelfTestReport")}
+                }
+            },
+            "/dev/auto-match/{unique_id}": {
+                "post": {
+                    "summary": "Plays the second party after a delay, mounted only with dev.auto_match",
+                    "parameters": [
+                        unique_id,
+                        {"name": "delay_ms", "in": "query", "schema": {"type": "integer"}}
+                    ],
+                    "responses": {"202": {"description": "Accepted", "content": {"application/json": {"schema": api_response}}}}
+                }
+            }
+        },
+        "components": {
+            "securitySchemes": {
+                "bearerAuth": {"type": "http", "scheme": "bearer", "description": "The configured admin_token"}
+            },
+            "schemas": {
+                "ApiResponse": {
+                    "type": "object",
+                    "required": ["status", "message"],
+                    "properties": {
+                        "status": {"type": "string", "enum": ["success", "timeout", "error"]},
+                        "message": {"type": "string"},
+                        "timeout_duration_sec": {"type": "integer"},
+                        "dry_run": {"type": "string", "enum": ["wait", "match", "conflict", "unavailable"]},
+                        "delivered": {"type": "boolean", "description": "Sent to notifying parties, whether a waiting party was woken"},
+                        "pair_id": {"type": "string", "format": "uuid", "description": "Sent on a match, the same for all its parties"},
+                        "receipt": {
+                            "type": "object",
+                            "description": "Sent on a pair match when receipts are enabled, the same for both parties",
+                            "required": ["payload", "signature"],
+                            "properties": {
+                                "payload": {"type": "string", "description": "Signed JSON: unique_id, pair_id, created_at, matched_at & parties (fingerprints)"},
+                                "signature": {"type": "string", "description": "Ed25519 signature of payload, hex"}
+                            }
+                        },
+                        "conflict": {
+                            "type": "object",
+                            "description": "Sent with 409, the state of the taken wait point",
+                            "required": ["parties", "created_at", "generation", "retry_after_sec"],
+                            "properties": {
+                                "parties": {"type": "integer", "description": "Parties which joined before the rejected one"},
+                                "created_at": {"type": "string", "format": "date-time"},
+                                "generation": {"type": "integer"},
+                                "retry_after_sec": {"type": "integer", "description": "Suggested delay before retrying"}
+                            }
+                        },
+                        "peer_recently_timed_out": {
+                            "type": "object",
+                            "description": "Sent with 410, when the waiting party gave up",
+                            "required": ["timed_out_at"],
+                            "properties": {
+                                "timed_out_at": {"type": "string", "format": "date-time"}
+                            }
+                        },
+                        "peer": {
+                            "allOf": [{"$ref": "#/components/schemas/PartyOrigin"}],
+                            "description": "Sent on a pair match when the server has `share_peer_origin`, where the peer came from (redacted)"
+                        },
+                        "errors": {
+                            "type": "array",
+                            "description": "Sent with 422, the fields of X-Sync-Metadata not conforming to the template of the id",
+                            "items": {
+                                "type": "object",
+                                "required": ["field", "message"],
+                                "properties": {
+                                    "field": {"type": "string", "description": "JSON pointer, empty for the whole metadata"},
+                                    "message": {"type": "string"}
+                                }
+                            }
+                        }
+                    }
+                },
+                "PartyOrigin": {
+                    "type": "object",
+                    "properties": {
+                        "ip": {"type": "string", "description": "Only the /24 or /48 network when redacted"},
+                        "user_agent": {"type": "string", "description": "Only the first product when redacted"},
+                        "api_key": {"type": "string", "description": "Last 4 characters"}
+                    }
+                },
+                "Completion": {
+                    "type": "object",
+                    "required": ["pair_id", "unique_id", "parties", "created_at", "matched_at", "wait_ms"],
+                    "properties": {
+                        "pair_id": {"type": "string"},
+                        "unique_id": {"type": "string"},
+                        "parties": {"type": "integer"},
+                        "created_at": {"type": "string", "format": "date-time"},
+                        "matched_at": {"type": "string", "format": "date-time"},
+                        "wait_ms": {"type": "integer", "description": "How long the first party waited"},
+                        "origins": {"type": "array", "items": {"$ref": "#/components/schemas/PartyOrigin"}, "description": "Redacted, in order of arrival"},
+                        "notes": {"type": "array", "items": {"type": "string"}},
+                        "receipt": {
+                            "type": "object",
+                            "description": "When receipts are enabled, as sent to the parties",
+                            "required": ["payload", "signature"],
+                            "properties": {
+                                "payload": {"type": "string"},
+                                "signature": {"type": "string"}
+                            }
+                        }
+                    }
+                },
+                "Observation": {
+                    "type": "object",
+                    "required": ["event", "unique_id", "parties", "time"],
+                    "properties": {
+                        "event": {"type": "string", "enum": ["waiting", "matched", "timeout"]},
+                        "unique_id": {"type": "string"},
+                        "pair_id": {"type": "string", "description": "Only for `matched`"},
+                        "parties": {"type": "integer", "description": "Waiting so far, matched, or 1 for `timeout`"},
+                        "time": {"type": "string", "format": "date-time"}
+                    }
+                },
+                "ReceiptKey": {
+                    "type": "object",
+                    "required": ["algorithm", "public_key"],
+                    "properties": {
+                        "algorithm": {"type": "string", "enum": ["ed25519"]},
+                        "public_key": {"type": "string", "description": "hex"}
+                    }
+                },
+                "ClusterOwner": {
+                    "type": "object",
+                    "required": ["unique_id", "node", "url", "local"],
+                    "properties": {
+                        "unique_id": {"type": "string"},
+                        "node": {"type": "string"},
+                        "url": {"type": "string"},
+                        "local": {"type": "boolean", "description": "Whether the answering node is the owner"}
+                    }
+                },
+                "HealthResponse": {
+                    "type": "object",
+                    "required": ["status"],
+                    "properties": {
+                        "status": {"type": "string", "enum": ["ok", "maintenance"]},
+                        "message": {"type": "string"}
+                    }
+                },
+                "ReadinessResponse": {
+                    "type": "object",
+                    "required": ["state"],
+                    "properties": {
+                        "state": {"type": "string", "enum": ["starting", "ready", "lame-duck", "unhealthy"]},
+                        "message": {"type": "string", "description": "Why the instance isn't ready"}
+                    }
+                },
+                "WaitPointStatus": {
+                    "type": "object",
+                    "required": ["unique_id", "parties", "waiting"],
+                    "properties": {
+                        "unique_id": {"type": "string"},
+                        "parties": {"type": "integer"},
+                        "waiting": {"type": "boolean"},
+                        "notes": {"type": "array", "items": {"type": "string"}, "description": "Admin listings only"},
+                        "origins": {"type": "array", "items": {"$ref": "#/components/schemas/PartyOrigin"}, "description": "Admin listings only, in order of arrival"}
+                    }
+                },
+                "WaitPointPage": {
+                    "type": "object",
+                    "required": ["items"],
+                    "properties": {
+                        "items": {"type": "array", "items": {"$ref": "#/components/schemas/WaitPointStatus"}},
+                        "next_cursor": {"type": "string", "nullable": true}
+                    }
+                },
+                "Stats": {
+                    "type": "object",
+                    "required": ["open_wait_points", "waiting_parties", "memory_bytes", "open_by_priority", "pending_notifications", "generation"],
+                    "properties": {
+                        "open_wait_points": {"type": "integer"},
+                        "waiting_parties": {"type": "integer"},
+                        "memory_bytes": {"type": "integer"},
+                        "open_by_priority": {"$ref": "#/components/schemas/PriorityCounts"},
+                        "pending_notifications": {"type": "integer"},
+                        "generation": {"type": "integer"}
+                    }
+                },
+                "AllocatorStats": {
+                    "type": "object",
+                    "required": ["allocator"],
+                    "properties": {
+                        "allocator": {"type": "string", "enum": ["system", "jemalloc", "mimalloc"]},
+                        "allocated_bytes": {"type": "integer"},
+                        "resident_bytes": {"type": "integer"}
+                    }
+                },
+                "ServerTime": {
+                    "type": "object",
+                    "required": ["server_time", "unix_ms"],
+                    "properties": {
+                        "server_time": {"type": "string", "format": "date-time"},
+                        "unix_ms": {"type": "integer"}
+                    }
+                },
+                "Reservation": {
+                    "type": "object",
+                    "required": ["unique_id", "token", "expires_in_sec"],
+                    "properties": {
+                        "unique_id": {"type": "string"},
+                        "token": {"type": "string", "format": "uuid", "description": "Sent back as X-Sync-Reservation header"},
+                        "expires_in_sec": {"type": "integer"}
+                    }
+                },
+                "OutcomeCounts": {
+                    "type": "object",
+                    "required": ["timed_out", "cancelled"],
+                    "properties": {
+                        "timed_out": {"type": "integer"},
+                        "cancelled": {"type": "integer"}
+                    }
+                },
+                "OutcomeStats": {
+                    "type": "object",
+                    "required": ["total", "by_prefix"],
+                    "properties": {
+                        "total": {"$ref": "#/components/schemas/OutcomeCounts"},
+                        "by_prefix": {"type": "object", "additionalProperties": {"$ref": "#/components/schemas/OutcomeCounts"}}
+                    }
+                },
+                "BatchWaitRequest": {
+                    "type": "object",
+                    "required": ["ids"],
+                    "properties": {
+                        "ids": {"type": "array", "items": {"type": "string"}, "minItems": 1, "maxItems": 64, "uniqueItems": true},
+                        "priority": {"$ref": "#/components/schemas/Priority"},
+                        "note": {"type": "string", "maxLength": MAX_NOTE_CHARS}
+                    }
+                },
+                "Priority": {"type": "string", "enum": ["high", "normal", "low"], "default": "normal"},
+                "PriorityCounts": {
+                    "type": "object",
+                    "required": ["high", "normal", "low"],
+                    "properties": {
+                        "high": {"type": "integer"},
+                        "normal": {"type": "integer"},
+                        "low": {"type": "integer"}
+                    }
+                },
+                "BatchWaitResult": {
+                    "allOf": [
+                        {
+                            "type": "object",
+                            "required": ["unique_id", "http_status"],
+                            "properties": {
+                                "unique_id": {"type": "string"},
+                                "http_status": {"type": "integer"}
+                            }
+                        },
+                        api_response
+                    ]
+                },
+                "RuntimeConfig": {
+                    "type": "object",
+                    "required": ["timeout", "max_wait_points", "log_level"],
+                    "properties": {
+                        "timeout": {"type": "integer"},
+                        "max_wait_points": {"type": "integer"},
+                        "log_level": {"type": "string"}
+                    }
+                },
+                "RuntimeConfigPatch": {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "properties": {
+                        "timeout": {"type": "integer"},
+                        "max_wait_points": {"type": "integer"},
+                        "log_level": {"type": "string"}
+                    }
+                },
+                "JsonPatch": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "required": ["op", "path"],
+                        "properties": {
+                            "op": {"type": "string", "enum": ["add", "remove", "replace", "move", "copy", "test"]},
+                            "path": {"type": "string"},
+                            "from": {"type": "string"},
+                            "value": {}
+                        }
+                    }
+                },
+                "LogLevelRequest": {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "properties": {
+                        "level": {"type": "string"},
+                        "modules": {"type": "object", "additionalProperties": {"type": "string"}}
+                    }
+                },
+                "LogLevelResponse": {
+                    "type": "object",
+                    "required": ["filter"],
+                    "properties": {"filter": {"type": "string"}}
+                },
+                "LameDuckRequest": {
+                    "type": "object",
+                    "required": ["enabled"],
+                    "additionalProperties": false,
+                    "properties": {"enabled": {"type": "boolean"}}
+                },
+                "MaintenanceRequest": {
+                    "type": "object",
+                    "required": ["enabled"],
+                    "additionalProperties": false,
+                    "properties": {
+                        "enabled": {"type": "boolean"},
+                        "message": {"type": "string"},
+                        "retry_after_sec": {"type": "integer"}
+                    }
+                },
+                "MaintenanceResponse": {
+                    "type": "object",
+                    "required": ["enabled"],
+                    "properties": {
+                        "enabled": {"type": "boolean"},
+                        "message": {"type": "string"},
+                        "retry_after_sec": {"type": "integer"}
+                    }
+                },
+                "SelfTestReport": {
+                    "type": "object",
+                    "required": ["passed", "checks"],
+                    "properties": {
+                        "passed": {"type": "boolean", "description": "No check failed"},
+                        "checks": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "required": ["name", "result", "message", "duration_ms"],
+                                "properties": {
+                                    "name": {"type": "string", "enum": ["rendezvous", "store_round_trip", "webhook_dry_run"]},
+                                    "result": {"type": "string", "enum": ["pass", "fail", "skipped"]},
+                                    "message": {"type": "string"},
+                                    "duration_ms": {"type": "integer"}
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::dev::auto_match;
+    use crate::build_rocket_with;
+    use crate::openapi::document;
+    use crate::App;
+    use rocket::routes;
+
+    /// Guards against the document drifting from the code
+    #[rocket::async_test]
+    async fn test_every_route_is_documented() {
+        let app = App::new(None).expect("default config");
+        // Routes are mounted by `SyncPointFairing` on ignite
+        let rocket = build_rocket_with(app)
+            .mount("/dev", routes![auto_match])
+            .ignite()
+            .await
+            .expect("ignite");
+        assert!(rocket.routes().count() > 1);
+        let document = document();
+
+        for route in rocket.routes() {
+            // e.g. `/status/<unique_id..>?<dry_run>` -> `/status/{unique_id}`
+            let path = route
+                .uri
+                .path()
+                .replace("..>", ">")
+                .replace('<', "{")
+                .replace('>', "}");
+            let method = route.method.as_str().to_lowercase();
+            assert!(
+                document["paths"][&path][&method].is_object(),
+                "{} {} is not documented",
+                method,
+                path
+            );
+        }
+    }
+}
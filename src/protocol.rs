@@ -0,0 +1,1063 @@
+//! Wire types of the HTTP API, shared by the server & clients (the C client in `ffi`, SDKs), so
+//! both sides serialize identically.
+//!
+//! Only depends on `core`, `alloc`, `serde` & `serde_json` (enforced by the lints below), so it compiles for
+//! `no_std` targets with an allocator & can move into its own crate without changes.
+#![deny(clippy::std_instead_of_core, clippy::std_instead_of_alloc)]
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::time::Duration;
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// HTTP status codes of the sync endpoints & what they mean. The server's `status_codes` may send
+/// timeouts, conflicts & busy rejections with others, `ApiResponse::parse` still tells the first two
+/// apart by their body
+pub mod status {
+    /// Both parties met (also dry runs that would succeed)
+    pub const MATCHED: u16 = 200;
+    /// Malformed request, e.g. an invalid batch body
+    pub const BAD_REQUEST: u16 = 400;
+    /// Not admitted by the wait point's ACL
+    pub const FORBIDDEN: u16 = 403;
+    /// Nobody was waiting to be released
+    pub const NOT_FOUND: u16 = 404;
+    /// The peer didn't arrive within the timeout
+    pub const TIMED_OUT: u16 = 408;
+    /// The wait point already has 2 parties
+    pub const CONFLICT: u16 = 409;
+    /// Nobody is waiting, the waiting party timed out within `late_arrival_grace_sec` before
+    pub const PEER_TIMED_OUT: u16 = 410;
+    /// The party's API key holds `max_waits_per_key` waits already
+    pub const TOO_MANY_WAITS: u16 = 429;
+    /// No capacity for a new wait point, or maintenance mode (with `Retry-After`)
+    pub const UNAVAILABLE: u16 = 503;
+}
+
+/// Headers the API reads or sends
+pub mod headers {
+    /// Sent with 503, seconds to wait before retrying
+    pub const RETRY_AFTER: &str = "Retry-After";
+    /// Sent by polled endpoints, quoted
+    pub const ETAG: &str = "ETag";
+    /// Sent back by pollers with the last `ETag`, answered with 304 while unchanged
+    pub const IF_NONE_MATCH: &str = "If-None-Match";
+    /// `Bearer <admin_token>` for the admin API
+    pub const AUTHORIZATION: &str = "Authorization";
+    /// `NDJSON_CONTENT_TYPE` switches listings to streaming exports
+    pub const ACCEPT: &str = "Accept";
+    /// Identifies a party to wait point ACLs
+    pub const API_KEY: &str = "X-Api-Key";
+    /// Sent by the party creating a wait point, comma separated address ranges allowed to join it
+    pub const ALLOW_CIDRS: &str = "X-Sync-Allow-Cidrs";
+    /// Sent by the party creating a wait point, comma separated `X-Api-Key`s allowed to join it
+    pub const ALLOW_KEYS: &str = "X-Sync-Allow-Keys";
+    /// Sent by a party holding a token of `POST /reserve/<unique_id>`, admitting it at capacity
+    pub const RESERVATION: &str = "X-Sync-Reservation";
+    /// Sent by joining parties, a JSON object checked against the template of the id
+    pub const METADATA: &str = "X-Sync-Metadata";
+    /// Sent to parties with an `X-Api-Key` when waits per key are limited, `<in use>/<limit>`
+    pub const WAITS_IN_USE: &str = "X-Api-Key-Waits";
+    /// Sent with every response, the server's clock as Unix time in milliseconds
+    pub const SERVER_TIME: &str = "X-Server-Time";
+    /// Recorded as the origin of a party, see `api::origins` module
+    pub const USER_AGENT: &str = "User-Agent";
+    /// Optionally sent by clients, their clock in the same format, to detect skew
+    pub const CLIENT_TIME: &str = "X-Client-Time";
+    /// Sent with webhook digests having a `secret`, `sha256=<hex HMAC-SHA256 of the body>`
+    pub const SIGNATURE: &str = "X-Sync-Signature";
+    /// Sent with the requests mirrored to a shadow instance, which doesn't mirror them again
+    pub const SHADOW: &str = "X-Sync-Shadow";
+    /// Media type of streamed responses (`/batch/wait`, exports), one JSON document per line
+    pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+}
+
+/// Query parameter of the sync endpoint, `true` only reports what would happen
+pub const DRY_RUN_PARAM: &str = "dry_run";
+
+/// Longest `note` a party may leave on its wait point for operators, in characters
+pub const MAX_NOTE_CHARS: usize = 200;
+
+/// Path of the sync endpoint for `unique_id`, relative to the server's base URL
+pub fn wait_path(unique_id: &str) -> String {
+    format!("/wait-for-second-party/{}", encode_path(unique_id))
+}
+
+/// Percent-encodes all but unreserved characters & `/`, e.g. an id as path segments
+pub fn encode_path(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{:02X}", byte);
+        }
+    }
+    encoded
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseStatus {
+    Success,
+    Timeout,
+    Error,
+}
+
+/// What a sync request would do, reported by dry runs
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DryRunOutcome {
+    /// Would create the wait point & wait as first party
+    Wait,
+    /// Would join as second party & notify the first one
+    Match,
+    /// Would be rejected, the wait point already has 2 parties
+    Conflict,
+    /// Would be rejected, no capacity for a new wait point
+    Unavailable,
+}
+
+/// Response body of the sync & admin endpoints, e.g. `{"status":"success","message":"[123] Welcome!"}`
+///
+/// Built on every request, so it avoids allocating where it can: messages are mostly static (`Cow`), and
+/// the `[unique_id]` prefix is written straight into the output when serializing instead of being
+/// `format!`ed into the message upfront.
+///
+/// Deserialized responses keep the prefixed message as is, `message()` reads the same either way.
+#[derive(Debug)]
+pub struct ApiResponse {
+    status: ResponseStatus,
+    /// Id of the wait point the message is about, rendered as `[unique_id] ` message prefix
+    unique_id: Option<Box<str>>,
+    message: Cow<'static, str>,
+    timeout_duration_sec: Option<u64>,
+    dry_run: Option<DryRunOutcome>,
+    /// Details of a 409, 410 or 422. Boxed, they're rare & would otherwise grow every response
+    rejection: Option<Box<Rejection>>,
+    /// Whether a notifying party actually woke a waiting one
+    delivered: Option<bool>,
+    /// Shared by all parties of a match
+    pair_id: Option<Box<str>>,
+    /// Signed proof of the match, boxed like `rejection`
+    receipt: Option<Box<Receipt>>,
+    /// Where the matched peer came from, boxed like `rejection`
+    peer: Option<Box<PartyOrigin>>,
+}
+
+/// Why a party was rejected, each kind sent as its own field of `ApiResponse`
+#[derive(Debug, PartialEq)]
+enum Rejection {
+    /// `conflict` field, with 409
+    Conflict(ConflictDetails),
+    /// `peer_recently_timed_out` field, with 410
+    PeerTimeout(PeerTimeout),
+    /// `errors` field, with 422
+    InvalidMetadata(Vec<FieldError>),
+}
+
+/// Why a party was rejected with 409, sent as `conflict` field, e.g.
+/// `{"parties":2,"created_at":"2024-12-28T06:41:51.123Z","generation":17,"retry_after_sec":1}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConflictDetails {
+    /// Parties which joined the wait point before the rejected one
+    pub parties: usize,
+    /// When the wait point was created, RFC 3339 UTC
+    pub created_at: String,
+    /// Service generation at creation, tells apart points re-created for the same id
+    pub generation: u64,
+    /// Suggested delay before retrying, the wait point should be gone by then
+    pub retry_after_sec: u64,
+}
+
+/// Why a late party was rejected with 410, sent as `peer_recently_timed_out` field, e.g.
+/// `{"timed_out_at":"2024-12-28T06:41:51.123Z"}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeerTimeout {
+    /// When the waiting party timed out, RFC 3339 UTC
+    pub timed_out_at: String,
+}
+
+/// A field of a party's metadata which doesn't conform to the template of its id, sent with 422
+/// as item of the `errors` field, e.g. `{"field":"/team","message":"is required"}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldError {
+    /// JSON pointer of the field in the metadata, empty for the metadata as a whole
+    pub field: String,
+    pub message: String,
+}
+
+/// Where a party came from, as recorded when it joined. Sent redacted as `peer` field to the
+/// parties of a pair match when the server has `share_peer_origin`, e.g.
+/// `{"ip":"10.1.2.0/24","user_agent":"curl/8.4.0","api_key":"…am-a"}`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PartyOrigin {
+    /// Client address, only its network when redacted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    /// `User-Agent` header, only its first product when redacted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    /// Last 4 characters of the `X-Api-Key`, which is never disclosed whole
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+}
+
+/// Proof of a pair match signed by the server (Ed25519), sent as `receipt` field when the server
+/// has a `receipt_key`. Both parties get the same one, a third system verifies it with the
+/// server's public key (`GET /receipts/key`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Receipt {
+    /// `ReceiptPayload` as JSON, exactly the bytes which are signed
+    pub payload: String,
+    /// Ed25519 signature of `payload`, hex
+    pub signature: String,
+}
+
+/// What a `Receipt` attests
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReceiptPayload {
+    pub unique_id: String,
+    pub pair_id: String,
+    /// When the wait point was created, RFC 3339 UTC
+    pub created_at: String,
+    /// When the second party arrived, RFC 3339 UTC
+    pub matched_at: String,
+    /// Fingerprints of the waiting & the notifying party, hex. Derived from the client address &
+    /// `X-Api-Key`, which aren't disclosed
+    pub parties: Vec<String>,
+}
+
+/// Record of a match returned by `GET /completions/<pair_id>` for the server's retention period,
+/// so systems offline at match time can verify it later, e.g.
+/// `{"pair_id":"…","unique_id":"123","parties":2,"created_at":"…","matched_at":"…","wait_ms":1520}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Completion {
+    pub pair_id: String,
+    pub unique_id: String,
+    /// Parties matched
+    pub parties: usize,
+    /// When the wait point was created, i.e. its first party arrived, RFC 3339 UTC
+    pub created_at: String,
+    /// When the parties matched, RFC 3339 UTC
+    pub matched_at: String,
+    /// How long the first party waited, in milliseconds
+    pub wait_ms: u64,
+    /// Where the parties came from in order of arrival, redacted like `peer`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub origins: Vec<PartyOrigin>,
+    /// Notes the parties left
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+    /// The receipt of a pair match, when the server issues them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<Receipt>,
+}
+
+/// What happened at a wait point, sent as data of the events of `GET /observe/<pattern>` (named
+/// after `event`), e.g.
+/// `{"event":"matched","unique_id":"deploy/eu/payments","pair_id":"…","parties":2,"time":"2024-12-28T06:41:51.123Z"}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Observation {
+    pub event: ObservedEvent,
+    pub unique_id: String,
+    /// Id of the match, only sent for `matched`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pair_id: Option<String>,
+    /// Parties waiting so far for `waiting`, matched for `matched`, 1 (the one giving up) for
+    /// `timeout`
+    pub parties: usize,
+    /// RFC 3339 UTC
+    pub time: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ObservedEvent {
+    /// A party started waiting
+    Waiting,
+    /// Parties matched
+    Matched,
+    /// A waiting party gave up after its timeout
+    Timeout,
+}
+
+impl ObservedEvent {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ObservedEvent::Waiting => "waiting",
+            ObservedEvent::Matched => "matched",
+            ObservedEvent::Timeout => "timeout",
+        }
+    }
+}
+
+/// Body of the webhook digests (see `api::digests` module): the match & timeout events since the
+/// previous digest, in order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventDigest {
+    /// 1 for the first digest of a webhook since the server started, one more for each following,
+    /// so receivers notice lost ones
+    pub sequence: u64,
+    pub events: Vec<Observation>,
+    /// Events left out since the previous digest, while the webhook was too slow
+    pub dropped: u64,
+}
+
+/// The `message` field with its `[unique_id] ` prefix
+struct Message<'a>(&'a str, &'a str);
+
+impl fmt::Display for Message<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[")?;
+        f.write_str(self.0)?;
+        f.write_str("] ")?;
+        f.write_str(self.1)
+    }
+}
+
+impl Serialize for ApiResponse {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut response = serializer.serialize_struct("ApiResponse", 11)?;
+        response.serialize_field("status", &self.status)?;
+        match &self.unique_id {
+            // `collect_str` streams the `Display` output (escaped) into the JSON writer
+            Some(unique_id) => {
+                let message = Message(unique_id, &self.message);
+                response.serialize_field("message", &CollectStr(&message))?
+            }
+            None => response.serialize_field("message", &self.message)?,
+        }
+        match self.timeout_duration_sec {
+            Some(timeout) => response.serialize_field("timeout_duration_sec", &timeout)?,
+            None => response.skip_field("timeout_duration_sec")?,
+        }
+        match self.dry_run {
+            Some(outcome) => response.serialize_field("dry_run", &outcome)?,
+            None => response.skip_field("dry_run")?,
+        }
+        match self.rejection.as_deref() {
+            Some(Rejection::Conflict(conflict)) => {
+                response.serialize_field("conflict", conflict)?
+            }
+            _ => response.skip_field("conflict")?,
+        }
+        match self.delivered {
+            Some(delivered) => response.serialize_field("delivered", &delivered)?,
+            None => response.skip_field("delivered")?,
+        }
+        match &self.pair_id {
+            Some(pair_id) => response.serialize_field("pair_id", pair_id)?,
+            None => response.skip_field("pair_id")?,
+        }
+        match &self.receipt {
+            Some(receipt) => response.serialize_field("receipt", receipt)?,
+            None => response.skip_field("receipt")?,
+        }
+        match self.rejection.as_deref() {
+            Some(Rejection::PeerTimeout(peer_timeout)) => {
+                response.serialize_field("peer_recently_timed_out", peer_timeout)?
+            }
+            _ => response.skip_field("peer_recently_timed_out")?,
+        }
+        match &self.peer {
+            Some(peer) => response.serialize_field("peer", peer)?,
+            None => response.skip_field("peer")?,
+        }
+        match self.rejection.as_deref() {
+            Some(Rejection::InvalidMetadata(errors)) => {
+                response.serialize_field("errors", errors)?
+            }
+            _ => response.skip_field("errors")?,
+        }
+        response.end()
+    }
+}
+
+/// Serializes a `Display` value as a string without rendering it into a `String` first
+struct CollectStr<'a, T>(&'a T);
+
+impl<T: fmt::Display> Serialize for CollectStr<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self.0)
+    }
+}
+
+/// `ApiResponse` as it's read from the wire. Unknown fields are ignored, so clients keep working
+/// when the server adds some
+#[derive(Deserialize)]
+struct WireResponse {
+    status: ResponseStatus,
+    message: String,
+    #[serde(default)]
+    timeout_duration_sec: Option<u64>,
+    #[serde(default)]
+    dry_run: Option<DryRunOutcome>,
+    #[serde(default)]
+    conflict: Option<ConflictDetails>,
+    #[serde(default)]
+    delivered: Option<bool>,
+    #[serde(default)]
+    pair_id: Option<Box<str>>,
+    #[serde(default)]
+    receipt: Option<Box<Receipt>>,
+    #[serde(default)]
+    peer_recently_timed_out: Option<PeerTimeout>,
+    #[serde(default)]
+    peer: Option<Box<PartyOrigin>>,
+    #[serde(default)]
+    errors: Option<Vec<FieldError>>,
+}
+
+impl<'de> Deserialize<'de> for ApiResponse {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let wire = WireResponse::deserialize(deserializer)?;
+        Ok(Self {
+            status: wire.status,
+            unique_id: None,
+            message: Cow::Owned(wire.message),
+            timeout_duration_sec: wire.timeout_duration_sec,
+            dry_run: wire.dry_run,
+            rejection: None
+                .or(wire.conflict.map(Rejection::Conflict))
+                .or(wire.peer_recently_timed_out.map(Rejection::PeerTimeout))
+                .or(wire.errors.map(Rejection::InvalidMetadata))
+                .map(Box::new),
+            delivered: wire.delivered,
+            pair_id: wire.pair_id,
+            receipt: wire.receipt,
+            peer: wire.peer,
+        })
+    }
+}
+
+/// Equal when they serialize the same, whether built or deserialized
+impl PartialEq for ApiResponse {
+    fn eq(&self, other: &Self) -> bool {
+        self.status == other.status
+            && self.message() == other.message()
+            && self.timeout_duration_sec == other.timeout_duration_sec
+            && self.dry_run == other.dry_run
+            && self.rejection == other.rejection
+            && self.delivered == other.delivered
+            && self.pair_id == other.pair_id
+            && self.receipt == other.receipt
+            && self.peer == other.peer
+    }
+}
+
+impl ApiResponse {
+    /// Generates successful API response with a message and unique identifier
+    ///
+    /// # Arguments
+    /// * `message` - A friendly welcome message
+    /// * `unique_id` - A unique identifier to track the response. This helps to make it distinguish
+    ///   about which route such response was generated (otherwise it will be same generic
+    ///   welcome message for each one)
+    ///
+    /// # Returns
+    /// `ApiResponse` instance with:
+    /// * `status` set to `ResponseStatus::Success`
+    /// * `message` rendered as "[unique_id] message"
+    /// * `timeout_duration_sec` set to `None`. Not visible in JSON response.
+    pub fn success(message: impl Into<Cow<'static, str>>, unique_id: &str) -> Self {
+        Self {
+            status: ResponseStatus::Success,
+            unique_id: Some(unique_id.into()),
+            message: message.into(),
+            timeout_duration_sec: None,
+            dry_run: None,
+            rejection: None,
+            delivered: None,
+            pair_id: None,
+            receipt: None,
+            peer: None,
+        }
+    }
+
+    /// Same as `success` response, but with additional `timeout_duration_sec` field`
+    pub fn timeout(duration: Duration, unique_id: &str) -> Self {
+        Self {
+            status: ResponseStatus::Timeout,
+            unique_id: Some(unique_id.into()),
+            message: Cow::Borrowed("Request timed out"),
+            timeout_duration_sec: Some(duration.as_secs()),
+            dry_run: None,
+            rejection: None,
+            delivered: None,
+            pair_id: None,
+            receipt: None,
+            peer: None,
+        }
+    }
+
+    /// Describes what a sync request would do, `status` being `Error` for rejections
+    pub fn dry_run(outcome: DryRunOutcome, unique_id: &str) -> Self {
+        let (status, message) = match outcome {
+            DryRunOutcome::Wait => (
+                ResponseStatus::Success,
+                "Dry run: would wait for the second party",
+            ),
+            DryRunOutcome::Match => (ResponseStatus::Success, "Dry run: would join as second party"),
+            DryRunOutcome::Conflict => (
+                ResponseStatus::Error,
+                "Dry run: would be rejected, only 2 parties allowed at a time",
+            ),
+            DryRunOutcome::Unavailable => (
+                ResponseStatus::Error,
+                "Dry run: would be rejected, too many open wait points",
+            ),
+        };
+
+        Self {
+            status,
+            unique_id: Some(unique_id.into()),
+            message: Cow::Borrowed(message),
+            timeout_duration_sec: None,
+            dry_run: Some(outcome),
+            rejection: None,
+            delivered: None,
+            pair_id: None,
+            receipt: None,
+            peer: None,
+        }
+    }
+
+    /// Rejection of a party beyond the second one, with details to act on
+    pub fn conflict(details: ConflictDetails) -> Self {
+        Self {
+            rejection: Some(Box::new(Rejection::Conflict(details))),
+            ..Self::error("Only 2 parties allowed at a time")
+        }
+    }
+
+    /// Rejection of a party arriving shortly after the waiting one timed out, instead of making it
+    /// wait in a fresh point for a peer which has given up
+    pub fn peer_recently_timed_out(details: PeerTimeout, unique_id: &str) -> Self {
+        Self {
+            unique_id: Some(unique_id.into()),
+            rejection: Some(Box::new(Rejection::PeerTimeout(details))),
+            ..Self::error("The waiting party recently timed out, nobody is waiting")
+        }
+    }
+
+    /// Rejection of a party whose metadata doesn't conform to the template of its id (422)
+    pub fn invalid_metadata(errors: Vec<FieldError>, unique_id: &str) -> Self {
+        Self {
+            unique_id: Some(unique_id.into()),
+            rejection: Some(Box::new(Rejection::InvalidMetadata(errors))),
+            ..Self::error("Metadata doesn't conform to the template of the id")
+        }
+    }
+
+    /// Will return critical error messages
+    pub fn error(message: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            status: ResponseStatus::Error,
+            unique_id: None,
+            message: message.into(),
+            timeout_duration_sec: None,
+            dry_run: None,
+            rejection: None,
+            delivered: None,
+            pair_id: None,
+            receipt: None,
+            peer: None,
+        }
+    }
+
+    pub fn status(&self) -> ResponseStatus {
+        self.status
+    }
+
+    /// The `message` field as sent, i.e. with the `[unique_id] ` prefix if there is one
+    pub fn message(&self) -> Cow<'_, str> {
+        match &self.unique_id {
+            Some(unique_id) => Cow::Owned(format!("{}", Message(unique_id, &self.message))),
+            None => Cow::Borrowed(&self.message),
+        }
+    }
+
+    pub fn timeout_duration_sec(&self) -> Option<u64> {
+        self.timeout_duration_sec
+    }
+
+    pub fn dry_run_outcome(&self) -> Option<DryRunOutcome> {
+        self.dry_run
+    }
+
+    pub fn conflict_details(&self) -> Option<&ConflictDetails> {
+        match self.rejection.as_deref() {
+            Some(Rejection::Conflict(details)) => Some(details),
+            _ => None,
+        }
+    }
+
+    pub fn peer_timeout(&self) -> Option<&PeerTimeout> {
+        match self.rejection.as_deref() {
+            Some(Rejection::PeerTimeout(details)) => Some(details),
+            _ => None,
+        }
+    }
+
+    /// Invalid metadata fields, only set on 422 responses
+    pub fn errors(&self) -> &[FieldError] {
+        match self.rejection.as_deref() {
+            Some(Rejection::InvalidMetadata(errors)) => errors,
+            _ => &[],
+        }
+    }
+
+    /// Whether the waiting party was woken, only set on responses to notifying parties (the
+    /// second party & `/notify`). Missing when the server couldn't tell
+    pub fn delivered(&self) -> Option<bool> {
+        self.delivered
+    }
+
+    /// Reports whether the waiting party was woken, sent as `delivered` field
+    pub fn with_delivered(mut self, delivered: bool) -> Self {
+        self.delivered = Some(delivered);
+        self
+    }
+
+    /// Id of the match, the same for all its parties (a UUID), so downstream systems can
+    /// correlate them & tell apart duplicate matches
+    pub fn pair_id(&self) -> Option<&str> {
+        self.pair_id.as_deref()
+    }
+
+    /// Sends `pair_id` with the response
+    pub fn with_pair_id(mut self, pair_id: &str) -> Self {
+        self.pair_id = Some(pair_id.into());
+        self
+    }
+
+    /// Signed proof of the match, only sent to the parties of a pair match
+    pub fn receipt(&self) -> Option<&Receipt> {
+        self.receipt.as_deref()
+    }
+
+    /// Sends `receipt` with the response
+    pub fn with_receipt(mut self, receipt: Receipt) -> Self {
+        self.receipt = Some(Box::new(receipt));
+        self
+    }
+
+    /// Where the matched peer came from, redacted. Only sent to the parties of a pair match, when
+    /// the server shares it
+    pub fn peer(&self) -> Option<&PartyOrigin> {
+        self.peer.as_deref()
+    }
+
+    /// Sends `peer` with the response
+    pub fn with_peer(mut self, peer: PartyOrigin) -> Self {
+        self.peer = Some(Box::new(peer));
+        self
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Ok,
+    Maintenance,
+}
+
+/// Response of the health endpoint
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HealthResponse {
+    pub status: HealthStatus,
+    /// Maintenance message, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Whether load balancers should route requests to the instance, see `api::readiness` module
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReadinessState {
+    /// Within `warmup_sec` of the start
+    Starting,
+    Ready,
+    /// Draining before a shutdown, requests are still served
+    LameDuck,
+    /// At its capacity, new wait points are rejected
+    Unhealthy,
+}
+
+impl ReadinessState {
+    pub const ALL: [ReadinessState; 4] = [
+        ReadinessState::Starting,
+        ReadinessState::Ready,
+        ReadinessState::LameDuck,
+        ReadinessState::Unhealthy,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReadinessState::Starting => "starting",
+            ReadinessState::Ready => "ready",
+            ReadinessState::LameDuck => "lame-duck",
+            ReadinessState::Unhealthy => "unhealthy",
+        }
+    }
+}
+
+/// Response of the readiness endpoint, sent with 200 when `ready` & 503 otherwise
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadinessResponse {
+    pub state: ReadinessState,
+    /// Why the instance isn't ready, if it isn't
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Every outcome of a sync request as a client sees it, parsed from the HTTP status & body with
+/// `ApiResponse::parse`, so clients match on variants instead of status strings
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncOutcome {
+    /// 200, both parties met. `pair_id` is missing for servers older than it
+    Matched {
+        message: String,
+        pair_id: Option<String>,
+    },
+    /// 202, accepted for later processing (e.g. the dev mock peer)
+    Accepted { message: String },
+    /// 408, the peer didn't arrive within `timeout_duration_sec`
+    TimedOut {
+        message: String,
+        timeout_duration_sec: u64,
+    },
+    /// 409, the wait point already has 2 parties. `details` are missing for servers older than them
+    Conflict {
+        message: String,
+        details: Option<ConflictDetails>,
+    },
+    /// 410, the waiting party timed out shortly before, there's no point waiting for it. `details`
+    /// are missing for servers older than them
+    PeerTimedOut {
+        message: String,
+        details: Option<PeerTimeout>,
+    },
+    /// 503, no capacity or maintenance mode, retry later
+    Unavailable { message: String },
+    /// What the request would have done (`?dry_run=true`), whatever the status
+    DryRun {
+        message: String,
+        outcome: DryRunOutcome,
+    },
+    /// 400, the request was malformed
+    BadRequest { message: String },
+    /// Any other error status, e.g. 404 for an unknown route or 401 from the admin API
+    Error { status: u16, message: String },
+}
+
+/// Why a response couldn't be parsed into a `SyncOutcome`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// Not an `ApiResponse` body, e.g. from a proxy in between
+    InvalidBody(String),
+    /// The body's `status` contradicts the HTTP status
+    Mismatch { status: u16, body: ResponseStatus },
+    /// A timeout response without `timeout_duration_sec`
+    MissingTimeout,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::InvalidBody(e) => write!(f, "Invalid response body: {}", e),
+            ParseError::Mismatch { status, body } => {
+                write!(f, "HTTP status {} doesn't match body status {:?}", status, body)
+            }
+            ParseError::MissingTimeout => f.write_str("Timeout response without timeout_duration_sec"),
+        }
+    }
+}
+
+impl core::error::Error for ParseError {}
+
+impl ApiResponse {
+    /// Parses a sync response from its HTTP status & body
+    ///
+    /// # Returns
+    /// * `Ok(SyncOutcome)` - The outcome
+    /// * `Err(ParseError)` - The body isn't an `ApiResponse` or contradicts `status`
+    pub fn parse(status: u16, body: &str) -> Result<SyncOutcome, ParseError> {
+        let response: ApiResponse =
+            serde_json::from_str(body).map_err(|e| ParseError::InvalidBody(format!("{}", e)))?;
+        response.into_outcome(status)
+    }
+
+    /// Interprets the response as sent with HTTP `status`
+    pub fn into_outcome(self, status: u16) -> Result<SyncOutcome, ParseError> {
+        let message = String::from(self.message());
+        if let Some(outcome) = self.dry_run {
+            return Ok(SyncOutcome::DryRun { message, outcome });
+        }
+        match (status, self.status) {
+            (status::MATCHED, ResponseStatus::Success) => Ok(SyncOutcome::Matched {
+                message,
+                pair_id: self.pair_id.map(String::from),
+            }),
+            (202, ResponseStatus::Success) => Ok(SyncOutcome::Accepted { message }),
+            (400..=599, ResponseStatus::Timeout) => Ok(SyncOutcome::TimedOut {
+                message,
+                timeout_duration_sec: self
+                    .timeout_duration_sec
+                    .ok_or(ParseError::MissingTimeout)?,
+            }),
+            (status::CONFLICT, ResponseStatus::Error) => Ok(SyncOutcome::Conflict {
+                message,
+                details: self.conflict_details().cloned(),
+            }),
+            // With another status, only recognizable by the details
+            (400..=599, ResponseStatus::Error) if self.conflict_details().is_some() => {
+                Ok(SyncOutcome::Conflict {
+                    message,
+                    details: self.conflict_details().cloned(),
+                })
+            }
+            (status::PEER_TIMED_OUT, ResponseStatus::Error) => Ok(SyncOutcome::PeerTimedOut {
+                message,
+                details: self.peer_timeout().cloned(),
+            }),
+            (status::UNAVAILABLE, ResponseStatus::Error) => {
+                Ok(SyncOutcome::Unavailable { message })
+            }
+            (status::BAD_REQUEST, ResponseStatus::Error) => Ok(SyncOutcome::BadRequest { message }),
+            (400..=599, ResponseStatus::Error) => Ok(SyncOutcome::Error { status, message }),
+            (status, body) => Err(ParseError::Mismatch { status, body }),
+        }
+    }
+}
+
+/// Same as `ApiResponse::parse`, for bodies already parsed as JSON
+impl TryFrom<(u16, serde_json::Value)> for SyncOutcome {
+    type Error = ParseError;
+
+    fn try_from((status, body): (u16, serde_json::Value)) -> Result<Self, ParseError> {
+        let response: ApiResponse = serde_json::from_value(body)
+            .map_err(|e| ParseError::InvalidBody(format!("{}", e)))?;
+        response.into_outcome(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::{
+        status, wait_path, ApiResponse, ConflictDetails, DryRunOutcome, ParseError, PeerTimeout,
+        ResponseStatus, SyncOutcome,
+    };
+    use serde_json::json;
+    use core::time::Duration;
+
+    #[test]
+    fn test_wait_path_encodes_the_id() {
+        assert_eq!(wait_path("deploy/eu"), "/wait-for-second-party/deploy/eu");
+        assert_eq!(
+            wait_path("shard 1?a#b~"),
+            "/wait-for-second-party/shard%201%3Fa%23b~"
+        );
+        assert_eq!(
+            wait_path("audit/app.log.2024 1~"),
+            "/wait-for-second-party/audit/app.log.2024%201~"
+        );
+    }
+
+    #[test]
+    fn test_message_is_prefixed_with_escaped_id() {
+        let response = ApiResponse::timeout(Duration::from_secs(10), "a\"b");
+        assert_eq!(
+            serde_json::to_value(&response).unwrap(),
+            json!({
+                "status": "timeout",
+                "message": "[a\"b] Request timed out",
+                "timeout_duration_sec": 10
+            })
+        );
+
+        let response = ApiResponse::error("Service temporarily unavailable");
+        assert_eq!(
+            serde_json::to_string(&response).unwrap(),
+            r#"{"status":"error","message":"Service temporarily unavailable"}"#
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let responses = [
+            ApiResponse::success("Welcome! (first party)", "123"),
+            ApiResponse::success("Welcome! (second party)", "123")
+                .with_delivered(true)
+                .with_pair_id("6f1c1a52-8a5e-4b0e-9f4a-3d2e1c0b9a87"),
+            ApiResponse::timeout(Duration::from_secs(10), "123"),
+            ApiResponse::dry_run(DryRunOutcome::Conflict, "123"),
+            ApiResponse::peer_recently_timed_out(
+                PeerTimeout {
+                    timed_out_at: "2024-12-28T06:41:51.123Z".into(),
+                },
+                "123",
+            ),
+            ApiResponse::error("Not Found"),
+        ];
+        for response in responses {
+            let json = serde_json::to_string(&response).unwrap();
+            let parsed: ApiResponse = serde_json::from_str(&json).unwrap();
+            assert_eq!(parsed, response);
+            assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+        }
+    }
+
+    #[test]
+    fn test_unknown_fields_are_ignored() {
+        let parsed: ApiResponse =
+            serde_json::from_str(r#"{"status":"success","message":"[1] Hi","added_later":1}"#)
+                .unwrap();
+        assert_eq!(parsed.status(), ResponseStatus::Success);
+        assert_eq!(parsed.message(), "[1] Hi");
+    }
+
+    /// Every response the server builds, with its status, parses into the expected outcome
+    #[test]
+    fn test_parse_server_responses() {
+        let timeout = Duration::from_secs(10);
+        let conflict = ConflictDetails {
+            parties: 2,
+            created_at: "2024-12-28T06:41:51.123Z".into(),
+            generation: 17,
+            retry_after_sec: 1,
+        };
+        let peer_timeout = PeerTimeout {
+            timed_out_at: "2024-12-28T06:41:51.123Z".into(),
+        };
+        let cases = [
+            (
+                status::MATCHED,
+                ApiResponse::success("Welcome! (first party)", "1").with_pair_id("p"),
+                SyncOutcome::Matched {
+                    message: "[1] Welcome! (first party)".into(),
+                    pair_id: Some("p".into()),
+                },
+            ),
+            (
+                202,
+                ApiResponse::success("Mock peer joins in 1000 ms", "1"),
+                SyncOutcome::Accepted {
+                    message: "[1] Mock peer joins in 1000 ms".into(),
+                },
+            ),
+            (
+                status::TIMED_OUT,
+                ApiResponse::timeout(timeout, "1"),
+                SyncOutcome::TimedOut {
+                    message: "[1] Request timed out".into(),
+                    timeout_duration_sec: 10,
+                },
+            ),
+            (
+                status::CONFLICT,
+                ApiResponse::error("Only 2 parties allowed at a time"),
+                SyncOutcome::Conflict {
+                    message: "Only 2 parties allowed at a time".into(),
+                    details: None,
+                },
+            ),
+            (
+                status::CONFLICT,
+                ApiResponse::conflict(conflict.clone()),
+                SyncOutcome::Conflict {
+                    message: "Only 2 parties allowed at a time".into(),
+                    details: Some(conflict.clone()),
+                },
+            ),
+            // Remapped by the server's `status_codes`
+            (
+                504,
+                ApiResponse::timeout(timeout, "1"),
+                SyncOutcome::TimedOut {
+                    message: "[1] Request timed out".into(),
+                    timeout_duration_sec: 10,
+                },
+            ),
+            (
+                423,
+                ApiResponse::conflict(conflict.clone()),
+                SyncOutcome::Conflict {
+                    message: "Only 2 parties allowed at a time".into(),
+                    details: Some(conflict),
+                },
+            ),
+            (
+                status::PEER_TIMED_OUT,
+                ApiResponse::peer_recently_timed_out(peer_timeout.clone(), "1"),
+                SyncOutcome::PeerTimedOut {
+                    message: "[1] The waiting party recently timed out, nobody is waiting".into(),
+                    details: Some(peer_timeout),
+                },
+            ),
+            (
+                status::UNAVAILABLE,
+                ApiResponse::error("Migrating"),
+                SyncOutcome::Unavailable {
+                    message: "Migrating".into(),
+                },
+            ),
+            (
+                status::CONFLICT,
+                ApiResponse::dry_run(DryRunOutcome::Conflict, "1"),
+                SyncOutcome::DryRun {
+                    message: "[1] Dry run: would be rejected, only 2 parties allowed at a time"
+                        .into(),
+                    outcome: DryRunOutcome::Conflict,
+                },
+            ),
+            (
+                status::BAD_REQUEST,
+                ApiResponse::error("Invalid batch request"),
+                SyncOutcome::BadRequest {
+                    message: "Invalid batch request".into(),
+                },
+            ),
+            (
+                404,
+                ApiResponse::error("Not Found"),
+                SyncOutcome::Error {
+                    status: 404,
+                    message: "Not Found".into(),
+                },
+            ),
+        ];
+
+        for (status, response, expected) in cases {
+            let body = serde_json::to_string(&response).unwrap();
+            assert_eq!(ApiResponse::parse(status, &body), Ok(expected.clone()));
+            let json = serde_json::to_value(&response).unwrap();
+            assert_eq!(SyncOutcome::try_from((status, json)), Ok(expected));
+        }
+    }
+
+    #[test]
+    fn test_parse_rejects_inconsistent_responses() {
+        let body = serde_json::to_string(&ApiResponse::success("Welcome!", "1")).unwrap();
+        assert_eq!(
+            ApiResponse::parse(status::TIMED_OUT, &body),
+            Err(ParseError::Mismatch {
+                status: status::TIMED_OUT,
+                body: ResponseStatus::Success
+            })
+        );
+        assert_eq!(
+            ApiResponse::parse(status::TIMED_OUT, r#"{"status":"timeout","message":"x"}"#),
+            Err(ParseError::MissingTimeout)
+        );
+        assert!(matches!(
+            ApiResponse::parse(502, "<html>Bad Gateway</html>"),
+            Err(ParseError::InvalidBody(_))
+        ));
+    }
+}
@@ -0,0 +1,310 @@
+//! Record & replay of HTTP interactions, so client teams can build hermetic tests against realistic
+//! server behavior:
+//! - `serve --record <dir>` (`record_dir`) serves as usual & appends every request/response pair to
+//!   `<dir>/interactions.jsonl`
+//! - `serve --replay <dir>` (`replay_dir`) serves the recorded responses instead of the API: the
+//!   n-th request with a method & URI gets the n-th response recorded for them, starting over once
+//!   all were served. Requests nothing was recorded for get 404
+//!
+//! Replayed responses are sent right away, `duration_ms` only tells how long they took when
+//! recorded. Streamed responses (`/batch/wait`, `/observe`) aren't recorded, their bodies only end
+//! with the stream. Neither are request bodies, which replay doesn't match on.
+use crate::api::response::ApiResponse;
+use log::{debug, error, warn};
+use parking_lot::Mutex;
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::http::{Header, Method, Status};
+use rocket::response::status::Custom;
+use rocket::route::{Handler, Outcome};
+use rocket::serde::json::Json;
+use rocket::{Build, Data, Request, Response, Rocket, Route};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+
+/// File of the interactions within the record & replay directory
+pub const INTERACTIONS_FILE: &str = "interactions.jsonl";
+
+/// Methods served in replay mode
+const METHODS: [Method; 7] = [
+    Method::Get,
+    Method::Head,
+    Method::Post,
+    Method::Put,
+    Method::Patch,
+    Method::Delete,
+    Method::Options,
+];
+
+/// A request & its response, a line of `INTERACTIONS_FILE`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub method: String,
+    /// Path & query, e.g. `/wait-for-second-party/123?note=deploy`
+    pub uri: String,
+    pub status: u16,
+    /// Response headers in order, e.g. `["Content-Type", "application/json"]`
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    /// Time the server took to respond
+    pub duration_ms: u64,
+}
+
+/// Reads the interactions recorded in `dir`, in order
+pub fn load(dir: &Path) -> io::Result<Vec<Interaction>> {
+    let path = dir.join(INTERACTIONS_FILE);
+    let mut interactions = Vec::new();
+    for line in BufReader::new(File::open(&path)?).lines() {
+        match serde_json::from_str(&line?) {
+            Ok(interaction) => interactions.push(interaction),
+            Err(e) => warn!("Skipping invalid interaction in {}: {}", path.display(), e),
+        }
+    }
+    Ok(interactions)
+}
+
+/// When the request arrived, in its local cache
+struct Arrival(Instant);
+
+/// Fairing appending the interactions to `<dir>/INTERACTIONS_FILE`, attached by `build_rocket_with`
+/// with `record_dir`. Failing to open it fails the launch
+pub struct Recorder {
+    dir: PathBuf,
+    file: OnceLock<Mutex<File>>,
+}
+
+impl Recorder {
+    pub fn new(dir: &str) -> Self {
+        Self {
+            dir: PathBuf::from(dir),
+            file: OnceLock::new(),
+        }
+    }
+
+    fn open(&self) -> io::Result<File> {
+        fs::create_dir_all(&self.dir)?;
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(INTERACTIONS_FILE))
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Recorder {
+    fn info(&self) -> Info {
+        Info {
+            name: "Recorder",
+            kind: Kind::Ignite | Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        match self.open() {
+            Ok(file) => {
+                let _ = self.file.set(Mutex::new(file));
+                warn!("Recording interactions to {}", self.dir.display());
+                Ok(rocket)
+            }
+            Err(e) => {
+                error!("Failed to open {}: {}", self.dir.display(), e);
+                Err(rocket)
+            }
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data<'_>) {
+        request.local_cache(|| Arrival(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some(file) = self.file.get() else {
+            return;
+        };
+        if response.body().preset_size().is_none() {
+            debug!("Not recording the streamed response of {}", request.uri());
+            return;
+        }
+        let body = match response.body_mut().to_bytes().await {
+            Ok(body) => body,
+            Err(e) => {
+                error!("Failed to read response body for recording: {}", e);
+                return;
+            }
+        };
+        response.set_sized_body(body.len(), Cursor::new(body.clone()));
+        let Ok(body) = String::from_utf8(body) else {
+            debug!("Not recording the binary response of {}", request.uri());
+            return;
+        };
+
+        let arrival = request.local_cache(|| Arrival(Instant::now()));
+        let interaction = Interaction {
+            method: request.method().as_str().to_owned(),
+            uri: request.uri().to_string(),
+            status: response.status().code,
+            headers: response
+                .headers()
+                .iter()
+                .map(|header| (header.name().to_string(), header.value().to_owned()))
+                .collect(),
+            body,
+            duration_ms: arrival.0.elapsed().as_millis() as u64,
+        };
+        let line = serde_json::to_string(&interaction).expect("serializable interaction");
+        if let Err(e) = writeln!(file.lock(), "{}", line) {
+            error!(
+                "Failed to record {} {}: {}",
+                interaction.method, interaction.uri, e
+            );
+        }
+    }
+}
+
+/// Recorded responses per method & URI, with the index of the next one to serve
+#[derive(Debug, Default)]
+struct Recorded {
+    by_request: HashMap<(String, String), (Vec<Interaction>, usize)>,
+}
+
+impl Recorded {
+    fn new(interactions: Vec<Interaction>) -> Self {
+        let mut recorded = Self::default();
+        for interaction in interactions {
+            let key = (interaction.method.clone(), interaction.uri.clone());
+            recorded
+                .by_request
+                .entry(key)
+                .or_default()
+                .0
+                .push(interaction);
+        }
+        recorded
+    }
+
+    /// The response to serve next for `method` & `uri`, see module docs
+    fn next(&mut self, method: &str, uri: &str) -> Option<Interaction> {
+        let (interactions, next) = self
+            .by_request
+            .get_mut(&(method.to_owned(), uri.to_owned()))?;
+        let interaction = interactions[*next].clone();
+        *next = (*next + 1) % interactions.len();
+        Some(interaction)
+    }
+}
+
+/// Handler of every request in replay mode
+#[derive(Clone)]
+struct ReplayHandler {
+    recorded: Arc<Mutex<Recorded>>,
+}
+
+#[rocket::async_trait]
+impl Handler for ReplayHandler {
+    async fn handle<'r>(&self, request: &'r Request<'_>, _data: Data<'r>) -> Outcome<'r> {
+        let method = request.method().as_str();
+        let uri = request.uri().to_string();
+        let Some(interaction) = self.recorded.lock().next(method, &uri) else {
+            let message = format!("Nothing recorded for {} {}", method, uri);
+            return Outcome::from(
+                request,
+                Custom(Status::NotFound, Json(ApiResponse::error(message))),
+            );
+        };
+        let mut response = Response::build();
+        response.status(Status::new(interaction.status));
+        for (name, value) in interaction.headers {
+            response.header_adjoin(Header::new(name, value));
+        }
+        response.sized_body(interaction.body.len(), Cursor::new(interaction.body));
+        Outcome::Success(response.finalize())
+    }
+}
+
+/// Fairing serving the interactions of `<dir>/INTERACTIONS_FILE` instead of the API, attached by
+/// `build_rocket_with` with `replay_dir`. Failing to read them fails the launch
+pub struct Replay {
+    dir: PathBuf,
+}
+
+impl Replay {
+    pub fn new(dir: &str) -> Self {
+        Self {
+            dir: PathBuf::from(dir),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Replay {
+    fn info(&self) -> Info {
+        Info {
+            name: "Replay",
+            kind: Kind::Ignite,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let interactions = match load(&self.dir) {
+            Ok(interactions) => interactions,
+            Err(e) => {
+                error!(
+                    "Failed to load interactions from {}: {}",
+                    self.dir.display(),
+                    e
+                );
+                return Err(rocket);
+            }
+        };
+        warn!(
+            "Replaying {} interactions from {}",
+            interactions.len(),
+            self.dir.display()
+        );
+        let handler = ReplayHandler {
+            recorded: Arc::new(Mutex::new(Recorded::new(interactions))),
+        };
+        let routes: Vec<_> = METHODS
+            .into_iter()
+            .map(|method| Route::new(method, "/<path..>", handler.clone()))
+            .collect();
+        Ok(rocket.mount("/", routes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::recording::{Interaction, Recorded};
+
+    fn interaction(uri: &str, status: u16) -> Interaction {
+        Interaction {
+            method: "POST".to_owned(),
+            uri: uri.to_owned(),
+            status,
+            headers: Vec::new(),
+            body: String::new(),
+            duration_ms: 0,
+        }
+    }
+
+    #[test]
+    fn test_replayed_in_recorded_order() {
+        let mut recorded = Recorded::new(vec![
+            interaction("/wait/1", 200),
+            interaction("/wait/2", 408),
+            interaction("/wait/1", 409),
+        ]);
+        let status = |recorded: &mut Recorded, uri| recorded.next("POST", uri).map(|i| i.status);
+        assert_eq!(status(&mut recorded, "/wait/1"), Some(200));
+        assert_eq!(status(&mut recorded, "/wait/1"), Some(409));
+        // Starting over
+        assert_eq!(status(&mut recorded, "/wait/1"), Some(200));
+        assert_eq!(status(&mut recorded, "/wait/2"), Some(408));
+        assert_eq!(status(&mut recorded, "/wait/3"), None);
+        assert_eq!(recorded.next("GET", "/wait/1"), None);
+    }
+}
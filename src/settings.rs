@@ -0,0 +1,858 @@
+#[cfg(feature = "amqp")]
+use crate::amqp::AmqpConfig;
+use crate::api::anomaly::AnomalyConfig;
+use crate::api::body_limits::BodyLimitsConfig;
+use crate::api::completions::CompletionsConfig;
+use crate::api::file_triggers::FileTriggersConfig;
+#[cfg(feature = "hooks")]
+use crate::api::hooks::HooksConfig;
+use crate::api::local_notify::LocalNotifyConfig;
+#[cfg(feature = "metrics")]
+use crate::api::metrics;
+use crate::api::modes::ModeRoutes;
+use crate::api::priorities::PriorityConfig;
+#[cfg(feature = "receipts")]
+use crate::api::receipts::ReceiptSigner;
+use crate::api::response::StatusCodes;
+use crate::api::shadow::ShadowConfig;
+use crate::api::shutdown::ShutdownReportConfig;
+use crate::api::templates::{TemplateConfig, Templates};
+#[cfg(feature = "archive")]
+use crate::archive::ArchiveConfig;
+use crate::background::BackgroundConfig;
+#[cfg(feature = "chaos")]
+use crate::chaos::ChaosConfig;
+#[cfg(feature = "cluster")]
+use crate::cluster::ClusterConfig;
+#[cfg(feature = "compression")]
+use crate::compression::CompressionConfig;
+use crate::listeners::{self, ListenerConfig};
+use crate::log_file::FileLogConfig;
+#[cfg(feature = "redis")]
+use crate::redis_stream::RedisStreamConfig;
+use crate::syslog::SyslogConfig;
+use config::builder::{ConfigBuilder, DefaultState};
+use config::{Config, ConfigError, Environment, File, FileFormat};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+#[cfg(feature = "metrics")]
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Base config file, used when no custom path is given
+const BASE_CONFIG_PATH: &str = "config.toml";
+
+/// Fields masked by `Settings::redacted`, wherever they are
+const SECRET_FIELDS: [&str; 7] = [
+    "admin_token",
+    "receipt_key",
+    "api_keys",
+    "secret",
+    "secret_access_key",
+    "password",
+    "webhook_url",
+];
+/// Lists whose entries also have their `url` masked, webhook URLs commonly embed tokens
+const WEBHOOK_LISTS: [&str; 2] = ["hooks", "digests"];
+
+/// Merged application configuration.
+///
+/// Sources are layered from lowest to highest precedence
+/// - built-in defaults
+/// - base file (`config.toml` or a custom TOML, YAML or JSON path)
+/// - profile file next to the base one (e.g. `config.prod.toml`), selected by `APP_PROFILE`
+/// - runtime config JSON file (`runtime_config_path`), written by the admin API
+/// - `APP_` prefix environment variables
+/// - `Overrides`, e.g. `serve` command-line flags
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Settings {
+    /// Active profile (e.g. dev, staging, prod), only settable via `APP_PROFILE`
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Timeout in seconds the first party waits for the second one
+    pub timeout: u64,
+    /// How long a notification stored by `POST /notify/<unique_id>?persistent=true` waits for a
+    /// party, in seconds
+    pub notification_ttl_sec: u64,
+    /// Time in seconds a request may take on top of `timeout` before it's cut with 504, so no
+    /// handler hangs indefinitely. See `api::deadline` module
+    pub request_deadline_sec: u64,
+    /// How long a seat reserved by `POST /reserve/<unique_id>` is held for its party, in seconds.
+    /// See `api::reservations` module
+    pub reservation_ttl_sec: u64,
+    /// Seconds after a waiting party timed out during which the next party arriving at its id is
+    /// told so (410) instead of waiting, see `api::late_arrivals` module. 0 disables it
+    #[serde(default)]
+    pub late_arrival_grace_sec: u64,
+    /// Maximum number of simultaneously open wait points, 0 means unlimited
+    #[serde(default)]
+    pub max_wait_points: usize,
+    /// Whether the parties of a pair get where their peer came from (redacted) with their match,
+    /// see `api::origins` module
+    #[serde(default)]
+    pub share_peer_origin: bool,
+    /// Maximum number of observers (`?observe=true`) per wait point, 0 disables observing
+    pub max_observers_per_point: usize,
+    /// Maximum number of simultaneous waits per `X-Api-Key`, 0 means unlimited. See `api::quotas`
+    /// module
+    #[serde(default)]
+    pub max_waits_per_key: usize,
+    /// Maximum number of simultaneous rendezvous requests per connection, 0 means unlimited. See
+    /// `api::connections` module
+    #[serde(default)]
+    pub max_requests_per_connection: usize,
+    /// Seconds idle keep-alive connections are kept open & interval of the HTTP/2 PINGs, 0 disables
+    /// keep-alive. Rocket's own configuration (`keep_alive`, 5) when missing. See `api::connections`
+    /// module
+    #[serde(default)]
+    pub keep_alive_sec: Option<u32>,
+    /// Port to listen on, Rocket's own configuration (`Rocket.toml`, `ROCKET_PORT`, 8000) when missing
+    #[serde(default)]
+    pub port: Option<u16>,
+    /// Addresses to listen on, each with its own TLS settings, instead of `port`. See `listeners`
+    /// module
+    #[serde(default)]
+    pub listeners: Vec<ListenerConfig>,
+    /// Approximate memory budget of the open wait points in bytes, 0 means unlimited.
+    /// New wait points are rejected (503) once it's exhausted, see `Stats::memory_bytes`
+    #[serde(default)]
+    pub max_memory_bytes: usize,
+    /// Seconds after launch during which `GET /ready` reports `starting`, see `api::readiness` module
+    #[serde(default)]
+    pub warmup_sec: u64,
+    /// Interval in milliseconds at which the counters of `/stats` & `/metrics` are refreshed, 0
+    /// computes them for every request. See `api::stats_cache` module
+    #[serde(default)]
+    pub stats_interval_ms: u64,
+    /// stdout & stderr (incl. logs) of a daemonized server (`serve --daemon`) are appended to it,
+    /// discarded when missing
+    #[serde(default)]
+    pub log_file: Option<String>,
+    /// Prefix of the metric names of `GET /metrics`, see `api::metrics` module
+    #[cfg(feature = "metrics")]
+    pub metric_prefix: String,
+    /// Labels added to every sample of `GET /metrics`, e.g. `{region = "eu", instance = "eu-1"}`
+    #[cfg(feature = "metrics")]
+    #[serde(default)]
+    pub metric_labels: BTreeMap<String, String>,
+    /// Thresholds of the `anomaly` log events, see `api::anomaly` module
+    #[serde(default)]
+    pub anomalies: AnomalyConfig,
+    /// JSON log file with rotation, see `log_file` module
+    #[serde(default)]
+    pub file_log: FileLogConfig,
+    /// Syslog output of the audit log, see `syslog` module
+    #[serde(default)]
+    pub audit_syslog: SyslogConfig,
+    /// Upload of the rotated log files to object storage, disabled when missing. See `archive`
+    /// module
+    #[cfg(feature = "archive")]
+    #[serde(default)]
+    pub archive: Option<ArchiveConfig>,
+    /// Retention of match records, see `api::completions` module
+    #[serde(default)]
+    pub completions: CompletionsConfig,
+    /// Threads of the background runtime, see `background` module
+    #[serde(default)]
+    pub background: BackgroundConfig,
+    /// Commands & webhooks run on match & timeout events, see `api::hooks` module
+    #[cfg(feature = "hooks")]
+    #[serde(default)]
+    pub on_event: HooksConfig,
+    /// Signal files & dbus signals on completions of some ids, see `api::local_notify` module
+    #[serde(default)]
+    pub local_notify: LocalNotifyConfig,
+    /// Parties joining when files appear, see `api::file_triggers` module
+    #[serde(default)]
+    pub file_triggers: FileTriggersConfig,
+    /// Mirroring of rendezvous requests to a shadow instance, see `api::shadow` module
+    #[serde(default)]
+    pub shadow: ShadowConfig,
+    /// Logging & webhook of the shutdown summary, see `api::shutdown` module
+    #[serde(default)]
+    pub shutdown_report: ShutdownReportConfig,
+    /// Bearer token required by the `/admin` API, which is disabled when missing
+    #[serde(default)]
+    pub admin_token: Option<String>,
+    /// JSON file where settings changed via the admin API are persisted (on request) & loaded from
+    #[serde(default)]
+    pub runtime_config_path: Option<String>,
+    /// Matching strategy per id pattern, see `api::modes` module
+    #[serde(default)]
+    pub modes: ModeRoutes,
+    /// Metadata schemas required per id pattern, see `api::templates` module
+    #[serde(default)]
+    pub templates: Vec<TemplateConfig>,
+    /// Statuses of timeouts, conflicts & busy rejections, see `api::response::StatusCodes`
+    #[serde(default)]
+    pub status_codes: StatusCodes,
+    /// Share of `max_wait_points` per priority class, see `api::priorities` module
+    #[serde(default)]
+    pub priorities: PriorityConfig,
+    /// Content type & size checks of request bodies, see `api::body_limits` module
+    #[serde(default)]
+    pub body_limits: BodyLimitsConfig,
+    /// Helpers for client development, see `DevSettings`
+    #[serde(default)]
+    pub dev: DevSettings,
+    /// Response compression, see `compression` module
+    #[cfg(feature = "compression")]
+    #[serde(default)]
+    pub compression: CompressionConfig,
+    /// Ed25519 secret key signing match receipts (32 bytes, hex), none are issued when missing.
+    /// See `api::receipts` module
+    #[cfg(feature = "receipts")]
+    #[serde(default)]
+    pub receipt_key: Option<String>,
+    /// Fault injection, see `chaos` module
+    #[cfg(feature = "chaos")]
+    #[serde(default)]
+    pub chaos: ChaosConfig,
+    /// Members & ownership of ids, see `cluster` module
+    #[cfg(feature = "cluster")]
+    #[serde(default)]
+    pub cluster: ClusterConfig,
+    /// Bridge of an AMQP queue into rendezvous, disabled when missing. See `amqp` module
+    #[cfg(feature = "amqp")]
+    #[serde(default)]
+    pub amqp: Option<AmqpConfig>,
+    /// Redis Stream the sync events are appended to, disabled when missing. See `redis_stream`
+    /// module
+    #[cfg(feature = "redis")]
+    #[serde(default)]
+    pub redis_stream: Option<RedisStreamConfig>,
+}
+
+/// `[dev]` config section, helpers for client development. Keep disabled in production
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DevSettings {
+    /// Mounts `POST /dev/auto-match/<id>`, which plays the second party for the given id
+    pub auto_match: bool,
+    /// Delay before the mock peer joins, unless given per request
+    pub auto_match_delay_ms: u64,
+    /// Directory the interactions are recorded to (`serve --record`), see `recording` module
+    pub record_dir: Option<String>,
+    /// Directory of recorded interactions served instead of the API (`serve --replay`), see
+    /// `recording` module
+    pub replay_dir: Option<String>,
+}
+
+impl Default for DevSettings {
+    fn default() -> Self {
+        Self {
+            auto_match: false,
+            auto_match_delay_ms: 1000,
+            record_dir: None,
+            replay_dir: None,
+        }
+    }
+}
+
+/// Values taking precedence over all other sources, e.g. from `serve` command-line flags.
+/// `None` fields leave the merged value as is
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    pub timeout: Option<u64>,
+    pub port: Option<u16>,
+    pub max_wait_points: Option<usize>,
+    pub record_dir: Option<String>,
+    pub replay_dir: Option<String>,
+}
+
+/// Settings subset which can be adjusted at runtime via the admin API
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RuntimeConfig {
+    pub timeout: u64,
+    pub max_wait_points: usize,
+    /// Log filter in effect (`RUST_LOG` syntax), may include per-module levels
+    pub log_level: String,
+}
+
+/// Partial update of `RuntimeConfig`. Unknown fields are rejected, so that other settings
+/// can't be mistaken as runtime-adjustable
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuntimeConfigPatch {
+    pub timeout: Option<u64>,
+    pub max_wait_points: Option<usize>,
+    /// A single level for all modules, use `PUT /admin/log-level` for per-module levels
+    pub log_level: Option<String>,
+}
+
+impl Settings {
+    // Currently hardcoded values, but could be configurable from outside.
+    pub const MIN_TIMEOUT: u64 = 5;
+    pub const MAX_TIMEOUT: u64 = 300;
+    pub const DEFAULT_TIMEOUT: u64 = 10;
+    pub const DEFAULT_NOTIFICATION_TTL_SEC: u64 = 60;
+    pub const DEFAULT_REQUEST_DEADLINE_SEC: u64 = 30;
+    pub const DEFAULT_RESERVATION_TTL_SEC: u64 = 30;
+    pub const DEFAULT_MAX_OBSERVERS_PER_POINT: u64 = 8;
+    pub const DEFAULT_METRIC_PREFIX: &'static str = "sync_point_";
+
+    /// Loads and validates settings from all layered sources.
+    ///
+    /// # Arguments
+    /// * `config_path` - Optional path to the base config file, its format is detected by extension
+    ///   (`.toml`, `.yaml`/`.yml`, `.json`). When missing, `config.toml` is used if it exists.
+    ///
+    /// # Returns
+    /// * `Ok(Settings)` - Merged and validated settings
+    /// * `Err(ConfigError)` - If a source cannot be read or the merged result is invalid
+    pub fn load(config_path: Option<&str>) -> Result<Self, ConfigError> {
+        Self::load_with(config_path, &Overrides::default())
+    }
+
+    /// Same as `load`, with `overrides` layered on top of all other sources
+    pub fn load_with(
+        config_path: Option<&str>,
+        overrides: &Overrides,
+    ) -> Result<Self, ConfigError> {
+        let profile = Self::profile_from_env()?;
+        let settings = Self::build(config_path, &profile, None, overrides)?;
+
+        // Runtime overrides location is only known once the other sources are merged
+        let settings = match settings.runtime_config_path.clone() {
+            Some(path) => Self::build(config_path, &profile, Some(&path), overrides)?,
+            None => settings,
+        };
+
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Merges all sources in precedence order (without validation)
+    fn build(
+        config_path: Option<&str>,
+        profile: &Option<String>,
+        runtime_config_path: Option<&str>,
+        overrides: &Overrides,
+    ) -> Result<Self, ConfigError> {
+        let base_path = config_path.unwrap_or(BASE_CONFIG_PATH);
+        let format = Self::file_format(base_path)?;
+
+        let mut builder = Self::defaults()?
+            .add_source(File::new(base_path, format).required(config_path.is_some()));
+
+        if let Some(profile) = &profile {
+            let profile_path = Self::profile_path(base_path, profile);
+            debug!(
+                "Using profile '{}' from {}",
+                profile,
+                profile_path.display()
+            );
+            // Selecting a profile without its file is most likely a deployment mistake
+            builder = builder.add_source(File::from(profile_path).format(format).required(true));
+        }
+
+        if let Some(path) = runtime_config_path {
+            // Doesn't exist until something was persisted via the admin API
+            builder = builder.add_source(File::new(path, FileFormat::Json).required(false));
+        }
+
+        builder
+            // e.g. APP_TIMEOUT=30, check relevant `test_app_env_timeout` test in `app.rs`
+            .add_source(Environment::with_prefix("APP"))
+            // The profile decides which files are read, so it must not be redefined by them
+            .set_override_option("profile", profile.clone())?
+            .set_override_option("timeout", overrides.timeout)?
+            .set_override_option("port", overrides.port)?
+            .set_override_option(
+                "max_wait_points",
+                overrides.max_wait_points.map(|max| max as u64),
+            )?
+            .set_override_option("dev.record_dir", overrides.record_dir.clone())?
+            .set_override_option("dev.replay_dir", overrides.replay_dir.clone())?
+            .build()?
+            .try_deserialize()
+    }
+
+    /// Same as `load`, from the content of a single config file (no profile, runtime overrides or
+    /// env vars), e.g. to check a config held in memory
+    pub fn parse(content: &str, format: FileFormat) -> Result<Self, ConfigError> {
+        let settings: Self = Self::defaults()?
+            .add_source(File::from_str(content, format))
+            .build()?
+            .try_deserialize()?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Defaults of the settings without `#[serde(default)]`, the lowest of the sources
+    fn defaults() -> Result<ConfigBuilder<DefaultState>, ConfigError> {
+        let builder = Config::builder()
+            .set_default("timeout", Self::DEFAULT_TIMEOUT)?
+            .set_default("notification_ttl_sec", Self::DEFAULT_NOTIFICATION_TTL_SEC)?
+            .set_default("request_deadline_sec", Self::DEFAULT_REQUEST_DEADLINE_SEC)?
+            .set_default("reservation_ttl_sec", Self::DEFAULT_RESERVATION_TTL_SEC)?
+            .set_default(
+                "max_observers_per_point",
+                Self::DEFAULT_MAX_OBSERVERS_PER_POINT,
+            )?;
+        #[cfg(feature = "metrics")]
+        let builder = builder.set_default("metric_prefix", Self::DEFAULT_METRIC_PREFIX)?;
+        Ok(builder)
+    }
+
+    /// Validates the merged settings.
+    ///
+    /// # Returns
+    /// * `Ok(())` - If all values are within acceptable bounds
+    /// * `Err(ConfigError)` - Describing the first invalid value
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        Self::validate_timeout(self.timeout)?;
+        if self.notification_ttl_sec == 0 {
+            return Err(ConfigError::Message(
+                "notification_ttl_sec must be at least 1".to_owned(),
+            ));
+        }
+        if self.request_deadline_sec == 0 {
+            return Err(ConfigError::Message(
+                "request_deadline_sec must be at least 1".to_owned(),
+            ));
+        }
+        if self.reservation_ttl_sec == 0 {
+            return Err(ConfigError::Message(
+                "reservation_ttl_sec must be at least 1".to_owned(),
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.anomalies.slow_fraction) {
+            return Err(ConfigError::Message(
+                "anomalies.slow_fraction must be between 0 and 1".to_owned(),
+            ));
+        }
+        if self.dev.record_dir.is_some() && self.dev.replay_dir.is_some() {
+            return Err(ConfigError::Message(
+                "dev.record_dir & dev.replay_dir can't be used together".to_owned(),
+            ));
+        }
+        listeners::validate(&self.listeners).map_err(ConfigError::Message)?;
+        if self.port.is_some() && !self.listeners.is_empty() {
+            return Err(ConfigError::Message(
+                "port & listeners can't be used together, set the port of the listeners".to_owned(),
+            ));
+        }
+        self.modes.validate().map_err(ConfigError::Message)?;
+        Templates::new(&self.templates).map_err(ConfigError::Message)?;
+        self.status_codes.validate().map_err(ConfigError::Message)?;
+        self.priorities.validate().map_err(ConfigError::Message)?;
+        self.audit_syslog.validate().map_err(ConfigError::Message)?;
+        #[cfg(feature = "archive")]
+        if let Some(archive) = &self.archive {
+            archive.validate().map_err(ConfigError::Message)?;
+            if self.file_log.path.is_none() {
+                return Err(ConfigError::Message(
+                    "archive needs file_log.path, the log file it ships".to_owned(),
+                ));
+            }
+        }
+        self.background.validate().map_err(ConfigError::Message)?;
+        self.completions.validate().map_err(ConfigError::Message)?;
+        self.shutdown_report
+            .validate()
+            .map_err(ConfigError::Message)?;
+        #[cfg(feature = "hooks")]
+        self.on_event.validate().map_err(ConfigError::Message)?;
+        self.local_notify.validate().map_err(ConfigError::Message)?;
+        self.file_triggers
+            .validate()
+            .map_err(ConfigError::Message)?;
+        self.shadow.validate().map_err(ConfigError::Message)?;
+        #[cfg(feature = "metrics")]
+        metrics::validate(&self.metric_prefix, &self.metric_labels)
+            .map_err(ConfigError::Message)?;
+        #[cfg(feature = "receipts")]
+        if let Some(key) = &self.receipt_key {
+            ReceiptSigner::from_hex(key).map_err(ConfigError::Message)?;
+        }
+        #[cfg(feature = "chaos")]
+        self.chaos.validate()?;
+        #[cfg(feature = "cluster")]
+        self.cluster.validate()?;
+        #[cfg(feature = "amqp")]
+        if let Some(amqp) = &self.amqp {
+            amqp.validate().map_err(ConfigError::Message)?;
+        }
+        #[cfg(feature = "redis")]
+        if let Some(redis_stream) = &self.redis_stream {
+            redis_stream.validate().map_err(ConfigError::Message)?;
+        }
+        Ok(())
+    }
+
+    /// Applies a runtime patch, leaving settings untouched if the result would be invalid.
+    /// Log level is a process-wide setting, so applying `patch.log_level` is left to the caller
+    ///
+    /// # Arguments
+    /// * `patch` - Values to change, `None` fields are kept as is
+    ///
+    /// # Returns
+    /// * `Ok(())` - If the patch was applied
+    /// * `Err(ConfigError)` - If any patched value is invalid
+    pub fn apply(&mut self, patch: &RuntimeConfigPatch) -> Result<(), ConfigError> {
+        let mut patched = self.clone();
+        if let Some(timeout) = patch.timeout {
+            patched.timeout = timeout;
+        }
+        if let Some(max_wait_points) = patch.max_wait_points {
+            patched.max_wait_points = max_wait_points;
+        }
+        patched.validate()?;
+
+        *self = patched;
+        Ok(())
+    }
+
+    /// Writes the runtime-adjustable settings (except process-wide log level) to `runtime_config_path`,
+    /// so they survive restarts
+    ///
+    /// # Returns
+    /// * `Ok(())` - If the file was written
+    /// * `Err(ConfigError)` - If no path is configured or the file can't be written
+    pub fn persist_runtime_config(&self) -> Result<(), ConfigError> {
+        let path = self.runtime_config_path.as_ref().ok_or_else(|| {
+            ConfigError::Message("runtime_config_path is not configured".to_owned())
+        })?;
+
+        let content = json!({
+            "timeout": self.timeout,
+            "max_wait_points": self.max_wait_points,
+        });
+        // Written aside & renamed into place, so a crash never leaves a truncated file
+        let temp_path = format!("{}.tmp", path);
+        std::fs::write(&temp_path, content.to_string())
+            .and_then(|()| std::fs::rename(&temp_path, path))
+            .map_err(|e| ConfigError::Message(format!("Failed to write {}: {}", path, e)))
+    }
+
+    /// Returns the settings as JSON with secret-like fields masked, safe for printing & logging
+    pub fn redacted(&self) -> Value {
+        let mut value = serde_json::to_value(self).unwrap_or(Value::Null);
+        redact(&mut value, false);
+        value
+    }
+
+    /// Validates that the timeout value is within acceptable bounds.
+    ///
+    /// # Arguments
+    /// * `timeout` - The timeout value in seconds
+    ///
+    /// # Returns
+    /// * `Ok(())` - If timeout is within [MIN_TIMEOUT, MAX_TIMEOUT] range
+    /// * `Err(ConfigError)` - If timeout is outside the valid range
+    fn validate_timeout(timeout: u64) -> Result<(), ConfigError> {
+        if timeout < Self::MIN_TIMEOUT {
+            return Err(ConfigError::Message(format!(
+                "Timeout cannot be less than {} seconds",
+                Self::MIN_TIMEOUT
+            )));
+        }
+        if timeout > Self::MAX_TIMEOUT {
+            return Err(ConfigError::Message(format!(
+                "timeout cannot exceed {} seconds",
+                Self::MAX_TIMEOUT
+            )));
+        }
+        Ok(())
+    }
+
+    /// Reads the `APP_PROFILE` environment variable, rejecting names unusable in a file name
+    fn profile_from_env() -> Result<Option<String>, ConfigError> {
+        let profile = match std::env::var("APP_PROFILE") {
+            Ok(profile) if !profile.trim().is_empty() => profile.trim().to_owned(),
+            _ => return Ok(None),
+        };
+
+        let valid = profile
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(ConfigError::Message(format!(
+                "Invalid profile '{}': only letters, digits, '-' and '_' are allowed",
+                profile
+            )));
+        }
+        Ok(Some(profile))
+    }
+
+    /// Detects the config file format by its extension, TOML being the default when there is none
+    ///
+    /// # Returns
+    /// * `Ok(FileFormat)` - The format to parse the file with
+    /// * `Err(ConfigError)` - If the extension is not a supported one
+    fn file_format(path: &str) -> Result<FileFormat, ConfigError> {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase());
+
+        match extension.as_deref() {
+            None | Some("toml") => Ok(FileFormat::Toml),
+            Some("yaml") | Some("yml") => Ok(FileFormat::Yaml),
+            Some("json") => Ok(FileFormat::Json),
+            Some(other) => Err(ConfigError::Message(format!(
+                "Unsupported config file extension '.{}' (expected .toml, .yaml, .yml or .json)",
+                other
+            ))),
+        }
+    }
+
+    /// Builds the profile file path next to the base file keeping its extension,
+    /// e.g. `conf/config.yaml` -> `conf/config.prod.yaml`
+    fn profile_path(base_path: &str, profile: &str) -> PathBuf {
+        let base = Path::new(base_path);
+        let stem = base
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("config");
+        let extension = base
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("toml");
+        base.with_file_name(format!("{}.{}.{}", stem, profile, extension))
+    }
+}
+
+/// Recursively masks the values of `SECRET_FIELDS`, & of `url` when `webhooks` (in an entry of
+/// `WEBHOOK_LISTS`)
+fn redact(value: &mut Value, webhooks: bool) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                let secret = SECRET_FIELDS.contains(&key.as_str()) || (webhooks && key == "url");
+                if secret && !value.is_null() {
+                    *value = Value::String("***".to_owned());
+                } else {
+                    redact(value, WEBHOOK_LISTS.contains(&key.as_str()));
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| redact(item, webhooks)),
+        _ => {}
+    }
+}
+
+/// Profile selection relies on env vars, hence `#[serial]` like the tests in `app.rs`
+#[cfg(test)]
+mod tests {
+    use crate::settings::{Overrides, RuntimeConfigPatch, Settings};
+    use config::ConfigError;
+    use serde_json::json;
+    use serial_test::serial;
+    use tempfile::TempDir;
+
+    fn write_configs(files: &[(&str, &str)]) -> TempDir {
+        let temp_dir = TempDir::new().unwrap();
+        for (name, content) in files {
+            std::fs::write(temp_dir.path().join(name), content)
+                .expect("Unable to write config file");
+        }
+        temp_dir
+    }
+
+    #[test]
+    #[serial]
+    fn test_profile_overrides_base_file() -> Result<(), ConfigError> {
+        let temp_dir = write_configs(&[
+            ("config.toml", "timeout = 20"),
+            ("config.prod.toml", "timeout = 30"),
+        ]);
+        let base_path = temp_dir.path().join("config.toml");
+
+        std::env::set_var("APP_PROFILE", "prod");
+        let settings = Settings::load(base_path.to_str());
+        std::env::remove_var("APP_PROFILE"); // reset
+
+        let settings = settings?;
+        assert_eq!(settings.timeout, 30);
+        assert_eq!(settings.profile.as_deref(), Some("prod"));
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_env_overrides_profile_file() -> Result<(), ConfigError> {
+        let temp_dir = write_configs(&[
+            ("config.toml", "timeout = 20"),
+            ("config.dev.toml", "timeout = 30"),
+        ]);
+        let base_path = temp_dir.path().join("config.toml");
+
+        std::env::set_var("APP_PROFILE", "dev");
+        std::env::set_var("APP_TIMEOUT", "40");
+        let settings = Settings::load(base_path.to_str());
+        std::env::remove_var("APP_PROFILE");
+        std::env::remove_var("APP_TIMEOUT");
+
+        assert_eq!(settings?.timeout, 40);
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_overrides_take_precedence() -> Result<(), ConfigError> {
+        let temp_dir = write_configs(&[("config.toml", "timeout = 20\nmax_wait_points = 5")]);
+        let base_path = temp_dir.path().join("config.toml");
+        let overrides = Overrides {
+            timeout: Some(50),
+            port: Some(9000),
+            ..Default::default()
+        };
+
+        std::env::set_var("APP_TIMEOUT", "40");
+        let settings = Settings::load_with(base_path.to_str(), &overrides);
+        std::env::remove_var("APP_TIMEOUT");
+
+        let settings = settings?;
+        assert_eq!(settings.timeout, 50);
+        assert_eq!(settings.port, Some(9000));
+        assert_eq!(settings.max_wait_points, 5);
+
+        // Overrides are validated like any other source
+        let overrides = Overrides {
+            timeout: Some(1),
+            ..Default::default()
+        };
+        assert!(Settings::load_with(base_path.to_str(), &overrides).is_err());
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_missing_profile_file_is_an_error() {
+        let temp_dir = write_configs(&[("config.toml", "timeout = 20")]);
+        let base_path = temp_dir.path().join("config.toml");
+
+        std::env::set_var("APP_PROFILE", "staging");
+        let settings = Settings::load(base_path.to_str());
+        std::env::remove_var("APP_PROFILE");
+
+        assert!(settings.is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn test_invalid_profile_name_is_rejected() {
+        std::env::set_var("APP_PROFILE", "../prod");
+        let settings = Settings::load(None);
+        std::env::remove_var("APP_PROFILE");
+
+        assert!(settings.is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn test_out_of_range_timeout_is_rejected() {
+        let temp_dir = write_configs(&[("config.toml", "timeout = 1")]);
+        let base_path = temp_dir.path().join("config.toml");
+
+        assert!(Settings::load(base_path.to_str()).is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn test_out_of_range_slow_fraction_is_rejected() {
+        let temp_dir = write_configs(&[("config.toml", "[anomalies]\nslow_fraction = 1.5")]);
+        let base_path = temp_dir.path().join("config.toml");
+
+        assert!(Settings::load(base_path.to_str()).is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn test_yaml_and_json_files_are_detected() -> Result<(), ConfigError> {
+        let temp_dir = write_configs(&[
+            ("config.yaml", "timeout: 25"),
+            ("config.json", r#"{"timeout": 35}"#),
+        ]);
+
+        let yaml_path = temp_dir.path().join("config.yaml");
+        assert_eq!(Settings::load(yaml_path.to_str())?.timeout, 25);
+
+        let json_path = temp_dir.path().join("config.json");
+        assert_eq!(Settings::load(json_path.to_str())?.timeout, 35);
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_profile_file_keeps_base_format() -> Result<(), ConfigError> {
+        let temp_dir = write_configs(&[
+            ("config.yml", "timeout: 25"),
+            ("config.dev.yml", "timeout: 45"),
+        ]);
+        let base_path = temp_dir.path().join("config.yml");
+
+        std::env::set_var("APP_PROFILE", "dev");
+        let settings = Settings::load(base_path.to_str());
+        std::env::remove_var("APP_PROFILE");
+
+        assert_eq!(settings?.timeout, 45);
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_unsupported_extension_is_rejected() {
+        let temp_dir = write_configs(&[("config.ini", "timeout = 20")]);
+        let path = temp_dir.path().join("config.ini");
+
+        assert!(Settings::load(path.to_str()).is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn test_runtime_config_file_is_layered() -> Result<(), ConfigError> {
+        let temp_dir = write_configs(&[("runtime.json", r#"{"timeout": 50}"#)]);
+        let runtime_path = temp_dir.path().join("runtime.json");
+        let config_path = temp_dir.path().join("config.toml");
+        std::fs::write(
+            &config_path,
+            format!("timeout = 20\nruntime_config_path = {:?}", runtime_path),
+        )
+        .unwrap();
+
+        assert_eq!(Settings::load(config_path.to_str())?.timeout, 50);
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_apply_rejects_invalid_patch() -> Result<(), ConfigError> {
+        let mut settings = Settings::load(None)?;
+
+        let patch = RuntimeConfigPatch {
+            timeout: Some(1),
+            max_wait_points: Some(5),
+            ..Default::default()
+        };
+        assert!(settings.apply(&patch).is_err());
+        assert_eq!(settings.timeout, Settings::DEFAULT_TIMEOUT);
+        assert_eq!(settings.max_wait_points, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_redacted_masks_secret_fields() {
+        let mut value = json!({
+            "timeout": 10,
+            "max_waits_per_key": 3,
+            "admin_token": "abc",
+            "nested": {"password": "x", "port": 1, "url": "amqp://rabbitmq"},
+            "on_event": {"hooks": [{"url": "https://hooks.example/T0K3N", "events": ["matched"]}]}
+        });
+        super::redact(&mut value, false);
+        assert_eq!(
+            value,
+            json!({
+                "timeout": 10,
+                "max_waits_per_key": 3,
+                "admin_token": "***",
+                "nested": {"password": "***", "port": 1, "url": "amqp://rabbitmq"},
+                "on_event": {"hooks": [{"url": "***", "events": ["matched"]}]}
+            })
+        );
+    }
+}
@@ -0,0 +1,187 @@
+//! Runs virtual clients against the sync logic under paused (virtual) time, so that algorithm
+//! changes can be evaluated without a real load test. Timeouts elapse instantly, since the
+//! runtime auto-advances the clock whenever all clients are waiting.
+//!
+//! Only compiled with the `simulation` feature, see `examples/simulate.rs`. Clients use ids of the
+//! reserved namespace (see `api::ids` module), so the run doesn't show up in the stats.
+use crate::api::acl::Access;
+use crate::api::ids::INTERNAL_NAMESPACE;
+use crate::api::response::{Failure, StatusCodes};
+use crate::api::sync_service::SyncRequest;
+use crate::app::App;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rocket::http::Status;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Shape of the simulated traffic
+#[derive(Debug, Clone)]
+pub struct SimulationConfig {
+    /// Number of virtual clients, each making a single sync request
+    pub clients: usize,
+    /// Number of distinct ids clients pick from (uniformly)
+    pub ids: usize,
+    /// Clients arrive at random offsets within this window
+    pub arrival_window: Duration,
+    /// Seed for reproducible runs
+    pub seed: u64,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            clients: 10_000,
+            ids: 5_000,
+            arrival_window: Duration::from_secs(60),
+            seed: 42,
+        }
+    }
+}
+
+/// Outcomes & latencies (in virtual time) of a simulation run
+#[derive(Debug, Clone, Serialize)]
+pub struct SimulationReport {
+    pub clients: usize,
+    pub matched: usize,
+    pub timeouts: usize,
+    pub conflicts: usize,
+    /// Any other response, e.g. 503
+    pub errors: usize,
+    /// Share of clients which met their peer
+    pub match_rate: f64,
+    pub latency_p50_ms: u128,
+    pub latency_p90_ms: u128,
+    pub latency_p99_ms: u128,
+    pub latency_max_ms: u128,
+}
+
+/// Runs a simulation on a dedicated runtime with paused time, blocking until all clients finish.
+///
+/// # Arguments
+/// * `app` - Application state to run against, its settings (e.g. timeout) apply
+/// * `config` - Traffic shape
+///
+/// # Returns
+/// Report of the outcomes & latencies
+pub fn run(app: App, config: &SimulationConfig) -> SimulationReport {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .start_paused(true)
+        .build()
+        .expect("Failed to build simulation runtime")
+        .block_on(simulate(Arc::new(app), config))
+}
+
+async fn simulate(app: Arc<App>, config: &SimulationConfig) -> SimulationReport {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let window_ms = config.arrival_window.as_millis().max(1) as u64;
+    let ids = config.ids.max(1);
+
+    let handles: Vec<_> = (0..config.clients)
+        .map(|_| {
+            let app = app.clone();
+            let arrival = Duration::from_millis(rng.gen_range(0..window_ms));
+            let unique_id = format!("{}/sim-{}", INTERNAL_NAMESPACE, rng.gen_range(0..ids));
+
+            tokio::spawn(async move {
+                tokio::time::sleep(arrival).await;
+                let started = Instant::now();
+                let status = match app
+                    .sync_service
+                    .join(&unique_id, SyncRequest::default(), Access::default(), &app)
+                    .await
+                {
+                    Ok(response) => response.0,
+                    Err(retry_after) => retry_after.0 .0,
+                };
+                (status, started.elapsed())
+            })
+        })
+        .collect();
+
+    let mut results = Vec::with_capacity(handles.len());
+    for handle in handles {
+        results.push(handle.await.expect("Simulated client panicked"));
+    }
+    report(results, &app.sync_service.status_codes)
+}
+
+fn report(results: Vec<(Status, Duration)>, status_codes: &StatusCodes) -> SimulationReport {
+    let count = |status: Status| results.iter().filter(|(s, _)| *s == status).count();
+    let matched = count(Status::Ok);
+    let timeouts = count(status_codes.status(Failure::Timeout));
+    let conflicts = count(status_codes.status(Failure::Conflict));
+
+    let mut latencies: Vec<u128> = results.iter().map(|(_, l)| l.as_millis()).collect();
+    latencies.sort_unstable();
+    let percentile = |p: f64| -> u128 {
+        if latencies.is_empty() {
+            return 0;
+        }
+        let index = ((latencies.len() as f64 * p).ceil() as usize).clamp(1, latencies.len());
+        latencies[index - 1]
+    };
+
+    SimulationReport {
+        clients: results.len(),
+        matched,
+        timeouts,
+        conflicts,
+        errors: results.len() - matched - timeouts - conflicts,
+        match_rate: if results.is_empty() {
+            0.0
+        } else {
+            matched as f64 / results.len() as f64
+        },
+        latency_p50_ms: percentile(0.50),
+        latency_p90_ms: percentile(0.90),
+        latency_p99_ms: percentile(0.99),
+        latency_max_ms: latencies.last().copied().unwrap_or(0),
+    }
+}
+
+/// App reads env vars, hence `#[serial]` like the tests in `app.rs`
+#[cfg(test)]
+mod tests {
+    use crate::app::App;
+    use crate::simulation::{run, SimulationConfig};
+    use serial_test::serial;
+    use std::time::Duration;
+
+    #[test]
+    #[serial]
+    fn test_pair_on_single_id_matches() {
+        let config = SimulationConfig {
+            clients: 2,
+            ids: 1,
+            arrival_window: Duration::from_secs(1),
+            seed: 1,
+        };
+        let report = run(App::new(None).unwrap(), &config);
+
+        assert_eq!(report.matched, 2);
+        assert_eq!(report.match_rate, 1.0);
+    }
+
+    #[test]
+    #[serial]
+    fn test_lonely_clients_time_out_in_virtual_time() {
+        let config = SimulationConfig {
+            clients: 100,
+            ids: 100_000,
+            arrival_window: Duration::from_secs(1),
+            seed: 1,
+        };
+        let started = std::time::Instant::now();
+        let report = run(App::new(None).unwrap(), &config);
+
+        assert_eq!(report.clients, 100);
+        assert!(report.timeouts > 90);
+        assert!(report.latency_max_ms >= 10_000);
+        // Virtual 10s timeouts take no real time
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,456 @@
+//! RFC 5424 syslog output of the audit log (records with the `audit` target), for compliance
+//! tooling which only ingests syslog. Written next to the stderr output & the `[file_log]` file,
+//! enabled by the `[audit_syslog]` config section, see `logging::audit_to_syslog`.
+//!
+//! Messages go over UDP (one per datagram), TCP (octet counting framing, RFC 6587) or TLS (RFC 5425,
+//! the same framing). TLS needs the `tls` Cargo feature, the collector's certificate is checked
+//! against `ca_certs` or the Mozilla roots, & a client certificate is presented when configured.
+use crate::log_file::rfc3339;
+use log::{Level, Record};
+#[cfg(feature = "tls")]
+use rustls::pki_types::pem::PemObject;
+#[cfg(feature = "tls")]
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+#[cfg(feature = "tls")]
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::path::Path;
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// `[audit_syslog]` config section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyslogConfig {
+    /// Collector as `host:port`, syslog output is disabled when missing
+    pub address: Option<String>,
+    pub transport: Transport,
+    /// Facility code (0-23), 13 being "log audit"
+    pub facility: u8,
+    /// `APP-NAME` field of the messages
+    pub app_name: String,
+    /// PEM file of the CAs the certificate of a TLS collector is checked against, the Mozilla
+    /// roots when missing
+    pub ca_certs: Option<String>,
+    /// PEM files of the certificate & private key presented to a TLS collector, both or neither
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+}
+
+impl Default for SyslogConfig {
+    fn default() -> Self {
+        Self {
+            address: None,
+            transport: Transport::Udp,
+            facility: 13,
+            app_name: "sync-point".to_owned(),
+            ca_certs: None,
+            client_cert: None,
+            client_key: None,
+        }
+    }
+}
+
+impl SyslogConfig {
+    /// # Returns
+    /// * `Ok(())` - If the section is valid
+    /// * `Err(String)` - Describing the first invalid value
+    pub fn validate(&self) -> Result<(), String> {
+        if self.facility > 23 {
+            return Err("audit_syslog.facility must be between 0 and 23".to_owned());
+        }
+        // PRINTUSASCII, at most 48 characters
+        let valid_name = |c: char| c.is_ascii_graphic();
+        if self.app_name.is_empty()
+            || self.app_name.len() > 48
+            || !self.app_name.chars().all(valid_name)
+        {
+            return Err(
+                "audit_syslog.app_name must be 1 to 48 printable ASCII characters".to_owned(),
+            );
+        }
+        if self.transport == Transport::Tls && cfg!(not(feature = "tls")) {
+            return Err("audit_syslog tls transport needs the `tls` Cargo feature".to_owned());
+        }
+        if self.client_cert.is_some() != self.client_key.is_some() {
+            return Err("audit_syslog.client_cert & client_key must be set together".to_owned());
+        }
+        for path in [&self.ca_certs, &self.client_cert, &self.client_key]
+            .into_iter()
+            .flatten()
+        {
+            if !Path::new(path).is_file() {
+                return Err(format!("audit_syslog file {} doesn't exist", path));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    Udp,
+    Tcp,
+    Tls,
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    /// `None` after a failed write, reconnected on the next one
+    Stream(Option<Stream>),
+}
+
+/// Connection to a TCP or TLS collector
+enum Stream {
+    Tcp(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.write(buf),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.flush(),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Sends records as syslog messages to the configured collector
+pub struct SyslogWriter {
+    config: SyslogConfig,
+    address: String,
+    hostname: String,
+    connection: Connection,
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<ClientConfig>>,
+}
+
+impl SyslogWriter {
+    /// Connects to the collector (for UDP, only binds a local socket). For TLS, the handshake is
+    /// done here, so a certificate the collector rejects or presents fails right away
+    ///
+    /// # Returns
+    /// * `Ok(SyslogWriter)` - Ready to write
+    /// * `Err(io::Error)` - If `config.address` is missing or can't be connected to
+    pub fn connect(config: &SyslogConfig) -> io::Result<Self> {
+        let address = config.address.clone().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "audit_syslog.address is not set",
+            )
+        })?;
+        let mut writer = Self {
+            config: config.clone(),
+            address,
+            hostname: hostname(),
+            connection: Connection::Stream(None),
+            #[cfg(feature = "tls")]
+            tls: match config.transport {
+                Transport::Tls => Some(Arc::new(tls_config(config)?)),
+                _ => None,
+            },
+        };
+        writer.connection = match config.transport {
+            Transport::Udp => {
+                let collector = writer.address.to_socket_addrs()?.next().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, "audit_syslog.address not found")
+                })?;
+                let local: IpAddr = if collector.is_ipv4() {
+                    Ipv4Addr::UNSPECIFIED.into()
+                } else {
+                    Ipv6Addr::UNSPECIFIED.into()
+                };
+                let socket = UdpSocket::bind((local, 0))?;
+                socket.connect(collector)?;
+                Connection::Udp(socket)
+            }
+            Transport::Tcp | Transport::Tls => Connection::Stream(Some(writer.open_stream()?)),
+        };
+        Ok(writer)
+    }
+
+    fn open_stream(&self) -> io::Result<Stream> {
+        let stream = TcpStream::connect(&self.address)?;
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &self.tls {
+            let mut connection = ClientConnection::new(tls.clone(), server_name(&self.address)?)
+                .map_err(io::Error::other)?;
+            let mut stream = stream;
+            while connection.is_handshaking() {
+                connection.complete_io(&mut stream)?;
+            }
+            return Ok(Stream::Tls(Box::new(StreamOwned::new(connection, stream))));
+        }
+        match self.config.transport {
+            Transport::Tls => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "audit_syslog tls transport needs the `tls` Cargo feature",
+            )),
+            _ => Ok(Stream::Tcp(stream)),
+        }
+    }
+
+    /// Sends a record as one message, e.g.
+    /// `<110>1 2024-12-28T06:41:51.123Z host sync-point 4242 audit - Matched 2 parties ...`
+    pub fn write_record(&mut self, record: &Record) -> io::Result<()> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let message = self.format(record, &rfc3339(now.as_secs(), now.subsec_millis()));
+        let stream = match &mut self.connection {
+            Connection::Udp(socket) => return socket.send(message.as_bytes()).map(|_| ()),
+            Connection::Stream(stream) => stream,
+        };
+        // A collector restart drops the connection, retry once with a new one
+        let result = match stream {
+            Some(stream) => write_framed(stream, &message),
+            None => Err(io::ErrorKind::NotConnected.into()),
+        };
+        if result.is_ok() {
+            return result;
+        }
+        *stream = None;
+        let mut new_stream = self.open_stream()?;
+        write_framed(&mut new_stream, &message)?;
+        self.connection = Connection::Stream(Some(new_stream));
+        Ok(())
+    }
+
+    fn format(&self, record: &Record, timestamp: &str) -> String {
+        let severity = match record.level() {
+            Level::Error => 3,
+            Level::Warn => 4,
+            Level::Info => 6,
+            Level::Debug | Level::Trace => 7,
+        };
+        format!(
+            "<{}>1 {} {} {} {} {} - {}",
+            u16::from(self.config.facility) * 8 + severity,
+            timestamp,
+            self.hostname,
+            self.config.app_name,
+            std::process::id(),
+            record.target(),
+            record.args()
+        )
+    }
+}
+
+/// Octet counting framing, `<length> <message>`
+fn write_framed(stream: &mut Stream, message: &str) -> io::Result<()> {
+    let framed = format!("{} {}", message.len(), message);
+    stream.write_all(framed.as_bytes())?;
+    stream.flush()
+}
+
+/// Client config trusting `ca_certs` (or the Mozilla roots) & presenting the client certificate
+#[cfg(feature = "tls")]
+fn tls_config(config: &SyslogConfig) -> io::Result<ClientConfig> {
+    let invalid = |e: rustls::pki_types::pem::Error| io::Error::new(io::ErrorKind::InvalidData, e);
+    let mut roots = RootCertStore::empty();
+    match &config.ca_certs {
+        Some(path) => {
+            for cert in CertificateDer::pem_file_iter(path).map_err(invalid)? {
+                roots
+                    .add(cert.map_err(invalid)?)
+                    .map_err(io::Error::other)?;
+            }
+        }
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+    let builder =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(io::Error::other)?
+            .with_root_certificates(roots);
+    match (&config.client_cert, &config.client_key) {
+        (Some(cert), Some(key)) => {
+            let certs = CertificateDer::pem_file_iter(cert)
+                .map_err(invalid)?
+                .collect::<Result<_, _>>()
+                .map_err(invalid)?;
+            let key = PrivateKeyDer::from_pem_file(key).map_err(invalid)?;
+            builder
+                .with_client_auth_cert(certs, key)
+                .map_err(io::Error::other)
+        }
+        _ => Ok(builder.with_no_client_auth()),
+    }
+}
+
+/// Host of `host:port` (IPv6 addresses in brackets), which the collector's certificate must name
+#[cfg(feature = "tls")]
+fn server_name(address: &str) -> io::Result<ServerName<'static>> {
+    let host = address
+        .rsplit_once(':')
+        .map_or(address, |(host, _)| host)
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    ServerName::try_from(host.to_owned())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+/// Host name of the machine, the nil value `-` if it's unknown
+pub(crate) fn hostname() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .map(|name| name.trim().to_owned())
+        .filter(|name| !name.is_empty() && name.chars().all(|c| c.is_ascii_graphic()))
+        .unwrap_or_else(|| "-".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::syslog::{SyslogConfig, SyslogWriter, Transport};
+    use log::{Level, Record};
+    use std::io::Read;
+    use std::net::{TcpListener, UdpSocket};
+
+    fn audit_record(f: impl FnOnce(&Record)) {
+        f(&Record::builder()
+            .level(Level::Info)
+            .target("audit")
+            .args(format_args!("Matched 2 parties on unique_id: 123"))
+            .build());
+    }
+
+    fn config(address: String, transport: Transport) -> SyslogConfig {
+        SyslogConfig {
+            address: Some(address),
+            transport,
+            ..SyslogConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_udp_message() {
+        let collector = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = collector.local_addr().unwrap().to_string();
+        let mut writer = SyslogWriter::connect(&config(address, Transport::Udp)).unwrap();
+        audit_record(|record| writer.write_record(record).unwrap());
+
+        let mut buffer = [0; 1024];
+        let len = collector.recv(&mut buffer).unwrap();
+        let message = std::str::from_utf8(&buffer[..len]).unwrap();
+        // Facility 13 (log audit) * 8 + severity 6 (info)
+        assert!(message.starts_with("<110>1 "), "{}", message);
+        assert!(message.ends_with(&format!(
+            " sync-point {} audit - Matched 2 parties on unique_id: 123",
+            std::process::id()
+        )));
+    }
+
+    #[test]
+    fn test_tcp_octet_counting() {
+        let collector = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = collector.local_addr().unwrap().to_string();
+        let mut writer = SyslogWriter::connect(&config(address, Transport::Tcp)).unwrap();
+        audit_record(|record| writer.write_record(record).unwrap());
+        drop(writer);
+
+        let mut received = String::new();
+        let (mut stream, _) = collector.accept().unwrap();
+        stream.read_to_string(&mut received).unwrap();
+        let (len, message) = received.split_once(' ').unwrap();
+        assert_eq!(len.parse::<usize>().unwrap(), message.len());
+        assert!(message.starts_with("<110>1 "));
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn test_tls_octet_counting() {
+        use rustls::pki_types::pem::PemObject;
+        use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+        use rustls::{ServerConfig, ServerConnection, StreamOwned};
+        use std::sync::Arc;
+
+        let fixture =
+            |name: &str| format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+        let certs = CertificateDer::pem_file_iter(fixture("syslog-cert.pem"))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let key = PrivateKeyDer::from_pem_file(fixture("syslog-key.pem")).unwrap();
+        let server = Arc::new(
+            ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_no_client_auth()
+                .with_single_cert(certs, key)
+                .unwrap(),
+        );
+        let collector = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("localhost:{}", collector.local_addr().unwrap().port());
+        let received = std::thread::spawn(move || {
+            // The first connection is the one checked against the Mozilla roots
+            for _ in 0..2 {
+                let (stream, _) = collector.accept().unwrap();
+                let connection = ServerConnection::new(server.clone()).unwrap();
+                let mut stream = StreamOwned::new(connection, stream);
+                // The writer drops the connection without a close_notify
+                let mut received = String::new();
+                let _ = stream.read_to_string(&mut received);
+                if !received.is_empty() {
+                    return received;
+                }
+            }
+            String::new()
+        });
+
+        // Not signed by a public CA
+        assert!(SyslogWriter::connect(&config(address.clone(), Transport::Tls)).is_err());
+
+        let config = SyslogConfig {
+            ca_certs: Some(fixture("syslog-ca.pem")),
+            ..config(address, Transport::Tls)
+        };
+        let mut writer = SyslogWriter::connect(&config).unwrap();
+        audit_record(|record| writer.write_record(record).unwrap());
+        drop(writer);
+
+        let received = received.join().unwrap();
+        let (len, message) = received.split_once(' ').unwrap();
+        assert_eq!(len.parse::<usize>().unwrap(), message.len());
+        assert!(message.starts_with("<110>1 "));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(SyslogConfig::default().validate().is_ok());
+        let invalid = SyslogConfig {
+            facility: 24,
+            ..SyslogConfig::default()
+        };
+        assert!(invalid.validate().is_err());
+        let invalid = SyslogConfig {
+            app_name: "sync point".to_owned(),
+            ..SyslogConfig::default()
+        };
+        assert!(invalid.validate().is_err());
+        let invalid = SyslogConfig {
+            client_cert: Some("Cargo.toml".to_owned()),
+            ..SyslogConfig::default()
+        };
+        assert!(invalid.validate().is_err());
+        let invalid = SyslogConfig {
+            ca_certs: Some("missing.pem".to_owned()),
+            ..SyslogConfig::default()
+        };
+        assert!(invalid.validate().is_err());
+    }
+}
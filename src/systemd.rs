@@ -0,0 +1,98 @@
+//! systemd integration for `Type=notify` services. Only compiled with the `systemd` feature.
+//!
+//! Readiness (`READY=1`) is reported once Rocket has bound its socket, `STOPPING=1` on graceful
+//! shutdown, and watchdog pings are sent at half of `WatchdogSec=` while the server runs.
+//! All of it is a no-op unless started by systemd (`NOTIFY_SOCKET` is set).
+//!
+//! Socket activation (`LISTEN_FDS`) isn't implemented: Rocket 0.5 binds its listener itself & keeps
+//! serving on any other one private, so the server always binds `address`/`port` on its own.
+use log::{debug, warn};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Orbit, Rocket};
+use sd_notify::NotifyState;
+use std::time::Duration;
+
+/// Fairing notifying systemd of the server lifecycle
+pub struct Systemd;
+
+#[rocket::async_trait]
+impl Fairing for Systemd {
+    fn info(&self) -> Info {
+        Info {
+            name: "systemd notify",
+            kind: Kind::Liftoff | Kind::Shutdown,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        notify(&[NotifyState::Ready]);
+
+        let mut usec = 0;
+        if !sd_notify::watchdog_enabled(false, &mut usec) {
+            return;
+        }
+        // Pinging at half the deadline tolerates one late tick
+        let period = Duration::from_micros(usec) / 2;
+        debug!("systemd watchdog enabled, pinging every {:?}", period);
+        let mut shutdown = rocket.shutdown();
+        rocket::tokio::spawn(async move {
+            let mut interval = rocket::tokio::time::interval(period);
+            loop {
+                rocket::tokio::select! {
+                    _ = interval.tick() => notify(&[NotifyState::Watchdog]),
+                    _ = &mut shutdown => break,
+                }
+            }
+        });
+    }
+
+    async fn on_shutdown(&self, _rocket: &Rocket<Orbit>) {
+        notify(&[NotifyState::Stopping]);
+    }
+}
+
+fn notify(state: &[NotifyState]) {
+    if let Err(e) = sd_notify::notify(false, state) {
+        warn!("Failed to notify systemd: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::build_rocket_with;
+    use crate::App;
+    use rocket::local::asynchronous::Client;
+    use rocket::tokio::net::UnixDatagram;
+    use rocket::tokio::time::timeout;
+    use serial_test::serial;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[rocket::async_test]
+    #[serial]
+    async fn test_notifies_readiness_and_watchdog() {
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("notify.sock");
+        let socket = UnixDatagram::bind(&socket_path).unwrap();
+
+        std::env::set_var("NOTIFY_SOCKET", &socket_path);
+        std::env::set_var("WATCHDOG_USEC", "100000");
+        std::env::set_var("WATCHDOG_PID", std::process::id().to_string());
+        let client = Client::tracked(build_rocket_with(App::new(None).unwrap())).await;
+
+        let mut buffer = [0; 64];
+        let mut received = Vec::new();
+        for _ in 0..2 {
+            let len = timeout(Duration::from_secs(5), socket.recv(&mut buffer))
+                .await
+                .map_or(0, |len| len.unwrap_or(0));
+            received.push(String::from_utf8_lossy(&buffer[..len]).into_owned());
+        }
+        std::env::remove_var("NOTIFY_SOCKET");
+        std::env::remove_var("WATCHDOG_USEC");
+        std::env::remove_var("WATCHDOG_PID");
+
+        assert!(client.is_ok());
+        assert_eq!(received, vec!["READY=1\n", "WATCHDOG=1\n"]);
+    }
+}
@@ -0,0 +1,111 @@
+//! Fixtures for integration tests against the sync-point API, e.g. from downstream crates
+//! mounting it in their own Rocket instance. Only compiled with the `test-support` feature.
+use crate::app::App;
+use crate::protocol::SyncOutcome;
+use crate::{build_rocket, build_rocket_with};
+use rocket::http::Status;
+use rocket::local::asynchronous::{Client, LocalResponse};
+use rocket::{Build, Rocket};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tempfile::TempDir;
+use tokio::task::JoinHandle;
+
+/// Status & parsed JSON body of a sync request
+pub struct TestResponse {
+    pub status: Status,
+    pub json: Value,
+}
+
+impl TestResponse {
+    /// The response as a typed outcome, panics if it's not a valid sync response
+    pub fn outcome(&self) -> SyncOutcome {
+        SyncOutcome::try_from((self.status.code, self.json.clone())).expect("valid sync response")
+    }
+}
+
+/// Parses the response body as JSON, panics if it's not
+pub async fn get_response_json(response: LocalResponse<'_>) -> Value {
+    response
+        .into_json::<Value>()
+        .await
+        .expect("Failed to parse JSON")
+}
+
+/// Makes a sync request for `unique_id` & waits for the response
+pub async fn make_sync_request(client: &Client, unique_id: &str) -> TestResponse {
+    let endpoint = format!("/wait-for-second-party/{}", unique_id);
+    let response = client.post(endpoint).dispatch().await;
+    let status = response.status();
+    let json = get_response_json(response).await;
+    TestResponse { status, json }
+}
+
+/// Same as `make_sync_request`, but in the background, so that several parties can wait at once
+pub fn spawn_request(client: Arc<Client>, unique_id: String) -> JoinHandle<TestResponse> {
+    tokio::spawn(async move { make_sync_request(&client, unique_id.as_str()).await })
+}
+
+/// Client for the default app (same as the server, i.e. `config.toml` & `APP_` env vars)
+pub async fn get_client() -> Client {
+    get_client_for(build_rocket()).await
+}
+
+/// Client for a custom Rocket instance, e.g. with the sync-point API mounted next to other routes
+pub async fn get_client_for(rocket: Rocket<Build>) -> Client {
+    Client::tracked(rocket)
+        .await
+        .expect("valid rocket instance")
+}
+
+/// Client for an app configured from the given TOML content.
+/// The returned `TempDir` holds the config file, keep it alive as long as the client
+pub async fn get_client_with_config(config: &str) -> (Client, TempDir) {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("config.toml");
+    std::fs::write(&config_path, config).expect("Unable to write config file");
+
+    let app = App::new(config_path.to_str()).expect("valid config");
+    (get_client_for(build_rocket_with(app)).await, temp_dir)
+}
+
+/// Asserts a successful rendezvous response for the `first` or `second` party
+pub fn assert_success_response(response: &TestResponse, unique_id: &str, party_type: &str) {
+    assert_eq!(response.status, Status::Ok);
+
+    let mut expected = json!({
+        "status": "success",
+        "message": format!("[{}] Welcome! ({} party)", unique_id, party_type)
+    });
+    // The second party learns it woke the first one
+    if party_type == "second" {
+        expected["delivered"] = json!(true);
+    }
+    // Random, see `assert_same_match` to compare it between the parties
+    let pair_id = &response.json["pair_id"];
+    assert!(pair_id.is_string(), "pair_id missing");
+    expected["pair_id"] = pair_id.clone();
+    assert_eq!(response.json, expected);
+    assert!(matches!(response.outcome(), SyncOutcome::Matched { .. }));
+}
+
+/// Asserts that both responses are of the same match, i.e. they got the same `pair_id`
+pub fn assert_same_match(first: &TestResponse, second: &TestResponse) {
+    assert!(first.json["pair_id"].is_string(), "pair_id missing");
+    assert_eq!(first.json["pair_id"], second.json["pair_id"]);
+}
+
+/// Asserts a timeout response matching the app's timeout
+pub fn assert_timeout_response(response: &TestResponse, app: &App, unique_id: &str) {
+    assert_eq!(response.status, Status::RequestTimeout);
+
+    assert_eq!(
+        response.json,
+        json!({
+            "status": "timeout",
+            "message": format!("[{}] Request timed out", unique_id),
+            "timeout_duration_sec": app.timeout().as_secs()
+        })
+    );
+    assert!(matches!(response.outcome(), SyncOutcome::TimedOut { .. }));
+}
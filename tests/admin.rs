@@ -0,0 +1,545 @@
+#[cfg(test)]
+mod tests {
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::local::asynchronous::{Client, LocalResponse};
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use sync_point::api::metrics::METRICS;
+    use sync_point::app::App;
+    use sync_point::test_support::{
+        get_client, get_client_with_config, get_response_json, make_sync_request,
+    };
+
+    const TOKEN: &str = "s3cret";
+    const CONFIG: &str = "admin_token = \"s3cret\"";
+
+    fn auth(token: &str) -> Header<'static> {
+        Header::new("Authorization", format!("Bearer {}", token))
+    }
+
+    async fn patch_config<'c>(client: &'c Client, uri: &str, body: Value) -> LocalResponse<'c> {
+        client
+            .patch(uri.to_owned())
+            .header(auth(TOKEN))
+            .header(ContentType::JSON)
+            .body(body.to_string())
+            .dispatch()
+            .await
+    }
+
+    #[rocket::async_test]
+    async fn test_admin_disabled_without_token() {
+        let client = get_client().await;
+        let response = client.get("/admin/config").dispatch().await;
+
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(
+            get_response_json(response).await,
+            json!({"status": "error", "message": "Not Found"})
+        );
+    }
+
+    #[rocket::async_test]
+    async fn test_admin_requires_valid_token() {
+        let (client, _dir) = get_client_with_config(CONFIG).await;
+
+        let response = client.get("/admin/config").dispatch().await;
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let response = client
+            .get("/admin/config")
+            .header(auth("wrong"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    #[rocket::async_test]
+    async fn test_get_config() {
+        let (client, _dir) = get_client_with_config(&format!("{}\ntimeout = 20", CONFIG)).await;
+        let response = client
+            .get("/admin/config")
+            .header(auth(TOKEN))
+            .dispatch()
+            .await;
+
+        assert_eq!(response.status(), Status::Ok);
+        let json = get_response_json(response).await;
+        assert_eq!(json["timeout"], 20);
+        assert_eq!(json["max_wait_points"], 0);
+    }
+
+    #[rocket::async_test]
+    async fn test_patch_config_applies_changes() {
+        let (client, _dir) = get_client_with_config(CONFIG).await;
+        let response = patch_config(
+            &client,
+            "/admin/config",
+            json!({"timeout": 30, "max_wait_points": 100}),
+        )
+        .await;
+
+        assert_eq!(response.status(), Status::Ok);
+        let json = get_response_json(response).await;
+        assert_eq!(json["timeout"], 30);
+        assert_eq!(json["max_wait_points"], 100);
+
+        let app = client.rocket().state::<App>().expect("App not found");
+        assert_eq!(app.timeout(), Duration::from_secs(30));
+        assert_eq!(app.max_wait_points(), 100);
+    }
+
+    #[rocket::async_test]
+    async fn test_patch_config_rejects_invalid_values() {
+        let (client, _dir) = get_client_with_config(CONFIG).await;
+
+        let response = patch_config(&client, "/admin/config", json!({"timeout": 1000})).await;
+        assert_eq!(response.status(), Status::BadRequest);
+        assert_eq!(
+            get_response_json(response).await,
+            json!({"status": "error", "message": "timeout cannot exceed 300 seconds"})
+        );
+
+        // Not a runtime-adjustable setting
+        let response = patch_config(&client, "/admin/config", json!({"admin_token": "x"})).await;
+        assert_eq!(response.status(), Status::BadRequest);
+
+        let app = client.rocket().state::<App>().expect("App not found");
+        assert_eq!(app.timeout(), Duration::from_secs(10));
+    }
+
+    #[rocket::async_test]
+    async fn test_patch_config_persist() {
+        let (client, dir) = get_client_with_config(CONFIG).await;
+        let response = patch_config(
+            &client,
+            "/admin/config?persist=true",
+            json!({"timeout": 30}),
+        )
+        .await;
+        // Nowhere to persist to
+        assert_eq!(response.status(), Status::BadRequest);
+
+        let runtime_path = dir.path().join("runtime.json");
+        let config = format!("{}\nruntime_config_path = {:?}", CONFIG, runtime_path);
+        let (client, _dir) = get_client_with_config(&config).await;
+        let response = patch_config(
+            &client,
+            "/admin/config?persist=true",
+            json!({"timeout": 30}),
+        )
+        .await;
+        assert_eq!(response.status(), Status::Ok);
+
+        let persisted: Value =
+            serde_json::from_str(&std::fs::read_to_string(runtime_path).unwrap()).unwrap();
+        assert_eq!(persisted["timeout"], 30);
+
+        // The log filter comes from RUST_LOG on startup, it can't be persisted
+        let response = patch_config(
+            &client,
+            "/admin/config?persist=true",
+            json!({"timeout": 40, "log_level": "debug"}),
+        )
+        .await;
+        assert_eq!(response.status(), Status::BadRequest);
+        let app = client.rocket().state::<App>().expect("App not found");
+        assert_eq!(app.timeout(), Duration::from_secs(30));
+
+        // Failing to persist changes nothing
+        let runtime_path = dir.path().join("missing").join("runtime.json");
+        let config = format!("{}\nruntime_config_path = {:?}", CONFIG, runtime_path);
+        let (client, _dir) = get_client_with_config(&config).await;
+        let response = patch_config(
+            &client,
+            "/admin/config?persist=true",
+            json!({"timeout": 30}),
+        )
+        .await;
+        assert_eq!(response.status(), Status::BadRequest);
+        let app = client.rocket().state::<App>().expect("App not found");
+        assert_eq!(app.timeout(), Duration::from_secs(10));
+    }
+
+    #[rocket::async_test]
+    async fn test_patch_config_with_json_patch() {
+        let (client, _dir) = get_client_with_config(CONFIG).await;
+        let json_patch = |operations: Value| {
+            client
+                .patch("/admin/config")
+                .header(auth(TOKEN))
+                .header(ContentType::new("application", "json-patch+json"))
+                .body(operations.to_string())
+                .dispatch()
+        };
+
+        let response = json_patch(json!([
+            {"op": "test", "path": "/timeout", "value": 10},
+            {"op": "replace", "path": "/timeout", "value": 30},
+            {"op": "copy", "from": "/timeout", "path": "/max_wait_points"}
+        ]))
+        .await;
+        assert_eq!(response.status(), Status::Ok);
+        let json = get_response_json(response).await;
+        assert_eq!(json["timeout"], 30);
+        assert_eq!(json["max_wait_points"], 30);
+
+        for operations in [
+            // Failed test, the timeout changed
+            json!([
+                {"op": "test", "path": "/timeout", "value": 10},
+                {"op": "replace", "path": "/timeout", "value": 20}
+            ]),
+            json!([{"op": "add", "path": "/admin_token", "value": "x"}]),
+            json!([{"op": "remove", "path": "/timeout"}]),
+            json!([{"op": "replace", "path": "/timeout", "value": 1000}]),
+            json!({"timeout": 20}),
+        ] {
+            let response = json_patch(operations.clone()).await;
+            assert_eq!(response.status(), Status::BadRequest, "{}", operations);
+        }
+
+        let app = client.rocket().state::<App>().expect("App not found");
+        assert_eq!(app.timeout(), Duration::from_secs(30));
+    }
+
+    #[rocket::async_test]
+    async fn test_patch_config_preview() {
+        let (client, _dir) = get_client_with_config(CONFIG).await;
+        let response = patch_config(
+            &client,
+            "/admin/config?preview=true",
+            json!({"timeout": 30, "log_level": "debug"}),
+        )
+        .await;
+        assert_eq!(response.status(), Status::Ok);
+        let json = get_response_json(response).await;
+        assert_eq!(json["timeout"], 30);
+        assert_eq!(json["log_level"], "debug");
+
+        let response = patch_config(
+            &client,
+            "/admin/config?preview=true",
+            json!({"timeout": 1000}),
+        )
+        .await;
+        assert_eq!(
+            get_response_json(response).await,
+            json!({"status": "error", "message": "timeout cannot exceed 300 seconds"})
+        );
+        let response = patch_config(
+            &client,
+            "/admin/config?preview=true&persist=true",
+            json!({"timeout": 30}),
+        )
+        .await;
+        assert_eq!(response.status(), Status::BadRequest);
+
+        // Nothing applied
+        let app = client.rocket().state::<App>().expect("App not found");
+        assert_eq!(app.timeout(), Duration::from_secs(10));
+        assert_ne!(app.runtime_config().log_level, "debug");
+    }
+
+    #[rocket::async_test]
+    async fn test_put_log_level() {
+        let (client, _dir) = get_client_with_config(CONFIG).await;
+        let response = client
+            .put("/admin/log-level")
+            .header(auth(TOKEN))
+            .header(ContentType::JSON)
+            .body(json!({"level": "info", "modules": {"sync_point::api": "debug"}}).to_string())
+            .dispatch()
+            .await;
+
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            get_response_json(response).await,
+            json!({"filter": "info,sync_point::api=debug"})
+        );
+        assert_eq!(log::max_level(), log::LevelFilter::Debug);
+
+        let response = client
+            .put("/admin/log-level")
+            .header(auth(TOKEN))
+            .header(ContentType::JSON)
+            .body(json!({"level": "loud"}).to_string())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[rocket::async_test]
+    async fn test_maintenance_mode() {
+        let (client, _dir) = get_client_with_config(CONFIG).await;
+        let put_maintenance = |body: Value| {
+            client
+                .put("/admin/maintenance")
+                .header(auth(TOKEN))
+                .header(ContentType::JSON)
+                .body(body.to_string())
+                .dispatch()
+        };
+
+        let response = put_maintenance(
+            json!({"enabled": true, "message": "Migrating", "retry_after_sec": 30}),
+        )
+        .await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            get_response_json(response).await,
+            json!({"enabled": true, "message": "Migrating", "retry_after_sec": 30})
+        );
+
+        let response = client.post("/wait-for-second-party/123").dispatch().await;
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+        assert_eq!(response.headers().get_one("Retry-After"), Some("30"));
+        assert_eq!(
+            get_response_json(response).await,
+            json!({"status": "error", "message": "Migrating"})
+        );
+
+        let response = client.get("/health").dispatch().await;
+        assert_eq!(
+            get_response_json(response).await,
+            json!({"status": "maintenance", "message": "Migrating"})
+        );
+
+        let response = put_maintenance(json!({"enabled": false})).await;
+        assert_eq!(get_response_json(response).await, json!({"enabled": false}));
+
+        let response = make_sync_request(&client, "123").await;
+        assert_eq!(response.status, Status::RequestTimeout);
+    }
+
+    #[rocket::async_test]
+    async fn test_lame_duck() {
+        let (client, _dir) = get_client_with_config(CONFIG).await;
+        let put_lame_duck = |body: Value| {
+            client
+                .put("/admin/lame-duck")
+                .header(auth(TOKEN))
+                .header(ContentType::JSON)
+                .body(body.to_string())
+                .dispatch()
+        };
+
+        let response = put_lame_duck(json!({"enabled": true})).await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(get_response_json(response).await["state"], "lame-duck");
+        let response = client.get("/ready").dispatch().await;
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+        assert_eq!(get_response_json(response).await["state"], "lame-duck");
+        // Still served while draining
+        let response = client.post("/wait/lame-duck-1?ttl=1").dispatch().await;
+        assert_eq!(response.status(), Status::RequestTimeout);
+
+        let response = put_lame_duck(json!({"enabled": false})).await;
+        assert_eq!(get_response_json(response).await, json!({"state": "ready"}));
+        let response = put_lame_duck(json!({"on": true})).await;
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[rocket::async_test]
+    async fn test_list_wait_points() {
+        let (client, _dir) = get_client_with_config(CONFIG).await;
+        let app = client.rocket().state::<App>().expect("App not found");
+        for unique_id in ["a", "b", "c"] {
+            app.sync_service
+                .get_or_create_point(unique_id, 0)
+                .await
+                .expect("wait point");
+        }
+        let list = |uri: String| client.get(uri).header(auth(TOKEN)).dispatch();
+
+        let response = list("/admin/wait-points?limit=2".to_owned()).await;
+        assert_eq!(response.status(), Status::Ok);
+        let json = get_response_json(response).await;
+        let ids: Vec<_> = json["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["unique_id"].clone())
+            .collect();
+        assert_eq!(ids, vec!["a", "b"]);
+        let cursor = json["next_cursor"].as_str().expect("next_cursor").to_owned();
+
+        // Changes after the first page neither repeat nor skip items
+        app.sync_service
+            .get_or_create_point("d", 0)
+            .await
+            .expect("wait point");
+        app.sync_service.wait_points.write().remove("a");
+
+        let response = list(format!("/admin/wait-points?limit=2&cursor={}", cursor)).await;
+        let json = get_response_json(response).await;
+        assert_eq!(
+            json,
+            json!({
+                "items": [{"unique_id": "c", "parties": 0, "waiting": false}],
+                "next_cursor": null
+            })
+        );
+
+        let response = list("/admin/wait-points?cursor=bogus".to_owned()).await;
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[rocket::async_test]
+    async fn test_export_wait_points_as_ndjson() {
+        let (client, _dir) = get_client_with_config(CONFIG).await;
+        let app = client.rocket().state::<App>().expect("App not found");
+        for unique_id in ["a", "b", "c"] {
+            app.sync_service
+                .get_or_create_point(unique_id, 0)
+                .await
+                .expect("wait point");
+        }
+
+        let response = client
+            .get("/admin/wait-points?limit=1")
+            .header(auth(TOKEN))
+            .header(Header::new("Accept", "application/x-ndjson"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.content_type().expect("content type").to_string(),
+            "application/x-ndjson"
+        );
+
+        // All points despite `limit`
+        let body = response.into_string().await.expect("body");
+        let ids: Vec<Value> = body
+            .lines()
+            .map(|line| {
+                let item: Value = serde_json::from_str(line).expect("JSON line");
+                item["unique_id"].clone()
+            })
+            .collect();
+        assert_eq!(ids, vec!["a", "b", "c"]);
+    }
+
+    #[rocket::async_test]
+    async fn test_wait_point_notes_and_origins() {
+        let (client, _dir) = get_client_with_config(CONFIG).await;
+        let client = Arc::new(client);
+        let waiter = client.clone();
+        let wait = tokio::spawn(async move {
+            let uri = "/wait/noted?ttl=1&note=waiting%20for%20payments%20v42";
+            let user_agent = Header::new("User-Agent", "payments-deployer/1.2");
+            waiter
+                .post(uri)
+                .header(user_agent)
+                .dispatch()
+                .await
+                .status()
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let response = client
+            .get("/admin/wait-points")
+            .header(auth(TOKEN))
+            .dispatch()
+            .await;
+        let json = get_response_json(response).await;
+        assert_eq!(
+            json["items"][0]["notes"],
+            json!(["waiting for payments v42"])
+        );
+        assert_eq!(
+            json["items"][0]["origins"],
+            json!([{"user_agent": "payments-deployer/1.2"}])
+        );
+        // Only shown to operators
+        let response = client.get("/status/noted").dispatch().await;
+        assert_eq!(get_response_json(response).await.get("notes"), None);
+        assert_eq!(wait.await.unwrap(), Status::RequestTimeout);
+
+        let too_long = "a".repeat(201);
+        let response = client
+            .post(format!("/wait/noted?note={}", too_long))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest);
+        let response = client.post("/wait/noted?note=a%0Ab").dispatch().await;
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[rocket::async_test]
+    async fn test_observability_templates() {
+        let (client, _dir) =
+            get_client_with_config(&format!("{}\nmax_wait_points = 10", CONFIG)).await;
+        let response = client
+            .get("/admin/observability/templates")
+            .header(auth(TOKEN))
+            .dispatch()
+            .await;
+
+        assert_eq!(response.status(), Status::Ok);
+        let json = get_response_json(response).await;
+        let dashboard = json["grafana_dashboard"].to_string();
+        for metric in METRICS {
+            assert!(dashboard.contains(metric.name), "{} not in dashboard", metric.name);
+        }
+        let rules = json["prometheus_alert_rules"]["groups"][0]["rules"]
+            .as_array()
+            .unwrap();
+        assert!(rules
+            .iter()
+            .any(|rule| rule["expr"] == "sync_point_open_wait_points >= 9"));
+    }
+
+    #[rocket::async_test]
+    async fn test_self_test() {
+        let (client, _dir) = get_client_with_config(CONFIG).await;
+        let self_test = || {
+            client
+                .post("/admin/self-test")
+                .header(auth(TOKEN))
+                .dispatch()
+        };
+
+        let response = self_test().await;
+        assert_eq!(response.status(), Status::Ok);
+        let json = get_response_json(response).await;
+        assert_eq!(json["passed"], true);
+        let results: Vec<_> = json["checks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|check| (check["name"].clone(), check["result"].clone()))
+            .collect();
+        assert_eq!(
+            results,
+            [
+                (json!("rendezvous"), json!("pass")),
+                (json!("store_round_trip"), json!("skipped")),
+                (json!("webhook_dry_run"), json!("skipped"))
+            ]
+        );
+
+        // Nothing is left behind
+        let response = client.get("/stats").dispatch().await;
+        assert_eq!(get_response_json(response).await["open_wait_points"], 0);
+
+        // Synthetic parties are rejected like real clients
+        client
+            .put("/admin/maintenance")
+            .header(auth(TOKEN))
+            .header(ContentType::JSON)
+            .body(json!({"enabled": true, "message": "Migrating"}).to_string())
+            .dispatch()
+            .await;
+        let response = self_test().await;
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+        let json = get_response_json(response).await;
+        assert_eq!(json["passed"], false);
+        assert_eq!(json["checks"][0]["result"], "fail");
+        assert!(json["checks"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("Migrating"));
+    }
+}
@@ -1,15 +1,25 @@
-mod common;
-
 #[cfg(test)]
 mod tests {
-    use crate::common::{
-        assert_success_response, assert_timeout_response, get_client, make_sync_request,
-        spawn_request,
-    };
-    use rocket::http::Status;
+    use rocket::http::{ContentType, Header, Method, Status};
+    use serde_json::{json, Value};
+    use std::net::SocketAddr;
     use std::sync::Arc;
-    use std::time::Duration;
+    use std::time::{Duration, Instant};
+    use sync_point::api::acl::Access;
+    #[cfg(feature = "metrics")]
+    use sync_point::api::metrics::METRICS;
+    use sync_point::api::registry::Registry;
+    use sync_point::api::shutdown::drain;
+    use sync_point::api::sync_service::SyncRequest;
     use sync_point::app::App;
+    use sync_point::protocol::{headers, DryRunOutcome, SyncOutcome};
+    use sync_point::test_support::{
+        assert_same_match, assert_success_response, assert_timeout_response, get_client,
+        get_client_with_config, get_response_json, make_sync_request, spawn_request,
+    };
+    #[cfg(feature = "shadow")]
+    use tokio::io::AsyncWriteExt;
+    use tokio::io::{AsyncBufReadExt, BufReader};
 
     const UNIQUE_ID: &str = "123";
 
@@ -24,6 +34,103 @@ mod tests {
         );
     }
 
+    #[rocket::async_test]
+    async fn test_health() {
+        let client = get_client().await;
+        let response = client.get("/health").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.into_string().await.unwrap(),
+            r#"{"status":"ok"}"#
+        );
+    }
+
+    #[rocket::async_test]
+    async fn test_readiness() {
+        let (client, _dir) = get_client_with_config("warmup_sec = 1\nmax_wait_points = 1").await;
+        let client = Arc::new(client);
+        let response = client.get("/ready").dispatch().await;
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+        assert_eq!(get_response_json(response).await["state"], "starting");
+
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        let response = client.get("/ready").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.into_string().await.unwrap(),
+            r#"{"state":"ready"}"#
+        );
+
+        // At `max_wait_points`
+        let waiter = spawn_request(client.clone(), UNIQUE_ID.to_string());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let response = client.get("/ready").dispatch().await;
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+        assert_eq!(get_response_json(response).await["state"], "unhealthy");
+        #[cfg(feature = "metrics")]
+        {
+            let response = client.get("/metrics").dispatch().await;
+            let body = response.into_string().await.unwrap();
+            assert!(body.contains("sync_point_readiness_state{state=\"unhealthy\"} 1\n"));
+            assert!(body.contains("sync_point_readiness_state{state=\"ready\"} 0\n"));
+        }
+        make_sync_request(&client, UNIQUE_ID).await;
+        waiter.await.unwrap();
+        let response = client.get("/ready").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[rocket::async_test]
+    async fn test_server_time() {
+        let client = get_client().await;
+        let response = client
+            .get("/time")
+            .header(Header::new(headers::CLIENT_TIME, "0"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let header: u64 = response
+            .headers()
+            .get_one(headers::SERVER_TIME)
+            .expect("server time header")
+            .parse()
+            .unwrap();
+        let json = get_response_json(response).await;
+        assert!(json["server_time"].as_str().unwrap().ends_with('Z'));
+        assert!(header.abs_diff(json["unix_ms"].as_u64().unwrap()) < 1000);
+
+        // Errors carry it too
+        let response = client.get("/unknown").dispatch().await;
+        assert!(response.headers().contains(headers::SERVER_TIME));
+    }
+
+    #[cfg(feature = "metrics")]
+    #[rocket::async_test]
+    async fn test_metrics() {
+        let client = get_client().await;
+        let response = client.get("/metrics").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+
+        let content_type = response.content_type().unwrap();
+        assert_eq!(content_type.sub(), "openmetrics-text");
+        let body = response.into_string().await.unwrap();
+        for metric in METRICS {
+            assert!(body.contains(&format!("# TYPE sync_point_{} ", metric.name)));
+        }
+        assert!(body.contains("sync_point_open_wait_points 0\n"));
+        if cfg!(feature = "jemalloc") {
+            assert!(body.contains("sync_point_allocator_resident_bytes{allocator=\"jemalloc\"} "));
+        }
+        assert!(body.ends_with("# EOF\n"));
+
+        let config = "metric_prefix = \"staging_\"\n[metric_labels]\nregion = \"eu\"";
+        let (client, _dir) = get_client_with_config(config).await;
+        let response = client.get("/metrics").dispatch().await;
+        let body = response.into_string().await.unwrap();
+        assert!(body.contains("staging_open_wait_points{region=\"eu\"} 0\n"));
+        assert!(body.contains("staging_waits_shed_total{region=\"eu\",priority=\"low\"} 0\n"));
+    }
+
     #[rocket::async_test]
     async fn test_single_party_timeout() {
         let client = get_client().await;
@@ -37,6 +144,156 @@ mod tests {
         assert_timeout_response(&response, app,  UNIQUE_ID);
     }
 
+    #[rocket::async_test]
+    async fn test_wait_point_ttl() {
+        let client = get_client().await;
+        let response = client.post("/wait/ttl-1?ttl=1").dispatch().await;
+        assert_eq!(response.status(), Status::RequestTimeout);
+        assert_eq!(get_response_json(response).await["timeout_duration_sec"], 1);
+
+        let response = client
+            .post("/wait-for-second-party/ttl-1?ttl=0")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    /// An unmatched first party counts as timed out, under the prefix of its id
+    #[rocket::async_test]
+    async fn test_timed_out_wait_outcome() {
+        let client = get_client().await;
+        let response = client.post("/wait/outcome-1?ttl=1").dispatch().await;
+        assert_eq!(response.status(), Status::RequestTimeout);
+
+        let response = client.get("/stats/outcomes").dispatch().await;
+        assert_eq!(
+            get_response_json(response).await,
+            json!({
+                "total": {"timed_out": 1, "cancelled": 0},
+                "by_prefix": {"outcome": {"timed_out": 1, "cancelled": 0}}
+            })
+        );
+    }
+
+    /// A cancelled first party (e.g. its batch client disconnected) counts as cancelled & doesn't
+    /// leave its wait point behind
+    #[rocket::async_test]
+    async fn test_cancelled_wait() {
+        let client = get_client().await;
+        let app = client.rocket().state::<App>().expect("App not found").clone();
+
+        let wait = tokio::spawn(async move {
+            let _ = app
+                .sync_service
+                .join("shard-1", SyncRequest::default(), Access::default(), &app)
+                .await;
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        wait.abort();
+        let _ = wait.await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let response = client.get("/stats/outcomes").dispatch().await;
+        let json = get_response_json(response).await;
+        assert_eq!(json["by_prefix"]["shard"], json!({"timed_out": 0, "cancelled": 1}));
+
+        let response = client.get("/status/shard-1").dispatch().await;
+        assert_eq!(get_response_json(response).await["parties"], 0);
+    }
+
+    /// Ids under `_internal/` are for the server's own synthetic parties, which don't show up in
+    /// the stats
+    #[rocket::async_test]
+    async fn test_internal_ids() {
+        let client = get_client().await;
+        let response = client.post("/wait/_internal/probe?ttl=1").dispatch().await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let response = client
+            .post("/batch/wait")
+            .json(&json!({"ids": ["_internal/probe"]}))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let line: Value =
+            serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+        assert_eq!(
+            line,
+            json!({
+                "unique_id": "_internal/probe",
+                "http_status": 400,
+                "status": "error",
+                "message": "Invalid id '_internal/probe': reserved id"
+            })
+        );
+
+        let app = client
+            .rocket()
+            .state::<App>()
+            .expect("App not found")
+            .clone();
+        let wait = tokio::spawn(async move {
+            let _ = app
+                .sync_service
+                .join("_internal/probe", SyncRequest::default(), Access::default(), &app)
+                .await;
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let response = client.get("/stats").dispatch().await;
+        let json = get_response_json(response).await;
+        assert_eq!(json["open_wait_points"], 0);
+        assert_eq!(json["waiting_parties"], 0);
+        assert_eq!(json["open_by_priority"]["normal"], 0);
+
+        wait.abort();
+        let _ = wait.await;
+        let response = client.get("/stats/outcomes").dispatch().await;
+        assert_eq!(get_response_json(response).await["by_prefix"], json!({}));
+    }
+
+    #[rocket::async_test]
+    async fn test_templates() {
+        let config = r#"
+            [[templates]]
+            pattern = "deploy/*"
+            schema = '{"required": ["team"], "properties": {"team": {"enum": ["payments"]}}}'
+        "#;
+        let (client, _dir) = get_client_with_config(config).await;
+        let response = client.post("/notify/deploy/eu").dispatch().await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let json = get_response_json(response).await;
+        assert_eq!(
+            json["message"],
+            "[deploy/eu] Metadata doesn't conform to the template of the id"
+        );
+        assert_eq!(
+            json["errors"],
+            json!([{"field": "/team", "message": "is required"}])
+        );
+
+        let response = client
+            .post("/notify/deploy/eu")
+            .header(Header::new(headers::METADATA, r#"{"team": "search"}"#))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let response = client
+            .post("/notify/deploy/eu")
+            .header(Header::new(headers::METADATA, "[1]"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest);
+
+        // Conforming, so it gets as far as finding nobody waiting
+        let response = client
+            .post("/notify/deploy/eu")
+            .header(Header::new(headers::METADATA, r#"{"team": "payments"}"#))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client.post("/notify/other").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
     #[rocket::async_test]
     async fn test_successful_sync() {
         let client = Arc::new(get_client().await);
@@ -52,6 +309,7 @@ mod tests {
 
         assert_success_response(&response1, UNIQUE_ID, "first");
         assert_success_response(&response2, UNIQUE_ID, "second");
+        assert_same_match(&response1, &response2);
     }
 
     #[rocket::async_test]
@@ -84,6 +342,826 @@ mod tests {
         assert_timeout_response(&response3, app, UNIQUE_ID);
     }
 
+    #[rocket::async_test]
+    async fn test_n_party_barrier() {
+        let config = "[modes]\n\"barrier-*\" = { mode = \"n-party\", count = 3 }";
+        let (client, _dir) = get_client_with_config(config).await;
+        let client = Arc::new(client);
+
+        let handle1 = spawn_request(client.clone(), "barrier-1".to_string());
+        let handle2 = spawn_request(client.clone(), "barrier-1".to_string());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let response = client.get("/status/barrier-1").dispatch().await;
+        assert_eq!(get_response_json(response).await["parties"], 2);
+
+        // Ids matching no pattern still pair up
+        let pair = spawn_request(client.clone(), UNIQUE_ID.to_string());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let response3 = make_sync_request(&client, "barrier-1").await;
+        assert_success_response(
+            &make_sync_request(&client, UNIQUE_ID).await,
+            UNIQUE_ID,
+            "second",
+        );
+        assert_success_response(&pair.await.expect("pair response"), UNIQUE_ID, "first");
+
+        let response1 = handle1.await.expect("first response");
+        let response2 = handle2.await.expect("second response");
+        assert_same_match(&response1, &response3);
+        assert_same_match(&response2, &response3);
+        let mut messages: Vec<Value> = [response1, response2, response3]
+            .into_iter()
+            .map(|response| {
+                assert_eq!(response.status, Status::Ok);
+                response.json["message"].clone()
+            })
+            .collect();
+        messages.sort_by_key(|message| message.to_string());
+        assert_eq!(
+            messages,
+            (1..=3)
+                .map(|party| json!(format!("[barrier-1] Welcome! (party {} of 3)", party)))
+                .collect::<Vec<_>>()
+        );
+        let response = client.get("/stats").dispatch().await;
+        assert_eq!(get_response_json(response).await["open_wait_points"], 0);
+    }
+
+    #[rocket::async_test]
+    async fn test_broadcast_release() {
+        let config = "[modes]\n\"gate-*\" = { mode = \"broadcast\" }";
+        let (client, _dir) = get_client_with_config(config).await;
+        let client = Arc::new(client);
+        let release = |unique_id: &str| {
+            let client = client.clone();
+            let endpoint = format!("/wait-for-second-party/{}?release=true", unique_id);
+            async move {
+                let response = client.post(endpoint).dispatch().await;
+                (response.status(), get_response_json(response).await)
+            }
+        };
+
+        let (status, _) = release("gate-deploy").await;
+        assert_eq!(status, Status::NotFound);
+        let (status, _) = release(UNIQUE_ID).await;
+        assert_eq!(status, Status::BadRequest);
+
+        let waiters: Vec<_> = (0..3)
+            .map(|_| spawn_request(client.clone(), "gate-deploy".to_string()))
+            .collect();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let response = client.get("/stats").dispatch().await;
+        assert_eq!(get_response_json(response).await["waiting_parties"], 3);
+
+        let (status, json) = release("gate-deploy").await;
+        assert_eq!(status, Status::Ok);
+        assert_eq!(json["message"], "[gate-deploy] Released 3 waiting parties");
+        for waiter in waiters {
+            let response = waiter.await.expect("waiter response");
+            assert_eq!(response.status, Status::Ok);
+            assert_eq!(
+                response.json["message"],
+                "[gate-deploy] Welcome! (released)"
+            );
+            // The releasing party is part of the match
+            assert_eq!(response.json["pair_id"], json["pair_id"]);
+        }
+    }
+
+    #[rocket::async_test]
+    async fn test_max_waits_per_key() {
+        let (client, _dir) = get_client_with_config("max_waits_per_key = 1").await;
+        let client = Arc::new(client);
+        let wait = |unique_id: &'static str, api_key: &'static str| {
+            let client = client.clone();
+            async move {
+                let response = client
+                    .post(format!("/wait/{}?ttl=1", unique_id))
+                    .header(Header::new(headers::API_KEY, api_key))
+                    .dispatch()
+                    .await;
+                let in_use = response.headers().get_one(headers::WAITS_IN_USE);
+                (response.status(), in_use.map(str::to_owned))
+            }
+        };
+
+        let first = tokio::spawn(wait("quota-1", "team-a"));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let (status, in_use) = wait("quota-2", "team-a").await;
+        assert_eq!(status, Status::TooManyRequests);
+        assert_eq!(in_use.as_deref(), Some("1/1"));
+        // Other keys have their own slots
+        assert_eq!(wait("quota-3", "team-b").await.0, Status::RequestTimeout);
+
+        let (status, in_use) = first.await.unwrap();
+        assert_eq!(status, Status::RequestTimeout);
+        assert_eq!(in_use.as_deref(), Some("0/1"));
+    }
+
+    #[rocket::async_test]
+    async fn test_max_requests_per_connection() {
+        let (client, _dir) = get_client_with_config("max_requests_per_connection = 1").await;
+        let client = Arc::new(client);
+        let post = |uri: &'static str, port: u16| {
+            let client = client.clone();
+            async move {
+                let remote = SocketAddr::from(([10, 0, 0, 1], port));
+                let response = client.post(uri).remote(remote).dispatch().await;
+                let status = response.status();
+                (status, get_response_json(response).await)
+            }
+        };
+
+        let first = tokio::spawn(post("/wait/conn-1?ttl=1", 50000));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let (status, json) = post("/wait/conn-2?ttl=1", 50000).await;
+        assert_eq!(status, Status::TooManyRequests);
+        assert_eq!(
+            json["message"],
+            "The connection already has 1 requests in progress, the limit"
+        );
+        let batch = client
+            .post("/batch/wait")
+            .remote(SocketAddr::from(([10, 0, 0, 1], 50000)))
+            .json(&json!({"ids": ["conn-3"]}))
+            .dispatch()
+            .await;
+        assert_eq!(batch.status(), Status::TooManyRequests);
+        // Another connection of the same client
+        assert_eq!(post("/notify/conn-1", 50001).await.0, Status::Ok);
+
+        assert_eq!(first.await.unwrap().0, Status::Ok);
+        let (status, _) = post("/wait/conn-2?ttl=1", 50000).await;
+        assert_eq!(status, Status::RequestTimeout);
+    }
+
+    #[rocket::async_test]
+    async fn test_shutdown_report() {
+        let client = Arc::new(get_client().await);
+        let wait = |uri: &'static str| {
+            let client = client.clone();
+            tokio::spawn(async move { client.post(uri).dispatch().await.status() })
+        };
+        let drained = wait("/wait/shutdown-1?ttl=1");
+        let cancelled = wait("/wait/shutdown-2");
+        let dropped = wait("/wait/shutdown-3");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let app = client.rocket().state::<App>().unwrap();
+        let (report, _) = tokio::join!(drain(&app.sync_service, Duration::from_secs(2)), async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            cancelled.abort();
+        });
+        assert_eq!(report.waiting_parties, 3);
+        assert_eq!(report.drained, 1);
+        assert_eq!(report.cancelled, 1);
+        assert_eq!(report.force_dropped, 1);
+        assert_eq!(report.stats.waiting_parties, 1);
+        assert_eq!(drained.await.unwrap(), Status::RequestTimeout);
+        dropped.abort();
+    }
+
+    #[rocket::async_test]
+    async fn test_priorities() {
+        let (client, _dir) =
+            get_client_with_config("max_wait_points = 2\n[priorities]\nlow = 50").await;
+        let client = Arc::new(client);
+        let wait = |url: &'static str| {
+            let client = client.clone();
+            async move { client.post(url).dispatch().await.status() }
+        };
+
+        let first = tokio::spawn(wait("/wait/prio-1?ttl=1&priority=low"));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let response = client.get("/stats").dispatch().await;
+        let json = get_response_json(response).await;
+        assert_eq!(json["open_by_priority"], json!({"high": 0, "normal": 0, "low": 1}));
+
+        // Low parties only get half of the wait points, the rest is kept for the others
+        let status = wait("/wait-for-second-party/prio-2?ttl=1&priority=low").await;
+        assert_eq!(status, Status::ServiceUnavailable);
+        assert_eq!(wait("/wait/prio-3?ttl=1").await, Status::RequestTimeout);
+        assert_eq!(first.await.unwrap(), Status::RequestTimeout);
+
+        #[cfg(feature = "metrics")]
+        {
+            let response = client.get("/metrics").dispatch().await;
+            let body = response.into_string().await.unwrap();
+            assert!(body.contains("sync_point_waits_shed_total{priority=\"low\"} 1\n"));
+        }
+    }
+
+    #[rocket::async_test]
+    async fn test_share_peer_origin() {
+        let (client, _dir) = get_client_with_config("share_peer_origin = true").await;
+        let client = Arc::new(client);
+        let waiter = client.clone();
+        let first = tokio::spawn(async move {
+            let response = waiter
+                .post("/wait/origin-1")
+                .header(Header::new(headers::USER_AGENT, "waiter/1.0 (linux)"))
+                .header(Header::new(headers::API_KEY, "payments-team-a"))
+                .dispatch()
+                .await;
+            get_response_json(response).await
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let response = client
+            .post("/notify/origin-1")
+            .header(Header::new(headers::USER_AGENT, "curl/8.4.0"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let json = get_response_json(response).await;
+        assert_eq!(
+            json["peer"],
+            json!({"user_agent": "waiter/1.0", "api_key": "…am-a"})
+        );
+        assert_eq!(
+            first.await.unwrap()["peer"],
+            json!({"user_agent": "curl/8.4.0"})
+        );
+
+        // Not shared by default
+        let client = get_client().await;
+        let (first, second) = tokio::join!(
+            client.post("/wait-for-second-party/origin-2").dispatch(),
+            client.post("/wait-for-second-party/origin-2").dispatch()
+        );
+        for response in [first, second] {
+            assert_eq!(get_response_json(response).await.get("peer"), None);
+        }
+    }
+
+    #[rocket::async_test]
+    async fn test_reservation() {
+        let (client, _dir) = get_client_with_config("max_wait_points = 1").await;
+        let response = client.post("/reserve/reserved-1").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let json = get_response_json(response).await;
+        let token = json["token"].as_str().unwrap().to_owned();
+        assert_eq!(json["expires_in_sec"], 30);
+
+        // The seat is held, neither other parties nor reservations get it
+        let response = client.post("/wait/reserved-2?ttl=1").dispatch().await;
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+        let response = client.post("/reserve/reserved-2").dispatch().await;
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+        // Only for its own id
+        let response = client
+            .post("/wait/reserved-2?ttl=1")
+            .header(Header::new(headers::RESERVATION, token.clone()))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+
+        let response = client
+            .post("/wait/reserved-1?ttl=1")
+            .header(Header::new(headers::RESERVATION, token.clone()))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::RequestTimeout);
+        // Used up, the seat is free again
+        let response = client.post("/wait/reserved-2?ttl=1").dispatch().await;
+        assert_eq!(response.status(), Status::RequestTimeout);
+
+        let response = client
+            .post("/wait/reserved-1")
+            .header(Header::new(headers::RESERVATION, "not-a-token"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    /// A holder joining an existing point releases its reserved seat, like one creating a point
+    #[rocket::async_test]
+    async fn test_reservation_is_redeemed_by_joining() {
+        let (client, _dir) = get_client_with_config("max_wait_points = 2").await;
+        let client = Arc::new(client);
+        let waiter = spawn_request(client.clone(), "joined-1".to_owned());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let response = client.post("/reserve/joined-1").dispatch().await;
+        let token = get_response_json(response).await["token"]
+            .as_str()
+            .unwrap()
+            .to_owned();
+        let response = client.post("/wait/joined-2?ttl=1").dispatch().await;
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+
+        let response = client
+            .post("/notify/joined-1")
+            .header(Header::new(headers::RESERVATION, token))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(waiter.await.unwrap().status, Status::Ok);
+        // Both seats are free again
+        let (second, third) = tokio::join!(
+            client.post("/wait/joined-2?ttl=1").dispatch(),
+            client.post("/wait/joined-3?ttl=1").dispatch()
+        );
+        assert_eq!(second.status(), Status::RequestTimeout);
+        assert_eq!(third.status(), Status::RequestTimeout);
+    }
+
+    #[rocket::async_test]
+    async fn test_late_arrival_grace() {
+        let (client, _dir) = get_client_with_config("late_arrival_grace_sec = 60").await;
+        let response = client.post("/wait/late-1?ttl=1").dispatch().await;
+        assert_eq!(response.status(), Status::RequestTimeout);
+
+        let response = client.post("/notify/late-1").dispatch().await;
+        assert_eq!(response.status(), Status::Gone);
+        let json = get_response_json(response).await;
+        let timed_out_at = json["peer_recently_timed_out"]["timed_out_at"].as_str();
+        assert!(timed_out_at.unwrap().ends_with('Z'));
+
+        // Only the first late party is told, the next one waits as usual
+        let response = client
+            .post("/wait-for-second-party/late-1?ttl=1")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::RequestTimeout);
+    }
+
+    #[rocket::async_test]
+    async fn test_wait_and_notify() {
+        let client = Arc::new(get_client().await);
+
+        // Arriving first doesn't make a notifier wait
+        let response = client.post(format!("/notify/{}", UNIQUE_ID)).dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(get_response_json(response).await["delivered"], false);
+        let response = client.get(format!("/status/{}", UNIQUE_ID)).dispatch().await;
+        assert_eq!(get_response_json(response).await["parties"], 0);
+
+        let waiter = {
+            let client = client.clone();
+            tokio::spawn(async move {
+                let response = client.post(format!("/wait/{}", UNIQUE_ID)).dispatch().await;
+                (response.status(), get_response_json(response).await)
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // Arriving second doesn't make a waiter notify
+        let response = client.post(format!("/wait/{}", UNIQUE_ID)).dispatch().await;
+        assert_eq!(response.status(), Status::Conflict);
+
+        let response = client.post(format!("/notify/{}", UNIQUE_ID)).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let json = get_response_json(response).await;
+        assert_eq!(
+            json["message"],
+            format!("[{}] Welcome! (second party)", UNIQUE_ID)
+        );
+        assert_eq!(json["delivered"], true);
+        let (status, json) = waiter.await.expect("waiter response");
+        assert_eq!(status, Status::Ok);
+        assert_eq!(
+            json["message"],
+            format!("[{}] Welcome! (first party)", UNIQUE_ID)
+        );
+    }
+
+    #[rocket::async_test]
+    async fn test_persistent_notify() {
+        let client = get_client().await;
+
+        let response = client
+            .post(format!("/notify/{}?persistent=true", UNIQUE_ID))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Accepted);
+        let stored = get_response_json(response).await;
+        assert_eq!(stored["delivered"], false);
+        let response = client.get("/stats").dispatch().await;
+        assert_eq!(get_response_json(response).await["pending_notifications"], 1);
+
+        // The next party to wait is matched right away, once
+        let response = client.post(format!("/wait/{}", UNIQUE_ID)).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(get_response_json(response).await["pair_id"], stored["pair_id"]);
+        let response = client.get("/stats").dispatch().await;
+        let json = get_response_json(response).await;
+        assert_eq!(json["pending_notifications"], 0);
+        assert_eq!(json["open_wait_points"], 0);
+    }
+
+    /// With `stats_interval_ms`, `/stats` serves the latest snapshot
+    #[rocket::async_test]
+    async fn test_cached_stats() {
+        let (client, _dir) = get_client_with_config("stats_interval_ms = 500").await;
+        let client = Arc::new(client);
+        let open_wait_points = |client: Arc<rocket::local::asynchronous::Client>| async move {
+            let response = client.get("/stats").dispatch().await;
+            get_response_json(response).await["open_wait_points"].clone()
+        };
+
+        // The first snapshot is taken right away
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let handle = spawn_request(client.clone(), UNIQUE_ID.to_string());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(open_wait_points(client.clone()).await, 0);
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        assert_eq!(open_wait_points(client.clone()).await, 1);
+
+        make_sync_request(&client, UNIQUE_ID).await;
+        handle.await.expect("first response");
+    }
+
+    /// Observers of a subtree see what happens at its ids, path-like or encoded, but not elsewhere
+    #[rocket::async_test]
+    async fn test_observe_subtree() {
+        let client = Arc::new(get_client().await);
+        let response = client.get("/observe/deploy/eu/*").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::EventStream));
+        let mut lines = BufReader::new(response).lines();
+
+        let other = spawn_request(client.clone(), "deploy/us/payments".to_owned());
+        make_sync_request(&client, "deploy/us/payments").await;
+        other.await.expect("first response");
+        let first = spawn_request(client.clone(), "deploy/eu/payments".to_owned());
+        let second = make_sync_request(&client, "deploy%2Feu%2Fpayments").await;
+        let first = first.await.expect("first response");
+        assert_same_match(&first, &second);
+        let message = second.json["message"].as_str().unwrap();
+        assert!(message.starts_with("[deploy/eu/payments] "), "{}", message);
+
+        let mut observed = Vec::new();
+        while observed.len() < 2 {
+            let line = lines.next_line().await.unwrap().expect("open stream");
+            if let Some(data) = line.strip_prefix("data:") {
+                observed.push(serde_json::from_str::<Value>(data).unwrap());
+            }
+        }
+        assert_eq!(observed[0]["event"], "waiting");
+        assert_eq!(observed[0]["unique_id"], "deploy/eu/payments");
+        assert_eq!(observed[0]["parties"], 1);
+        assert_eq!(observed[1]["event"], "matched");
+        assert_eq!(observed[1]["pair_id"], first.json["pair_id"]);
+        assert_eq!(observed[1]["parties"], 2);
+    }
+
+    /// Observers wait for the match without taking the second party's seat, up to the cap per point
+    #[rocket::async_test]
+    async fn test_observe_wait_point() {
+        let (client, _dir) = get_client_with_config("max_observers_per_point = 1").await;
+        let client = Arc::new(client);
+        let observe = |client: Arc<rocket::local::asynchronous::Client>| {
+            tokio::spawn(async move {
+                let uri = format!("/wait-for-second-party/{}?observe=true", UNIQUE_ID);
+                let response = client.post(uri).dispatch().await;
+                let status = response.status();
+                (status, get_response_json(response).await)
+            })
+        };
+
+        let (status, _) = observe(client.clone()).await.unwrap();
+        assert_eq!(status, Status::NotFound);
+
+        let first = spawn_request(client.clone(), UNIQUE_ID.to_string());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let observer = observe(client.clone());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let (status, json) = observe(client.clone()).await.unwrap();
+        assert_eq!(status, Status::TooManyRequests, "{}", json);
+        let status = format!("/status/{}", UNIQUE_ID);
+        let response = client.get(status).dispatch().await;
+        assert_eq!(get_response_json(response).await["parties"], 1);
+
+        let second = make_sync_request(&client, UNIQUE_ID).await;
+        assert_eq!(second.status, Status::Ok);
+        let first = first.await.unwrap();
+        let (status, json) = observer.await.unwrap();
+        assert_eq!(status, Status::Ok);
+        assert_eq!(json["pair_id"], first.json["pair_id"]);
+        assert_same_match(&first, &second);
+    }
+
+    #[rocket::async_test]
+    async fn test_completion_record() {
+        let client = Arc::new(get_client().await);
+        let first = spawn_request(client.clone(), UNIQUE_ID.to_string());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let second = make_sync_request(&client, UNIQUE_ID).await;
+        let first = first.await.unwrap();
+        assert_same_match(&first, &second);
+
+        let pair_id = first.json["pair_id"].as_str().unwrap();
+        let response = client
+            .get(format!("/completions/{}", pair_id))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let json = get_response_json(response).await;
+        assert_eq!(json["unique_id"], UNIQUE_ID);
+        assert_eq!(json["parties"], 2);
+        assert!(json["wait_ms"].as_u64().unwrap() >= 100, "{}", json);
+
+        let unknown = format!("/completions/{}", uuid::Uuid::new_v4());
+        let response = client.get(unknown).dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client.get("/completions/nope").dispatch().await;
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    /// Thousands of waiting parties don't keep the registry locked: it's free to take while they
+    /// wait, & a hot id polled meanwhile doesn't get any notification rejected
+    #[rocket::async_test]
+    async fn test_registry_stays_unlocked_with_thousands_of_ids() {
+        const OPEN: usize = 3000;
+        let client = Arc::new(get_client().await);
+        let waiters: Vec<_> = (0..OPEN)
+            .map(|i| spawn_request(client.clone(), format!("flat-{}", i)))
+            .collect();
+        loop {
+            let response = client.get("/stats").dispatch().await;
+            if get_response_json(response).await["open_wait_points"] == OPEN {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let app = client.rocket().state::<App>().unwrap();
+        assert!(app.sync_service.wait_points.try_write().is_some());
+
+        let poller = tokio::spawn({
+            let client = client.clone();
+            async move {
+                loop {
+                    client.get("/status/hot").dispatch().await;
+                    tokio::task::yield_now().await;
+                }
+            }
+        });
+        let ids: Vec<usize> = (0..OPEN).collect();
+        for chunk in ids.chunks(32) {
+            let notifiers: Vec<_> = chunk
+                .iter()
+                .map(|i| {
+                    let client = client.clone();
+                    let uri = format!("/notify/flat-{}", i);
+                    tokio::spawn(async move { client.post(uri).dispatch().await.status() })
+                })
+                .collect();
+            for notifier in notifiers {
+                assert_eq!(notifier.await.unwrap(), Status::Ok);
+            }
+        }
+        poller.abort();
+        for waiter in waiters {
+            assert_eq!(waiter.await.unwrap().status, Status::Ok);
+        }
+        assert!(app.sync_service.wait_points.read().is_empty());
+    }
+
+    /// Only parties admitted by the ACL of the creating party may join its wait point
+    #[rocket::async_test]
+    async fn test_wait_point_acl() {
+        let client = Arc::new(get_client().await);
+
+        let waiter = client.clone();
+        let first = tokio::spawn(async move {
+            let response = waiter
+                .post(format!("/wait/{}", UNIQUE_ID))
+                .header(Header::new(headers::ALLOW_KEYS, "team-a, team-b"))
+                .dispatch()
+                .await;
+            (response.status(), get_response_json(response).await)
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        for api_key in [None, Some("team-c")] {
+            let mut request = client.post(format!("/notify/{}", UNIQUE_ID));
+            if let Some(api_key) = api_key {
+                request = request.header(Header::new(headers::API_KEY, api_key));
+            }
+            let response = request.dispatch().await;
+            assert_eq!(response.status(), Status::Forbidden);
+            assert_eq!(
+                get_response_json(response).await["message"],
+                format!("Not allowed to join {}", UNIQUE_ID)
+            );
+        }
+
+        let response = client
+            .post(format!("/notify/{}", UNIQUE_ID))
+            .header(Header::new(headers::API_KEY, "team-b"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let (status, json) = first.await.expect("first response");
+        assert_eq!(status, Status::Ok);
+        assert_eq!(get_response_json(response).await["pair_id"], json["pair_id"]);
+
+        // Invalid ranges are rejected up front
+        let response = client
+            .post(format!("/wait/{}", UNIQUE_ID))
+            .header(Header::new(headers::ALLOW_CIDRS, "10.0.0.0/99"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    /// Both parties of a pair match get the same receipt, verifiable with the server's public key
+    #[cfg(feature = "cluster")]
+    #[rocket::async_test]
+    async fn test_cluster_owner() {
+        let client = get_client().await;
+        let response = client.get("/cluster/owner/123").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+
+        let config = r#"
+            [cluster]
+            node = "node-a"
+            nodes = [
+                { name = "node-a", url = "http://10.0.0.1:8000" },
+                { name = "node-b", url = "http://10.0.0.2:8000" },
+            ]
+        "#;
+        let (client, _dir) = get_client_with_config(config).await;
+        let mut owners = Vec::new();
+        for id in 0..20 {
+            let response = client
+                .get(format!("/cluster/owner/id-{}", id))
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::Ok);
+            let json = get_response_json(response).await;
+            assert_eq!(json["local"], json["node"] == "node-a");
+            owners.push(json["node"].as_str().expect("node").to_owned());
+        }
+        assert!(owners.contains(&"node-a".to_owned()) && owners.contains(&"node-b".to_owned()));
+    }
+
+    #[cfg(feature = "receipts")]
+    #[rocket::async_test]
+    async fn test_match_receipts() {
+        use sync_point::api::receipts::verify;
+        use sync_point::protocol::Receipt;
+
+        let config =
+            "receipt_key = \"9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60\"";
+        let (client, _dir) = get_client_with_config(config).await;
+        let client = Arc::new(client);
+
+        let handle1 = spawn_request(client.clone(), UNIQUE_ID.to_string());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let response2 = client
+            .post(format!("/notify/{}", UNIQUE_ID))
+            .header(Header::new(headers::API_KEY, "team-a"))
+            .dispatch()
+            .await;
+        let json2 = get_response_json(response2).await;
+        let response1 = handle1.await.expect("first response");
+        assert_eq!(response1.json["receipt"], json2["receipt"]);
+
+        let response = client.get("/receipts/key").dispatch().await;
+        let public_key = get_response_json(response).await["public_key"].clone();
+        let receipt: Receipt = serde_json::from_value(json2["receipt"].clone()).unwrap();
+        let payload = verify(&receipt, public_key.as_str().unwrap()).expect("valid receipt");
+        assert_eq!(payload.unique_id, UNIQUE_ID);
+        assert_eq!(json2["pair_id"], payload.pair_id.as_str());
+        assert_eq!(payload.parties.len(), 2);
+        assert_ne!(payload.parties[0], payload.parties[1]);
+
+        // Not issued without a key
+        let client = get_client().await;
+        let response = client.get("/receipts/key").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[cfg(feature = "hooks")]
+    #[rocket::async_test]
+    async fn test_event_hooks() {
+        let out = tempfile::TempDir::new().unwrap();
+        let config = format!(
+            "[[on_event.hooks]]\ncommand = [\"touch\", \"{}/{{event}}-{{unique_id}}-{{parties}}\"]",
+            out.path().display()
+        );
+        let (client, _dir) = get_client_with_config(&config).await;
+        let client = Arc::new(client);
+
+        let handle = spawn_request(client.clone(), UNIQUE_ID.to_string());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        make_sync_request(&client, UNIQUE_ID).await;
+        handle.await.expect("first response");
+        let response = client.post("/wait/lonely?ttl=1").dispatch().await;
+        assert_eq!(response.status(), Status::RequestTimeout);
+
+        // Hooks run in the background
+        let expected = [
+            format!("matched-{}-2", UNIQUE_ID),
+            "timeout-lonely-1".to_owned(),
+        ];
+        for _ in 0..40 {
+            if expected.iter().all(|name| out.path().join(name).exists()) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("hooks didn't run for {:?}", expected);
+    }
+
+    /// The party of a file trigger joins once the file lands & removes it when matched
+    #[rocket::async_test]
+    async fn test_file_trigger_joins_when_file_appears() {
+        let out = tempfile::TempDir::new().unwrap();
+        let artifact = out.path().join("app.tar");
+        let config = format!(
+            "[file_triggers]\npoll_interval_ms = 20\n\n[[file_triggers.triggers]]\n\
+             path = \"{}\"\nunique_id = \"artifact-ready\"\nremove = true",
+            artifact.display()
+        );
+        let (client, _dir) = get_client_with_config(&config).await;
+
+        std::fs::write(&artifact, "built").unwrap();
+        // The trigger's party waits first
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let response = make_sync_request(&client, "artifact-ready").await;
+        assert_eq!(response.status, Status::Ok);
+        assert_eq!(response.json["delivered"], true);
+
+        for _ in 0..40 {
+            if !artifact.exists() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(!artifact.exists(), "matched file wasn't removed");
+        let metrics = client.get("/metrics").dispatch().await;
+        let body = metrics.into_string().await.unwrap();
+        assert!(body.contains("sync_point_file_trigger_parties_total{outcome=\"matched\"} 1"));
+    }
+
+    /// Both parties are mirrored to the shadow, whose answers are compared with ours
+    #[cfg(feature = "shadow")]
+    #[rocket::async_test]
+    async fn test_rendezvous_requests_are_mirrored_to_the_shadow() {
+        let shadow = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = format!(
+            "[shadow]\nurl = \"http://{}/next\"",
+            shadow.local_addr().unwrap()
+        );
+        let (client, _dir) = get_client_with_config(&config).await;
+        let client = Arc::new(client);
+        let shadow = tokio::spawn(async move {
+            let mut mirrors = Vec::new();
+            for answer in ["200 OK", "408 Request Timeout"] {
+                let (stream, _) = shadow.accept().await.unwrap();
+                let mut stream = BufReader::new(stream);
+                let mut head = String::new();
+                while !head.ends_with("\r\n\r\n") {
+                    stream.read_line(&mut head).await.unwrap();
+                }
+                let answer = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", answer);
+                stream.get_mut().write_all(answer.as_bytes()).await.unwrap();
+                mirrors.push(head);
+            }
+            mirrors
+        });
+
+        let first = client
+            .post("/wait-for-second-party/mirrored?note=canary")
+            .header(Header::new(headers::API_KEY, "team-a"))
+            .dispatch();
+        let second = client.post("/wait-for-second-party/mirrored").dispatch();
+        let (first, second) = tokio::join!(first, second);
+        assert_eq!(first.status(), Status::Ok);
+        assert_eq!(second.status(), Status::Ok);
+
+        let mirrors = shadow.await.unwrap();
+        let canary = mirrors
+            .iter()
+            .find(|mirror| mirror.contains("?note=canary"))
+            .expect("mirror of the first party");
+        assert!(canary
+            .starts_with("POST /next/wait-for-second-party/mirrored?note=canary HTTP/1.1\r\n"));
+        assert!(canary.contains("X-Sync-Shadow: 1\r\n"));
+        assert!(canary.contains("X-Api-Key: team-a\r\n"));
+
+        let expected = [
+            "sync_point_shadow_mirrors_total{outcome=\"agreed\"} 1",
+            "sync_point_shadow_mirrors_total{outcome=\"differed\"} 1",
+        ];
+        for _ in 0..50 {
+            let body = client
+                .get("/metrics")
+                .dispatch()
+                .await
+                .into_string()
+                .await
+                .unwrap();
+            if expected.iter().all(|sample| body.contains(sample)) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("mirrors weren't compared");
+    }
+
     /// Let's make sure our API is functional for 2 unique endpoints
     /// & have no concurrent access issues
     #[rocket::async_test]
@@ -108,4 +1186,519 @@ mod tests {
         assert_success_response(&response3, ANOTHER_UNIQUE_ID, "first");
         assert_success_response(&response4, ANOTHER_UNIQUE_ID, "second");
     }
+
+    #[rocket::async_test]
+    async fn test_max_wait_points_limit() {
+        let (client, _dir) = get_client_with_config("timeout = 5\nmax_wait_points = 1").await;
+        let client = Arc::new(client);
+
+        let handle1 = spawn_request(client.clone(), UNIQUE_ID.to_string());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // No room for a second wait point
+        let response2 = make_sync_request(&client, "another-id").await;
+        assert_eq!(response2.status, Status::ServiceUnavailable);
+
+        // Joining the existing one is fine
+        let response3 = make_sync_request(&client, UNIQUE_ID).await;
+        assert_success_response(&response3, UNIQUE_ID, "second");
+        assert_success_response(&handle1.await.expect("first response"), UNIQUE_ID, "first");
+    }
+
+    #[rocket::async_test]
+    async fn test_status_codes() {
+        let config =
+            "max_wait_points = 1\n[status_codes]\ntimeout = 504\nconflict = 423\nbusy = 429";
+        let (client, _dir) = get_client_with_config(config).await;
+        let client = Arc::new(client);
+        let first = client.clone();
+        let handle = tokio::spawn(async move {
+            let response = first
+                .post(format!("/wait/{}?ttl=1", UNIQUE_ID))
+                .dispatch()
+                .await;
+            (response.status().code, get_response_json(response).await)
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let response = client.post(format!("/wait/{}", UNIQUE_ID)).dispatch().await;
+        assert_eq!(response.status().code, 423);
+        assert!(get_response_json(response).await["conflict"].is_object());
+        let response = make_sync_request(&client, "another-id").await;
+        assert_eq!(response.status.code, 429);
+        let response = client
+            .post("/wait-for-second-party/another-id?dry_run=true")
+            .dispatch()
+            .await;
+        assert_eq!(response.status().code, 429);
+
+        // Still recognized as a timeout by clients
+        let (status, json) = handle.await.expect("first response");
+        assert_eq!(status, 504);
+        let outcome = SyncOutcome::try_from((status, json)).unwrap();
+        assert!(matches!(outcome, SyncOutcome::TimedOut { .. }));
+    }
+
+    #[rocket::async_test]
+    async fn test_record_replay() {
+        let recorded = tempfile::TempDir::new().unwrap();
+        let config = format!("[dev]\nrecord_dir = \"{}\"", recorded.path().display());
+        let (client, _dir) = get_client_with_config(&config).await;
+        let response = client
+            .post(format!("/wait/{}?ttl=1", UNIQUE_ID))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::RequestTimeout);
+        let timeout = get_response_json(response).await;
+        let response = client.get("/health").dispatch().await;
+        let health = get_response_json(response).await;
+
+        let config = format!("[dev]\nreplay_dir = \"{}\"", recorded.path().display());
+        let (client, _dir) = get_client_with_config(&config).await;
+        let start = Instant::now();
+        let response = client
+            .post(format!("/wait/{}?ttl=1", UNIQUE_ID))
+            .dispatch()
+            .await;
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(response.status(), Status::RequestTimeout);
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+        assert_eq!(get_response_json(response).await, timeout);
+        let response = client.get("/health").dispatch().await;
+        assert_eq!(get_response_json(response).await, health);
+
+        let response = client.get("/stats").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(
+            get_response_json(response).await["message"],
+            "Nothing recorded for GET /stats"
+        );
+    }
+
+    /// Hundreds of parties & pollers hit the wait points at once on several worker threads.
+    /// Lock contention must only delay them, never fail a request
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_contention_causes_no_failures() {
+        let (client, _dir) = get_client_with_config("timeout = 30").await;
+        let client = Arc::new(client);
+
+        let parties: Vec<_> = (0..200)
+            .flat_map(|i| [format!("stress-{}", i), format!("stress-{}", i)])
+            .map(|unique_id| spawn_request(client.clone(), unique_id))
+            .collect();
+        let pollers: Vec<_> = (0..200)
+            .map(|i| {
+                let client = client.clone();
+                tokio::spawn(async move {
+                    let status = client.get(format!("/status/stress-{}", i)).dispatch().await;
+                    let stats = client.get("/stats").dispatch().await;
+                    (status.status(), stats.status())
+                })
+            })
+            .collect();
+
+        for party in parties {
+            let response = party.await.expect("party response");
+            assert_eq!(response.status, Status::Ok, "{}", response.json);
+        }
+        for poller in pollers {
+            assert_eq!(poller.await.expect("poller"), (Status::Ok, Status::Ok));
+        }
+    }
+
+    #[rocket::async_test]
+    async fn test_batch_wait_streams_results_as_they_resolve() {
+        let client = Arc::new(get_client().await);
+
+        let batch = {
+            let client = client.clone();
+            tokio::spawn(async move {
+                let response = client
+                    .post("/batch/wait")
+                    .body(r#"{"ids": ["a", "b"]}"#)
+                    .dispatch()
+                    .await;
+                let content_type = response.content_type().expect("content type").to_string();
+                (content_type, response.into_string().await.expect("body"))
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let b = make_sync_request(&client, "b").await;
+        assert_success_response(&b, "b", "second");
+        let a = make_sync_request(&client, "a").await;
+        assert_success_response(&a, "a", "second");
+
+        let (content_type, body) = batch.await.expect("batch response");
+        assert_eq!(content_type, "application/x-ndjson");
+        let lines: Vec<Value> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("JSON line"))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                json!({
+                    "unique_id": "b",
+                    "http_status": 200,
+                    "status": "success",
+                    "message": "[b] Welcome! (first party)",
+                    "pair_id": b.json["pair_id"]
+                }),
+                json!({
+                    "unique_id": "a",
+                    "http_status": 200,
+                    "status": "success",
+                    "message": "[a] Welcome! (first party)",
+                    "pair_id": a.json["pair_id"]
+                }),
+            ]
+        );
+
+        // A batch can't match itself, ids are normalized like paths first
+        for ids in [r#"["a", "a"]"#, r#"["a/b", "a//b/"]"#] {
+            let response = client
+                .post("/batch/wait")
+                .body(format!(r#"{{"ids": {}}}"#, ids))
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::BadRequest);
+        }
+        let response = client
+            .post("/batch/wait")
+            .body(r#"{"ids": ["", "/"]}"#)
+            .dispatch()
+            .await;
+        let body = response.into_string().await.unwrap();
+        let statuses: Vec<_> = body
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap()["http_status"].clone())
+            .collect();
+        assert_eq!(statuses, [400, 400]);
+        let response = client.get("/stats").dispatch().await;
+        assert_eq!(get_response_json(response).await["open_wait_points"], 0);
+    }
+
+    #[rocket::async_test]
+    async fn test_request_bodies_are_checked() {
+        let (client, _dir) =
+            get_client_with_config("timeout = 5\n[body_limits]\nmax_json_bytes = 32").await;
+
+        let response = client
+            .post(format!("/wait-for-second-party/{}", UNIQUE_ID))
+            .body("hello")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::PayloadTooLarge);
+        let response = client
+            .post(format!("/notify/{}", UNIQUE_ID))
+            .header(ContentType::JSON)
+            .body("{}")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnsupportedMediaType);
+        assert_eq!(
+            get_response_json(response).await,
+            json!({
+                "status": "error",
+                "message": format!("Unexpected application/json body, /notify/{} takes none", UNIQUE_ID)
+            })
+        );
+        // Rejected before reaching the route, so no wait point was opened
+        let response = client.get("/stats").dispatch().await;
+        assert_eq!(get_response_json(response).await["open_wait_points"], 0);
+
+        let response = client
+            .post("/batch/wait")
+            .header(ContentType::Form)
+            .body("ids=a")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnsupportedMediaType);
+        let response = client
+            .post("/batch/wait")
+            .header(ContentType::JSON)
+            // The local client doesn't set it, unlike HTTP clients
+            .header(Header::new("Content-Length", "39"))
+            .body(r#"{"ids": ["a", "b", "c", "d", "e", "f"]}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::PayloadTooLarge);
+        // Empty bodies are fine, whatever their type
+        let response = client
+            .post("/notify/nobody")
+            .header(ContentType::JSON)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    /// The body checks go by a table of paths, which every route taking a body must be in
+    #[rocket::async_test]
+    async fn test_every_route_taking_a_body_is_checked() {
+        let (client, _dir) = get_client_with_config("[dev]\nauto_match = true").await;
+        let routes: Vec<_> = client
+            .rocket()
+            .routes()
+            .filter(|route| matches!(route.method, Method::Post | Method::Put | Method::Patch))
+            .map(|route| {
+                let path = route
+                    .uri
+                    .path()
+                    .split('/')
+                    .map(|segment| if segment.starts_with('<') { "x" } else { segment })
+                    .collect::<Vec<_>>()
+                    .join("/");
+                (route.method, path)
+            })
+            .collect();
+        assert!(routes.iter().any(|(_, path)| path == "/admin/lame-duck"));
+        for (method, path) in routes {
+            let response = client
+                .req(method, path.clone())
+                .header(ContentType::Plain)
+                .body("x")
+                .dispatch()
+                .await;
+            assert_eq!(
+                response.status(),
+                Status::UnsupportedMediaType,
+                "{} {}",
+                method,
+                path
+            );
+        }
+    }
+
+    #[rocket::async_test]
+    async fn test_allocator_stats() {
+        let client = get_client().await;
+        let response = client.get("/stats/allocator").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+
+        let json = get_response_json(response).await;
+        if cfg!(feature = "jemalloc") {
+            assert_eq!(json["allocator"], "jemalloc");
+            assert!(json["allocated_bytes"].as_u64().expect("allocated_bytes") > 0);
+        } else {
+            assert!(json["allocator"].is_string());
+        }
+    }
+
+    #[rocket::async_test]
+    async fn test_memory_budget() {
+        let budget = Registry::entry_bytes(UNIQUE_ID);
+        let config = format!("timeout = 5\nmax_memory_bytes = {}", budget);
+        let (client, _dir) = get_client_with_config(&config).await;
+        let client = Arc::new(client);
+
+        let handle1 = spawn_request(client.clone(), UNIQUE_ID.to_string());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let stats = get_response_json(client.get("/stats").dispatch().await).await;
+        assert_eq!(stats["memory_bytes"], budget);
+
+        // Budget is used up by the first wait point
+        let response2 = make_sync_request(&client, "another-id").await;
+        assert_eq!(response2.status, Status::ServiceUnavailable);
+
+        let response3 = make_sync_request(&client, UNIQUE_ID).await;
+        assert_success_response(&response3, UNIQUE_ID, "second");
+        assert_success_response(&handle1.await.expect("first response"), UNIQUE_ID, "first");
+
+        let stats = get_response_json(client.get("/stats").dispatch().await).await;
+        assert_eq!(stats["memory_bytes"], 0);
+    }
+
+    #[cfg(feature = "chaos")]
+    #[rocket::async_test]
+    async fn test_chaos_lock_failure() {
+        let config = "[chaos]\nenabled = true\nlock_failure_probability = 1.0";
+        let (client, _dir) = get_client_with_config(config).await;
+
+        let response = make_sync_request(&client, UNIQUE_ID).await;
+        assert_eq!(response.status, Status::ServiceUnavailable);
+    }
+
+    #[rocket::async_test]
+    async fn test_dev_auto_match_disabled_by_default() {
+        let client = get_client().await;
+        let response = client.post("/dev/auto-match/123").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[rocket::async_test]
+    async fn test_dev_auto_match_plays_second_party() {
+        let (client, _dir) = get_client_with_config("[dev]\nauto_match = true").await;
+
+        let response = client
+            .post(format!("/dev/auto-match/{}?delay_ms=200", UNIQUE_ID))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Accepted);
+
+        // Client waits first, mock peer joins later
+        let response = make_sync_request(&client, UNIQUE_ID).await;
+        assert_success_response(&response, UNIQUE_ID, "first");
+    }
+
+    #[rocket::async_test]
+    async fn test_dev_auto_match_waits_for_client() {
+        let (client, _dir) = get_client_with_config("[dev]\nauto_match = true").await;
+
+        let response = client
+            .post(format!("/dev/auto-match/{}?delay_ms=0", UNIQUE_ID))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Accepted);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // Mock peer waits first, client joins as second party
+        let response = make_sync_request(&client, UNIQUE_ID).await;
+        assert_success_response(&response, UNIQUE_ID, "second");
+    }
+
+    #[rocket::async_test]
+    async fn test_dry_run() {
+        let client = Arc::new(get_client().await);
+        let dry_run = |client: Arc<rocket::local::asynchronous::Client>| async move {
+            let endpoint = format!("/wait-for-second-party/{}?dry_run=true", UNIQUE_ID);
+            let response = client.post(endpoint).dispatch().await;
+            (response.status(), get_response_json(response).await)
+        };
+
+        let (status, json) = dry_run(client.clone()).await;
+        assert_eq!(status, Status::Ok);
+        assert_eq!(
+            json,
+            json!({
+                "status": "success",
+                "message": format!("[{}] Dry run: would wait for the second party", UNIQUE_ID),
+                "dry_run": "wait"
+            })
+        );
+
+        // Nothing was created
+        let app = client.rocket().state::<App>().expect("App not found");
+        assert!(app.sync_service.wait_points.read().is_empty());
+
+        let handle1 = spawn_request(client.clone(), UNIQUE_ID.to_string());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let (status, json) = dry_run(client.clone()).await;
+        assert_eq!(
+            SyncOutcome::try_from((status.code, json)),
+            Ok(SyncOutcome::DryRun {
+                message: format!("[{}] Dry run: would join as second party", UNIQUE_ID),
+                outcome: DryRunOutcome::Match
+            })
+        );
+
+        // Dry run didn't take the second slot
+        let response2 = make_sync_request(&client, UNIQUE_ID).await;
+        assert_success_response(&response2, UNIQUE_ID, "second");
+        assert_success_response(&handle1.await.expect("first response"), UNIQUE_ID, "first");
+    }
+
+    #[cfg(feature = "compression")]
+    #[rocket::async_test]
+    async fn test_compression() {
+        use flate2::read::GzDecoder;
+        use rocket::http::Header;
+        use std::io::Read;
+
+        let config = "[compression]\nenabled = true\nmin_size = 10";
+        let (client, _dir) = get_client_with_config(config).await;
+
+        let response = client
+            .get("/health")
+            .header(Header::new("Accept-Encoding", "gzip"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("Content-Encoding"), Some("gzip"));
+        assert_eq!(response.headers().get_one("Vary"), Some("Accept-Encoding"));
+
+        let compressed = response.into_bytes().await.expect("body");
+        let mut body = String::new();
+        GzDecoder::new(compressed.as_slice())
+            .read_to_string(&mut body)
+            .expect("valid gzip");
+        assert_eq!(body, json!({"status": "ok"}).to_string());
+
+        // Not accepted by the client
+        let response = client.get("/health").dispatch().await;
+        assert_eq!(response.headers().get_one("Content-Encoding"), None);
+
+        // Below the threshold
+        let (client, _dir) = get_client_with_config("[compression]\nenabled = true").await;
+        let response = client
+            .get("/health")
+            .header(Header::new("Accept-Encoding", "gzip, br"))
+            .dispatch()
+            .await;
+        assert_eq!(response.headers().get_one("Content-Encoding"), None);
+    }
+
+    #[rocket::async_test]
+    async fn test_status_etag() {
+        use rocket::http::Header;
+
+        let client = Arc::new(get_client().await);
+        let endpoint = format!("/status/{}", UNIQUE_ID);
+
+        let response = client.get(endpoint.clone()).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("Cache-Control"), Some("no-cache"));
+        let etag = response.headers().get_one("ETag").expect("ETag").to_owned();
+        let json = get_response_json(response).await;
+        assert_eq!(json, json!({"unique_id": UNIQUE_ID, "parties": 0, "waiting": false}));
+
+        let revalidate = |etag: String| {
+            let client = client.clone();
+            let endpoint = endpoint.clone();
+            async move {
+                let response = client
+                    .get(endpoint)
+                    .header(Header::new("If-None-Match", etag))
+                    .dispatch()
+                    .await;
+                let etag = response.headers().get_one("ETag").map(str::to_owned);
+                (response.status(), etag, response.into_json::<serde_json::Value>().await)
+            }
+        };
+        let (status, new_etag, body) = revalidate(etag.clone()).await;
+        assert_eq!(status, Status::NotModified);
+        assert_eq!(new_etag, Some(etag.clone()));
+        assert_eq!(body, None);
+
+        let handle1 = spawn_request(client.clone(), UNIQUE_ID.to_string());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let (status, _, json) = revalidate(etag).await;
+        assert_eq!(status, Status::Ok);
+        let json = json.expect("status body");
+        assert_eq!(json["parties"], 1);
+        assert_eq!(json["waiting"], true);
+
+        let stats = client.get("/stats").dispatch().await;
+        let stats_etag = stats.headers().get_one("ETag").expect("ETag").to_owned();
+        assert_eq!(get_response_json(stats).await["waiting_parties"], 1);
+        let stats = client
+            .get("/stats")
+            .header(Header::new("If-None-Match", stats_etag.clone()))
+            .dispatch()
+            .await;
+        assert_eq!(stats.status(), Status::NotModified);
+
+        make_sync_request(&client, UNIQUE_ID).await;
+        handle1.await.expect("first response");
+        let stats = client
+            .get("/stats")
+            .header(Header::new("If-None-Match", stats_etag))
+            .dispatch()
+            .await;
+        assert_eq!(stats.status(), Status::Ok);
+        assert_eq!(get_response_json(stats).await["open_wait_points"], 0);
+    }
 }
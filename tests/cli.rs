@@ -0,0 +1,160 @@
+#[cfg(test)]
+mod tests {
+    use std::process::{Command, Output};
+    use std::time::{Duration, Instant};
+    use tempfile::TempDir;
+
+    fn check_config(content: &str) -> Output {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("config.toml");
+        std::fs::write(&config_path, content).expect("Unable to write config file");
+
+        Command::new(env!("CARGO_BIN_EXE_sync-point"))
+            .args(["check-config", config_path.to_str().unwrap()])
+            .env("RUST_LOG", "off")
+            .output()
+            .expect("Failed to run binary")
+    }
+
+    #[test]
+    fn test_check_config_valid() {
+        let output = check_config("timeout = 20");
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout).trim(),
+            "Configuration OK"
+        );
+    }
+
+    #[test]
+    fn test_check_config_invalid_timeout() {
+        let output = check_config("timeout = 1");
+        assert_eq!(output.status.code(), Some(1));
+        assert!(String::from_utf8_lossy(&output.stderr)
+            .contains("Timeout cannot be less than 5 seconds"));
+    }
+
+    #[test]
+    fn test_check_config_missing_file() {
+        let output = Command::new(env!("CARGO_BIN_EXE_sync-point"))
+            .args(["check-config", "does-not-exist.toml"])
+            .env("RUST_LOG", "off")
+            .output()
+            .expect("Failed to run binary");
+        assert_eq!(output.status.code(), Some(1));
+    }
+
+    fn run(args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_sync-point"))
+            .args(args)
+            .env("RUST_LOG", "off")
+            .output()
+            .expect("Failed to run binary")
+    }
+
+    #[test]
+    fn test_export_openapi() {
+        let output = run(&["export-openapi"]);
+        assert!(output.status.success());
+        let document: serde_json::Value =
+            serde_json::from_slice(&output.stdout).expect("JSON document");
+        assert_eq!(document["openapi"], "3.0.3");
+        assert!(document["paths"]["/wait-for-second-party/{unique_id}"]["post"].is_object());
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("openapi.json");
+        let output = run(&["export-openapi", "--output", path.to_str().unwrap()]);
+        assert!(output.status.success());
+        assert!(std::fs::read_to_string(path).unwrap().contains("\"openapi\""));
+    }
+
+    #[test]
+    fn test_generate_id() {
+        let output = run(&["generate-id", "-n", "3", "--prefix", "ci-"]);
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let ids: Vec<&str> = stdout.lines().collect();
+        assert_eq!(ids.len(), 3);
+        assert!(ids.iter().all(|id| id.starts_with("ci-") && id.len() == 39));
+        assert_ne!(ids[0], ids[1]);
+    }
+
+    #[test]
+    fn test_serve_flags_override_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("config.toml");
+        std::fs::write(&config_path, "timeout = 20\nmax_wait_points = 5").unwrap();
+
+        let output = run(&[
+            "serve",
+            "--config",
+            config_path.to_str().unwrap(),
+            "--timeout",
+            "30",
+            "--port",
+            "9000",
+            "--print-config",
+        ]);
+        assert!(output.status.success());
+        let settings: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(settings["timeout"], 30);
+        assert_eq!(settings["port"], 9000);
+        assert_eq!(settings["max_wait_points"], 5);
+
+        let output = run(&["serve", "--timeout", "1", "--print-config"]);
+        assert_eq!(output.status.code(), Some(1));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_daemon_writes_and_removes_pid_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let pid_path = temp_dir.path().join("sync-point.pid");
+        let log_path = temp_dir.path().join("sync-point.log");
+        let config_path = temp_dir.path().join("config.toml");
+        std::fs::write(&config_path, format!("log_file = {:?}", log_path)).unwrap();
+
+        // Returns once the daemon is forked
+        let output = Command::new(env!("CARGO_BIN_EXE_sync-point"))
+            .args([
+                "serve",
+                "--config",
+                config_path.to_str().unwrap(),
+                "--port",
+                "0",
+                "--daemon",
+                "--pid-file",
+                pid_path.to_str().unwrap(),
+            ])
+            .env("RUST_LOG", "warn")
+            .output()
+            .expect("Failed to run binary");
+        assert!(output.status.success());
+
+        let wait_until = |condition: &dyn Fn() -> bool| {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while !condition() {
+                assert!(Instant::now() < deadline, "timed out");
+                std::thread::sleep(Duration::from_millis(50));
+            }
+        };
+        // Logged to `log_file`, SIGTERM is handled gracefully from then on
+        wait_until(&|| {
+            std::fs::read_to_string(&log_path).is_ok_and(|log| log.contains("Rocket has launched"))
+        });
+        let pid = std::fs::read_to_string(&pid_path).unwrap();
+
+        let status = Command::new("kill")
+            .args(["-TERM", pid.trim()])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        wait_until(&|| !pid_path.exists());
+    }
+
+    #[test]
+    fn test_unknown_subcommand() {
+        let output = run(&["frobnicate"]);
+        assert_eq!(output.status.code(), Some(2));
+    }
+}
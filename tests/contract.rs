@@ -0,0 +1,458 @@
+/// Contract tests: the actual responses of every route must conform to the OpenAPI document
+/// (`sync_point::openapi`), so the two can't drift. Their status & content type must be documented
+/// for the operation, & their bodies valid against its schema: types, required fields, enum values
+/// & formats. Fields an object schema doesn't list are drift too, unless it allows them.
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+    use rocket::http::{ContentType, Header, Method};
+    use rocket::local::asynchronous::{LocalRequest, LocalResponse};
+    use serde_json::{json, Map, Value};
+    use std::collections::BTreeSet;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use sync_point::openapi::document;
+    use sync_point::test_support::{get_client_with_config, make_sync_request};
+    use tokio::io::{AsyncBufReadExt, BufReader};
+    use uuid::Uuid;
+
+    const CONFIG: &str = r#"
+admin_token = "s3cret"
+receipt_key = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60"
+[dev]
+auto_match = true
+auto_match_delay_ms = 100
+"#;
+
+    /// A response read to its end, except streamed events where it's the data of the first one
+    struct Reply {
+        status: u16,
+        /// Without parameters, e.g. `application/json`
+        content_type: Option<String>,
+        body: String,
+    }
+
+    async fn read(response: LocalResponse<'_>) -> Reply {
+        Reply {
+            status: response.status().code,
+            content_type: media_type(&response),
+            body: response.into_string().await.unwrap_or_default(),
+        }
+    }
+
+    fn media_type(response: &LocalResponse<'_>) -> Option<String> {
+        response
+            .content_type()
+            .map(|content_type| format!("{}/{}", content_type.top(), content_type.sub()))
+    }
+
+    fn admin(request: LocalRequest<'_>) -> LocalRequest<'_> {
+        request.header(Header::new("Authorization", "Bearer s3cret"))
+    }
+
+    fn json_body(request: LocalRequest<'_>, body: Value) -> LocalRequest<'_> {
+        request.header(ContentType::JSON).body(body.to_string())
+    }
+
+    /// Checks replies against the document, remembering the operations checked
+    struct Contract {
+        document: Value,
+        /// e.g. `POST /wait/{unique_id}`
+        covered: BTreeSet<String>,
+        date_time: Regex,
+    }
+
+    impl Contract {
+        fn new() -> Self {
+            Self {
+                document: document(),
+                covered: BTreeSet::new(),
+                date_time: Regex::new(
+                    r"^\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(\.\d+)?(Z|[+-]\d{2}:\d{2})$",
+                )
+                .unwrap(),
+            }
+        }
+
+        /// Panics describing the first way `reply` of `method` at `path` (as documented, e.g.
+        /// `/status/{unique_id}`) breaks the contract
+        fn check(&mut self, method: Method, path: &str, reply: &Reply) {
+            let operation = &self.document["paths"][path][method.as_str().to_lowercase()];
+            assert!(
+                operation.is_object(),
+                "{} {} isn't documented",
+                method,
+                path
+            );
+            self.covered.insert(format!("{} {}", method, path));
+
+            let at = format!("{} {} -> {}", method, path, reply.status);
+            let documented = &operation["responses"][reply.status.to_string()];
+            assert!(documented.is_object(), "{}: undocumented status", at);
+            let Some(content) = documented["content"].as_object() else {
+                assert!(reply.body.is_empty(), "{}: undocumented body", at);
+                return;
+            };
+            let content_type = reply.content_type.as_deref().unwrap_or("none");
+            let media = content
+                .get(content_type)
+                .unwrap_or_else(|| panic!("{}: undocumented content type {}", at, content_type));
+            let bodies: Vec<&str> = match content_type {
+                "application/json" | "text/event-stream" => vec![&reply.body],
+                "application/x-ndjson" => reply.body.lines().collect(),
+                // Text
+                _ => return,
+            };
+            for body in bodies {
+                let value: Value = serde_json::from_str(body)
+                    .unwrap_or_else(|e| panic!("{}: invalid JSON ({}): {}", at, e, body));
+                let mut errors = Vec::new();
+                self.validate(&media["schema"], &value, "", &mut errors);
+                assert!(errors.is_empty(), "{}: {}\n{}", at, errors.join(", "), body);
+            }
+        }
+
+        /// The schema `$ref` points to, if any
+        fn resolve<'a>(&'a self, schema: &'a Value) -> &'a Value {
+            match schema["$ref"].as_str() {
+                Some(reference) => {
+                    let target = self
+                        .document
+                        .pointer(reference.trim_start_matches('#'))
+                        .unwrap_or_else(|| panic!("Dangling $ref {}", reference));
+                    self.resolve(target)
+                }
+                None => schema,
+            }
+        }
+
+        /// `schema` with its `$ref` resolved & the parts of its `allOf` merged
+        fn flatten(&self, schema: &Value) -> Map<String, Value> {
+            let mut flat = self
+                .resolve(schema)
+                .as_object()
+                .cloned()
+                .unwrap_or_default();
+            let Some(Value::Array(parts)) = flat.remove("allOf") else {
+                return flat;
+            };
+            for part in parts {
+                for (keyword, value) in self.flatten(&part) {
+                    match (flat.get_mut(&keyword), value) {
+                        (Some(Value::Array(required)), Value::Array(more)) => required.extend(more),
+                        (Some(Value::Object(properties)), Value::Object(more)) => {
+                            properties.extend(more)
+                        }
+                        (None, value) => {
+                            flat.insert(keyword, value);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            flat
+        }
+
+        /// Adds the ways `value` (at JSON pointer `at`) breaks `schema` to `errors`
+        fn validate(&self, schema: &Value, value: &Value, at: &str, errors: &mut Vec<String>) {
+            let schema = self.flatten(schema);
+            if value.is_null() && schema.get("nullable") == Some(&Value::Bool(true)) {
+                return;
+            }
+            if let Some(kind) = schema.get("type").and_then(Value::as_str) {
+                let conforms = match kind {
+                    "object" => value.is_object(),
+                    "array" => value.is_array(),
+                    "string" => value.is_string(),
+                    "integer" => value.is_i64() || value.is_u64(),
+                    "number" => value.is_number(),
+                    "boolean" => value.is_boolean(),
+                    _ => true,
+                };
+                if !conforms {
+                    return errors.push(format!("{}: {} isn't of type {}", at, value, kind));
+                }
+            }
+            if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+                if !allowed.contains(value) {
+                    errors.push(format!("{}: {} isn't one of {:?}", at, value, allowed));
+                }
+            }
+            if let (Some(format), Some(text)) =
+                (schema.get("format").and_then(Value::as_str), value.as_str())
+            {
+                let conforms = match format {
+                    "uuid" => Uuid::parse_str(text).is_ok(),
+                    "date-time" => self.date_time.is_match(text),
+                    _ => true,
+                };
+                if !conforms {
+                    errors.push(format!("{}: {} isn't a {}", at, text, format));
+                }
+            }
+
+            match value {
+                Value::Object(object) => {
+                    let required = schema.get("required").and_then(Value::as_array);
+                    for name in required.into_iter().flatten().filter_map(Value::as_str) {
+                        if !object.contains_key(name) {
+                            errors.push(format!("{}/{}: is required", at, name));
+                        }
+                    }
+                    let properties = schema.get("properties").and_then(Value::as_object);
+                    for (name, field) in object {
+                        let at = format!("{}/{}", at, name);
+                        let documented = properties.and_then(|properties| properties.get(name));
+                        match (documented, schema.get("additionalProperties")) {
+                            (Some(property), _) => self.validate(property, field, &at, errors),
+                            (None, Some(additional)) if additional.is_object() => {
+                                self.validate(additional, field, &at, errors)
+                            }
+                            (None, Some(Value::Bool(true))) => {}
+                            (None, _) if properties.is_some() => {
+                                errors.push(format!("{}: isn't documented", at))
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                Value::Array(items) => {
+                    if let Some(item_schema) = schema.get("items") {
+                        for (index, item) in items.iter().enumerate() {
+                            self.validate(item_schema, item, &format!("{}/{}", at, index), errors);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    #[rocket::async_test]
+    async fn test_responses_conform_to_openapi() {
+        let (client, _dir) = get_client_with_config(CONFIG).await;
+        let client = Arc::new(client);
+        let mut contract = Contract::new();
+
+        // Read-only routes
+        let unknown_pair = format!("/completions/{}", Uuid::new_v4());
+        let reads = [
+            ("/", "/"),
+            ("/health", "/health"),
+            ("/ready", "/ready"),
+            ("/stats", "/stats"),
+            ("/stats/allocator", "/stats/allocator"),
+            ("/stats/outcomes", "/stats/outcomes"),
+            ("/time", "/time"),
+            ("/metrics", "/metrics"),
+            ("/receipts/key", "/receipts/key"),
+            ("/status/{unique_id}", "/status/contract/nobody"),
+            ("/completions/{pair_id}", "/completions/not-a-uuid"),
+            ("/completions/{pair_id}", unknown_pair.as_str()),
+        ];
+        for (path, uri) in reads {
+            let reply = read(client.get(uri).dispatch().await).await;
+            contract.check(Method::Get, path, &reply);
+        }
+        #[cfg(feature = "cluster")]
+        {
+            let response = client.get("/cluster/owner/contract").dispatch().await;
+            contract.check(
+                Method::Get,
+                "/cluster/owner/{unique_id}",
+                &read(response).await,
+            );
+        }
+
+        // A matched pair, with receipts & completion record
+        let first = {
+            let client = client.clone();
+            tokio::spawn(async move {
+                read(
+                    client
+                        .post("/wait-for-second-party/contract/pair")
+                        .dispatch()
+                        .await,
+                )
+                .await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let response = client
+            .post("/wait-for-second-party/contract/pair")
+            .dispatch()
+            .await;
+        let second = read(response).await;
+        let first = first.await.unwrap();
+        for reply in [&first, &second] {
+            assert_eq!(reply.status, 200);
+            contract.check(Method::Post, "/wait-for-second-party/{unique_id}", reply);
+        }
+        let matched: Value = serde_json::from_str(&second.body).unwrap();
+        let uri = format!("/completions/{}", matched["pair_id"].as_str().unwrap());
+        let reply = read(client.get(uri).dispatch().await).await;
+        assert_eq!(reply.status, 200);
+        contract.check(Method::Get, "/completions/{pair_id}", &reply);
+
+        // A waiting party: its status, a conflict, the admin listing & its timeout
+        let waiting = {
+            let client = client.clone();
+            tokio::spawn(async move {
+                read(client.post("/wait/contract/waiting?ttl=1").dispatch().await).await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let response = client.get("/status/contract/waiting").dispatch().await;
+        contract.check(Method::Get, "/status/{unique_id}", &read(response).await);
+        let response = client.post("/wait/contract/waiting").dispatch().await;
+        let conflict = read(response).await;
+        assert_eq!(conflict.status, 409);
+        contract.check(Method::Post, "/wait/{unique_id}", &conflict);
+        let response = admin(client.get("/admin/wait-points")).dispatch().await;
+        contract.check(Method::Get, "/admin/wait-points", &read(response).await);
+        let timeout = waiting.await.unwrap();
+        assert_eq!(timeout.status, 408);
+        contract.check(Method::Post, "/wait/{unique_id}", &timeout);
+
+        // Notifications: nobody waiting, stored, picked up & matching a waiting party
+        let notifications = [
+            "/notify/contract/notified",
+            "/notify/contract/notified?persistent=true",
+        ];
+        for uri in notifications {
+            let reply = read(client.post(uri).dispatch().await).await;
+            contract.check(Method::Post, "/notify/{unique_id}", &reply);
+        }
+        let response = client.post("/wait/contract/notified").dispatch().await;
+        contract.check(Method::Post, "/wait/{unique_id}", &read(response).await);
+        let waiting = {
+            let client = client.clone();
+            tokio::spawn(async move {
+                read(client.post("/wait/contract/notify").dispatch().await).await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let response = client.post("/notify/contract/notify").dispatch().await;
+        contract.check(Method::Post, "/notify/{unique_id}", &read(response).await);
+        contract.check(Method::Post, "/wait/{unique_id}", &waiting.await.unwrap());
+
+        // Dry runs, invalid parameters & observing nobody
+        let uris = [
+            "/wait-for-second-party/contract/dry?dry_run=true",
+            "/wait-for-second-party/contract/invalid?ttl=0",
+            "/wait-for-second-party/contract/nobody?observe=true",
+        ];
+        for uri in uris {
+            let reply = read(client.post(uri).dispatch().await).await;
+            contract.check(Method::Post, "/wait-for-second-party/{unique_id}", &reply);
+        }
+        let response = client.post("/reserve/contract/reserved").dispatch().await;
+        contract.check(Method::Post, "/reserve/{unique_id}", &read(response).await);
+
+        // Batches, streamed until their ids resolve
+        let batch = {
+            let client = client.clone();
+            tokio::spawn(async move {
+                let request = json_body(
+                    client.post("/batch/wait"),
+                    json!({"ids": ["contract/batch"]}),
+                );
+                read(request.dispatch().await).await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        make_sync_request(&client, "contract/batch").await;
+        contract.check(Method::Post, "/batch/wait", &batch.await.unwrap());
+        let response = json_body(client.post("/batch/wait"), json!({"ids": []}))
+            .dispatch()
+            .await;
+        contract.check(Method::Post, "/batch/wait", &read(response).await);
+
+        // Observed events
+        let response = client.get("/observe/contract/observed").dispatch().await;
+        let status = response.status().code;
+        let content_type = media_type(&response);
+        let mut lines = BufReader::new(response).lines();
+        let party = {
+            let client = client.clone();
+            tokio::spawn(async move {
+                client
+                    .post("/wait/contract/observed?ttl=1")
+                    .dispatch()
+                    .await
+                    .status()
+            })
+        };
+        let event = loop {
+            let line = lines.next_line().await.unwrap().expect("open stream");
+            if let Some(data) = line.strip_prefix("data:") {
+                break data.trim().to_owned();
+            }
+        };
+        let observed = Reply {
+            status,
+            content_type,
+            body: event,
+        };
+        contract.check(Method::Get, "/observe/{pattern}", &observed);
+        party.await.unwrap();
+
+        // Mock peer
+        let response = client.post("/dev/auto-match/contract/dev").dispatch().await;
+        contract.check(
+            Method::Post,
+            "/dev/auto-match/{unique_id}",
+            &read(response).await,
+        );
+        make_sync_request(&client, "contract/dev").await;
+
+        // Admin API
+        let requests = [
+            (Method::Get, "/admin/config", None),
+            (Method::Patch, "/admin/config", Some(json!({"timeout": 20}))),
+            (Method::Patch, "/admin/config", Some(json!({"timeout": 1}))),
+            (
+                Method::Put,
+                "/admin/log-level",
+                Some(json!({"level": "debug"})),
+            ),
+            (Method::Get, "/admin/maintenance", None),
+            (
+                Method::Put,
+                "/admin/maintenance",
+                Some(json!({"enabled": false})),
+            ),
+            (
+                Method::Put,
+                "/admin/lame-duck",
+                Some(json!({"enabled": false})),
+            ),
+            (Method::Get, "/admin/observability/templates", None),
+            (Method::Post, "/admin/self-test", None),
+        ];
+        for (method, path, body) in requests {
+            let request = admin(client.req(method, path));
+            let request = match body {
+                Some(body) => json_body(request, body),
+                None => request,
+            };
+            contract.check(method, path, &read(request.dispatch().await).await);
+        }
+
+        // Every mounted route was checked
+        for route in client.rocket().routes() {
+            let path = route
+                .uri
+                .path()
+                .replace("..>", ">")
+                .replace('<', "{")
+                .replace('>', "}");
+            let operation = format!("{} {}", route.method, path);
+            assert!(
+                contract.covered.contains(&operation),
+                "{} isn't checked",
+                operation
+            );
+        }
+    }
+}